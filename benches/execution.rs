@@ -0,0 +1,50 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use litechain::{execute_parallel, execute_sequential, AccountsDB, Transaction, TransactionSign, TransferTransaction, Wallet};
+
+const BLOCK_SIZE: usize = 256;
+
+// A funded db & a block of transfers. With `senders == BLOCK_SIZE` every transfer has its own sender &
+// recipient, so the whole block runs as one parallel batch; with one sender every transfer conflicts.
+fn setup(senders: usize) -> (AccountsDB, Vec<Transaction>) {
+    let db = AccountsDB::new();
+    let wallets: Vec<Wallet> = (0..senders).map(|_| Wallet::generate()).collect();
+    for wallet in &wallets {
+        db.add_account(wallet.public_key, wallet.account());
+        db.increase_account_balance(&wallet.public_key, 1_000_000).unwrap();
+    }
+
+    let transactions = (0..BLOCK_SIZE)
+        .map(|i| {
+            let from = &wallets[i % senders];
+            let to = Wallet::generate();
+            db.add_account(to.public_key, to.account());
+
+            let mut tx = TransferTransaction::new(to.public_key, from.public_key, 1, i as u64);
+            tx.sign(from);
+            Transaction::from(tx)
+        })
+        .collect();
+
+    (db, transactions)
+}
+
+fn execution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execution");
+    group.throughput(Throughput::Elements(BLOCK_SIZE as u64));
+
+    for (name, senders) in [("independent", BLOCK_SIZE), ("conflicting", 1)] {
+        let (db, transactions) = setup(senders);
+
+        group.bench_with_input(BenchmarkId::new("sequential", name), &transactions, |b, transactions| {
+            b.iter_batched(|| db.clone(), |mut db| execute_sequential(transactions, &mut db).unwrap(), BatchSize::LargeInput)
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", name), &transactions, |b, transactions| {
+            b.iter_batched(|| db.clone(), |mut db| execute_parallel(transactions, &mut db).unwrap(), BatchSize::LargeInput)
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, execution);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+use std::io;
+
+use prost::Message;
+
+// Generate the gRPC bindings for proto/litechain.proto. The proto is parsed in Rust rather than by
+// `protoc`, so building doesn't need it installed.
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed=proto/litechain.proto");
+    let descriptors = protobuf_parse::Parser::new()
+        .pure()
+        .include("proto")
+        .input("proto/litechain.proto")
+        .file_descriptor_set()
+        .map_err(io::Error::other)?;
+    let bytes = protobuf::Message::write_to_bytes(&descriptors).map_err(io::Error::other)?;
+    let descriptors = prost_types::FileDescriptorSet::decode(bytes.as_slice()).map_err(io::Error::other)?;
+    tonic_build::configure().build_client(true).build_server(true).compile_fds(descriptors)
+}
@@ -0,0 +1,83 @@
+use std::{
+    env,
+    io::{self, Error, ErrorKind},
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+use clap::Parser;
+use litechain::{load_or_create, Node, NodeConfig};
+use tracing_subscriber::EnvFilter;
+
+const PASSPHRASE_VAR: &str = "LITECHAIN_KEYSTORE_PASSPHRASE";
+
+#[derive(Parser)]
+#[command(name = "litechain-node", about = "Run a litechain validator node")]
+struct Args {
+    // TOML config file, defaults are used for anything it doesn't set
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(long)]
+    rpc_addr: Option<String>,
+
+    #[arg(long)]
+    p2p_addr: Option<String>,
+
+    // Genesis file of the chain to join
+    #[arg(long)]
+    genesis: Option<PathBuf>,
+
+    // Encrypted validator key, created on first run. The passphrase is read from LITECHAIN_KEYSTORE_PASSPHRASE.
+    #[arg(long)]
+    keystore: Option<PathBuf>,
+
+    // Run a development chain with a faucet serving `requestAirdrop`
+    #[arg(long)]
+    dev: bool,
+
+    // Peer to connect to on startup, may be repeated
+    #[arg(long = "peer")]
+    peers: Vec<SocketAddr>,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args = Args::parse();
+    // Filtered with RUST_LOG, e.g. `RUST_LOG=litechain=debug` for block build & execution spans
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    let mut config = match &args.config {
+        Some(path) => NodeConfig::load(path)?,
+        None => NodeConfig::default(),
+    };
+    if let Some(rpc_addr) = args.rpc_addr {
+        config.rpc_addr = rpc_addr;
+    }
+    if let Some(p2p_addr) = args.p2p_addr {
+        config.p2p_addr = p2p_addr;
+    }
+    config.peers.extend(args.peers);
+    if let Some(genesis) = args.genesis {
+        config.genesis = Some(genesis);
+    }
+    if let Some(keystore) = args.keystore {
+        config.keystore = Some(keystore);
+    }
+    config.dev |= args.dev;
+
+    let node = match config.keystore.clone() {
+        Some(path) => {
+            let passphrase = env::var(PASSPHRASE_VAR)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{} must be set to unlock the keystore", PASSPHRASE_VAR)))?;
+            let wallet = load_or_create(&path, &passphrase)?;
+            Node::with_wallet(config, wallet)?
+        }
+        None => Node::new(config)?,
+    };
+    node.run(async {
+        let _ = tokio::signal::ctrl_c().await;
+    }).await
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::{structures::Pubkey, wire};
+
+// Size of a block's account bloom filter & how many of its bits each key sets. A block touching a couple
+// of hundred accounts turns up a false positive for under 2% of the keys it doesn't touch.
+pub const BLOOM_BYTES: usize = 256;
+pub const BLOOM_HASHES: usize = 3;
+
+// Over-approximate set of the accounts a block's transactions touch, carried in its header. A key that
+// isn't in it definitely isn't touched by the block, so wallets & indexers scanning history can skip the
+// block without fetching its body; a key that is may be a false positive.
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct AccountBloom(#[serde(with = "wire::byte_array")] [u8; BLOOM_BYTES]);
+
+impl Default for AccountBloom {
+    fn default() -> Self {
+        Self([0; BLOOM_BYTES])
+    }
+}
+
+impl fmt::Debug for AccountBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AccountBloom").field(&hex::encode(self.0)).finish()
+    }
+}
+
+impl AccountBloom {
+    pub fn from_accounts<'a>(accounts: impl IntoIterator<Item = &'a Pubkey>) -> Self {
+        let mut bloom = Self::default();
+        for pubkey in accounts {
+            bloom.insert(pubkey);
+        }
+        bloom
+    }
+
+    pub fn insert(&mut self, pubkey: &Pubkey) {
+        for bit in Self::bits(pubkey) {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    // False means `pubkey` was never inserted; true only that it may have been
+    pub fn contains(&self, pubkey: &Pubkey) -> bool {
+        Self::bits(pubkey).iter().all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    // Bits `pubkey` sets, from a hash of it so nobody can pick keys that pile onto the same bits
+    fn bits(pubkey: &Pubkey) -> [usize; BLOOM_HASHES] {
+        let digest = Sha256::new().chain_update(b"litechain_account_bloom").chain_update(pubkey).finalize();
+        std::array::from_fn(|index| u16::from_le_bytes([digest[2 * index], digest[2 * index + 1]]) as usize % (BLOOM_BYTES * 8))
+    }
+}
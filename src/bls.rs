@@ -0,0 +1,66 @@
+use blst::{
+    min_pk::{AggregateSignature, PublicKey, SecretKey, Signature},
+    BLST_ERROR,
+};
+use sha2::{Digest, Sha256};
+
+use crate::structures::Seckey;
+
+// BLS12-381 keys validators vote with, alongside their ed25519 identity. Signatures on the same message
+// add up to one of the same size, so a whole quorum's votes check as a single signature. Keys are
+// registered with a proof of possession, which is what makes checking an aggregate against the sum of
+// its signers' keys safe.
+pub type BlsPubkey = [u8; 48];
+pub type BlsSignature = [u8; 96];
+
+// Ciphersuites of the proof of possession scheme, per the IETF BLS signature draft
+const SIGNATURE_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+const POSSESSION_DST: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+// The BLS key belonging to an ed25519 secret key, so a wallet needs no second secret to back up
+pub(crate) fn secret_key(seed: &Seckey) -> SecretKey {
+    let ikm: [u8; 32] = Sha256::new().chain_update(b"litechain_bls").chain_update(seed).finalize().into();
+    SecretKey::key_gen(&ikm, &[]).expect("Key material is 32 bytes")
+}
+
+pub(crate) fn public_key(secret: &SecretKey) -> BlsPubkey {
+    secret.sk_to_pk().compress()
+}
+
+pub(crate) fn sign(secret: &SecretKey, message: &[u8]) -> BlsSignature {
+    secret.sign(message, SIGNATURE_DST, &[]).compress()
+}
+
+// Signature over the key itself, proving whoever registers it holds the secret half
+pub(crate) fn prove_possession(secret: &SecretKey) -> BlsSignature {
+    secret.sign(&public_key(secret), POSSESSION_DST, &[]).compress()
+}
+
+pub fn verify(key: &BlsPubkey, message: &[u8], signature: &BlsSignature) -> bool {
+    verify_with(key, message, signature, SIGNATURE_DST)
+}
+
+pub fn verify_possession(key: &BlsPubkey, proof: &BlsSignature) -> bool {
+    verify_with(key, key, proof, POSSESSION_DST)
+}
+
+fn verify_with(key: &BlsPubkey, message: &[u8], signature: &BlsSignature, dst: &[u8]) -> bool {
+    let (Ok(key), Ok(signature)) = (PublicKey::key_validate(key), Signature::uncompress(signature)) else { return false };
+    signature.verify(true, message, dst, &[], &key, false) == BLST_ERROR::BLST_SUCCESS
+}
+
+// Fold signatures into one, or `None` if there are none or any doesn't decode
+pub fn aggregate(signatures: &[BlsSignature]) -> Option<BlsSignature> {
+    let signatures = signatures.iter().map(|signature| Signature::uncompress(signature)).collect::<Result<Vec<_>, _>>().ok()?;
+    let signatures: Vec<&Signature> = signatures.iter().collect();
+    AggregateSignature::aggregate(&signatures, true).ok().map(|aggregate| aggregate.to_signature().compress())
+}
+
+// Whether `signature` is every one of `keys` signing `message`. Only sound for keys registered with a
+// proof of possession.
+pub fn verify_aggregate(keys: &[BlsPubkey], message: &[u8], signature: &BlsSignature) -> bool {
+    let Ok(keys) = keys.iter().map(|key| PublicKey::key_validate(key)).collect::<Result<Vec<_>, _>>() else { return false };
+    let Ok(signature) = Signature::uncompress(signature) else { return false };
+    let keys: Vec<&PublicKey> = keys.iter().collect();
+    !keys.is_empty() && signature.fast_aggregate_verify(true, message, SIGNATURE_DST, &keys) == BLST_ERROR::BLST_SUCCESS
+}
@@ -1,74 +1,450 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+use dashmap::DashMap;
+use rayon::prelude::*;
+use tracing::{instrument, warn};
 use crate::{
+    chain::Blockchain,
+    config::ChainConfig,
     db::AccountsDB,
-    structures::{Block, Blockhash, Pubkey, ValidatorAccount, TransactionSign},
-    pool::{Mempool, MAX_TRANSACTIONS_PER_BLOCK},
+    events::{Event, EventBus},
+    metrics::Metrics,
+    network::Network,
+    rewards::RewardConfig,
+    structures::{Block, Blockhash, Pubkey, Transaction, TransactionId, TransactionStatus, TransactionSign},
+    pool::Mempool,
+    tower::Tower,
+    vote::{Quorum, Vote, VoteLock, VotePhase},
+    wallet::Wallet,
 };
 
 #[derive(Default, Debug, Clone)]
 pub struct BlockBuilder {
     pub mempool: Arc<RwLock<Mempool>>,
     pub db: Arc<RwLock<AccountsDB>>,
+    pub chain: Arc<RwLock<Blockchain>>,
+    pub network: Option<Network>,
+    pub rewards: RewardConfig,
+    pub config: ChainConfig,
+    // Wallets of the validators running in this process, which vote on blocks proposed here
+    pub signers: Arc<DashMap<Pubkey, Wallet>>,
+    // What each of the signers last precommitted, see `VoteLock`
+    pub locks: Arc<DashMap<Pubkey, VoteLock>>,
+    // Each of the signers' precommits & the lockouts they carry, see `Tower`
+    pub towers: Arc<DashMap<Pubkey, Tower>>,
+    pub metrics: Arc<Metrics>,
+    pub events: EventBus,
+    // When we started waiting on a partially full mempool
+    partial_since: Arc<Mutex<Option<Instant>>>,
+    // The tip we're waiting on a block for, & since when, see `current_slot`
+    slot_clock: Arc<Mutex<Option<(Blockhash, Instant)>>>,
 }
 
 impl BlockBuilder {
-    pub fn new(mempool: Arc<RwLock<Mempool>>, db: Arc<RwLock<AccountsDB>>) -> Self {
-        Self { mempool, db }
+    pub fn new(mempool: Arc<RwLock<Mempool>>, db: Arc<RwLock<AccountsDB>>, chain: Arc<RwLock<Blockchain>>) -> Self {
+        Self {
+            mempool,
+            db,
+            chain,
+            network: None,
+            rewards: RewardConfig::default(),
+            config: ChainConfig::default(),
+            signers: Arc::default(),
+            locks: Arc::default(),
+            towers: Arc::default(),
+            metrics: Arc::default(),
+            events: EventBus::default(),
+            partial_since: Arc::default(),
+            slot_clock: Arc::default(),
+        }
+    }
+
+    pub fn with_rewards(mut self, rewards: RewardConfig) -> Self {
+        self.rewards = rewards;
+        self
+    }
+
+    pub fn with_config(mut self, config: ChainConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Share `metrics` with the validator loop & whoever serves them, e.g. the mempool's too
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    // Announce proposed & finalized blocks on `events`, e.g. the same bus the mempool uses
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    // Attach a gossip node so finalized blocks are relayed to peers
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
     }
 
+    // The genesis block of the chain we're building on
     pub fn build_genesis(&self) -> Block {
-        Block::create_genesis()
+        self.chain.read().unwrap().block_at(0).expect("Chain always contains genesis").clone()
+    }
+
+    // Drain a block's worth of transactions from the mempool into a new block signed by `proposer`, or
+    // `None` if there's nothing to propose: the mempool is empty, or it isn't full & the partial block
+    // timeout hasn't passed. Drained transactions that fail validation are dropped from the pool, & ones
+    // that don't fit alongside the rest of the block are put back.
+    pub fn build(&self, prev_hash: Blockhash, proposer: &Wallet) -> Result<Option<Block>, &'static str> {
+        let slot = self.chain.read().unwrap().get(&prev_hash).ok_or("Parent block unknown")?.slot() + 1;
+        self.build_for_slot(prev_hash, slot, proposer)
     }
 
-    pub fn build(&self, prev_hash: Blockhash) -> Result<Block, &'static str> {
+    // `build`, for a slot past the one after the parent's when leaders before it have timed out
+    #[instrument(name = "build", level = "debug", skip_all, fields(parent = %hex::encode(prev_hash)))]
+    pub fn build_for_slot(&self, prev_hash: Blockhash, slot: u64, proposer: &Wallet) -> Result<Option<Block>, &'static str> {
+        let started = Instant::now();
+        let chain_lock = self.chain.read().unwrap();
+        let Some(parent) = chain_lock.get(&prev_hash).cloned() else { return Err("Parent block unknown") };
+        if slot <= parent.slot() {
+            return Err("Slot does not follow the parent's")
+        }
+        // The votes that finalized the parent, to carry in our header
+        let certificate = chain_lock.certificate(&prev_hash).cloned();
+        drop(chain_lock);
+
         // Acquire locks on mempool & accountsdb
         let mempool_lock = self.mempool.read().unwrap();
         let db_lock = self.db.read().unwrap();
 
-        if mempool_lock.pool.len() >= MAX_TRANSACTIONS_PER_BLOCK {
-            let transactions = mempool_lock.get_transactions_for_block();
+        let max_transactions = self.config.max_transactions_per_block;
+        let pending = mempool_lock.pool.len();
+        if pending == 0 {
+            *self.partial_since.lock().unwrap() = None;
+            return Ok(None)
+        }
+        if pending < max_transactions && !self.partial_timeout_elapsed() {
+            return Ok(None)
+        }
+        *self.partial_since.lock().unwrap() = None;
 
-            for tx in &transactions {
-                let signer: Pubkey = tx.get_signer();
-                if !tx.verify_signature(&signer) {
-                    return Err("Invalid transaction signature");
-                }
-                if !tx.validate(&*db_lock) {
-                    return Err("Invalid transaction in block building");
+        // Budget against the header as it'll be built, so a full block still fits once the parent's
+        // certificate & our leader proof are in
+        let certificate = certificate.filter(|certificate| certificate.verify(&db_lock).is_ok());
+        let header = Block::extending(&parent, vec![])
+            .with_slot(slot)
+            .with_certificate(certificate.clone())
+            .with_leader_proof(proposer, &db_lock.epoch_seed);
+        let max_bytes = self.config.max_block_bytes.saturating_sub(header.encoded_len());
+        let mut transactions = mempool_lock.drain_for_block(max_transactions, max_bytes);
+        if transactions.is_empty() {
+            return Ok(None)
+        }
+        order_nonces(&mut transactions);
+
+        // Invalid transactions are dropped rather than sinking the whole block
+        let errors = Self::validate_transactions(&transactions, &db_lock);
+        for (index, e) in &errors {
+            warn!(tx = %hex::encode(transactions[*index].hash()), error = e, "Dropping invalid transaction");
+        }
+        let transactions: Vec<Transaction> = transactions
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !errors.iter().any(|(failed, _)| failed == index))
+            .map(|(_, tx)| tx)
+            .collect();
+
+        // Whatever doesn't fit in the block's compute budget waits for a later block
+        let mut units_left = self.config.max_block_compute_units;
+        let (transactions, over_budget): (Vec<Transaction>, Vec<Transaction>) = transactions
+            .into_iter()
+            .partition(|tx| match units_left.checked_sub(tx.compute_units()) {
+                Some(left) => {
+                    units_left = left;
+                    true
                 }
+                None => false,
+            });
+        mempool_lock.requeue(over_budget);
+
+        // Each transaction is valid on its own, but together they may spend more than a sender has.
+        // Running them in order against a working copy keeps only a set that executes cleanly; the
+        // rest go back to the pool, since they may fit in a later block.
+        let overlay = db_lock.overlay(&transactions);
+        let (transactions, deferred): (Vec<Transaction>, Vec<Transaction>) = transactions
+            .into_iter()
+            .partition(|tx| tx.apply_state(&overlay).is_ok());
+        units_left += deferred.iter().map(Transaction::compute_units).sum::<u64>();
+        mempool_lock.requeue(deferred);
+
+        if transactions.is_empty() {
+            return Ok(None)
+        }
+
+        // Pending votes ride along in whatever room is left. Ones that no longer validate are dropped.
+        let mut transactions = transactions;
+        let mut room = max_bytes.saturating_sub(transactions.iter().map(|tx| tx.to_bytes().len()).sum());
+        let mut deferred_votes = vec![];
+        for vote in mempool_lock.drain_votes() {
+            let tx = Transaction::from(vote);
+            if !tx.validate(&db_lock) {
+                continue
+            }
+            let (len, units) = (tx.to_bytes().len(), tx.compute_units());
+            if len > room || units > units_left {
+                deferred_votes.push(vote);
+                continue
             }
-    
-            let block = Block::new(transactions, prev_hash);
-    
-            Ok(block)
+            room -= len;
+            units_left -= units;
+            transactions.push(tx);
+        }
+        mempool_lock.add_votes(deferred_votes);
+
+        // Never stamp a block earlier than its parent, even if our clock is behind the proposer's
+        let timestamp = SystemTime::now().max(parent.header.timestamp);
+        let block = Block::extending(&parent, transactions)
+            .with_slot(slot)
+            .with_timestamp(timestamp)
+            .with_state_root(db_lock.state_root())
+            .with_certificate(certificate)
+            .with_leader_proof(proposer, &db_lock.epoch_seed)
+            .with_proposer(proposer);
+        self.metrics.blocks_built.inc();
+        self.metrics.block_build_time.observe(started.elapsed());
+        self.events.emit(Event::BlockProposed {
+            hash: block.hash,
+            height: block.height(),
+            slot: block.slot(),
+            transactions: block.transactions.len(),
+        });
+        Ok(Some(block))
+    }
+
+    // Whether a partial block has waited long enough to be built. The clock starts the first time
+    // the builder finds the mempool non-empty but short of a full block.
+    fn partial_timeout_elapsed(&self) -> bool {
+        let Some(timeout) = self.config.partial_block_timeout_ms else { return false };
+        let mut partial_since = self.partial_since.lock().unwrap();
+        partial_since.get_or_insert_with(Instant::now).elapsed() >= Duration::from_millis(timeout)
+    }
 
-        } else {
-            Ok(self.build_genesis())
+    // Whether a transaction is on chain, waiting in the mempool, or neither
+    pub fn get_transaction_status(&self, id: &TransactionId) -> TransactionStatus {
+        let chain_lock = self.chain.read().unwrap();
+        if let Some(block_hash) = chain_lock.find_transaction(id) {
+            let height = chain_lock.height_of(&block_hash).expect("Included transactions are in canonical blocks");
+            return TransactionStatus::Included { block_hash, height }
+        }
+        drop(chain_lock);
+
+        if self.mempool.read().unwrap().contains_id(id) {
+            return TransactionStatus::Pending
+        }
+        TransactionStatus::Unknown
+    }
+
+    // Start timing out the tip's leader, if we weren't already. Leaders only have something to do once
+    // transactions are waiting, so the clock runs from then rather than from the tip's timestamp, or a
+    // chain that had sat idle would skip a slot for every timeout it spent idle. It keeps running until
+    // the tip moves on, even while a proposal has the mempool drained.
+    pub fn start_slot_clock(&self) {
+        let tip = self.chain.read().unwrap().tip().hash;
+        let mut clock = self.slot_clock.lock().unwrap();
+        if clock.is_none_or(|(waiting_on, _)| waiting_on != tip) {
+            *clock = Some((tip, Instant::now()));
         }
     }
 
-    pub fn get_leader(&self) -> ValidatorAccount {
+    // The slot up for proposal on top of the tip: the one after it, & one more for every
+    // `leader_timeout_ms` the slot clock has run without the tip moving on
+    pub fn current_slot(&self) -> u64 {
+        let (tip, slot) = {
+            let chain_lock = self.chain.read().unwrap();
+            (chain_lock.tip().hash, chain_lock.tip().slot())
+        };
+        let waited = match *self.slot_clock.lock().unwrap() {
+            Some((waiting_on, since)) if waiting_on == tip => since.elapsed(),
+            _ => Duration::ZERO,
+        };
+        slot + 1 + (waited.as_millis() / self.config.leader_timeout_ms.max(1) as u128) as u64
+    }
+
+    // Which of the validators running here leads `slot`: the one with the lowest draw, see
+    // `AccountsDB::draw_leader`. Validators elsewhere draw for themselves, & voters turn down a block
+    // whose proposer drew higher than they did.
+    pub fn get_leader(&self, slot: u64) -> Option<Wallet> {
         let db_lock = self.db.read().unwrap();
-        db_lock.validators
+        self.signers
             .iter()
-            .max_by_key(|validator| validator.stake)
-            .map(|entry| entry.clone())
-            .unwrap()
+            .filter_map(|signer| db_lock.draw_leader(signer.value(), slot).map(|(score, _)| (score, signer.value().clone())))
+            .min_by_key(|(score, wallet)| (*score, wallet.public_key))
+            .map(|(_, wallet)| wallet)
     }
 
+    // `wallet`'s prevote for the block, if it's valid against our view of state, was proposed by a
+    // validator that proves it drew lower for the slot than `wallet` did, breaks none of `wallet`'s tower
+    // lockouts, & `wallet` isn't locked on another block at its height
+    pub fn prevote(&self, block: &Block, wallet: &Wallet) -> Option<Vote> {
+        self.check_proposal(block, wallet)?;
+        if self.locks.get(&wallet.public_key).is_some_and(|lock| lock.forbids(block.height(), &block.hash)) {
+            return None
+        }
+        Some(Vote::prevote(block.hash, block.slot(), wallet))
+    }
+
+    // `wallet`'s precommit for the block once `prevotes` show a quorum prevoted it, locking `wallet` on
+    // it. A lock on another block at the same height only gives way to prevotes from a later slot.
+    pub fn precommit(&self, block: &Block, prevotes: &Quorum, wallet: &Wallet) -> Option<Vote> {
+        if prevotes.phase != VotePhase::Prevote {
+            return None
+        }
+        self.check_proposal(block, wallet)?;
+        // Recount rather than trust the weight we were handed
+        Quorum::aggregate(block.hash, block.slot(), prevotes.votes.clone(), &self.db.read().unwrap()).ok()?;
+
+        let lock = VoteLock { height: block.height(), slot: block.slot(), block_hash: block.hash };
+        if self.locks.get(&wallet.public_key).is_some_and(|held| held.forbids(lock.height, &lock.block_hash) && held.slot >= lock.slot) {
+            return None
+        }
+        self.locks.insert(wallet.public_key, lock);
+        self.towers.entry(wallet.public_key).or_default().record(block.slot(), block.hash);
+        Some(Vote::new(block.hash, block.slot(), wallet))
+    }
+
+    fn check_proposal(&self, block: &Block, wallet: &Wallet) -> Option<()> {
+        self.validate_block(block).ok()?;
+        // Slots can only be skipped once their leaders have timed out by our clock too
+        if block.slot() > self.current_slot() {
+            return None
+        }
+        // Nothing off a fork `wallet`'s earlier precommits still lock it into
+        let chain_lock = self.chain.read().unwrap();
+        if self.towers.get(&wallet.public_key).is_some_and(|tower| !tower.can_vote(block.slot(), |voted| chain_lock.is_ancestor(voted, &block.prev_hash()))) {
+            return None
+        }
+        drop(chain_lock);
+        let db_lock = self.db.read().unwrap();
+        if !db_lock.is_validator(&block.header.proposer) {
+            return None
+        }
+        let score = db_lock.leader_score(block)?;
+        if db_lock.draw_leader(wallet, block.slot()).is_some_and(|(ours, _)| ours < score) {
+            return None
+        }
+        Some(())
+    }
+
+    #[instrument(level = "debug", skip_all, fields(block = %hex::encode(block.hash), height = block.height()))]
     pub fn validate_block(&self, block: &Block) -> Result<(), &'static str> {
+        if !block.is_consistent() {
+            return Err("Block hash does not match contents");
+        }
+        if !block.verify_proposer() {
+            return Err("Invalid proposer signature");
+        }
+
+        // Only a block that extends our tip can be checked against our state
+        let chain_lock = self.chain.read().unwrap();
+        let tip = chain_lock.tip();
+        if block.prev_hash() != tip.hash {
+            return Err("Block does not extend the tip");
+        }
+        if block.height() != tip.height() + 1 {
+            return Err("Block height does not follow its parent");
+        }
+        if block.slot() <= tip.slot() {
+            return Err("Block slot does not follow its parent");
+        }
+        if block.header.timestamp < tip.header.timestamp {
+            return Err("Block timestamp is before its parent");
+        }
+        if block.transactions.iter().filter_map(|tx| tx.vote()).any(|vote| !chain_lock.contains(&vote.block_hash)) {
+            return Err("Block records a vote for an unknown block");
+        }
+        if block.header.certificate.as_ref().is_some_and(|certificate| certificate.block_hash != tip.hash || certificate.slot != tip.slot()) {
+            return Err("Certificate is not for the parent block");
+        }
+        drop(chain_lock);
+
+        // Recorded votes don't take up transaction slots
+        if block.transactions.iter().filter(|tx| tx.vote().is_none()).count() > self.config.max_transactions_per_block {
+            return Err("Block has too many transactions");
+        }
+        let size = block.encoded_len();
+        if block.size != size as u64 {
+            return Err("Block size does not match its encoding");
+        }
+        if size > self.config.max_block_bytes {
+            return Err("Block exceeds the maximum size");
+        }
+        if block.compute_units() > self.config.max_block_compute_units {
+            return Err("Block exceeds the compute budget");
+        }
+
         let db_lock = self.db.read().unwrap();
+        if let Some(certificate) = &block.header.certificate {
+            certificate.verify(&db_lock)?;
+        }
+        if let Some((_, e)) = Self::validate_transactions(&block.transactions, &db_lock).first() {
+            return Err(e);
+        }
 
-        for tx in &block.transactions {
-            let signer: Pubkey = tx.get_signer();
-            if !tx.verify_signature(&signer) {
-                return Err("Invalid transaction signature");
-            }
-            if !tx.validate(&*db_lock) {
-                return Err("Invalid transaction in block validation");
-            }
+        // Catch transactions that only fail once the ones before them in the block have run
+        let overlay = db_lock.overlay(&block.transactions);
+        if !block.transactions.iter().all(|tx| tx.apply_state(&overlay).is_ok()) {
+            return Err("Block overspends an account");
+        }
+        if block.header.state_root != db_lock.state_root() {
+            return Err("Block state root does not match our state");
         }
-        
+
         Ok(())
     }
+
+    // Check every transaction against `db` on the rayon pool, returning the index & error of each one
+    // that fails, in block order. A single batch signature check covers the common all-valid case;
+    // only when it fails are signatures checked one by one to find the culprits.
+    pub fn validate_transactions(transactions: &[Transaction], db: &AccountsDB) -> Vec<(usize, &'static str)> {
+        let signatures_valid = Transaction::verify_batch(transactions);
+
+        transactions
+            .par_iter()
+            .enumerate()
+            .filter_map(|(index, tx)| {
+                if !signatures_valid && !tx.verify_signatures() {
+                    return Some((index, "Invalid transaction signature"))
+                }
+                // A transaction following another of its signer's can only be checked once that one has
+                // run, which running them in order does
+                if tx.vote().is_none() && tx.nonce() > db.nonce(&tx.get_signer()) {
+                    return None
+                }
+                if !tx.validate_state(db) {
+                    return Some((index, "Invalid transaction state"))
+                }
+                None
+            })
+            .collect()
+    }
+}
+
+// Put each signer's transactions in nonce order, in the places their bids earned, so a later nonce
+// bidding more doesn't come ahead of the one it follows
+fn order_nonces(transactions: &mut [Transaction]) {
+    let mut by_signer: HashMap<Pubkey, Vec<usize>> = HashMap::new();
+    for (index, tx) in transactions.iter().enumerate() {
+        by_signer.entry(tx.get_signer()).or_default().push(index);
+    }
+    for indices in by_signer.into_values().filter(|indices| indices.len() > 1) {
+        let mut ordered: Vec<Transaction> = indices.iter().map(|&index| transactions[index].clone()).collect();
+        ordered.sort_by_key(Transaction::nonce);
+        for (index, tx) in indices.into_iter().zip(ordered) {
+            transactions[index] = tx;
+        }
+    }
 }
@@ -0,0 +1,732 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::{self, Error, ErrorKind},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    checkpoint::Checkpoint,
+    config::GenesisConfig,
+    db::{AccountsDB, HistoryError},
+    export::{ChainExport, ExportedBlock, EXPORT_FORMAT, EXPORT_VERSION},
+    plugin::{PluginSet, StatePlugin},
+    structures::{Block, BlockHeader, Blockhash, Pubkey, StateDiff, Transaction, TransactionId, TransactionReceipt, TransactionSign, Txhash},
+    vote::{Quorum, QuorumCertificate, Vote, VotePhase},
+    wal::{WalRecord, WriteAheadLog},
+};
+
+// How settled a block is. Processed blocks have been executed here, confirmed ones have been voted
+// for by a quorum, & finalized ones have enough confirmed blocks built on them that reverting them
+// would take reverting all of those too. Readers trade freshness for safety by picking one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    #[default]
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+// Store of every known block, including competing forks. The canonical chain is the branch
+// with the heaviest stake-weighted votes and is indexed by height (genesis is height 0). A chain
+// loaded from a snapshot is rooted at the snapshot's block instead, with nothing kept below it.
+#[derive(Debug, Clone)]
+pub struct Blockchain {
+    blocks: HashMap<Blockhash, Block>,
+    canonical: Vec<Blockhash>,
+    // What the chain was started from, the default config unless it came from `from_genesis`
+    genesis: GenesisConfig,
+    // Height of the root, the first canonical block
+    base: u64,
+    heights: HashMap<Blockhash, u64>,
+    // Stake that has voted for each block
+    weights: HashMap<Blockhash, u64>,
+    // Precommits carried in every block we hold, by the block they're for, with the block carrying each,
+    // so votes recorded on chain are found without scanning the blocks after
+    precommits: HashMap<Blockhash, Vec<(Blockhash, Vote)>>,
+    // Aggregated votes of the blocks finalized here, for the blocks built on them to carry
+    certificates: HashMap<Blockhash, QuorumCertificate>,
+    // Canonical blocks at multiples of the checkpoint interval that have a certificate, by height. Kept
+    // through pruning.
+    checkpoints: BTreeMap<u64, Checkpoint>,
+    // Issuance credited when each block was finalized, re-credited if the block is reapplied after a reorg
+    rewards: HashMap<Blockhash, Vec<(Pubkey, u64)>>,
+    // What each canonical block's transactions did when it was executed
+    receipts: HashMap<Blockhash, Vec<TransactionReceipt>>,
+    // Balances & stakes each canonical block changed
+    diffs: HashMap<Blockhash, StateDiff>,
+    // The canonical block each included transaction is in, by hash & by each of its signatures
+    included: HashMap<Txhash, Blockhash>,
+    signatures: HashMap<[u8; 64], Txhash>,
+    // (height, index in block) of every canonical transaction touching each account, oldest first
+    history: HashMap<Pubkey, Vec<(u64, u64)>>,
+    invalid: HashSet<Blockhash>,
+    // Canonical blocks below this height only keep their headers
+    pruned_below: u64,
+    // Where every state change is recorded before it's acknowledged, if anywhere
+    wal: Option<Arc<Mutex<WriteAheadLog>>>,
+    // Told about every account & block the canonical chain changes
+    plugins: PluginSet,
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blockchain {
+    pub fn new() -> Self {
+        Self::with_genesis(Block::create_genesis())
+    }
+
+    // A chain starting from the genesis `config` describes, along with its initial state
+    pub fn from_genesis(config: &GenesisConfig) -> Result<(Self, AccountsDB), &'static str> {
+        let db = AccountsDB::from_genesis(config)?;
+        let genesis = Block::genesis(config, db.state_root());
+        let chain = Self { genesis: config.clone(), ..Self::with_genesis(genesis) };
+        Ok((chain, db))
+    }
+
+    // Write the genesis config & every canonical block to a single file that `import` rebuilds from
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        if self.base > 0 || self.pruned_below > 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Chain history is incomplete"))
+        }
+        let blocks = self.canonical.iter()
+            .map(|hash| ExportedBlock {
+                block: self.blocks[hash].clone(),
+                rewards: self.rewards.get(hash).cloned().unwrap_or_default(),
+            })
+            .collect();
+        let export = ChainExport {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            genesis: self.genesis.clone(),
+            blocks,
+        };
+        export.write(path)
+    }
+
+    // Rebuild an exported chain & its state by replaying it from genesis
+    pub fn import(path: &Path) -> io::Result<(Self, AccountsDB)> {
+        ChainExport::read(path)?.rebuild().map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn genesis_config(&self) -> &GenesisConfig {
+        &self.genesis
+    }
+
+    // A chain rooted at a snapshot's block, for the state `Snapshot::restore` rebuilds. Blocks below it
+    // count as pruned.
+    pub fn from_snapshot(root: Block) -> Self {
+        Self::with_genesis(root)
+    }
+
+    // Start over from a snapshot's block, forgetting every block we had
+    pub fn reset_to(&mut self, root: Block) {
+        let wal = self.wal.take();
+        let genesis = std::mem::take(&mut self.genesis);
+        let plugins = std::mem::take(&mut self.plugins);
+        *self = Self { genesis, wal, plugins, ..Self::with_genesis(root) };
+    }
+
+    fn with_genesis(genesis: Block) -> Self {
+        let genesis_hash = genesis.hash;
+        let base = genesis.height();
+
+        Self {
+            blocks: HashMap::from([(genesis_hash, genesis)]),
+            canonical: vec![genesis_hash],
+            genesis: GenesisConfig::default(),
+            base,
+            heights: HashMap::from([(genesis_hash, base)]),
+            weights: HashMap::new(),
+            precommits: HashMap::new(),
+            certificates: HashMap::new(),
+            checkpoints: BTreeMap::new(),
+            rewards: HashMap::new(),
+            receipts: HashMap::new(),
+            diffs: HashMap::new(),
+            included: HashMap::new(),
+            signatures: HashMap::new(),
+            history: HashMap::new(),
+            invalid: HashSet::new(),
+            pruned_below: base,
+            wal: None,
+            plugins: PluginSet::default(),
+        }
+    }
+
+    pub fn with_wal(mut self, wal: WriteAheadLog) -> Self {
+        self.wal = Some(Arc::new(Mutex::new(wal)));
+        self
+    }
+
+    pub fn add_plugin(&mut self, plugin: Arc<dyn StatePlugin>) {
+        self.plugins.register(plugin);
+    }
+
+    pub fn tip(&self) -> &Block {
+        let hash = self.canonical.last().expect("Chain always contains genesis");
+        &self.blocks[hash]
+    }
+
+    pub fn height(&self) -> u64 {
+        self.base + self.canonical.len() as u64 - 1
+    }
+
+    // Height of the first block we hold, 0 unless the chain was loaded from a snapshot
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    // Whether we know about this block at all, canonical or not
+    pub fn contains(&self, hash: &Blockhash) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    pub fn is_canonical(&self, hash: &Blockhash) -> bool {
+        self.heights.contains_key(hash)
+    }
+
+    pub fn height_of(&self, hash: &Blockhash) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    // Any known block, canonical or not
+    pub fn get(&self, hash: &Blockhash) -> Option<&Block> {
+        self.blocks.get(hash)
+    }
+
+    pub fn block_at(&self, height: u64) -> Option<&Block> {
+        let index = height.checked_sub(self.base)?;
+        self.canonical.get(index as usize).map(|hash| &self.blocks[hash])
+    }
+
+    // Canonical blocks in the inclusive height range [start, end], clamped to the tip
+    pub fn range(&self, start: u64, end: u64) -> Vec<Block> {
+        let (start, end) = (start.max(self.base), end.min(self.height()));
+        if start > end {
+            return vec![]
+        }
+        self.canonical[(start - self.base) as usize..=(end - self.base) as usize]
+            .iter()
+            .map(|hash| self.blocks[hash].clone())
+            .collect()
+    }
+
+    // Receipts for a canonical block's transactions, in block order
+    pub fn receipts(&self, hash: &Blockhash) -> Option<&[TransactionReceipt]> {
+        self.receipts.get(hash).map(Vec::as_slice)
+    }
+
+    // What a canonical block changed when it was executed
+    pub fn state_diff(&self, hash: &Blockhash) -> Option<&StateDiff> {
+        self.diffs.get(hash)
+    }
+
+    // The canonical block a transaction was included in
+    pub fn find_transaction(&self, id: &TransactionId) -> Option<Blockhash> {
+        let hash = match id {
+            TransactionId::Hash(hash) => hash,
+            TransactionId::Signature(signature) => self.signatures.get(signature)?,
+        };
+        self.included.get(hash).copied()
+    }
+
+    // Up to `limit` of the transactions that touched `pubkey`, as (height, index in block), newest
+    // first. Pass the last entry of a page as `before` to get the next one.
+    pub fn account_history(&self, pubkey: &Pubkey, limit: usize, before: Option<(u64, u64)>) -> Vec<(u64, u64)> {
+        let Some(history) = self.history.get(pubkey) else { return vec![] };
+        let end = match before {
+            Some(before) => history.partition_point(|entry| *entry < before),
+            None => history.len(),
+        };
+        history[..end].iter().rev().take(limit).copied().collect()
+    }
+
+    // Headers of the canonical blocks in [start, end], clamped like `range`, without copying their transactions
+    pub fn headers(&self, start: u64, end: u64) -> Vec<(Blockhash, BlockHeader)> {
+        let (start, end) = (start.max(self.base), end.min(self.height()));
+        if start > end {
+            return vec![]
+        }
+        self.canonical[(start - self.base) as usize..=(end - self.base) as usize]
+            .iter()
+            .map(|hash| (*hash, self.blocks[hash].header.clone()))
+            .collect()
+    }
+
+    // Oldest height whose block is still kept in full. Genesis has no transactions to prune.
+    pub fn horizon(&self) -> u64 {
+        self.pruned_below
+    }
+
+    pub fn ensure_unpruned(&self, height: u64) -> Result<(), HistoryError> {
+        if height > 0 && height < self.pruned_below {
+            return Err(HistoryError::Pruned { horizon: self.pruned_below })
+        }
+        Ok(())
+    }
+
+    // Drop the transactions of canonical blocks below `horizon`, keeping their headers, along with what's
+    // indexed from them & any fork branching off below it. The tip is always kept. Pruned blocks can't be
+    // served to peers or reorged away afterwards.
+    // Returns the canonical blocks that were pruned, as they were before losing their transactions.
+    pub fn prune(&mut self, horizon: u64) -> Vec<Block> {
+        let horizon = horizon.min(self.height());
+        if horizon <= self.pruned_below {
+            return vec![]
+        }
+
+        let mut pruned = vec![];
+        for height in self.pruned_below.max(1)..horizon {
+            let hash = self.canonical[(height - self.base) as usize];
+            self.receipts.remove(&hash);
+            self.diffs.remove(&hash);
+            self.rewards.remove(&hash);
+            self.certificates.remove(&hash);
+            let block = self.blocks.get_mut(&hash).expect("Canonical blocks are stored");
+            pruned.push(block.clone());
+            let transactions = std::mem::take(&mut block.transactions);
+            for tx in transactions {
+                self.included.remove(&tx.hash());
+                for (_, signature) in tx.signatures() {
+                    self.signatures.remove(&signature.to_bytes());
+                }
+            }
+        }
+        for history in self.history.values_mut() {
+            history.retain(|(height, _)| *height >= horizon);
+        }
+        self.history.retain(|_, history| !history.is_empty());
+        // Pruned blocks no longer hold the votes they carried
+        let heights = &self.heights;
+        for votes in self.precommits.values_mut() {
+            votes.retain(|(carrier, _)| heights.get(carrier).is_none_or(|height| *height >= horizon));
+        }
+        self.precommits.retain(|_, votes| !votes.is_empty());
+
+        let stale: Vec<Blockhash> = self.blocks.values()
+            .filter(|block| block.height() < horizon && !self.is_canonical(&block.hash))
+            .map(|block| block.hash)
+            .collect();
+        for hash in stale {
+            self.discard(&hash);
+        }
+        self.pruned_below = horizon;
+        pruned
+    }
+
+    pub fn weight_of(&self, hash: &Blockhash) -> u64 {
+        self.weights.get(hash).copied().unwrap_or(0)
+    }
+
+    // Votes for `hash` recorded in the canonical blocks after it that still hold their transactions, at
+    // most one per validator, the earliest recorded
+    pub fn recorded_votes(&self, hash: &Blockhash) -> Vec<Vote> {
+        let Some(height) = self.height_of(hash) else { return vec![] };
+        let mut recorded: Vec<(u64, Vote)> = self.precommits
+            .get(hash)
+            .into_iter()
+            .flatten()
+            .filter_map(|(carrier, vote)| Some((self.height_of(carrier)?, *vote)))
+            .filter(|(carrier_height, _)| *carrier_height > height && *carrier_height >= self.pruned_below)
+            .collect();
+        recorded.sort_by_key(|(carrier_height, _)| *carrier_height);
+
+        let mut votes: Vec<Vote> = vec![];
+        for (_, vote) in recorded {
+            if !votes.iter().any(|counted| counted.validator == vote.validator) {
+                votes.push(vote);
+            }
+        }
+        votes
+    }
+
+    // The certificate for `hash` carried in the header of the canonical block after it
+    pub fn recorded_certificate(&self, hash: &Blockhash) -> Option<&QuorumCertificate> {
+        let child = self.block_at(self.height_of(hash)? + 1)?;
+        child.header.certificate.as_ref().filter(|certificate| certificate.block_hash == *hash)
+    }
+
+    // Aggregated votes for `hash`, as gathered here or else as recorded on chain
+    pub fn certificate(&self, hash: &Blockhash) -> Option<&QuorumCertificate> {
+        self.certificates.get(hash).or_else(|| self.recorded_certificate(hash))
+    }
+
+    pub fn add_certificate(&mut self, certificate: QuorumCertificate) {
+        let hash = certificate.block_hash;
+        self.certificates.insert(hash, certificate);
+        self.record_checkpoint(&hash);
+    }
+
+    // Checkpoint `hash` if it's a canonical block at a multiple of the checkpoint interval & we have a
+    // certificate for it, gathered here or carried by the block after it
+    fn record_checkpoint(&mut self, hash: &Blockhash) {
+        let interval = self.genesis.chain.checkpoint_interval;
+        let Some(height) = self.height_of(hash) else { return };
+        if interval == 0 || height % interval != 0 || self.checkpoints.contains_key(&height) {
+            return
+        }
+        if let Some(certificate) = self.certificate(hash).cloned() {
+            let checkpoint = Checkpoint::new(&self.blocks[hash], certificate);
+            self.checkpoints.insert(height, checkpoint);
+        }
+    }
+
+    pub fn checkpoint(&self, height: u64) -> Option<&Checkpoint> {
+        self.checkpoints.get(&height)
+    }
+
+    pub fn latest_checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoints.values().next_back()
+    }
+
+    // Whether the votes recorded on chain for `hash`, one by one or aggregated in the next header, make
+    // up a quorum of `db`'s validators, so its finality can be checked from chain data alone
+    pub fn is_confirmed(&self, hash: &Blockhash, db: &AccountsDB) -> bool {
+        let Some(block) = self.blocks.get(hash) else { return false };
+        if self.recorded_certificate(hash).is_some_and(|certificate| certificate.slot == block.slot() && certificate.verify(db).is_ok()) {
+            return true
+        }
+        let votes = self.recorded_votes(hash).into_iter().filter(|vote| db.is_validator(&vote.validator)).collect();
+        Quorum::aggregate(*hash, block.slot(), votes, db).is_ok()
+    }
+
+    // Whether a quorum voted for `hash`, as counted here or as recorded on chain
+    pub fn has_quorum(&self, hash: &Blockhash, db: &AccountsDB) -> bool {
+        self.weight_of(hash) > 0 || self.certificates.contains_key(hash) || self.is_confirmed(hash, db)
+    }
+
+    // Height of the newest canonical block at `commitment`. The root counts as finalized.
+    pub fn commitment_height(&self, commitment: Commitment, db: &AccountsDB) -> u64 {
+        let confirmed = || {
+            (self.base..=self.height())
+                .rev()
+                .find(|height| self.has_quorum(&self.canonical[(height - self.base) as usize], db))
+                .unwrap_or(self.base)
+        };
+        match commitment {
+            Commitment::Processed => self.height(),
+            Commitment::Confirmed => confirmed(),
+            Commitment::Finalized => confirmed().saturating_sub(self.genesis.chain.finality_depth).max(self.base),
+        }
+    }
+
+    pub fn add_votes(&mut self, hash: &Blockhash, stake: u64) {
+        let weight = self.weights.entry(*hash).or_insert(0);
+        *weight = weight.saturating_add(stake);
+    }
+
+    // Store a block without executing it. Its parent must already be known.
+    pub fn insert(&mut self, block: Block) -> Result<(), &'static str> {
+        if self.contains(&block.hash) {
+            return Err("Block already in chain")
+        }
+        if self.invalid.contains(&block.prev_hash()) {
+            return Err("Block extends an invalid block")
+        }
+        let Some(parent) = self.blocks.get(&block.prev_hash()) else {
+            return Err("Block parent unknown")
+        };
+        if block.height() != parent.height() + 1 {
+            return Err("Block height does not follow its parent")
+        }
+        if block.slot() <= parent.slot() {
+            return Err("Block slot does not follow its parent")
+        }
+        if block.header.timestamp < parent.header.timestamp {
+            return Err("Block timestamp is before its parent")
+        }
+        if !block.is_consistent() {
+            return Err("Block hash does not match contents")
+        }
+
+        for vote in block.transactions.iter().filter_map(|tx| tx.vote()).filter(|vote| vote.phase == VotePhase::Precommit) {
+            self.precommits.entry(vote.block_hash).or_default().push((block.hash, *vote));
+        }
+        self.blocks.insert(block.hash, block);
+        Ok(())
+    }
+
+    // Store a block & execute it against the db if it extends the tip, otherwise track it as a fork
+    // and re-run fork choice. Returns the blocks rolled back by a reorg, if any.
+    pub fn apply(&mut self, block: Block, db: &mut AccountsDB) -> Result<Vec<Block>, &'static str> {
+        let hash = block.hash;
+        let extends_tip = block.prev_hash() == self.tip().hash;
+        self.insert(block)?;
+
+        if !extends_tip {
+            return self.apply_fork_choice(db)
+        }
+
+        if let Err(e) = self.extend(&hash, db) {
+            self.discard(&hash);
+            return Err(e)
+        }
+        Ok(vec![])
+    }
+
+    // Total vote weight along the branch ending at `tip`
+    fn branch_weight(&self, tip: &Blockhash) -> u64 {
+        self.branch(tip).iter().map(|hash| self.weight_of(hash)).fold(0u64, u64::saturating_add)
+    }
+
+    // Hashes from genesis up to & including `tip`
+    fn branch(&self, tip: &Blockhash) -> Vec<Blockhash> {
+        let mut branch = vec![*tip];
+        let mut current = *tip;
+        while !self.is_genesis(&current) {
+            current = self.blocks[&current].prev_hash();
+            branch.push(current);
+        }
+        branch.reverse();
+        branch
+    }
+
+    // Whether `ancestor` is `block` or one of the blocks it builds on
+    pub fn is_ancestor(&self, ancestor: &Blockhash, block: &Blockhash) -> bool {
+        let Some(height) = self.blocks.get(ancestor).map(Block::height) else { return false };
+        let mut current = self.blocks.get(block);
+        while let Some(parent) = current.filter(|current| current.height() > height).map(Block::prev_hash) {
+            current = self.blocks.get(&parent);
+        }
+        current.is_some_and(|current| current.hash == *ancestor)
+    }
+
+    fn is_genesis(&self, hash: &Blockhash) -> bool {
+        self.canonical[0] == *hash
+    }
+
+    // Fork choice: the leaf with the heaviest stake-weighted branch, ties broken by height & then by
+    // staying on the current tip
+    pub fn best_tip(&self) -> Blockhash {
+        let parents: HashSet<Blockhash> = self.blocks.values()
+            .filter(|block| !self.is_genesis(&block.hash))
+            .map(|block| block.prev_hash())
+            .collect();
+
+        let current = self.tip().hash;
+        self.blocks.keys()
+            .filter(|hash| !parents.contains(*hash))
+            .max_by_key(|hash| (self.branch_weight(hash), self.branch(hash).len(), **hash == current))
+            .copied()
+            .unwrap_or(current)
+    }
+
+    // Switch the canonical chain to the fork-choice winner, rolling back & reapplying account state.
+    // Returns the blocks that were rolled back. If the new branch fails to execute the old one is restored.
+    pub fn apply_fork_choice(&mut self, db: &mut AccountsDB) -> Result<Vec<Block>, &'static str> {
+        let best = self.best_tip();
+        if best == self.tip().hash {
+            return Ok(vec![])
+        }
+
+        let new_branch = self.branch(&best);
+        let fork_point = self.canonical.iter()
+            .zip(&new_branch)
+            .take_while(|(old, new)| old == new)
+            .count();
+
+        // State from before the horizon can't be rebuilt, so the fork can never be switched to
+        if self.base + (fork_point as u64) < self.pruned_below {
+            self.discard(&new_branch[fork_point]);
+            return Err("Fork branches off below the pruning horizon")
+        }
+
+        let old_branch = self.canonical[fork_point..].to_vec();
+        self.rollback(fork_point, db);
+
+        for hash in &new_branch[fork_point..] {
+            if let Err(e) = self.extend(hash, db) {
+                // Drop the bad block & everything built on it, then go back to where we were
+                self.discard(hash);
+                self.rollback(fork_point, db);
+                for old in &old_branch {
+                    self.extend(old, db).expect("Previously canonical block must reapply");
+                }
+                return Err(e)
+            }
+        }
+
+        Ok(old_branch.iter().map(|hash| self.blocks[hash].clone()).collect())
+    }
+
+    // Re-check the canonical chain from genesis: every block links to & follows its parent, is signed
+    // by its proposer, commits to the state replayed from `genesis_state` & executes cleanly against it.
+    // Returns the height & reason of the first problem found.
+    pub fn verify(&self, genesis_state: &AccountsDB) -> Result<(), (u64, &'static str)> {
+        if self.base > 0 {
+            return Err((self.base, "Chain starts from a snapshot, not genesis"))
+        }
+        let mut db = genesis_state.clone();
+
+        for (height, pair) in self.canonical.windows(2).enumerate() {
+            let (parent, block) = (&self.blocks[&pair[0]], &self.blocks[&pair[1]]);
+            let height = height as u64 + 1;
+
+            let problem = if height < self.pruned_below {
+                Some("Block has been pruned")
+            } else if block.prev_hash() != parent.hash {
+                Some("Block does not link to its parent")
+            } else if block.height() != height {
+                Some("Block height is out of sequence")
+            } else if block.slot() <= parent.slot() {
+                Some("Block slot does not follow its parent")
+            } else if block.header.timestamp < parent.header.timestamp {
+                Some("Block timestamp is before its parent")
+            } else if !block.is_consistent() {
+                Some("Block hash does not match contents")
+            } else if !block.verify_proposer() {
+                Some("Invalid proposer signature")
+            } else if block.header.state_root != db.state_root() {
+                Some("Block state root does not match replayed state")
+            } else if !Transaction::verify_batch(&block.transactions) {
+                Some("Invalid transaction signature")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                return Err((height, problem))
+            }
+
+            db.finalize_block(block).map_err(|e| (height, e))?;
+            for (pubkey, amount) in self.rewards.get(&block.hash).into_iter().flatten() {
+                db.credit_reward(pubkey, *amount);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Credit block rewards for a canonical block. They're undone & redone along with the block on reorgs.
+    pub fn credit_rewards(&mut self, hash: &Blockhash, rewards: Vec<(Pubkey, u64)>, db: &mut AccountsDB) -> Result<(), &'static str> {
+        if !self.is_canonical(hash) {
+            return Err("Block is not canonical")
+        }
+        db.credit_block_rewards(hash, &rewards)?;
+        if !self.plugins.is_empty() {
+            let recipients = rewards.iter().map(|(pubkey, _)| *pubkey).collect();
+            self.plugins.accounts_updated(&recipients, db, self.heights[hash]);
+        }
+        self.rewards.entry(*hash).or_default().extend(rewards);
+        if let Some(diff) = db.state_diff(hash) {
+            self.log(WalRecord::applied(db, &diff));
+            self.diffs.insert(*hash, diff);
+        }
+        Ok(())
+    }
+
+    // Execute a stored block on top of the current tip. The db keeps its undo record for reorgs.
+    fn extend(&mut self, hash: &Blockhash, db: &mut AccountsDB) -> Result<(), &'static str> {
+        let receipts = db.apply_block(&self.blocks[hash])?;
+        if let Some(rewards) = self.rewards.get(hash) {
+            db.credit_block_rewards(hash, rewards)?;
+        }
+        let diff = db.state_diff(hash).expect("Block was just applied");
+        self.log(WalRecord::applied(db, &diff));
+        self.diffs.insert(*hash, diff);
+        self.receipts.insert(*hash, receipts);
+        let height = self.base + self.canonical.len() as u64;
+        for (index, tx) in self.blocks[hash].transactions.iter().enumerate() {
+            let mut touched = tx.accounts();
+            touched.sort();
+            touched.dedup();
+            for pubkey in touched {
+                self.history.entry(pubkey).or_default().push((height, index as u64));
+            }
+
+            let tx_hash = tx.hash();
+            self.included.insert(tx_hash, *hash);
+            for (_, signature) in tx.signatures() {
+                self.signatures.insert(signature.to_bytes(), tx_hash);
+            }
+        }
+        self.heights.insert(*hash, height);
+        self.canonical.push(*hash);
+        // The block may carry its parent's certificate, & ours for it may have come in before it was applied
+        let parent = self.blocks[hash].prev_hash();
+        self.record_checkpoint(&parent);
+        self.record_checkpoint(hash);
+
+        if !self.plugins.is_empty() {
+            let block = &self.blocks[hash];
+            let mut touched = self.touched(block);
+            touched.extend(self.diffs[hash].balances.iter().map(|change| change.pubkey));
+            self.plugins.accounts_updated(&touched, db, height);
+            self.plugins.block_finalized(block, height);
+        }
+        Ok(())
+    }
+
+    // Accounts a block's transactions & rewards may have changed
+    fn touched(&self, block: &Block) -> BTreeSet<Pubkey> {
+        let mut touched: BTreeSet<Pubkey> = block.transactions.iter().flat_map(|tx| tx.accounts()).collect();
+        touched.extend(self.rewards.get(&block.hash).into_iter().flatten().map(|(pubkey, _)| *pubkey));
+        touched
+    }
+
+    // Forget an invalid block along with every block built on top of it
+    fn discard(&mut self, hash: &Blockhash) {
+        let mut stale = vec![*hash];
+        while let Some(hash) = stale.pop() {
+            self.blocks.remove(&hash);
+            self.weights.remove(&hash);
+            self.precommits.remove(&hash);
+            self.rewards.remove(&hash);
+            self.certificates.remove(&hash);
+            self.invalid.insert(hash);
+            stale.extend(self.blocks.values().filter(|block| block.prev_hash() == hash).map(|block| block.hash));
+        }
+    }
+
+    // Revert canonical blocks until the chain is `len` blocks long
+    fn rollback(&mut self, len: usize, db: &mut AccountsDB) {
+        let mut reverted = vec![];
+        let mut reverted_blocks = vec![];
+        while self.canonical.len() > len {
+            let hash = self.canonical.pop().unwrap();
+            self.heights.remove(&hash);
+            self.receipts.remove(&hash);
+            let diff = self.diffs.remove(&hash);
+            let height = self.base + self.canonical.len() as u64;
+            if !self.plugins.is_empty() {
+                let mut touched = self.touched(&self.blocks[&hash]);
+                touched.extend(diff.iter().flat_map(|diff| diff.balances.iter().map(|change| change.pubkey)));
+                reverted_blocks.push((hash, height, touched));
+            }
+            reverted.extend(diff);
+            for tx in &self.blocks[&hash].transactions {
+                for pubkey in tx.accounts() {
+                    if let Some(history) = self.history.get_mut(&pubkey) {
+                        history.retain(|(included, _)| *included != height);
+                    }
+                }
+                self.included.remove(&tx.hash());
+                for (_, signature) in tx.signatures() {
+                    self.signatures.remove(&signature.to_bytes());
+                }
+            }
+        }
+        self.checkpoints.split_off(&(self.base + len as u64));
+        db.rollback_to(self.base + len as u64 - 1).expect("Canonical blocks are applied with their undo records");
+        for diff in reverted {
+            self.log(WalRecord::reverted(db, &diff));
+        }
+        let height = db.latest_height;
+        for (hash, reverted_height, touched) in reverted_blocks {
+            self.plugins.block_reverted(&self.blocks[&hash], reverted_height);
+            self.plugins.accounts_updated(&touched, db, height);
+        }
+    }
+
+    // A node that can't log its state can't recover it after a crash, so failing to write is fatal
+    fn log(&self, record: WalRecord) {
+        if let Some(wal) = &self.wal {
+            wal.lock().unwrap().append(&record).expect("Failed to write the state log");
+        }
+    }
+}
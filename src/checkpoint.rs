@@ -0,0 +1,45 @@
+use crate::{
+    db::AccountsDB,
+    merkle::Hash,
+    structures::{Block, BlockHeader, Blockhash},
+    vote::QuorumCertificate,
+};
+
+// A block a quorum finalized, taken every `checkpoint_interval` blocks: its hash, height & the state root
+// it commits to, with the quorum's precommits aggregated into one signature. Small enough to hand out as
+// a trust anchor, so light clients & snapshot sync can start from the latest one instead of genesis.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Checkpoint {
+    pub block_hash: Blockhash,
+    pub height: u64,
+    pub slot: u64,
+    // State the block executes on top of, as its header commits to
+    pub state_root: Hash,
+    pub certificate: QuorumCertificate,
+}
+
+impl Checkpoint {
+    pub fn new(block: &Block, certificate: QuorumCertificate) -> Self {
+        Self {
+            block_hash: block.hash,
+            height: block.height(),
+            slot: block.slot(),
+            state_root: block.header.state_root,
+            certificate,
+        }
+    }
+
+    // Whether `header` is the block this checkpoints
+    pub fn matches(&self, header: &BlockHeader) -> bool {
+        header.hash() == self.block_hash && header.height == self.height && header.slot == self.slot && header.state_root == self.state_root
+    }
+
+    // Check the certificate is for this block & carries a quorum of `db`'s validators, `db` being the
+    // state the block was voted on in
+    pub fn verify(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if self.certificate.block_hash != self.block_hash || self.certificate.slot != self.slot {
+            return Err("Certificate is for a different block")
+        }
+        self.certificate.verify(db).map(|_| ())
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::{
+    chain::Commitment,
+    db::EpochInfo,
+    rpc::{RpcError, RpcRequest, RpcResponse, API_KEY_HEADER},
+    structures::{Block, Blockhash, Pubkey, SimulationResult, Transaction, TransactionSign, TransactionStatus, TransferTransaction, Txhash, UserAccount, ValidatorAccount},
+    wallet::Wallet,
+};
+
+// How long a request may take to connect, send or answer before it's abandoned
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+// Wait between status checks while waiting on a transaction
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long a transaction the node has lost track of goes before `send_and_confirm` sends it again
+pub const DEFAULT_REBROADCAST_INTERVAL: Duration = Duration::from_secs(2);
+
+// Why a call to the node failed
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    // The node answered with an HTTP status other than 200, without a JSON-RPC error to say why
+    Http(u16),
+    InvalidResponse(&'static str),
+    // The node understood the request & refused it
+    Rpc(RpcError),
+}
+
+// How `send_and_confirm` left a transaction
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendOutcome {
+    // In a canonical block at the client's commitment
+    Confirmed { block_hash: Blockhash, height: u64 },
+    // Not in a block at the commitment when time ran out. It may still land.
+    Expired,
+    // The node refused it & doesn't hold it
+    Rejected(RpcError),
+}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        ClientError::Io(error)
+    }
+}
+
+// Blocking JSON-RPC client for a node's RPC server, with the requests & responses typed, so applications
+// can read state & submit transactions without an `AccountsDB` of their own. Queries read at the
+// client's commitment, processed unless set otherwise.
+#[derive(Debug, Clone)]
+pub struct LitechainClient {
+    // host:port the RPC server listens on
+    addr: String,
+    timeout: Duration,
+    commitment: Commitment,
+    rebroadcast_interval: Duration,
+    // Ask the node not to check submissions against its state first
+    skip_preflight: bool,
+    // Sent with every request, for the node to charge its rate limits to
+    api_key: Option<String>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl LitechainClient {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            timeout: DEFAULT_CLIENT_TIMEOUT,
+            commitment: Commitment::default(),
+            rebroadcast_interval: DEFAULT_REBROADCAST_INTERVAL,
+            skip_preflight: false,
+            api_key: None,
+            next_id: Arc::default(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_commitment(mut self, commitment: Commitment) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn with_rebroadcast_interval(mut self, rebroadcast_interval: Duration) -> Self {
+        self.rebroadcast_interval = rebroadcast_interval;
+        self
+    }
+
+    pub fn with_skip_preflight(mut self, skip_preflight: bool) -> Self {
+        self.skip_preflight = skip_preflight;
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn commitment(&self) -> Commitment {
+        self.commitment
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> Result<Option<UserAccount>, ClientError> {
+        self.query("getAccount", vec![json!(hex::encode(pubkey))])
+    }
+
+    pub fn get_balance(&self, pubkey: &Pubkey) -> Result<u64, ClientError> {
+        self.query("getBalance", vec![json!(hex::encode(pubkey))])
+    }
+
+    pub fn get_validator(&self, pubkey: &Pubkey) -> Result<Option<ValidatorAccount>, ClientError> {
+        self.query("getValidator", vec![json!(hex::encode(pubkey))])
+    }
+
+    pub fn get_epoch_info(&self) -> Result<EpochInfo, ClientError> {
+        self.query("getEpochInfo", vec![])
+    }
+
+    pub fn get_block_height(&self) -> Result<u64, ClientError> {
+        self.query("getBlockHeight", vec![])
+    }
+
+    pub fn get_latest_blockhash(&self) -> Result<Option<Blockhash>, ClientError> {
+        let hash: Option<String> = self.query("getLatestBlockhash", vec![])?;
+        hash.map(|hash| parse_hash(&hash)).transpose()
+    }
+
+    pub fn get_block(&self, height: u64) -> Result<Option<Block>, ClientError> {
+        self.query("getBlock", vec![json!(height)])
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Blockhash) -> Result<Option<Block>, ClientError> {
+        self.query("getBlockByHash", vec![json!(hex::encode(hash))])
+    }
+
+    pub fn get_transaction_status(&self, hash: &Txhash) -> Result<TransactionStatus, ClientError> {
+        self.query("getTransactionStatus", vec![json!(hex::encode(hash))])
+    }
+
+    // Submit a signed transaction, returning the hash to follow it by
+    pub fn send_transaction(&self, tx: &Transaction) -> Result<Txhash, ClientError> {
+        let mut params = vec![json!(hex::encode(tx.to_bytes()))];
+        if self.skip_preflight {
+            params.push(json!({ "skipPreflight": true }));
+        }
+        let _: u64 = self.request("sendTransaction", params)?;
+        Ok(tx.hash())
+    }
+
+    // What `tx` would do if it ran on state at the client's commitment, without submitting it
+    pub fn simulate_transaction(&self, tx: &Transaction) -> Result<SimulationResult, ClientError> {
+        self.query("simulateTransaction", vec![json!(hex::encode(tx.to_bytes()))])
+    }
+
+    // Build, sign & send a transfer from `from`. A pending transaction from the same signer at the same
+    // nonce is replaced if this one bids more, & refused otherwise.
+    pub fn transfer(&self, from: &Wallet, to: &Pubkey, amt: u64, nonce: u64) -> Result<Txhash, ClientError> {
+        let mut tx = TransferTransaction::new(*to, from.public_key, amt, nonce);
+        tx.sign(from);
+        self.send_transaction(&Transaction::from(tx))
+    }
+
+    // Poll until the transaction is in a block at the client's commitment, or `timeout` runs out.
+    // Returns the last status seen either way.
+    pub fn poll_confirmation(&self, hash: &Txhash, timeout: Duration) -> Result<TransactionStatus, ClientError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.get_transaction_status(hash)?;
+            if matches!(status, TransactionStatus::Included { .. }) || Instant::now() >= deadline {
+                return Ok(status)
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+    }
+
+    // Send a transaction & wait up to `timeout` for it to reach the client's commitment. It's watched at
+    // processed first, since that's where it shows up or drops out, & only checked at the commitment
+    // once it's in a block. If the node loses track of it, say because the block it was in was rolled
+    // back or it was dropped from the mempool, it's sent again every `rebroadcast_interval`.
+    pub fn send_and_confirm(&self, tx: &Transaction, timeout: Duration) -> Result<SendOutcome, ClientError> {
+        let deadline = Instant::now() + timeout;
+        let hash = tx.hash();
+        if let Err(error) = self.broadcast(tx)? {
+            return Ok(SendOutcome::Rejected(error))
+        }
+        let mut sent_at = Instant::now();
+
+        let processed = self.clone().with_commitment(Commitment::Processed);
+        loop {
+            match processed.get_transaction_status(&hash)? {
+                TransactionStatus::Included { block_hash, height } => {
+                    let status = if self.commitment == Commitment::Processed {
+                        TransactionStatus::Included { block_hash, height }
+                    } else {
+                        self.get_transaction_status(&hash)?
+                    };
+                    if let TransactionStatus::Included { block_hash, height } = status {
+                        return Ok(SendOutcome::Confirmed { block_hash, height })
+                    }
+                }
+                TransactionStatus::Pending => {}
+                TransactionStatus::Unknown => {
+                    if sent_at.elapsed() >= self.rebroadcast_interval {
+                        if let Err(error) = self.broadcast(tx)? {
+                            return Ok(SendOutcome::Rejected(error))
+                        }
+                        sent_at = Instant::now();
+                    }
+                }
+            }
+            if Instant::now() >= deadline {
+                return Ok(SendOutcome::Expired)
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+    }
+
+    // Submit `tx`, with the node's refusal as the inner error. Refusing one it already holds, in the
+    // mempool or a block, doesn't count.
+    fn broadcast(&self, tx: &Transaction) -> Result<Result<(), RpcError>, ClientError> {
+        match self.send_transaction(tx) {
+            Ok(_) => Ok(Ok(())),
+            Err(ClientError::Rpc(error)) => {
+                let status = self.clone().with_commitment(Commitment::Processed).get_transaction_status(&tx.hash())?;
+                Ok(if status == TransactionStatus::Unknown { Err(error) } else { Ok(()) })
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    // Call a method with `params` as given, decoding its result
+    pub fn request<T: DeserializeOwned>(&self, method: &str, params: Vec<Value>) -> Result<T, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = RpcRequest { jsonrpc: "2.0".to_string(), id: json!(id), method: method.to_string(), params: Value::Array(params) };
+        let body = serde_json::to_vec(&request).expect("Requests always encode");
+        let (status, body) = self.post(&body)?;
+        let response = serde_json::from_slice::<RpcResponse>(&body);
+        if status != 200 {
+            // Throttling, say, comes back as a 429 with the details in an error
+            return Err(match response {
+                Ok(RpcResponse { error: Some(error), .. }) => ClientError::Rpc(error),
+                _ => ClientError::Http(status),
+            })
+        }
+        let response = response.map_err(|_| ClientError::InvalidResponse("Response is not JSON-RPC"))?;
+        if response.id != json!(id) {
+            return Err(ClientError::InvalidResponse("Response is for a different request"))
+        }
+        if let Some(error) = response.error {
+            return Err(ClientError::Rpc(error))
+        }
+        serde_json::from_value(response.result.unwrap_or(Value::Null))
+            .map_err(|_| ClientError::InvalidResponse("Unexpected result type"))
+    }
+
+    // A request reading state, at the client's commitment
+    fn query<T: DeserializeOwned>(&self, method: &str, mut params: Vec<Value>) -> Result<T, ClientError> {
+        params.push(json!({ "commitment": self.commitment }));
+        self.request(method, params)
+    }
+
+    // One HTTP/1.1 POST to the server's root, on a fresh connection, returning the response's status
+    // & body
+    fn post(&self, body: &[u8]) -> Result<(u16, Vec<u8>), ClientError> {
+        let addr = self.addr.to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "RPC address did not resolve"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let api_key = self.api_key.as_ref().map(|api_key| format!("{}: {}\r\n", API_KEY_HEADER, api_key)).unwrap_or_default();
+        let head = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+            self.addr, body.len(), api_key
+        );
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        let split = response.windows(4).position(|window| window == b"\r\n\r\n")
+            .ok_or(ClientError::InvalidResponse("Truncated HTTP response"))?;
+        let head = std::str::from_utf8(&response[..split]).map_err(|_| ClientError::InvalidResponse("Malformed HTTP response"))?;
+        let status = head.split_whitespace().nth(1).and_then(|status| status.parse::<u16>().ok())
+            .ok_or(ClientError::InvalidResponse("Malformed HTTP response"))?;
+        Ok((status, response.split_off(split + 4)))
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<Blockhash, ClientError> {
+    hex::decode(hash).ok().and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ClientError::InvalidResponse("Hash is not 32 bytes of hex"))
+}
@@ -0,0 +1,40 @@
+use crate::structures::{Transaction, TransactionBody, TransactionSign};
+
+// Costs, in compute units, of the work a transaction puts on every node executing it. Units are worked
+// out from the transaction alone, so they're known before it runs & every node agrees on them.
+pub const SIGNATURE_UNITS: u64 = 1_000;
+// For each account the transaction reads or writes
+pub const ACCOUNT_UNITS: u64 = 200;
+// For each byte of the encoded transaction
+pub const BYTE_UNITS: u64 = 1;
+
+// Most units a single transaction may cost. Contract gas counts one for one, so this bounds it too.
+pub const MAX_TRANSACTION_UNITS: u64 = 1_400_000;
+
+pub fn compute_units(tx: &Transaction) -> u64 {
+    let gas = match tx.body() {
+        TransactionBody::Invoke(invoke) => invoke.gas_limit,
+        _ => 0,
+    };
+    (tx.signatures().len() as u64).saturating_mul(SIGNATURE_UNITS)
+        .saturating_add((tx.accounts().len() as u64).saturating_mul(ACCOUNT_UNITS))
+        .saturating_add((tx.to_bytes().len() as u64).saturating_mul(BYTE_UNITS))
+        .saturating_add(gas)
+}
+
+// Most the base fee moves in one block, as a fraction of itself: 1/8 when a block is completely full
+// or completely empty
+pub const BASE_FEE_CHANGE_DENOMINATOR: u64 = 8;
+
+// Base fee for the block after one that cost `used` units, given the chain's per-block budget. Blocks
+// are aimed at half full: fuller ones push the fee up & emptier ones let it fall, either way by at
+// least one, so a zero fee can still rise & a small one can still get back down to zero.
+pub fn next_base_fee(base_fee: u64, used: u64, max_block_units: u64) -> u64 {
+    let target = (max_block_units / 2).max(1);
+    let change = |delta: u64| (base_fee as u128 * delta as u128 / target as u128 / BASE_FEE_CHANGE_DENOMINATOR as u128) as u64;
+    match used.cmp(&target) {
+        std::cmp::Ordering::Greater => base_fee.saturating_add(change(used - target).max(1)),
+        std::cmp::Ordering::Less => base_fee.saturating_sub(change(target - used).max(1)),
+        std::cmp::Ordering::Equal => base_fee,
+    }
+}
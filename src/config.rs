@@ -0,0 +1,120 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{merkle::Hash, structures::Address};
+
+// Consensus parameters every node on a chain must agree on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize)]
+#[serde(default)]
+pub struct ChainConfig {
+    // Most transactions the builder packs into one block
+    pub max_transactions_per_block: usize,
+    // Largest serialized block the builder produces & validators accept. Kept small enough that a
+    // full sync batch of blocks still fits in one network message.
+    pub max_block_bytes: usize,
+    // Most compute units a block's transactions may cost between them, see `compute`
+    pub max_block_compute_units: u64,
+    // Least fee a transaction must pay at genesis. It moves with block fullness from there, see `compute`.
+    pub initial_base_fee: u64,
+    // How long pending transactions wait for a full block before a partial one is built instead.
+    // Unset means blocks are only ever built full.
+    pub partial_block_timeout_ms: Option<u64>,
+    // Least self-bond an account must put up to register as a validator. Validators below it, e.g. from
+    // genesis, don't lead slots or count towards quorums.
+    pub min_validator_stake: u64,
+    // Blocks withdrawn stake stays locked before it's paid out
+    pub unbonding_blocks: u64,
+    // Leader slots a validator may miss in a row before it's jailed. Zero never jails anyone.
+    pub max_missed_slots: u64,
+    // Blocks a jailed validator has to wait before it can unjail itself
+    pub jail_cooldown_blocks: u64,
+    // How long a slot's leader has to get a block finalized once there are transactions waiting. After
+    // that the next slot's leader may propose instead, & the skipped slot counts as missed.
+    pub leader_timeout_ms: u64,
+    // Slots per epoch. Rewards are paid out, rent collected & the leader seed rolled over at every epoch
+    // boundary, see `AccountsDB::ends_epoch`.
+    pub epoch_slots: u64,
+    // Accounts holding at least this much, locked funds included, don't pay rent
+    pub rent_exempt_minimum: u64,
+    // Charged each epoch to accounts below the exempt minimum. Zero turns rent off.
+    pub rent_per_epoch: u64,
+    // Confirmed blocks that have to be built on top of a block before it counts as finalized
+    pub finality_depth: u64,
+    // Blocks between checkpoints, see `Checkpoint`. Zero takes none.
+    pub checkpoint_interval: u64,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            max_transactions_per_block: 2,
+            max_block_bytes: 128 * 1024,
+            max_block_compute_units: 48_000_000,
+            initial_base_fee: 0,
+            partial_block_timeout_ms: None,
+            min_validator_stake: 100,
+            unbonding_blocks: 10,
+            max_missed_slots: 50,
+            jail_cooldown_blocks: 100,
+            leader_timeout_ms: 2_000,
+            epoch_slots: 100,
+            rent_exempt_minimum: 10,
+            rent_per_epoch: 0,
+            finality_depth: 32,
+            checkpoint_interval: 100,
+        }
+    }
+}
+
+// An account funded at genesis
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize)]
+pub struct GenesisAccount {
+    pub address: Address,
+    pub balance: u64,
+}
+
+// A member of the initial validator set & the stake it starts with
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize)]
+pub struct GenesisValidator {
+    pub address: Address,
+    pub stake: u64,
+    // Percent of delegators' rewards the validator keeps
+    #[serde(default)]
+    pub commission: u8,
+    // Hex BLS key the validator votes with. Trusted like the rest of the config, so unlike a
+    // registration it needs no proof of possession.
+    #[serde(default)]
+    pub bls_key: Option<String>,
+}
+
+// Initial state & parameters of a chain, loaded from a TOML file. Nodes only agree on a chain if
+// they start from the same one, so the genesis block commits to its hash.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize)]
+#[serde(default)]
+pub struct GenesisConfig {
+    // Seconds since the unix epoch, stamped on the genesis block
+    pub timestamp: u64,
+    pub accounts: Vec<GenesisAccount>,
+    pub validators: Vec<GenesisValidator>,
+    // Dev chains only: the key allowed to mint tokens with airdrop transactions
+    pub faucet: Option<Address>,
+    // The key allowed to freeze & thaw accounts, if the chain has one
+    pub freeze_authority: Option<Address>,
+    pub chain: ChainConfig,
+}
+
+impl GenesisConfig {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn hash(&self) -> Hash {
+        Sha256::digest(borsh::to_vec(self).expect("Genesis encoding is infallible")).into()
+    }
+}
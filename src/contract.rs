@@ -0,0 +1,217 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use sha2::{Digest, Sha256};
+use wasmi::{core::TrapCode, Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store};
+
+use crate::{
+    db::AccountsDB,
+    structures::{InvokeTransaction, Pubkey, UserAccount, MAX_ACCOUNT_DATA_BYTES},
+};
+
+// Owner of every deployed program's account. Nobody holds its key, so a program's bytecode can never
+// be changed or its account debited.
+pub const WASM_LOADER: Pubkey = *b"litechain_wasm_loader___________";
+
+// Most gas a single invocation may be given. One unit is roughly one wasm instruction.
+pub const MAX_GAS: u64 = 1_000_000;
+
+// Flat gas charged for every host call, on top of one unit per byte it copies
+pub const HOST_CALL_GAS: u64 = 100;
+
+// Most bytes of input an invocation may carry
+pub const MAX_INPUT_BYTES: usize = 1024;
+
+// Programs export this function, taking nothing & returning zero on success, along with their "memory"
+const ENTRYPOINT: &str = "entrypoint";
+const MEMORY: &str = "memory";
+const HOST_MODULE: &str = "env";
+
+// Where `authority`'s program deployed with `nonce` lives
+pub fn contract_address(authority: &Pubkey, nonce: u64) -> Pubkey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"contract");
+    hasher.update(authority);
+    hasher.update(nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+// Everything a running program can see. Accounts are staged here & only written back if it succeeds.
+struct Context {
+    caller: Pubkey,
+    program: Pubkey,
+    input: Vec<u8>,
+    // Account indices used by the host functions: the caller, the program, then the listed accounts
+    keys: Vec<Pubkey>,
+    accounts: HashMap<Pubkey, UserAccount>,
+}
+
+impl Context {
+    fn key(&self, index: i32) -> Option<Pubkey> {
+        usize::try_from(index).ok().and_then(|index| self.keys.get(index)).copied()
+    }
+
+    fn account(&self, index: i32) -> Option<&UserAccount> {
+        self.key(index).and_then(|key| self.accounts.get(&key))
+    }
+
+    // The program may change accounts it owns, & spend from the caller who signed the invocation
+    fn may_debit(&self, key: &Pubkey) -> bool {
+        self.accounts.get(key).is_some_and(|account| !account.frozen && (account.owner == self.program || *key == self.caller))
+    }
+
+    fn transfer(&mut self, from: i32, to: i32, amt: u64) -> Result<(), ()> {
+        let (from, to) = (self.key(from).ok_or(())?, self.key(to).ok_or(())?);
+        if !self.may_debit(&from) || !self.accounts.contains_key(&to) {
+            return Err(())
+        }
+        let source = self.accounts.get_mut(&from).ok_or(())?;
+        source.balance = source.balance.checked_sub(amt).ok_or(())?;
+        let destination = self.accounts.get_mut(&to).ok_or(())?;
+        destination.balance = destination.balance.saturating_add(amt);
+        Ok(())
+    }
+}
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Engine::new(&config)
+    })
+}
+
+// Take `amount` gas from the running program, trapping once it runs out
+fn charge(caller: &mut Caller<'_, Context>, amount: u64) -> Result<(), wasmi::Error> {
+    let fuel = caller.get_fuel().map_err(|e| wasmi::Error::new(e.to_string()))?;
+    let remaining = fuel.checked_sub(amount).ok_or(wasmi::Error::from(TrapCode::OutOfFuel))?;
+    caller.set_fuel(remaining).map_err(|e| wasmi::Error::new(e.to_string()))
+}
+
+fn memory(caller: &Caller<'_, Context>) -> Result<Memory, wasmi::Error> {
+    caller.get_export(MEMORY).and_then(Extern::into_memory).ok_or_else(|| wasmi::Error::new("Program exports no memory"))
+}
+
+fn out_of_bounds(_: wasmi::errors::MemoryError) -> wasmi::Error {
+    wasmi::Error::from(TrapCode::MemoryOutOfBounds)
+}
+
+// The functions programs import from "env". Account arguments are indices into `Context::keys`.
+// Calls that are refused return -1 for the program to handle; bad memory accesses trap.
+fn linker() -> Result<Linker<Context>, wasmi::Error> {
+    let mut linker = Linker::new(engine());
+
+    linker.func_wrap(HOST_MODULE, "input_len", |mut caller: Caller<'_, Context>| -> Result<i32, wasmi::Error> {
+        charge(&mut caller, HOST_CALL_GAS)?;
+        Ok(caller.data().input.len() as i32)
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "read_input", |mut caller: Caller<'_, Context>, ptr: u32| -> Result<(), wasmi::Error> {
+        let len = caller.data().input.len();
+        charge(&mut caller, HOST_CALL_GAS + len as u64)?;
+        let (memory, context) = memory(&caller)?.data_and_store_mut(&mut caller);
+        let destination = memory.get_mut(ptr as usize..ptr as usize + context.input.len()).ok_or(TrapCode::MemoryOutOfBounds)?;
+        destination.copy_from_slice(&context.input);
+        Ok(())
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "account_data_len", |mut caller: Caller<'_, Context>, index: i32| -> Result<i32, wasmi::Error> {
+        charge(&mut caller, HOST_CALL_GAS)?;
+        Ok(caller.data().account(index).map_or(-1, |account| account.data.len() as i32))
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "read_account_data", |mut caller: Caller<'_, Context>, index: i32, ptr: u32| -> Result<i32, wasmi::Error> {
+        let Some(data) = caller.data().account(index).map(|account| account.data.clone()) else { return Ok(-1) };
+        charge(&mut caller, HOST_CALL_GAS + data.len() as u64)?;
+        memory(&caller)?.write(&mut caller, ptr as usize, &data).map_err(out_of_bounds)?;
+        Ok(0)
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "write_account_data", |mut caller: Caller<'_, Context>, index: i32, ptr: u32, len: u32| -> Result<i32, wasmi::Error> {
+        charge(&mut caller, HOST_CALL_GAS + len as u64)?;
+        let program = caller.data().program;
+        let Some(key) = caller.data().key(index) else { return Ok(-1) };
+        if len as usize > MAX_ACCOUNT_DATA_BYTES || caller.data().account(index).is_none_or(|account| account.owner != program) {
+            return Ok(-1)
+        }
+        let mut data = vec![0; len as usize];
+        memory(&caller)?.read(&caller, ptr as usize, &mut data).map_err(out_of_bounds)?;
+        if let Some(account) = caller.data_mut().accounts.get_mut(&key) {
+            account.data = data;
+        }
+        Ok(0)
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "balance", |mut caller: Caller<'_, Context>, index: i32| -> Result<i64, wasmi::Error> {
+        charge(&mut caller, HOST_CALL_GAS)?;
+        Ok(caller.data().account(index).map_or(-1, |account| account.balance as i64))
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "transfer", |mut caller: Caller<'_, Context>, from: i32, to: i32, amt: i64| -> Result<i32, wasmi::Error> {
+        charge(&mut caller, HOST_CALL_GAS)?;
+        let Ok(amt) = u64::try_from(amt) else { return Ok(-1) };
+        Ok(caller.data_mut().transfer(from, to, amt).map_or(-1, |_| 0))
+    })?;
+
+    Ok(linker)
+}
+
+fn instantiate(store: &mut Store<Context>, code: &[u8]) -> Result<Instance, &'static str> {
+    let module = Module::new(engine(), code).map_err(|_| "Program is not a valid wasm module")?;
+    let linker = linker().map_err(|_| "Failed to link host functions")?;
+    let instance = linker
+        .instantiate(&mut *store, &module)
+        .map_err(|_| "Program imports something the runtime doesn't provide")?
+        .ensure_no_start(&mut *store)
+        .map_err(|_| "Program may not have a start function")?;
+    if instance.get_memory(&*store, MEMORY).is_none() {
+        return Err("Program exports no memory")
+    }
+    Ok(instance)
+}
+
+// Check bytecode is something `invoke` could run, before it's deployed
+pub fn check_code(code: &[u8]) -> Result<(), &'static str> {
+    let mut store = Store::new(engine(), Context {
+        caller: Pubkey::default(),
+        program: Pubkey::default(),
+        input: vec![],
+        keys: vec![],
+        accounts: HashMap::new(),
+    });
+    let instance = instantiate(&mut store, code)?;
+    instance.get_typed_func::<(), i32>(&store, ENTRYPOINT).map_err(|_| "Program exports no entrypoint")?;
+    Ok(())
+}
+
+// Run `tx`'s program with its fee already taken from the caller. Every account it touches is staged,
+// so running out of gas, trapping or returning non-zero leaves `db` exactly as it was.
+// Returns the gas used.
+pub fn invoke(db: &AccountsDB, tx: &InvokeTransaction) -> Result<u64, &'static str> {
+    let keys: Vec<Pubkey> = [tx.caller, tx.program].into_iter().chain(tx.accounts.iter().copied()).collect();
+    let mut accounts = HashMap::new();
+    for key in &keys {
+        accounts.insert(*key, db.get_account(key).ok_or("Account not found.")?);
+    }
+    let code = accounts.get(&tx.program).filter(|program| program.owner == WASM_LOADER).ok_or("Not a program account")?.data.clone();
+    let caller = accounts.get_mut(&tx.caller).ok_or("Account not found.")?;
+    caller.balance = caller.balance.checked_sub(tx.fee).ok_or("Insufficient balance.")?;
+
+    let mut store = Store::new(engine(), Context { caller: tx.caller, program: tx.program, input: tx.data.clone(), keys, accounts });
+    store.set_fuel(tx.gas_limit).map_err(|_| "Gas metering is disabled")?;
+    let instance = instantiate(&mut store, &code)?;
+    let entrypoint = instance.get_typed_func::<(), i32>(&store, ENTRYPOINT).map_err(|_| "Program exports no entrypoint")?;
+
+    match entrypoint.call(&mut store, ()) {
+        Ok(0) => {}
+        Ok(_) => return Err("Program returned an error"),
+        Err(e) if e.as_trap_code() == Some(TrapCode::OutOfFuel) => return Err("Out of gas"),
+        Err(_) => return Err("Program trapped"),
+    }
+
+    let gas_used = tx.gas_limit - store.get_fuel().unwrap_or(0);
+    for (key, account) in store.into_data().accounts {
+        db.add_account(key, account);
+    }
+    Ok(gas_used)
+}
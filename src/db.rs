@@ -1,22 +1,279 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
 use dashmap::DashMap;
-use crate::{structures::{Block, Pubkey, UserAccount, Blockhash, ValidatorAccount}, TransactionSign};
+use sha2::{Digest, Sha256};
+use crate::{
+    compute,
+    config::{ChainConfig, GenesisConfig},
+    contract::WASM_LOADER,
+    merkle::{hash_leaf, merkle_root, Hash, MerkleProof},
+    program::{Program, ProgramRegistry},
+    scheduler,
+    vrf::{self, VrfProof},
+    wallet::Wallet,
+    structures::{bls_key_from_hex, pubkey_from_address, Allowance, MAX_ACCOUNT_DATA_BYTES, Block, Delegation, Mint, MintId, Pubkey, SimulationResult, StateDiff, TokenAccount, Transaction, TransactionReceipt, TransactionSign, Unbonding, UserAccount, Blockhash, ValidatorAccount, ValueChange, Vesting},
+};
+
+// Why `AccountsDB::transfer` refused to move funds. Nothing has moved when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferError {
+    AccountNotFound,
+    InsufficientBalance,
+    BalanceOverflow,
+}
 
+impl From<TransferError> for &'static str {
+    fn from(error: TransferError) -> Self {
+        match error {
+            TransferError::AccountNotFound => "Account not found.",
+            TransferError::InsufficientBalance => "Insufficient balance.",
+            TransferError::BalanceOverflow => "Balance overflows.",
+        }
+    }
+}
+
+// Why a query about past state or blocks couldn't be answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryError {
+    NotArchive,
+    AboveTip,
+    // Older than the oldest height still kept
+    Pruned { horizon: u64 },
+}
+
+impl From<HistoryError> for &'static str {
+    fn from(error: HistoryError) -> Self {
+        match error {
+            HistoryError::NotArchive => "Historical state is only kept in archive mode",
+            HistoryError::AboveTip => "Height is beyond the latest block",
+            HistoryError::Pruned { .. } => "Height has been pruned",
+        }
+    }
+}
+
+// Where the latest block sits in the epochs of `epoch_slots` slots each
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EpochInfo {
+    pub epoch: u64,
+    pub slot_index: u64,
+    pub slots_in_epoch: u64,
+    pub absolute_slot: u64,
+    pub block_height: u64,
+}
+
+// Pre-block values of everything a block touched, enough to revert it
 #[derive(Default, Debug, Clone)]
+pub struct BlockUndo {
+    latest_blockhash: Blockhash,
+    latest_height: u64,
+    latest_slot: u64,
+    total_supply: u64,
+    base_fee: u64,
+    epoch_seed: Hash,
+    active_set: BTreeMap<Pubkey, u64>,
+    accounts: Vec<(Pubkey, Option<UserAccount>)>,
+    // Each touched key's validator, or `None` if it wasn't a validator yet
+    validators: Vec<(Pubkey, Option<ValidatorAccount>)>,
+    unbonding: Vec<(Pubkey, Option<Vec<Unbonding>>)>,
+    delegations: Vec<(Pubkey, Option<Vec<Delegation>>)>,
+    pending_rewards: Vec<(Pubkey, Option<u64>)>,
+    mints: Vec<(MintId, Option<Mint>)>,
+    token_accounts: Vec<(Pubkey, Option<TokenAccount>)>,
+    allowances: Vec<(Pubkey, Option<Allowance>)>,
+}
+
+impl BlockUndo {
+    // Remember the current value of an account/validator, unless we already hold an older one
+    fn capture(&mut self, db: &AccountsDB, pubkey: Pubkey) {
+        if self.accounts.iter().any(|(touched, _)| touched == &pubkey) {
+            return
+        }
+        self.accounts.push((pubkey, db.get_account(&pubkey)));
+        self.validators.push((pubkey, db.get_validator(&pubkey)));
+        self.unbonding.push((pubkey, db.unbonding.get(&pubkey).map(|pending| pending.clone())));
+        self.delegations.push((pubkey, db.delegations.get(&pubkey).map(|delegations| delegations.clone())));
+        self.pending_rewards.push((pubkey, db.pending_rewards.get(&pubkey).map(|owed| *owed)));
+        self.mints.push((pubkey, db.get_mint(&pubkey)));
+        self.token_accounts.push((pubkey, db.token_accounts.get(&pubkey).map(|account| *account)));
+        self.allowances.push((pubkey, db.allowances.get(&pubkey).map(|allowance| *allowance)));
+    }
+
+    // How `db` differs from what this record captured
+    fn diff(&self, db: &AccountsDB) -> StateDiff {
+        let changed = |pubkey: &Pubkey, old: Option<u64>, new: Option<u64>| (old != new).then_some(ValueChange { pubkey: *pubkey, old, new });
+        let mut diff = StateDiff {
+            balances: self.accounts
+                .iter()
+                .filter_map(|(pubkey, old)| changed(pubkey, old.as_ref().map(|account| account.balance), db.get_account(pubkey).map(|account| account.balance)))
+                .collect(),
+            stakes: self.validators
+                .iter()
+                .filter_map(|(pubkey, old)| changed(pubkey, old.as_ref().map(|validator| validator.stake), db.validators.get(pubkey).map(|validator| validator.stake)))
+                .collect(),
+        };
+        diff.balances.sort_by_key(|change| change.pubkey);
+        diff.stakes.sort_by_key(|change| change.pubkey);
+        diff
+    }
+}
+
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct AccountsDB {
     pub latest_blockhash: Blockhash,
+    // Height of the block being executed, or last executed
+    pub latest_height: u64,
+    // Slot of the last block executed
+    pub latest_slot: u64,
+    pub total_supply: u64,
     pub accounts: DashMap<Pubkey, UserAccount>,
     pub validators: DashMap<Pubkey, ValidatorAccount>,
+    // Key allowed to sign airdrops, only ever set on dev chains
+    pub faucet: Option<Pubkey>,
+    // Key allowed to sign freezes & thaws, set at genesis
+    pub freeze_authority: Option<Pubkey>,
+    // Least self-bond a validator registration must put up, & below which a validator neither leads
+    // slots nor counts towards quorums. From the genesis chain config.
+    pub min_validator_stake: u64,
+    // Blocks withdrawn stake waits in `unbonding` before it's paid out, from the genesis chain config
+    pub unbonding_blocks: u64,
+    // Jailing for missed leader slots, from the genesis chain config
+    pub max_missed_slots: u64,
+    pub jail_cooldown_blocks: u64,
+    // Most compute units a block may cost, from the genesis chain config
+    pub max_block_compute_units: u64,
+    // Least fee a transaction must pay to be included in the next block. Burned; whatever a transaction
+    // pays above it is a tip for the block's proposer.
+    pub base_fee: u64,
+    // Randomness this epoch's leaders are drawn from, see `vrf`. Starts out as the genesis config's hash
+    // & takes in the block that ends each epoch.
+    pub epoch_seed: Hash,
+    pub epoch_slots: u64,
+    // Validators that lead slots & vote this epoch, with the stake they count with. Taken from
+    // `validators` as each epoch starts, so registrations & stake changes only count from the next one &
+    // nobody can join mid-epoch to reshape the current schedule. Empty until the first is taken, e.g. in
+    // a state built up by hand, when the live validators stand in.
+    pub active_set: BTreeMap<Pubkey, u64>,
+    // Rent schedule, from the genesis chain config
+    pub rent_exempt_minimum: u64,
+    pub rent_per_epoch: u64,
+    // Withdrawn stake waiting out the unbonding period, by the validator it's owed to
+    pub unbonding: DashMap<Pubkey, Vec<Unbonding>>,
+    // Stake other accounts have bonded to each validator, by validator & then delegator
+    pub delegations: DashMap<Pubkey, Vec<Delegation>>,
+    // Block rewards each validator has earned this epoch, paid out to it & its delegators at the end of it.
+    // Already counted in the supply.
+    pub pending_rewards: DashMap<Pubkey, u64>,
+    pub mints: DashMap<MintId, Mint>,
+    // Balances of non-native tokens, keyed by `TokenAccount::address`
+    pub token_accounts: DashMap<Pubkey, TokenAccount>,
+    // Spending allowances, keyed by `Allowance::address`
+    pub allowances: DashMap<Pubkey, Allowance>,
+    // Native programs transactions can call, registered by the node rather than stored on chain
+    #[serde(skip)]
+    pub programs: ProgramRegistry,
+    // Undo records of the blocks applied through `apply_block`, oldest first, by height & hash
+    #[serde(skip)]
+    versions: Vec<(u64, Blockhash, BlockUndo)>,
+    // Serve state as of past heights, read back through the undo records
+    pub archive: bool,
+    // Undo records of blocks below this height have been pruned
+    #[serde(skip)]
+    pruned_below: u64,
+    // Accounts as of heights whose undo records have been pruned, kept by archive nodes
+    #[serde(skip)]
+    snapshots: BTreeMap<u64, HashMap<Pubkey, UserAccount>>,
 }
    
 impl AccountsDB {
     pub fn new() -> Self {
         Self {
             latest_blockhash: Blockhash::default(),
+            latest_height: 0,
+            latest_slot: 0,
+            total_supply: 0,
             accounts: DashMap::new(),
             validators: DashMap::new(),
+            faucet: None,
+            freeze_authority: None,
+            min_validator_stake: ChainConfig::default().min_validator_stake,
+            unbonding_blocks: ChainConfig::default().unbonding_blocks,
+            max_missed_slots: ChainConfig::default().max_missed_slots,
+            jail_cooldown_blocks: ChainConfig::default().jail_cooldown_blocks,
+            max_block_compute_units: ChainConfig::default().max_block_compute_units,
+            base_fee: ChainConfig::default().initial_base_fee,
+            epoch_seed: Hash::default(),
+            epoch_slots: ChainConfig::default().epoch_slots,
+            active_set: BTreeMap::new(),
+            rent_exempt_minimum: ChainConfig::default().rent_exempt_minimum,
+            rent_per_epoch: ChainConfig::default().rent_per_epoch,
+            unbonding: DashMap::new(),
+            delegations: DashMap::new(),
+            pending_rewards: DashMap::new(),
+            mints: DashMap::new(),
+            token_accounts: DashMap::new(),
+            allowances: DashMap::new(),
+            programs: ProgramRegistry::default(),
+            versions: vec![],
+            archive: false,
+            pruned_below: 0,
+            snapshots: BTreeMap::new(),
         }
     }
 
+    // The initial state a genesis config describes. Genesis balances & stakes make up the initial supply.
+    pub fn from_genesis(config: &GenesisConfig) -> Result<Self, &'static str> {
+        let mut db = AccountsDB::new();
+        let mut supply: u64 = 0;
+
+        for genesis in &config.accounts {
+            let pubkey = pubkey_from_address(&genesis.address)?;
+            if db.accounts.contains_key(&pubkey) {
+                return Err("Duplicate genesis account")
+            }
+            let mut account = UserAccount::from_public_key(pubkey);
+            account.balance = genesis.balance;
+            db.add_account(pubkey, account);
+            supply = supply.checked_add(genesis.balance).ok_or("Genesis supply overflows")?;
+        }
+
+        for genesis in &config.validators {
+            let pubkey = pubkey_from_address(&genesis.address)?;
+            if db.is_validator(&pubkey) {
+                return Err("Duplicate genesis validator")
+            }
+            let mut validator = ValidatorAccount::new(pubkey);
+            validator.stake = genesis.stake;
+            validator.commission = genesis.commission;
+            validator.bls_key = genesis.bls_key.as_deref().map(bls_key_from_hex).transpose()?;
+            db.add_validator(pubkey, validator);
+            supply = supply.checked_add(genesis.stake).ok_or("Genesis supply overflows")?;
+        }
+
+        db.faucet = config.faucet.as_deref().map(pubkey_from_address).transpose()?;
+        db.freeze_authority = config.freeze_authority.as_deref().map(pubkey_from_address).transpose()?;
+        db.min_validator_stake = config.chain.min_validator_stake;
+        db.unbonding_blocks = config.chain.unbonding_blocks;
+        db.max_missed_slots = config.chain.max_missed_slots;
+        db.jail_cooldown_blocks = config.chain.jail_cooldown_blocks;
+        db.max_block_compute_units = config.chain.max_block_compute_units;
+        db.base_fee = config.chain.initial_base_fee;
+        db.epoch_seed = config.hash();
+        db.epoch_slots = config.chain.epoch_slots;
+        db.rent_exempt_minimum = config.chain.rent_exempt_minimum;
+        db.rent_per_epoch = config.chain.rent_per_epoch;
+        db.active_set = db.eligible_stakes();
+        db.total_supply = supply;
+        Ok(db)
+    }
+
+    pub fn register_program(&mut self, program: Arc<dyn Program>) -> Result<(), &'static str> {
+        self.programs.register(program)
+    }
+
     pub fn add_account(&self, pubkey: Pubkey, account: UserAccount) {
         self.accounts.insert(pubkey, account);
     }
@@ -25,6 +282,81 @@ impl AccountsDB {
         self.accounts.get(pubkey).map(|acc| acc.clone())
     }
 
+    // Many accounts at once, in the order asked for. Keys are grouped by shard, so each shard's lock
+    // is taken once however many of the keys land in it.
+    pub fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Vec<Option<UserAccount>> {
+        let mut by_shard: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, pubkey) in pubkeys.iter().enumerate() {
+            by_shard.entry(self.accounts.determine_map(pubkey)).or_default().push(index);
+        }
+
+        let mut accounts = vec![None; pubkeys.len()];
+        let shards = self.accounts.shards();
+        for (shard, indices) in by_shard {
+            let shard = shards[shard].read();
+            for index in indices {
+                accounts[index] = shard.get(&pubkeys[index]).map(|account| account.get().clone());
+            }
+        }
+        accounts
+    }
+
+    pub fn get_account_data(&self, pubkey: &Pubkey) -> Option<Vec<u8>> {
+        self.accounts.get(pubkey).map(|account| account.data.clone())
+    }
+
+    // Replace an account's data
+    pub fn set_account_data(&self, pubkey: &Pubkey, data: Vec<u8>) -> Result<(), &'static str> {
+        if data.len() > MAX_ACCOUNT_DATA_BYTES {
+            return Err("Account data too large.")
+        }
+        let mut account = self.accounts.get_mut(pubkey).ok_or("Account not found.")?;
+        account.data = data;
+        Ok(())
+    }
+
+    pub fn assign_owner(&self, pubkey: &Pubkey, owner: Pubkey) -> Result<(), &'static str> {
+        let mut account = self.accounts.get_mut(pubkey).ok_or("Account not found.")?;
+        account.owner = owner;
+        Ok(())
+    }
+
+    pub fn set_frozen(&self, pubkey: &Pubkey, frozen: bool) -> Result<(), &'static str> {
+        let mut account = self.accounts.get_mut(pubkey).ok_or("Account not found.")?;
+        account.frozen = frozen;
+        Ok(())
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    // A page of accounts in key order, so paging through stays stable while accounts are added
+    pub fn iter_accounts(&self, offset: usize, limit: usize) -> Vec<UserAccount> {
+        page(&self.accounts, offset, limit)
+    }
+
+    pub fn validator_count(&self) -> usize {
+        self.validators.len()
+    }
+
+    // A page of validators in key order
+    pub fn iter_validators(&self, offset: usize, limit: usize) -> Vec<ValidatorAccount> {
+        page(&self.validators, offset, limit)
+    }
+
+    // The nonce the account's next transaction has to carry, 0 for an account not yet opened
+    pub fn nonce(&self, pubkey: &Pubkey) -> u64 {
+        self.accounts.get(pubkey).map_or(0, |account| account.nonce)
+    }
+
+    // Opens the account if it doesn't exist yet, as it may not for a faucet that only signs airdrops
+    pub fn increment_nonce(&self, pubkey: &Pubkey) -> Result<(), &'static str> {
+        let mut account = self.accounts.entry(*pubkey).or_insert_with(|| UserAccount::from_public_key(*pubkey));
+        account.nonce = account.nonce.checked_add(1).ok_or("Nonce overflows")?;
+        Ok(())
+    }
+
     pub fn increase_account_balance(&self, pubkey: &Pubkey, delta: u64) -> Result<(), &'static str> {
         if let Some(mut account) = self.accounts.get_mut(pubkey) {
             account.balance = account.balance.saturating_add(delta);
@@ -36,7 +368,7 @@ impl AccountsDB {
 
     pub fn decrease_account_balance(&self, pubkey: &Pubkey, delta: u64) -> Result<(), &'static str> {
         if let Some(mut account) = self.accounts.get_mut(pubkey) {
-            if account.balance.gt(&delta) {
+            if account.balance.ge(&delta) {
                 account.balance = account.balance.saturating_sub(delta);
                 Ok(())
             } else {
@@ -47,6 +379,330 @@ impl AccountsDB {
         }
     }
 
+    // Move `amt` between two existing accounts as one step. Both accounts' shards stay write-locked
+    // from the balance checks through both legs, taken in shard order so opposing transfers can't deadlock.
+    pub fn transfer(&self, from: &Pubkey, to: &Pubkey, amt: u64) -> Result<(), TransferError> {
+        let shards = self.accounts.shards();
+        let (from_shard, to_shard) = (self.accounts.determine_map(from), self.accounts.determine_map(to));
+
+        if from_shard == to_shard {
+            let mut shard = shards[from_shard].write();
+            if from == to {
+                let account = shard.get(from).ok_or(TransferError::AccountNotFound)?;
+                return if account.get().balance >= amt { Ok(()) } else { Err(TransferError::InsufficientBalance) }
+            }
+            let [Some(from), Some(to)] = shard.get_disjoint_mut([from, to]) else {
+                return Err(TransferError::AccountNotFound)
+            };
+            return move_balance(from.get_mut(), to.get_mut(), amt)
+        }
+
+        let (mut low, mut high) = (shards[from_shard.min(to_shard)].write(), shards[from_shard.max(to_shard)].write());
+        let (from_map, to_map) = if from_shard < to_shard { (&mut *low, &mut *high) } else { (&mut *high, &mut *low) };
+        let from = from_map.get_mut(from).ok_or(TransferError::AccountNotFound)?;
+        let to = to_map.get_mut(to).ok_or(TransferError::AccountNotFound)?;
+        move_balance(from.get_mut(), to.get_mut(), amt)
+    }
+
+    // Merkle root over every balance & stake, ordered by key. Local bookkeeping such as a validator's
+    // last finalized hash isn't consensus state & is left out.
+    pub fn state_root(&self) -> Hash {
+        merkle_root(&self.state_leaves().into_iter().map(|(_, _, leaf)| leaf).collect::<Vec<_>>())
+    }
+
+    // `pubkey`'s account & proof it's under the current state root, see `verify_account_proof`
+    pub fn get_account_with_proof(&self, pubkey: &Pubkey) -> Option<(UserAccount, MerkleProof)> {
+        Some((self.get_account(pubkey)?, self.prove_account(pubkey)?))
+    }
+
+    // Proof that `pubkey`'s account, as it stands, is under the state root
+    pub fn prove_account(&self, pubkey: &Pubkey) -> Option<MerkleProof> {
+        let leaves = self.state_leaves();
+        let index = leaves.iter().position(|(key, kind, _)| key == pubkey && *kind == ACCOUNT_LEAF)?;
+        MerkleProof::generate(&leaves.into_iter().map(|(_, _, leaf)| leaf).collect::<Vec<_>>(), index)
+    }
+
+    // Every piece of consensus state as a leaf of the state tree, in order, with the key & kind of entry
+    // it's for
+    fn state_leaves(&self) -> Vec<(Pubkey, u8, Hash)> {
+        let mut entries: Vec<(Pubkey, u8, Vec<u8>)> = vec![];
+        for account in self.accounts.iter() {
+            entries.push((*account.key(), ACCOUNT_LEAF, account_state(&account)));
+        }
+        for validator in self.validators.iter() {
+            let mut data = validator.stake.to_le_bytes().to_vec();
+            data.push(validator.commission);
+            data.extend(validator.missed_slots.to_le_bytes());
+            data.extend(borsh::to_vec(&validator.jailed_since).expect("Option encoding is infallible"));
+            data.extend(borsh::to_vec(&validator.bls_key).expect("Option encoding is infallible"));
+            entries.push((*validator.key(), 1, data));
+        }
+        for pending in self.unbonding.iter() {
+            let data = pending.iter().flat_map(|unbonding| [unbonding.amount, unbonding.release_height]).flat_map(u64::to_le_bytes).collect();
+            entries.push((*pending.key(), 2, data));
+        }
+        for mint in self.mints.iter() {
+            let mut data = mint.authority.to_vec();
+            data.push(mint.decimals);
+            data.extend(mint.supply.to_le_bytes());
+            entries.push((*mint.key(), 3, data));
+        }
+        for account in self.token_accounts.iter() {
+            let data = [&account.mint[..], &account.owner[..], &account.balance.to_le_bytes()].concat();
+            entries.push((*account.key(), 4, data));
+        }
+        for allowance in self.allowances.iter() {
+            let data = [&allowance.owner[..], &allowance.delegate[..], &allowance.amount.to_le_bytes()].concat();
+            entries.push((*allowance.key(), 5, data));
+        }
+        for delegations in self.delegations.iter() {
+            let data = delegations.iter().flat_map(|delegation| [&delegation.delegator[..], &delegation.amount.to_le_bytes()].concat()).collect();
+            entries.push((*delegations.key(), 6, data));
+        }
+        for owed in self.pending_rewards.iter() {
+            entries.push((*owed.key(), 7, owed.to_le_bytes().to_vec()));
+        }
+        for (pubkey, stake) in &self.active_set {
+            entries.push((*pubkey, 8, stake.to_le_bytes().to_vec()));
+        }
+        // Chain-wide values, under the zero key, so a snapshot can't come with a different fee, supply or
+        // seed for leader draws
+        let mut chain: Vec<u8> = [self.base_fee, self.total_supply].into_iter().flat_map(u64::to_le_bytes).collect();
+        chain.extend(self.epoch_seed);
+        entries.push(([0; 32], CHAIN_LEAF, chain));
+        entries.sort();
+
+        entries
+            .into_iter()
+            .map(|(pubkey, kind, data)| (pubkey, kind, hash_leaf(&[&pubkey[..], &[kind], &data].concat())))
+            .collect()
+    }
+
+    // A working copy of just the accounts & validators `transactions` touch, to run them against
+    // without affecting committed state
+    pub fn overlay(&self, transactions: &[Transaction]) -> AccountsDB {
+        let pubkeys: Vec<Pubkey> = transactions.iter().flat_map(|tx| tx.accounts()).collect();
+        self.stage(&pubkeys)
+    }
+
+    // Run `tx` against a working copy of the state it touches, reporting what it would do without
+    // changing anything
+    pub fn simulate_transaction(&self, tx: &Transaction) -> SimulationResult {
+        let mut overlay = self.overlay(std::slice::from_ref(tx));
+        if let Err(error) = tx.execute(&mut overlay) {
+            return SimulationResult { error: Some(error.to_string()), compute_units: tx.compute_units(), ..SimulationResult::default() }
+        }
+
+        let mut pubkeys = tx.accounts();
+        pubkeys.sort();
+        pubkeys.dedup();
+        let changed = |pubkey: &Pubkey, old: Option<u64>, new: Option<u64>| (old != new).then_some(ValueChange { pubkey: *pubkey, old, new });
+        let changes = StateDiff {
+            balances: pubkeys.iter()
+                .filter_map(|pubkey| changed(pubkey, self.get_account(pubkey).map(|account| account.balance), overlay.get_account(pubkey).map(|account| account.balance)))
+                .collect(),
+            stakes: pubkeys.iter()
+                .filter_map(|pubkey| changed(pubkey, self.get_validator(pubkey).map(|validator| validator.stake), overlay.get_validator(pubkey).map(|validator| validator.stake)))
+                .collect(),
+        };
+        SimulationResult { error: None, fee: tx.fee(), compute_units: tx.compute_units(), changes }
+    }
+
+    // A working copy of the state under `pubkeys`, which `commit` can write back
+    pub fn stage(&self, pubkeys: &[Pubkey]) -> AccountsDB {
+        let overlay = AccountsDB {
+            latest_blockhash: self.latest_blockhash,
+            latest_height: self.latest_height,
+            latest_slot: self.latest_slot,
+            total_supply: self.total_supply,
+            faucet: self.faucet,
+            freeze_authority: self.freeze_authority,
+            min_validator_stake: self.min_validator_stake,
+            unbonding_blocks: self.unbonding_blocks,
+            max_missed_slots: self.max_missed_slots,
+            jail_cooldown_blocks: self.jail_cooldown_blocks,
+            max_block_compute_units: self.max_block_compute_units,
+            base_fee: self.base_fee,
+            epoch_seed: self.epoch_seed,
+            epoch_slots: self.epoch_slots,
+            rent_exempt_minimum: self.rent_exempt_minimum,
+            rent_per_epoch: self.rent_per_epoch,
+            programs: self.programs.clone(),
+            ..AccountsDB::default()
+        };
+
+        for (pubkey, account) in pubkeys.iter().zip(self.get_multiple_accounts(pubkeys)) {
+            if let Some(account) = account {
+                overlay.add_account(*pubkey, account);
+            }
+        }
+
+        for &pubkey in pubkeys {
+            if let Some(validator) = self.get_validator(&pubkey) {
+                overlay.add_validator(pubkey, validator);
+            }
+            if let Some(pending) = self.unbonding.get(&pubkey) {
+                overlay.unbonding.insert(pubkey, pending.clone());
+            }
+            if let Some(delegations) = self.delegations.get(&pubkey) {
+                overlay.delegations.insert(pubkey, delegations.clone());
+            }
+            if let Some(owed) = self.pending_rewards.get(&pubkey) {
+                overlay.pending_rewards.insert(pubkey, *owed);
+            }
+            if let Some(mint) = self.get_mint(&pubkey) {
+                overlay.add_mint(pubkey, mint);
+            }
+            if let Some(account) = self.token_accounts.get(&pubkey) {
+                overlay.token_accounts.insert(pubkey, *account);
+            }
+            if let Some(allowance) = self.allowances.get(&pubkey) {
+                overlay.allowances.insert(pubkey, *allowance);
+            }
+        }
+
+        overlay
+    }
+
+    // Replace the state under `pubkeys` with `staged`'s, including anything it removed
+    pub fn commit(&self, staged: &AccountsDB, pubkeys: &[Pubkey]) {
+        fn replace<V: Clone>(ours: &DashMap<Pubkey, V>, theirs: &DashMap<Pubkey, V>, pubkey: Pubkey) {
+            match theirs.get(&pubkey) {
+                Some(value) => ours.insert(pubkey, value.clone()),
+                None => ours.remove(&pubkey).map(|(_, value)| value),
+            };
+        }
+
+        for &pubkey in pubkeys {
+            replace(&self.accounts, &staged.accounts, pubkey);
+            replace(&self.validators, &staged.validators, pubkey);
+            replace(&self.unbonding, &staged.unbonding, pubkey);
+            replace(&self.delegations, &staged.delegations, pubkey);
+            replace(&self.pending_rewards, &staged.pending_rewards, pubkey);
+            replace(&self.mints, &staged.mints, pubkey);
+            replace(&self.token_accounts, &staged.token_accounts, pubkey);
+            replace(&self.allowances, &staged.allowances, pubkey);
+        }
+    }
+
+    pub fn lock_funds(&self, pubkey: &Pubkey, vesting: Vesting) -> Result<(), &'static str> {
+        let mut account = self.accounts.get_mut(pubkey).ok_or("Account not found.")?;
+        account.locked.push(vesting);
+        Ok(())
+    }
+
+    // Accounts holding funds that unlock by `height`
+    fn vesting(&self, height: u64) -> Vec<Pubkey> {
+        self.accounts
+            .iter()
+            .filter(|account| account.locked.iter().any(|vesting| vesting.unlock_height <= height))
+            .map(|account| *account.key())
+            .collect()
+    }
+
+    // Move every locked amount that unlocks by `height` into its account's balance
+    fn release_vested(&self, height: u64) {
+        for pubkey in self.vesting(height) {
+            let Some(mut account) = self.accounts.get_mut(&pubkey) else { continue };
+            let released = account.locked.iter().filter(|vesting| vesting.unlock_height <= height).map(|vesting| vesting.amount).fold(0u64, u64::saturating_add);
+            account.locked.retain(|vesting| vesting.unlock_height > height);
+            account.balance = account.balance.saturating_add(released);
+        }
+    }
+
+    // Accounts that owe rent at an epoch boundary, i.e. those under the exempt minimum. Programs hold no
+    // balance of their own & are exempt, or they'd be reaped & their address open to a redeploy.
+    fn rent_due(&self) -> Vec<Pubkey> {
+        if self.rent_per_epoch == 0 {
+            return vec![]
+        }
+        self.accounts
+            .iter()
+            .filter(|account| account.owner != WASM_LOADER)
+            .filter(|account| account.balance.saturating_add(account.locked_balance()) < self.rent_exempt_minimum)
+            .map(|account| *account.key())
+            .collect()
+    }
+
+    // Charge rent at an epoch boundary, taking it out of the supply. Accounts left with nothing, locked
+    // funds included, are removed.
+    fn collect_rent(&mut self) {
+        let mut collected: u64 = 0;
+        for pubkey in self.rent_due() {
+            let Some(mut account) = self.accounts.get_mut(&pubkey) else { continue };
+            let rent = account.balance.min(self.rent_per_epoch);
+            account.balance -= rent;
+            collected = collected.saturating_add(rent);
+            let reap = account.balance == 0 && account.locked.is_empty();
+            drop(account);
+
+            if reap {
+                self.accounts.remove(&pubkey);
+            }
+        }
+        self.burn(collected);
+    }
+
+    // What `delegate` may still move out of `owner`'s balance
+    pub fn allowance(&self, owner: &Pubkey, delegate: &Pubkey) -> u64 {
+        self.allowances.get(&Allowance::address(owner, delegate)).map_or(0, |allowance| allowance.amount)
+    }
+
+    // Replace `delegate`'s allowance over `owner`'s balance. Zero removes it.
+    pub fn set_allowance(&self, owner: &Pubkey, delegate: &Pubkey, amount: u64) {
+        let address = Allowance::address(owner, delegate);
+        if amount == 0 {
+            self.allowances.remove(&address);
+        } else {
+            self.allowances.insert(address, Allowance { owner: *owner, delegate: *delegate, amount });
+        }
+    }
+
+    pub fn spend_allowance(&self, owner: &Pubkey, delegate: &Pubkey, amount: u64) -> Result<(), &'static str> {
+        let remaining = self.allowance(owner, delegate).checked_sub(amount).ok_or("Allowance exceeded.")?;
+        self.set_allowance(owner, delegate, remaining);
+        Ok(())
+    }
+
+    pub fn add_mint(&self, id: MintId, mint: Mint) {
+        self.mints.insert(id, mint);
+    }
+
+    pub fn get_mint(&self, id: &MintId) -> Option<Mint> {
+        self.mints.get(id).map(|mint| *mint)
+    }
+
+    // Count newly issued units of a token in its supply
+    pub fn issue_tokens(&self, id: &MintId, amt: u64) -> Result<(), &'static str> {
+        let mut mint = self.mints.get_mut(id).ok_or("Mint not found.")?;
+        mint.supply = mint.supply.checked_add(amt).ok_or("Token supply overflows.")?;
+        Ok(())
+    }
+
+    // `owner`'s balance of a token, zero if they've never held any
+    pub fn token_balance(&self, mint: &MintId, owner: &Pubkey) -> u64 {
+        self.token_accounts.get(&TokenAccount::address(mint, owner)).map_or(0, |account| account.balance)
+    }
+
+    // Add to `owner`'s balance of a token, opening their token account if it doesn't exist yet
+    pub fn increase_token_balance(&self, mint: &MintId, owner: &Pubkey, delta: u64) {
+        let mut account = self.token_accounts
+            .entry(TokenAccount::address(mint, owner))
+            .or_insert(TokenAccount { mint: *mint, owner: *owner, balance: 0 });
+        account.balance = account.balance.saturating_add(delta);
+    }
+
+    pub fn decrease_token_balance(&self, mint: &MintId, owner: &Pubkey, delta: u64) -> Result<(), &'static str> {
+        match self.token_accounts.get_mut(&TokenAccount::address(mint, owner)) {
+            Some(mut account) if account.balance >= delta => {
+                account.balance -= delta;
+                Ok(())
+            }
+            Some(_) => Err("Insufficient token balance."),
+            None => Err("Token account not found."),
+        }
+    }
+
     pub fn add_validator(&self, pubkey: Pubkey, validator: ValidatorAccount) {
         self.validators.insert(pubkey, validator);
     }
@@ -59,6 +715,235 @@ impl AccountsDB {
         self.validators.get(pubkey).map(|val| val.clone())
     }
 
+    // Whether a validator has bonded enough itself to lead slots & count towards quorums, & isn't jailed.
+    // Registration & unstaking hold validators to the same minimum, so only genesis validators can start
+    // out below it.
+    pub fn is_eligible(&self, validator: &ValidatorAccount) -> bool {
+        !validator.is_jailed() && self.self_stake(validator) >= self.min_validator_stake
+    }
+
+    // Validators that could lead slots & vote as things stand, with the stake they'd count with: the
+    // eligible ones, or every validator as an equal while none is eligible, e.g. a dev chain started
+    // without stake
+    fn eligible_stakes(&self) -> BTreeMap<Pubkey, u64> {
+        let eligible: BTreeMap<Pubkey, u64> = self.validators
+            .iter()
+            .filter(|validator| self.is_eligible(validator))
+            .map(|validator| (*validator.key(), validator.stake))
+            .collect();
+        if !eligible.is_empty() {
+            return eligible
+        }
+        self.validators.iter().map(|validator| (*validator.key(), 0)).collect()
+    }
+
+    // Validators that may lead slots & vote this epoch & the stake they count with: the active set, less
+    // any jailed since it was taken
+    pub fn candidates(&self) -> Vec<(Pubkey, u64)> {
+        if self.active_set.is_empty() {
+            return self.eligible_stakes().into_iter().collect()
+        }
+        self.active_set
+            .iter()
+            .filter(|(pubkey, _)| !self.get_validator(pubkey).is_some_and(|validator| validator.is_jailed()))
+            .map(|(pubkey, stake)| (*pubkey, *stake))
+            .collect()
+    }
+
+    // Stake `pubkey` leads & votes with this epoch, if it's a candidate
+    pub fn active_stake(&self, pubkey: &Pubkey) -> Option<u64> {
+        self.candidates().into_iter().find(|(candidate, _)| candidate == pubkey).map(|(_, stake)| stake)
+    }
+
+    // What `wallet` draws for `slot` & the proof of it, if it's a candidate to lead. The lowest score
+    // among the candidates wins the slot.
+    pub fn draw_leader(&self, wallet: &Wallet, slot: u64) -> Option<(u128, VrfProof)> {
+        let stake = self.active_stake(&wallet.public_key)?;
+        let (output, proof) = vrf::prove(wallet, &vrf::leader_input(&self.epoch_seed, slot));
+        Some((vrf::leader_score(&output, stake), proof))
+    }
+
+    // What `block`'s proposer drew for its slot, if it was a candidate & the header proves the draw
+    pub fn leader_score(&self, block: &Block) -> Option<u128> {
+        let stake = self.active_stake(&block.header.proposer)?;
+        let output = block.leader_output(&self.epoch_seed)?;
+        Some(vrf::leader_score(&output, stake))
+    }
+
+    // Charge the slots skipped between the last block & `block` to the candidates other than its proposer,
+    // jailing any that have missed too many in a row. Nobody can name a skipped slot's winner without its
+    // proof, so each of them sat it out. Proposing a block clears the count.
+    fn record_missed_slots(&self, block: &Block) {
+        let missed = block.slot().saturating_sub(self.latest_slot).saturating_sub(1);
+        if missed > 0 {
+            for (candidate, _) in self.candidates().into_iter().filter(|(candidate, _)| *candidate != block.header.proposer) {
+                let Some(mut validator) = self.validators.get_mut(&candidate) else { continue };
+                validator.missed_slots = validator.missed_slots.saturating_add(missed);
+                if self.max_missed_slots > 0 && validator.missed_slots >= self.max_missed_slots && !validator.is_jailed() {
+                    validator.jailed_since = Some(block.height());
+                }
+            }
+        }
+
+        if let Some(mut proposer) = self.validators.get_mut(&block.header.proposer) {
+            proposer.missed_slots = 0;
+        }
+    }
+
+    pub fn epoch_of(&self, slot: u64) -> u64 {
+        slot / self.epoch_slots.max(1)
+    }
+
+    pub fn epoch_info(&self) -> EpochInfo {
+        let slots_in_epoch = self.epoch_slots.max(1);
+        EpochInfo {
+            epoch: self.epoch_of(self.latest_slot),
+            slot_index: self.latest_slot % slots_in_epoch,
+            slots_in_epoch,
+            absolute_slot: self.latest_slot,
+            block_height: self.latest_height,
+        }
+    }
+
+    // Whether `block`, yet to be applied, is the first in a new epoch. Slots can go unfilled, so the
+    // epoch before is closed out by whichever block first lands past it, once that block has run.
+    pub fn ends_epoch(&self, block: &Block) -> bool {
+        self.epoch_of(block.slot()) > self.epoch_of(self.latest_slot)
+    }
+
+    // Everything that waits for an epoch boundary
+    fn end_epoch(&mut self, block: &Block) {
+        self.distribute_rewards();
+        self.collect_rent();
+        self.roll_epoch_seed(block);
+        self.active_set = self.eligible_stakes();
+    }
+
+    // Mix the block ending the epoch into the seed the next epoch's leaders are drawn from
+    fn roll_epoch_seed(&mut self, block: &Block) {
+        self.epoch_seed = Sha256::new().chain_update(self.epoch_seed).chain_update(block.hash).finalize().into();
+    }
+
+    pub fn unjail(&self, pubkey: &Pubkey) -> Result<(), &'static str> {
+        let mut validator = self.validators.get_mut(pubkey).ok_or("Validator not found.")?;
+        validator.jailed_since = None;
+        validator.missed_slots = 0;
+        Ok(())
+    }
+
+    // Stake the validator bonded itself, rather than its delegators
+    pub fn self_stake(&self, validator: &ValidatorAccount) -> u64 {
+        validator.stake.saturating_sub(self.delegated(&validator.public_key))
+    }
+
+    // Stake delegated to `validator`, by delegator
+    pub fn delegations(&self, validator: &Pubkey) -> Vec<Delegation> {
+        self.delegations.get(validator).map(|delegations| delegations.clone()).unwrap_or_default()
+    }
+
+    pub fn delegated(&self, validator: &Pubkey) -> u64 {
+        self.delegations.get(validator).map_or(0, |delegations| delegations.iter().map(|delegation| delegation.amount).fold(0, u64::saturating_add))
+    }
+
+    // Record `amount` more of `delegator`'s stake with `validator`. Kept sorted by delegator, so every node
+    // stores, hashes & pays them out in the same order.
+    pub fn delegate(&self, validator: &Pubkey, delegator: &Pubkey, amount: u64) {
+        let mut delegations = self.delegations.entry(*validator).or_default();
+        match delegations.binary_search_by_key(delegator, |delegation| delegation.delegator) {
+            Ok(index) => delegations[index].amount = delegations[index].amount.saturating_add(amount),
+            Err(index) => delegations.insert(index, Delegation { delegator: *delegator, amount }),
+        }
+    }
+
+    // Rewards `validator` has earned this epoch that haven't been paid out yet
+    pub fn pending_reward(&self, validator: &Pubkey) -> u64 {
+        self.pending_rewards.get(validator).map_or(0, |owed| *owed)
+    }
+
+    // Validators with rewards to pay out at an epoch boundary, & their delegators
+    fn rewards_due(&self) -> Vec<Pubkey> {
+        self.pending_rewards
+            .iter()
+            .flat_map(|owed| {
+                let delegators = self.delegations(owed.key()).into_iter().map(|delegation| delegation.delegator);
+                std::iter::once(*owed.key()).chain(delegators).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Pay out the epoch's rewards once it ends. Each validator keeps its commission on what its
+    // delegators' stake earned, & delegators split the rest by how much they've delegated. Shares are
+    // worked out from stake at the end of the epoch, with rounding dust left to the validator.
+    fn distribute_rewards(&self) {
+        let owed: Vec<(Pubkey, u64)> = self.pending_rewards.iter().map(|owed| (*owed.key(), *owed.value())).collect();
+        self.pending_rewards.clear();
+
+        for (pubkey, reward) in owed {
+            let Some(validator) = self.get_validator(&pubkey) else {
+                self.deposit(&pubkey, reward);
+                continue
+            };
+            let mut paid = 0u64;
+            if validator.stake > 0 {
+                let commission = validator.commission.min(100) as u128;
+                for delegation in self.delegations(&pubkey) {
+                    let earned = reward as u128 * delegation.amount as u128 / validator.stake as u128;
+                    let share = (earned - earned * commission / 100) as u64;
+                    self.deposit(&delegation.delegator, share);
+                    paid = paid.saturating_add(share);
+                }
+            }
+            self.deposit(&pubkey, reward.saturating_sub(paid));
+        }
+    }
+
+    pub fn decrease_validator_stake(&self, pubkey: &Pubkey, amt: u64) -> Result<(), &'static str> {
+        match self.validators.get_mut(pubkey) {
+            Some(mut validator) if validator.stake >= amt => {
+                validator.stake -= amt;
+                Ok(())
+            }
+            Some(_) => Err("Insufficient stake."),
+            None => Err("Validator not found."),
+        }
+    }
+
+    // Lock `amount` of withdrawn stake owed to `pubkey` until `unbonding_blocks` after the current block
+    pub fn queue_unbonding(&self, pubkey: &Pubkey, amount: u64) {
+        let release_height = self.latest_height.saturating_add(self.unbonding_blocks);
+        self.unbonding.entry(*pubkey).or_default().push(Unbonding { amount, release_height });
+    }
+
+    // Withdrawn stake still waiting to be paid out to `pubkey`, oldest first
+    pub fn pending_withdrawals(&self, pubkey: &Pubkey) -> Vec<Unbonding> {
+        self.unbonding.get(pubkey).map(|pending| pending.clone()).unwrap_or_default()
+    }
+
+    // Keys with withdrawals that mature by `height`
+    fn maturing(&self, height: u64) -> Vec<Pubkey> {
+        self.unbonding
+            .iter()
+            .filter(|pending| pending.iter().any(|unbonding| unbonding.release_height <= height))
+            .map(|pending| *pending.key())
+            .collect()
+    }
+
+    // Pay out every withdrawal that's matured by `height`. It was already counted in the supply as stake.
+    fn release_unbonded(&self, height: u64) {
+        for pubkey in self.maturing(height) {
+            let Some(mut pending) = self.unbonding.get_mut(&pubkey) else { continue };
+            let released = pending.iter().filter(|unbonding| unbonding.release_height <= height).map(|unbonding| unbonding.amount).fold(0u64, u64::saturating_add);
+            pending.retain(|unbonding| unbonding.release_height > height);
+            let empty = pending.is_empty();
+            drop(pending);
+
+            if empty {
+                self.unbonding.remove(&pubkey);
+            }
+            self.deposit(&pubkey, released);
+        }
+    }
+
     pub fn increase_validator_stake(&self, pubkey: &Pubkey, amt: u64) -> Result<(), &'static str> {
         if let Some(mut validator) = self.validators.get_mut(pubkey) {
             validator.stake = validator.stake.saturating_add(amt);
@@ -68,12 +953,367 @@ impl AccountsDB {
         }
     }
 
-    pub fn finalize_block(&mut self, block: &Block) -> Result<(), &'static str> {
+    // Take fees out of circulation. Balances seeded outside of issuance aren't in the supply, so this saturates.
+    pub fn burn(&mut self, amt: u64) {
+        self.total_supply = self.total_supply.saturating_sub(amt);
+    }
+
+    // Transactions that don't share accounts execute concurrently, see `scheduler`
+    // Withdrawals maturing at this block's height are paid out once its transactions have run, & rewards
+    // paid out & rent collected if it ends an epoch.
+    // Returns a receipt for each transaction, in block order.
+    pub fn finalize_block(&mut self, block: &Block) -> Result<Vec<TransactionReceipt>, &'static str> {
+        if block.compute_units() > self.max_block_compute_units {
+            return Err("Block exceeds the compute budget")
+        }
+        self.record_missed_slots(block);
+        let ends_epoch = self.ends_epoch(block);
+        self.latest_height = block.height();
+        self.latest_slot = block.slot();
+        // One failed transaction fails the whole block, so there are only receipts for successes
+        if scheduler::execute_parallel_results(&block.transactions, self).iter().any(Result::is_err) {
+            return Err("Failed to execute transaction")
+        }
+        let receipts: Vec<TransactionReceipt> = block.transactions
+            .iter()
+            .enumerate()
+            .map(|(index, tx)| TransactionReceipt::new(block, index as u64, tx))
+            .collect();
+        // Fees were burned in full above; the part bid over the base fee is the proposer's. Blocks reach
+        // here without their signature necessarily checked, so only a proposer who signed the block gets
+        // paid, or anyone could gossip pending transactions in a block naming themselves & take the tips.
+        let tips = block.tips(self.base_fee);
+        if tips > 0 && block.verify_proposer() {
+            self.credit_reward(&block.header.proposer, tips);
+        }
+        self.base_fee = compute::next_base_fee(self.base_fee, block.compute_units(), self.max_block_compute_units);
+        self.release_unbonded(block.height());
+        self.release_vested(block.height());
+        if ends_epoch {
+            self.end_epoch(block);
+        }
+        self.latest_blockhash = block.hash;
+        Ok(receipts)
+    }
+
+    // Finalize a block, recording what it overwrote. If any transaction fails the block is reverted entirely.
+    pub fn finalize_block_with_undo(&mut self, block: &Block) -> Result<(BlockUndo, Vec<TransactionReceipt>), &'static str> {
+        let mut undo = BlockUndo {
+            latest_blockhash: self.latest_blockhash,
+            latest_height: self.latest_height,
+            latest_slot: self.latest_slot,
+            total_supply: self.total_supply,
+            base_fee: self.base_fee,
+            epoch_seed: self.epoch_seed,
+            active_set: self.active_set.clone(),
+            ..BlockUndo::default()
+        };
+        undo.capture(self, block.header.proposer);
+        for (candidate, _) in self.candidates() {
+            undo.capture(self, candidate);
+        }
+
         for tx in &block.transactions {
-            if tx.execute(self).is_err() {
-                return Err("Failed to execute transaction")
+            for pubkey in tx.accounts() {
+                undo.capture(self, pubkey);
+            }
+        }
+        // Withdrawals queued by this block can't mature in it, so these are all the payouts it makes
+        for pubkey in self.maturing(block.height()) {
+            undo.capture(self, pubkey);
+        }
+        // Locks this block adds are on accounts its transactions touch, captured above
+        for pubkey in self.vesting(block.height()) {
+            undo.capture(self, pubkey);
+        }
+        // As are the validators & delegators paid if it ends an epoch
+        if self.ends_epoch(block) {
+            // Accounts the block's transactions drop below the exempt minimum were captured above too
+            for pubkey in self.rewards_due().into_iter().chain(self.rent_due()) {
+                undo.capture(self, pubkey);
+            }
+        }
+
+        match self.finalize_block(block) {
+            Ok(receipts) => Ok((undo, receipts)),
+            Err(e) => {
+                self.revert_block(undo);
+                Err(e)
+            }
+        }
+    }
+
+    // Finalize a block & keep its undo record, so `rollback_to` can take the state back past it later.
+    // A block that fails to execute leaves the state as it was.
+    #[tracing::instrument(level = "debug", skip_all, fields(block = %hex::encode(block.hash), height = block.height(), transactions = block.transactions.len()))]
+    pub fn apply_block(&mut self, block: &Block) -> Result<Vec<TransactionReceipt>, &'static str> {
+        let (undo, receipts) = self.finalize_block_with_undo(block)?;
+        self.versions.push((block.height(), block.hash, undo));
+        Ok(receipts)
+    }
+
+    // Revert every block applied above `height`, newest first. Fails without changing anything if one of
+    // them wasn't applied through `apply_block`.
+    pub fn rollback_to(&mut self, height: u64) -> Result<(), &'static str> {
+        self.recorded_since(height)?;
+        while let Some((_, _, undo)) = self.versions.pop_if(|(applied, _, _)| *applied > height) {
+            self.revert_block(undo);
+        }
+        Ok(())
+    }
+
+    // What the newest block applied through `apply_block` changed, or `None` if `hash` isn't that block
+    pub fn state_diff(&self, hash: &Blockhash) -> Option<StateDiff> {
+        let (_, applied, undo) = self.versions.last()?;
+        (applied == hash).then(|| undo.diff(self))
+    }
+
+    // Undo records of every block applied after `height`, oldest first
+    fn recorded_since(&self, height: u64) -> Result<&[(u64, Blockhash, BlockUndo)], &'static str> {
+        // Each height from here up to the current one needs its own record
+        let recorded = self.versions.iter().rev().take_while(|(applied, _, _)| *applied > height).count();
+        if recorded as u64 != self.latest_height.saturating_sub(height) || (recorded > 0 && self.version() != Some(self.latest_height)) {
+            return Err("State at this height is no longer recorded")
+        }
+        Ok(&self.versions[self.versions.len() - recorded..])
+    }
+
+    // An account as it was once the block at `height` was finalized. The oldest undo record after that
+    // height to capture the account holds its value then; if none did, it hasn't changed since.
+    // Heights below the horizon are only served from snapshots.
+    pub fn get_account_at(&self, pubkey: &Pubkey, height: u64) -> Result<Option<UserAccount>, HistoryError> {
+        if !self.archive {
+            return Err(HistoryError::NotArchive)
+        }
+        if height > self.latest_height {
+            return Err(HistoryError::AboveTip)
+        }
+        if let Some(snapshot) = self.snapshots.get(&height) {
+            return Ok(snapshot.get(pubkey).cloned())
+        }
+        let recorded = self.recorded_since(height).map_err(|_| HistoryError::Pruned { horizon: self.history_horizon() })?;
+        Ok(self.rewind(recorded, pubkey))
+    }
+
+    fn rewind(&self, recorded: &[(u64, Blockhash, BlockUndo)], pubkey: &Pubkey) -> Option<UserAccount> {
+        let captured = recorded.iter().find_map(|(_, _, undo)| undo.accounts.iter().find(|(touched, _)| touched == pubkey));
+        match captured {
+            Some((_, account)) => account.clone(),
+            None => self.get_account(pubkey),
+        }
+    }
+
+    // Every account as of `height`
+    fn accounts_at(&self, height: u64) -> Result<HashMap<Pubkey, UserAccount>, &'static str> {
+        let recorded = self.recorded_since(height)?;
+        let mut pubkeys: HashSet<Pubkey> = self.accounts.iter().map(|account| *account.key()).collect();
+        pubkeys.extend(recorded.iter().flat_map(|(_, _, undo)| undo.accounts.iter().map(|(pubkey, _)| *pubkey)));
+        Ok(pubkeys.into_iter().filter_map(|pubkey| Some((pubkey, self.rewind(recorded, &pubkey)?))).collect())
+    }
+
+    // Oldest height whose state the undo records can still rebuild
+    pub fn history_horizon(&self) -> u64 {
+        self.pruned_below.saturating_sub(1)
+    }
+
+    // Forget the undo records of blocks below `horizon`, so state can't be rolled back or queried past it.
+    // Archive nodes first snapshot the accounts at each multiple of `snapshot_interval` that stops being
+    // reachable, to keep answering queries there.
+    pub fn prune(&mut self, horizon: u64, snapshot_interval: Option<u64>) {
+        if horizon <= self.pruned_below {
+            return
+        }
+        if let Some(interval) = snapshot_interval.filter(|interval| self.archive && *interval > 0) {
+            for height in self.history_horizon()..horizon.saturating_sub(1) {
+                if !height.is_multiple_of(interval) {
+                    continue
+                }
+                if let Ok(accounts) = self.accounts_at(height) {
+                    self.snapshots.insert(height, accounts);
+                }
+            }
+        }
+        self.versions.retain(|(applied, _, _)| *applied >= horizon);
+        self.pruned_below = horizon;
+    }
+
+    // All of the state as it was once the block at `height` was finalized, rolled back on a copy. Unlike
+    // `get_account_at` this needs no archive, but only reaches as far back as blocks can be reverted.
+    pub fn state_at(&self, height: u64) -> Result<Cow<'_, AccountsDB>, HistoryError> {
+        if height > self.latest_height {
+            return Err(HistoryError::AboveTip)
+        }
+        if height == self.latest_height {
+            return Ok(Cow::Borrowed(self))
+        }
+        let mut past = self.clone();
+        past.rollback_to(height).map_err(|_| HistoryError::Pruned { horizon: self.history_horizon() })?;
+        Ok(Cow::Owned(past))
+    }
+
+    pub fn get_balance_at(&self, pubkey: &Pubkey, height: u64) -> Result<u64, HistoryError> {
+        Ok(self.get_account_at(pubkey, height)?.map_or(0, |account| account.balance))
+    }
+
+    // Height of the newest block `rollback_to` can revert
+    pub fn version(&self) -> Option<u64> {
+        self.versions.last().map(|(height, _, _)| *height)
+    }
+
+    pub fn revert_block(&mut self, undo: BlockUndo) {
+        for (pubkey, account) in undo.accounts {
+            match account {
+                Some(account) => { self.accounts.insert(pubkey, account); }
+                None => { self.accounts.remove(&pubkey); }
+            }
+        }
+
+        for (pubkey, validator) in undo.validators {
+            match validator {
+                Some(validator) => { self.validators.insert(pubkey, validator); }
+                None => { self.validators.remove(&pubkey); }
+            }
+        }
+
+        for (pubkey, pending) in undo.unbonding {
+            match pending {
+                Some(pending) => { self.unbonding.insert(pubkey, pending); }
+                None => { self.unbonding.remove(&pubkey); }
+            }
+        }
+
+        for (pubkey, delegations) in undo.delegations {
+            match delegations {
+                Some(delegations) => { self.delegations.insert(pubkey, delegations); }
+                None => { self.delegations.remove(&pubkey); }
+            }
+        }
+
+        for (pubkey, owed) in undo.pending_rewards {
+            match owed {
+                Some(owed) => { self.pending_rewards.insert(pubkey, owed); }
+                None => { self.pending_rewards.remove(&pubkey); }
+            }
+        }
+
+        for (id, mint) in undo.mints {
+            match mint {
+                Some(mint) => { self.mints.insert(id, mint); }
+                None => { self.mints.remove(&id); }
+            }
+        }
+
+        for (address, account) in undo.token_accounts {
+            match account {
+                Some(account) => { self.token_accounts.insert(address, account); }
+                None => { self.token_accounts.remove(&address); }
             }
         }
+
+        for (address, allowance) in undo.allowances {
+            match allowance {
+                Some(allowance) => { self.allowances.insert(address, allowance); }
+                None => { self.allowances.remove(&address); }
+            }
+        }
+
+        self.latest_blockhash = undo.latest_blockhash;
+        self.latest_height = undo.latest_height;
+        self.latest_slot = undo.latest_slot;
+        self.total_supply = undo.total_supply;
+        self.base_fee = undo.base_fee;
+        self.epoch_seed = undo.epoch_seed;
+        self.active_set = undo.active_set;
+    }
+
+    // Add to an account's balance, opening the account if it doesn't exist yet. Leaves the supply alone.
+    pub fn deposit(&self, pubkey: &Pubkey, amount: u64) {
+        let mut account = self.accounts.entry(*pubkey).or_insert_with(|| UserAccount::from_public_key(*pubkey));
+        account.balance = account.balance.saturating_add(amount);
+    }
+
+    // Count newly created tokens in the supply
+    pub fn mint(&mut self, amt: u64) {
+        self.total_supply = self.total_supply.saturating_add(amt);
+    }
+
+    // Mint newly issued tokens to `pubkey`, opening a balance for it if needed (e.g. a validator's
+    // identity key receiving its first reward)
+    pub fn credit_reward(&mut self, pubkey: &Pubkey, amount: u64) {
+        self.deposit(pubkey, amount);
+        self.mint(amount);
+    }
+
+    // Credit a block's rewards, recording what they overwrote in that block's undo record. Validators'
+    // rewards wait for the end of the epoch, to be shared with their delegators.
+    pub fn credit_rewards(&mut self, rewards: &[(Pubkey, u64)], undo: &mut BlockUndo) {
+        for (pubkey, amount) in rewards {
+            undo.capture(self, *pubkey);
+            if self.is_validator(pubkey) {
+                let mut owed = self.pending_rewards.entry(*pubkey).or_default();
+                *owed = owed.saturating_add(*amount);
+                drop(owed);
+                self.mint(*amount);
+            } else {
+                self.credit_reward(pubkey, *amount);
+            }
+        }
+    }
+
+    // Credit rewards for a block applied through `apply_block`, so rolling it back takes them back too
+    pub fn credit_block_rewards(&mut self, hash: &Blockhash, rewards: &[(Pubkey, u64)]) -> Result<(), &'static str> {
+        let index = self.versions.iter().rposition(|(_, applied, _)| applied == hash).ok_or("Block has no recorded state")?;
+        let mut undo = std::mem::take(&mut self.versions[index].2);
+        self.credit_rewards(rewards, &mut undo);
+        self.versions[index].2 = undo;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Kind of entry in the state tree that accounts' leaves are
+const ACCOUNT_LEAF: u8 = 0;
+// & that the chain-wide values' leaf is
+const CHAIN_LEAF: u8 = 9;
+
+// Whether `account` is under `state_root`, by a proof from `AccountsDB::get_account_with_proof`. Needs
+// nothing else, so a balance can be trusted by anyone who trusts the root, e.g. from a block header.
+pub fn verify_account_proof(account: &UserAccount, proof: &MerkleProof, state_root: &Hash) -> bool {
+    proof.verify(&account_leaf(account), state_root)
+}
+
+// An account's leaf in the state tree
+pub fn account_leaf(account: &UserAccount) -> Hash {
+    hash_leaf(&[&account.public_key[..], &[ACCOUNT_LEAF], &account_state(account)].concat())
+}
+
+fn account_state(account: &UserAccount) -> Vec<u8> {
+    let mut data = account.nonce.to_le_bytes().to_vec();
+    data.extend(account.balance.to_le_bytes());
+    data.extend(account.owner);
+    data.push(account.frozen as u8);
+    // Length-prefixed so the account's data can't run into the vesting entries after it
+    data.extend((account.data.len() as u64).to_le_bytes());
+    data.extend(&account.data);
+    data.extend(account.locked.iter().flat_map(|vesting| [vesting.amount, vesting.unlock_height]).flat_map(u64::to_le_bytes));
+    data
+}
+
+// Entries `offset..offset + limit` of a map ordered by key
+fn page<V: Clone>(map: &DashMap<Pubkey, V>, offset: usize, limit: usize) -> Vec<V> {
+    let mut keys: Vec<Pubkey> = map.iter().map(|entry| *entry.key()).collect();
+    keys.sort();
+    keys.into_iter()
+        .skip(offset)
+        .take(limit)
+        .filter_map(|key| map.get(&key).map(|value| value.clone()))
+        .collect()
+}
+
+fn move_balance(from: &mut UserAccount, to: &mut UserAccount, amt: u64) -> Result<(), TransferError> {
+    if from.balance < amt {
+        return Err(TransferError::InsufficientBalance)
+    }
+    to.balance = to.balance.checked_add(amt).ok_or(TransferError::BalanceOverflow)?;
+    from.balance -= amt;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    io::{self, Error},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+use crate::structures::{pubkey_from_address, Pubkey};
+
+// Most nodes the peer table holds
+pub const MAX_KNOWN_PEERS: usize = 1000;
+// Most records a `Peers` answer lists
+pub const MAX_SHARED_PEERS: usize = 100;
+// Service nodes announce themselves under on local networks
+pub const MDNS_SERVICE: &str = "_litechain._tcp.local.";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    // Peers to stay connected to. While short of it, nodes from the peer table & the bootstrap list
+    // are dialed.
+    pub target_peers: usize,
+    // How often peers are pinged & asked for theirs
+    pub interval_ms: u64,
+    // Peers heard nothing from for this long are dropped
+    pub peer_timeout_ms: u64,
+    // Dials in a row a node can fail before it's forgotten. Each failure doubles the wait to the next.
+    pub max_dial_failures: u32,
+    // Announce this node & find others on the local network over mDNS
+    pub mdns: bool,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            target_peers: 8,
+            interval_ms: 5000,
+            peer_timeout_ms: 30_000,
+            max_dial_failures: 5,
+            mdns: false,
+        }
+    }
+}
+
+// A node that can be dialed: where it takes connections & the identity it proves there
+#[derive(Debug, Clone, PartialEq, Eq, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct PeerRecord {
+    pub addr: String,
+    pub identity: Pubkey,
+}
+
+// How we heard where a node listens, least trusted first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Source {
+    // Another node's peer exchange, or the local network, where anyone can claim anything
+    Hearsay,
+    // The node itself, over a connection it proved its identity on
+    Announced,
+    // We dialed it there & it proved its identity
+    Dialed,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct KnownPeer {
+    addr: SocketAddr,
+    source: Source,
+    failures: u32,
+    retry_at: Instant,
+}
+
+// Nodes heard of from peers or the local network, by identity, whether or not we're connected to them
+#[derive(Debug, Default)]
+pub(crate) struct PeerTable(Mutex<HashMap<Pubkey, KnownPeer>>);
+
+impl PeerTable {
+    // Note where `identity` listens. Hearsay only adds nodes not yet known, so no peer can move one
+    // elsewhere; a node's own word moves it unless we've dialed it since. New nodes are dropped once
+    // the table is full.
+    pub(crate) fn insert(&self, identity: Pubkey, addr: SocketAddr, source: Source) {
+        let mut peers = self.0.lock().unwrap();
+        if let Some(peer) = peers.get_mut(&identity) {
+            if source > Source::Hearsay && source >= peer.source {
+                (peer.addr, peer.source) = (addr, source);
+            }
+        } else if peers.len() < MAX_KNOWN_PEERS {
+            peers.insert(identity, KnownPeer { addr, source, failures: 0, retry_at: Instant::now() });
+        }
+    }
+
+    pub(crate) fn records(&self, limit: usize) -> Vec<PeerRecord> {
+        let peers = self.0.lock().unwrap();
+        peers.iter().take(limit).map(|(identity, peer)| PeerRecord { addr: peer.addr.to_string(), identity: *identity }).collect()
+    }
+
+    // Nodes not waiting out a failed dial
+    pub(crate) fn due(&self, now: Instant) -> Vec<(Pubkey, SocketAddr)> {
+        let peers = self.0.lock().unwrap();
+        peers.iter().filter(|(_, peer)| peer.retry_at <= now).map(|(identity, peer)| (*identity, peer.addr)).collect()
+    }
+
+    pub(crate) fn dialed(&self, identity: &Pubkey, connected: bool, config: &DiscoveryConfig) {
+        let mut peers = self.0.lock().unwrap();
+        let Some(peer) = peers.get_mut(identity) else { return };
+        if connected {
+            peer.failures = 0;
+            return
+        }
+        peer.failures += 1;
+        let backoff = Duration::from_millis(config.interval_ms).saturating_mul(2u32.saturating_pow(peer.failures));
+        match Instant::now().checked_add(backoff) {
+            Some(retry_at) if peer.failures < config.max_dial_failures => peer.retry_at = retry_at,
+            _ => {
+                peers.remove(identity);
+            }
+        }
+    }
+}
+
+// Announce `identity` as listening on `port` to the local network, & add every other node announced
+// there to `table`, for as long as the process runs
+pub(crate) fn start_mdns(identity: Pubkey, port: u16, table: Arc<PeerTable>) -> io::Result<()> {
+    let daemon = ServiceDaemon::new().map_err(Error::other)?;
+    // Instance names are single DNS labels, too short for a whole hex key
+    let address = hex::encode(identity);
+    let instance = &address[..16];
+    let service = ServiceInfo::new(MDNS_SERVICE, instance, &format!("{instance}.local."), "", port, &[("identity", address.as_str())][..])
+        .map_err(Error::other)?
+        .enable_addr_auto();
+    daemon.register(service).map_err(Error::other)?;
+    let events = daemon.browse(MDNS_SERVICE).map_err(Error::other)?;
+
+    thread::spawn(move || {
+        // Owns the daemon, which stops once dropped
+        let _daemon = daemon;
+        while let Ok(event) = events.recv() {
+            let ServiceEvent::ServiceResolved(service) = event else { continue };
+            let Some(peer) = service.get_property_val_str("identity").and_then(|address| pubkey_from_address(address).ok()) else { continue };
+            if peer == identity {
+                continue
+            }
+            for ip in service.get_addresses() {
+                table.insert(peer, SocketAddr::new(*ip, service.get_port()), Source::Hearsay);
+            }
+        }
+    });
+    Ok(())
+}
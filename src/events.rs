@@ -0,0 +1,47 @@
+use tokio::sync::broadcast;
+
+use crate::structures::{Blockhash, Pubkey, Txhash};
+
+// Most events a subscriber can fall behind by before it starts missing them
+pub const EVENT_CAPACITY: usize = 1024;
+
+// Something that happened to the chain, as it happened on this node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    // Entered the mempool under `id`
+    TransactionAdmitted { hash: Txhash, id: u64 },
+    // Built by a local validator & put up for votes
+    BlockProposed { hash: Blockhash, height: u64, slot: u64, transactions: usize },
+    BlockFinalized { hash: Blockhash, height: u64 },
+    // A balance changed by a finalized block or its rewards, `None` if the account is gone
+    AccountUpdated { pubkey: Pubkey, height: u64, balance: Option<u64> },
+}
+
+// Fans events out to every subscriber. Emitting never blocks: a subscriber that lags more than
+// `EVENT_CAPACITY` events behind skips ahead & is told how many it missed.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self { sender: broadcast::channel(capacity).0 }
+    }
+
+    // Events emitted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: Event) {
+        // Nobody listening isn't an error
+        let _ = self.sender.send(event);
+    }
+}
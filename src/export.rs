@@ -0,0 +1,74 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    chain::Blockchain,
+    config::GenesisConfig,
+    db::AccountsDB,
+    structures::{Block, Pubkey},
+};
+
+// Identifies an exported chain file & the layout it was written with
+pub const EXPORT_FORMAT: &str = "litechain-chain";
+pub const EXPORT_VERSION: u32 = 1;
+
+// A whole chain in one JSON file: the genesis config it started from & every canonical block after it,
+// enough to rebuild the chain & its state anywhere
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChainExport {
+    pub format: String,
+    pub version: u32,
+    pub genesis: GenesisConfig,
+    pub blocks: Vec<ExportedBlock>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExportedBlock {
+    pub block: Block,
+    // Issuance credited alongside the block, which isn't part of its contents
+    #[serde(default)]
+    pub rewards: Vec<(Pubkey, u64)>,
+}
+
+impl ChainExport {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(self).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(path, contents)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let export: Self = serde_json::from_slice(&fs::read(path)?).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if export.format != EXPORT_FORMAT {
+            return Err(Error::new(ErrorKind::InvalidData, "Not an exported chain"))
+        }
+        if export.version != EXPORT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "Unsupported export version"))
+        }
+        Ok(export)
+    }
+
+    // Replay the export from its genesis, executing every block & crediting its rewards
+    pub fn rebuild(&self) -> Result<(Blockchain, AccountsDB), &'static str> {
+        let (mut chain, mut db) = Blockchain::from_genesis(&self.genesis)?;
+        let Some((genesis, blocks)) = self.blocks.split_first() else {
+            return Err("Export has no genesis block")
+        };
+        if genesis.block.hash != chain.tip().hash {
+            return Err("Genesis block does not match the genesis config")
+        }
+
+        for exported in blocks {
+            if exported.block.prev_hash() != chain.tip().hash {
+                return Err("Exported block does not extend the chain")
+            }
+            chain.apply(exported.block.clone(), &mut db)?;
+            if !exported.rewards.is_empty() {
+                chain.credit_rewards(&exported.block.hash, exported.rewards.clone(), &mut db)?;
+            }
+        }
+        Ok((chain, db))
+    }
+}
@@ -0,0 +1,723 @@
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use prost::Message;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast::error::RecvError, mpsc, watch},
+};
+use tokio_stream::{
+    wrappers::{ReceiverStream, TcpListenerStream},
+    Stream,
+};
+use tonic::{transport::Server, Code, Request, Response, Status, Streaming};
+
+use crate::{
+    chain::Commitment,
+    checkpoint::Checkpoint,
+    db::EpochInfo,
+    events::{Event, EventBus, EVENT_CAPACITY},
+    merkle::MerkleProof,
+    rate_limit::{RateLimitKey, RateLimiter},
+    rpc::{RpcError, RpcServer, API_KEY_HEADER, INVALID_PARAMS, METHOD_NOT_FOUND, PRUNED, RATE_LIMITED, SERVER_ERROR},
+    structures::{
+        Block, BlockHeader, Mint, SimulationResult, StateDiff, Transaction, TransactionStatus, Txhash, Unbonding, UserAccount, ValidatorAccount,
+        ValueChange,
+    },
+    vote::QuorumCertificate,
+};
+
+// Bindings generated from proto/litechain.proto by build.rs
+pub mod proto {
+    tonic::include_proto!("litechain.v1");
+}
+
+use proto::{
+    node_server::{Node, NodeServer},
+    submission_update::Update,
+    transaction_status::{Included, Status as TxStatus},
+};
+
+type EventStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+// The limiter & key a client's calls are charged to, if the server rate limits
+type Limit<'a> = Option<(&'a RateLimiter, RateLimitKey)>;
+
+// The node API over gRPC. Every query is answered by the JSON-RPC method of the same name, so both
+// serve the same state under the same rules & rate limits, & the streams follow the node's `EventBus`.
+#[derive(Debug, Clone)]
+pub struct GrpcServer {
+    rpc: RpcServer,
+    events: EventBus,
+    // Set on shutdown, ending every open stream so the server isn't left waiting on them
+    stopping: Arc<watch::Sender<bool>>,
+}
+
+impl GrpcServer {
+    pub fn new(rpc: RpcServer) -> Self {
+        let events = rpc.builder.events.clone();
+        Self { rpc, events, stopping: Arc::new(watch::channel(false).0) }
+    }
+
+    // Accept connections on `listener` until `shutdown` resolves
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> io::Result<()> {
+        let stopping = Arc::clone(&self.stopping);
+        let shutdown = async move {
+            shutdown.await;
+            stopping.send_replace(true);
+        };
+        Server::builder()
+            .add_service(NodeServer::new(self))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+            .await
+            .map_err(io::Error::other)
+    }
+
+    // Run the JSON-RPC `method` & read its result back as a `T`
+    fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, RpcError> {
+        let result = self.rpc.dispatch(method, &params)?;
+        serde_json::from_value(result).map_err(|_| RpcError::new(SERVER_ERROR, "Unexpected RPC result"))
+    }
+
+    // Submit through `sendTransaction`, returning the mempool id
+    fn send(&self, request: &proto::SendTransactionRequest) -> Result<u64, RpcError> {
+        let config = json!({ "skipPreflight": request.skip_preflight });
+        self.call("sendTransaction", json!([hex::encode(&request.transaction), config]))
+    }
+
+    // The limiter & key to charge `client` to for `bytes` they sent, or a `RATE_LIMITED` error if
+    // they're over quota
+    fn rate_limit(&self, client: &Client, bytes: usize) -> Result<Limit<'_>, RpcError> {
+        self.rpc.rate_limit(client.addr, client.api_key.as_deref(), bytes as u64).map_err(RpcError::rate_limited)
+    }
+
+    // Answer a unary call with `answer`, charging its sender for the request & then the response
+    fn limited<T: Message, U: Message>(&self, request: Request<T>, answer: impl FnOnce(T) -> Result<U, RpcError>) -> Result<Response<U>, RpcError> {
+        let limit = self.rate_limit(&Client::of(&request), request.get_ref().encoded_len())?;
+        let response = answer(request.into_inner());
+        if let Some((limiter, key)) = limit {
+            limiter.charge(&key, response.as_ref().map_or_else(|error| error.message.len(), Message::encoded_len) as u64);
+        }
+        response.map(Response::new)
+    }
+
+    // Charge the call opening a stream of `bytes` to its sender, keeping the limiter & key to charge
+    // whatever the stream sends back to
+    fn open_stream<T>(&self, request: &Request<T>, bytes: usize) -> Result<Option<(RateLimiter, RateLimitKey)>, RpcError> {
+        let limit = self.rate_limit(&Client::of(request), bytes)?;
+        Ok(limit.map(|(limiter, key)| (limiter.clone(), key)))
+    }
+
+    // Events from now on, as `T`s for whichever `map` keeps, until the client goes, falls behind or the
+    // server stops. Each one sent is charged to `limit`.
+    fn subscribe<T: Message + 'static>(&self, limit: Option<(RateLimiter, RateLimitKey)>, map: impl Fn(Event) -> Option<T> + Send + 'static) -> EventStream<T> {
+        let (sender, receiver) = mpsc::channel(EVENT_CAPACITY);
+        let (mut events, mut stopping) = (self.events.subscribe(), self.stopping.subscribe());
+        tokio::spawn(async move {
+            loop {
+                let item = tokio::select! {
+                    _ = stopping.wait_for(|stopping| *stopping) => break,
+                    event = events.recv() => match event {
+                        Ok(event) => match map(event) {
+                            Some(item) => Ok(item),
+                            None => continue,
+                        },
+                        Err(RecvError::Lagged(missed)) => Err(Status::data_loss(format!("Fell {} events behind", missed))),
+                        Err(RecvError::Closed) => break,
+                    },
+                };
+                if let (Ok(item), Some((limiter, key))) = (&item, &limit) {
+                    limiter.charge(key, item.encoded_len() as u64);
+                }
+                let last = item.is_err();
+                tokio::select! {
+                    _ = stopping.wait_for(|stopping| *stopping) => break,
+                    sent = sender.send(item) => if sent.is_err() || last { break },
+                }
+            }
+        });
+        Box::pin(ReceiverStream::new(receiver))
+    }
+
+    // Answer a transaction sent over `StreamTransactions` as `SendTransaction` would, charging it to
+    // `client` like a call of its own, & hold on to its hash in `pending` if it's accepted
+    fn submission(&self, client: &Client, request: proto::SendTransactionRequest, pending: &mut Vec<(Txhash, bool)>) -> proto::SubmissionUpdate {
+        let tx = Transaction::from_bytes(&request.transaction);
+        let hash = tx.as_ref().map_or_else(|_| Vec::new(), |tx| tx.hash().to_vec());
+        let sent = self
+            .rate_limit(client, request.encoded_len())
+            .and_then(|_| tx.map_err(|e| RpcError::new(INVALID_PARAMS, e)))
+            .and_then(|tx| Ok((tx.hash(), self.send(&request)?)));
+        let update = match sent {
+            Ok((hash, id)) => {
+                pending.push((hash, false));
+                Update::Accepted(proto::SendTransactionResponse { id, hash: hash.to_vec() })
+            }
+            Err(error) => Update::Rejected(error.message),
+        };
+        proto::SubmissionUpdate { hash, update: Some(update) }
+    }
+
+    // The status of each `pending` transaction that's since been included or dropped, leaving only
+    // those still pending. One neither in the mempool nor a block may just be in a block still being
+    // proposed, so it's only taken as dropped once it's been missing at two checks running.
+    fn status_changes(&self, pending: &mut Vec<(Txhash, bool)>) -> Vec<proto::SubmissionUpdate> {
+        let mut updates = vec![];
+        pending.retain_mut(|(hash, missing)| {
+            let status = self.call("getTransactionStatus", json!([hex::encode(*hash)])).unwrap_or(TransactionStatus::Pending);
+            match status {
+                TransactionStatus::Pending => *missing = false,
+                TransactionStatus::Unknown if !*missing => *missing = true,
+                status => {
+                    updates.push(proto::SubmissionUpdate { hash: hash.to_vec(), update: Some(Update::Status(status.into())) });
+                    return false
+                }
+            }
+            true
+        });
+        updates
+    }
+}
+
+#[tonic::async_trait]
+impl Node for GrpcServer {
+    async fn get_account(&self, request: Request<proto::AccountRequest>) -> Result<Response<proto::AccountResponse>, Status> {
+        self.limited(request, |request| {
+            let account: Option<UserAccount> = self.call("getAccount", params(vec![json!(hex::encode(&request.pubkey))], request.commitment()))?;
+            Ok(proto::AccountResponse { account: account.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_account_with_proof(&self, request: Request<proto::AccountRequest>) -> Result<Response<proto::AccountProofResponse>, Status> {
+        self.limited(request, |request| {
+            let proven: Option<AccountProof> = self.call("getAccountWithProof", params(vec![json!(hex::encode(&request.pubkey))], request.commitment()))?;
+            let Some(proven) = proven else { return Ok(proto::AccountProofResponse::default()) };
+            Ok(proto::AccountProofResponse {
+                account: Some(proven.account.into()),
+                height: proven.height,
+                state_root: decode_hex(&proven.state_root)?,
+                proof: Some(proven.proof.into()),
+            })
+        }).map_err(Into::into)
+    }
+
+    async fn get_account_at(&self, request: Request<proto::HistoricalRequest>) -> Result<Response<proto::AccountResponse>, Status> {
+        self.limited(request, |request| {
+            let account: Option<UserAccount> = self.call("getAccountAt", json!([hex::encode(&request.pubkey), request.height]))?;
+            Ok(proto::AccountResponse { account: account.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        request: Request<proto::MultipleAccountsRequest>,
+    ) -> Result<Response<proto::MultipleAccountsResponse>, Status> {
+        self.limited(request, |request| {
+            let addresses: Vec<String> = request.pubkeys.iter().map(hex::encode).collect();
+            let accounts: Vec<Option<UserAccount>> = self.call("getMultipleAccounts", params(vec![json!(addresses)], request.commitment()))?;
+            let accounts = accounts.into_iter().map(|account| proto::AccountResponse { account: account.map(Into::into) }).collect();
+            Ok(proto::MultipleAccountsResponse { accounts })
+        }).map_err(Into::into)
+    }
+
+    async fn get_balance(&self, request: Request<proto::AccountRequest>) -> Result<Response<proto::BalanceResponse>, Status> {
+        self.limited(request, |request| {
+            let balance = self.call("getBalance", params(vec![json!(hex::encode(&request.pubkey))], request.commitment()))?;
+            Ok(proto::BalanceResponse { balance })
+        }).map_err(Into::into)
+    }
+
+    async fn get_balance_at(&self, request: Request<proto::HistoricalRequest>) -> Result<Response<proto::BalanceResponse>, Status> {
+        self.limited(request, |request| {
+            let balance = self.call("getBalanceAt", json!([hex::encode(&request.pubkey), request.height]))?;
+            Ok(proto::BalanceResponse { balance })
+        }).map_err(Into::into)
+    }
+
+    async fn get_account_count(&self, request: Request<proto::StateRequest>) -> Result<Response<proto::CountResponse>, Status> {
+        self.limited(request, |request| {
+            let count = self.call("getAccountCount", params(vec![], request.commitment()))?;
+            Ok(proto::CountResponse { count })
+        }).map_err(Into::into)
+    }
+
+    async fn get_accounts(&self, request: Request<proto::PageRequest>) -> Result<Response<proto::MultipleAccountsResponse>, Status> {
+        self.limited(request, |request| {
+            let accounts: Vec<UserAccount> = self.call("getAccounts", params(vec![json!(request.offset), json!(request.limit)], request.commitment()))?;
+            let accounts = accounts.into_iter().map(|account| proto::AccountResponse { account: Some(account.into()) }).collect();
+            Ok(proto::MultipleAccountsResponse { accounts })
+        }).map_err(Into::into)
+    }
+
+    async fn get_account_history(
+        &self,
+        request: Request<proto::AccountHistoryRequest>,
+    ) -> Result<Response<proto::AccountHistoryResponse>, Status> {
+        self.limited(request, |request| {
+            let before = request.before.map(|cursor| (cursor.height, cursor.index));
+            let params = params(vec![json!(hex::encode(&request.pubkey)), json!(request.limit), json!(before)], request.commitment());
+            let entries: Vec<(u64, u64)> = self.call("getAccountHistory", params)?;
+            let entries = entries.into_iter().map(|(height, index)| proto::HistoryCursor { height, index }).collect();
+            Ok(proto::AccountHistoryResponse { entries })
+        }).map_err(Into::into)
+    }
+
+    async fn get_validators(&self, request: Request<proto::PageRequest>) -> Result<Response<proto::ValidatorsResponse>, Status> {
+        self.limited(request, |request| {
+            let validators: Vec<ValidatorAccount> = self.call("getValidators", params(vec![json!(request.offset), json!(request.limit)], request.commitment()))?;
+            Ok(proto::ValidatorsResponse { validators: validators.into_iter().map(Into::into).collect() })
+        }).map_err(Into::into)
+    }
+
+    async fn get_validator(&self, request: Request<proto::AccountRequest>) -> Result<Response<proto::ValidatorResponse>, Status> {
+        self.limited(request, |request| {
+            let validator: Option<ValidatorAccount> = self.call("getValidator", params(vec![json!(hex::encode(&request.pubkey))], request.commitment()))?;
+            Ok(proto::ValidatorResponse { validator: validator.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_pending_withdrawals(
+        &self,
+        request: Request<proto::AccountRequest>,
+    ) -> Result<Response<proto::PendingWithdrawalsResponse>, Status> {
+        self.limited(request, |request| {
+            let withdrawals: Vec<Unbonding> = self.call("getPendingWithdrawals", params(vec![json!(hex::encode(&request.pubkey))], request.commitment()))?;
+            let withdrawals = withdrawals.into_iter().map(|unbonding| proto::Unbonding { amount: unbonding.amount, release_height: unbonding.release_height }).collect();
+            Ok(proto::PendingWithdrawalsResponse { withdrawals })
+        }).map_err(Into::into)
+    }
+
+    async fn get_epoch_info(&self, request: Request<proto::StateRequest>) -> Result<Response<proto::EpochInfo>, Status> {
+        self.limited(request, |request| {
+            let info: EpochInfo = self.call("getEpochInfo", params(vec![], request.commitment()))?;
+            Ok(info.into())
+        }).map_err(Into::into)
+    }
+
+    async fn get_allowance(&self, request: Request<proto::AllowanceRequest>) -> Result<Response<proto::BalanceResponse>, Status> {
+        self.limited(request, |request| {
+            let keys = vec![json!(hex::encode(&request.owner)), json!(hex::encode(&request.delegate))];
+            let balance = self.call("getAllowance", params(keys, request.commitment()))?;
+            Ok(proto::BalanceResponse { balance })
+        }).map_err(Into::into)
+    }
+
+    async fn get_mint(&self, request: Request<proto::MintRequest>) -> Result<Response<proto::MintResponse>, Status> {
+        self.limited(request, |request| {
+            let mint: Option<Mint> = self.call("getMint", params(vec![json!(hex::encode(&request.mint))], request.commitment()))?;
+            let mint = mint.map(|mint| proto::Mint { authority: mint.authority.to_vec(), decimals: mint.decimals.into(), supply: mint.supply });
+            Ok(proto::MintResponse { mint })
+        }).map_err(Into::into)
+    }
+
+    async fn get_token_balance(&self, request: Request<proto::TokenBalanceRequest>) -> Result<Response<proto::BalanceResponse>, Status> {
+        self.limited(request, |request| {
+            let keys = vec![json!(hex::encode(&request.mint)), json!(hex::encode(&request.owner))];
+            let balance = self.call("getTokenBalance", params(keys, request.commitment()))?;
+            Ok(proto::BalanceResponse { balance })
+        }).map_err(Into::into)
+    }
+
+    async fn get_total_supply(&self, request: Request<proto::StateRequest>) -> Result<Response<proto::BalanceResponse>, Status> {
+        self.limited(request, |request| {
+            let balance = self.call("getTotalSupply", params(vec![], request.commitment()))?;
+            Ok(proto::BalanceResponse { balance })
+        }).map_err(Into::into)
+    }
+
+    async fn get_block_height(&self, request: Request<proto::StateRequest>) -> Result<Response<proto::HeightResponse>, Status> {
+        self.limited(request, |request| {
+            let height = self.call("getBlockHeight", params(vec![], request.commitment()))?;
+            Ok(proto::HeightResponse { height })
+        }).map_err(Into::into)
+    }
+
+    async fn get_latest_blockhash(&self, request: Request<proto::StateRequest>) -> Result<Response<proto::BlockhashResponse>, Status> {
+        self.limited(request, |request| {
+            let hash: Option<String> = self.call("getLatestBlockhash", params(vec![], request.commitment()))?;
+            Ok(proto::BlockhashResponse { hash: hash.as_deref().map(decode_hex).transpose()? })
+        }).map_err(Into::into)
+    }
+
+    async fn get_block(&self, request: Request<proto::BlockRequest>) -> Result<Response<proto::BlockResponse>, Status> {
+        self.limited(request, |request| {
+            let block: Option<Block> = self.call("getBlock", params(vec![json!(request.height)], request.commitment()))?;
+            Ok(proto::BlockResponse { block: block.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_block_by_hash(&self, request: Request<proto::BlockByHashRequest>) -> Result<Response<proto::BlockResponse>, Status> {
+        self.limited(request, |request| {
+            let block: Option<Block> = self.call("getBlockByHash", params(vec![json!(hex::encode(&request.hash))], request.commitment()))?;
+            Ok(proto::BlockResponse { block: block.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_blocks(&self, request: Request<proto::RangeRequest>) -> Result<Response<proto::BlocksResponse>, Status> {
+        self.limited(request, |request| {
+            let blocks: Vec<Block> = self.call("getBlocks", params(vec![json!(request.start), json!(request.end)], request.commitment()))?;
+            Ok(proto::BlocksResponse { blocks: blocks.into_iter().map(Into::into).collect() })
+        }).map_err(Into::into)
+    }
+
+    async fn get_block_headers(&self, request: Request<proto::RangeRequest>) -> Result<Response<proto::BlockHeadersResponse>, Status> {
+        self.limited(request, |request| {
+            let headers: Vec<HashedHeader> = self.call("getBlockHeaders", params(vec![json!(request.start), json!(request.end)], request.commitment()))?;
+            Ok(proto::BlockHeadersResponse { headers: headers.into_iter().map(|hashed| hashed.header.into()).collect() })
+        }).map_err(Into::into)
+    }
+
+    async fn get_checkpoint(&self, request: Request<proto::CheckpointRequest>) -> Result<Response<proto::CheckpointResponse>, Status> {
+        self.limited(request, |request| {
+            let checkpoint: Option<Checkpoint> = self.call("getCheckpoint", json!(request.height.into_iter().collect::<Vec<_>>()))?;
+            Ok(proto::CheckpointResponse { checkpoint: checkpoint.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_state_diff(&self, request: Request<proto::BlockByHashRequest>) -> Result<Response<proto::StateDiffResponse>, Status> {
+        self.limited(request, |request| {
+            let diff: Option<StateDiff> = self.call("getStateDiff", params(vec![json!(hex::encode(&request.hash))], request.commitment()))?;
+            Ok(proto::StateDiffResponse { diff: diff.map(Into::into) })
+        }).map_err(Into::into)
+    }
+
+    async fn get_transaction_status(
+        &self,
+        request: Request<proto::TransactionStatusRequest>,
+    ) -> Result<Response<proto::TransactionStatus>, Status> {
+        self.limited(request, |request| {
+            let status: TransactionStatus = self.call("getTransactionStatus", params(vec![json!(hex::encode(&request.id))], request.commitment()))?;
+            Ok(status.into())
+        }).map_err(Into::into)
+    }
+
+    async fn get_transaction(&self, request: Request<proto::TransactionStatusRequest>) -> Result<Response<proto::TransactionResponse>, Status> {
+        self.limited(request, |request| {
+            let found: Option<FoundTransaction> = self.call("getTransaction", params(vec![json!(hex::encode(&request.id))], request.commitment()))?;
+            let Some(found) = found else { return Ok(proto::TransactionResponse::default()) };
+            let transaction = proto::IncludedTransaction {
+                transaction: found.transaction.to_bytes(),
+                block_hash: decode_hex(&found.block_hash)?,
+                height: found.height,
+                index: found.index,
+            };
+            Ok(proto::TransactionResponse { transaction: Some(transaction) })
+        }).map_err(Into::into)
+    }
+
+    async fn send_transaction(&self, request: Request<proto::SendTransactionRequest>) -> Result<Response<proto::SendTransactionResponse>, Status> {
+        self.limited(request, |request| {
+            let tx = Transaction::from_bytes(&request.transaction).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+            let id = self.send(&request)?;
+            Ok(proto::SendTransactionResponse { id, hash: tx.hash().to_vec() })
+        }).map_err(Into::into)
+    }
+
+    async fn simulate_transaction(&self, request: Request<proto::SimulateTransactionRequest>) -> Result<Response<proto::SimulationResult>, Status> {
+        self.limited(request, |request| {
+            let result: SimulationResult = self.call("simulateTransaction", params(vec![json!(hex::encode(&request.transaction))], request.commitment()))?;
+            Ok(result.into())
+        }).map_err(Into::into)
+    }
+
+    async fn request_airdrop(&self, request: Request<proto::AirdropRequest>) -> Result<Response<proto::SendTransactionResponse>, Status> {
+        self.limited(request, |request| {
+            let id = self.call("requestAirdrop", json!([hex::encode(&request.to), request.amount]))?;
+            Ok(proto::SendTransactionResponse { id, hash: Vec::new() })
+        }).map_err(Into::into)
+    }
+
+    type SubscribeBlocksStream = EventStream<proto::Block>;
+
+    async fn subscribe_blocks(&self, request: Request<proto::SubscribeBlocksRequest>) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        let limit = self.open_stream(&request, request.get_ref().encoded_len())?;
+        let server = self.clone();
+        Ok(Response::new(self.subscribe(limit, move |event| match event {
+            Event::BlockFinalized { hash, .. } => {
+                let block: Option<Block> = server.call("getBlockByHash", json!([hex::encode(hash)])).ok().flatten();
+                block.map(Into::into)
+            }
+            _ => None,
+        })))
+    }
+
+    type SubscribeTransactionsStream = EventStream<proto::AdmittedTransaction>;
+
+    async fn subscribe_transactions(
+        &self,
+        request: Request<proto::SubscribeTransactionsRequest>,
+    ) -> Result<Response<Self::SubscribeTransactionsStream>, Status> {
+        let limit = self.open_stream(&request, request.get_ref().encoded_len())?;
+        Ok(Response::new(self.subscribe(limit, |event| match event {
+            Event::TransactionAdmitted { hash, id } => Some(proto::AdmittedTransaction { id, hash: hash.to_vec() }),
+            _ => None,
+        })))
+    }
+
+    type SubscribeAccountsStream = EventStream<proto::AccountUpdate>;
+
+    async fn subscribe_accounts(&self, request: Request<proto::SubscribeAccountsRequest>) -> Result<Response<Self::SubscribeAccountsStream>, Status> {
+        let limit = self.open_stream(&request, request.get_ref().encoded_len())?;
+        let pubkeys = request.into_inner().pubkeys;
+        Ok(Response::new(self.subscribe(limit, move |event| match event {
+            Event::AccountUpdated { pubkey, height, balance } if pubkeys.is_empty() || pubkeys.iter().any(|key| key[..] == pubkey[..]) => {
+                Some(proto::AccountUpdate { pubkey: pubkey.to_vec(), height, balance })
+            }
+            _ => None,
+        })))
+    }
+
+    type StreamTransactionsStream = EventStream<proto::SubmissionUpdate>;
+
+    async fn stream_transactions(
+        &self,
+        request: Request<Streaming<proto::SendTransactionRequest>>,
+    ) -> Result<Response<Self::StreamTransactionsStream>, Status> {
+        let client = Client::of(&request);
+        let limit = self.open_stream(&request, 0)?;
+        let mut submissions = request.into_inner();
+        let (sender, receiver) = mpsc::channel(EVENT_CAPACITY);
+        let (server, mut events, mut stopping) = (self.clone(), self.events.subscribe(), self.stopping.subscribe());
+        tokio::spawn(async move {
+            // Transactions accepted & not yet included or dropped, with whether each was missing when
+            // last checked
+            let mut pending = vec![];
+            let mut sending = true;
+            while sending || !pending.is_empty() {
+                let updates = tokio::select! {
+                    _ = stopping.wait_for(|stopping| *stopping) => break,
+                    submission = submissions.message(), if sending => match submission {
+                        Ok(Some(submission)) => vec![server.submission(&client, submission, &mut pending)],
+                        Ok(None) => {
+                            sending = false;
+                            continue
+                        }
+                        // The client broke the stream off
+                        Err(_) => break,
+                    },
+                    // A block may have taken or dropped some, & having missed events is no reason to
+                    // give up, since statuses are read afresh
+                    event = events.recv() => match event {
+                        Ok(Event::BlockFinalized { .. }) | Err(RecvError::Lagged(_)) => server.status_changes(&mut pending),
+                        Ok(_) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                };
+                for update in updates {
+                    if let Some((limiter, key)) = &limit {
+                        limiter.charge(key, update.encoded_len() as u64);
+                    }
+                    tokio::select! {
+                        _ = stopping.wait_for(|stopping| *stopping) => return,
+                        sent = sender.send(Ok(update)) => if sent.is_err() { return },
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+// Who's calling, as the rate limiter tells clients apart: their address & the API key in the call's
+// metadata
+#[derive(Debug, Clone)]
+struct Client {
+    addr: Option<SocketAddr>,
+    api_key: Option<String>,
+}
+
+impl Client {
+    fn of<T>(request: &Request<T>) -> Self {
+        let api_key = request.metadata().get(API_KEY_HEADER).and_then(|api_key| api_key.to_str().ok());
+        Self { addr: request.remote_addr(), api_key: api_key.map(str::to_string) }
+    }
+}
+
+// JSON-RPC results with no type of their own
+#[derive(serde::Deserialize)]
+struct AccountProof {
+    height: u64,
+    state_root: String,
+    account: UserAccount,
+    proof: MerkleProof,
+}
+
+#[derive(serde::Deserialize)]
+struct HashedHeader {
+    header: BlockHeader,
+}
+
+#[derive(serde::Deserialize)]
+struct FoundTransaction {
+    transaction: Transaction,
+    block_hash: String,
+    height: u64,
+    index: u64,
+}
+
+// Positional JSON-RPC params, ending with the commitment to read at
+fn params(mut params: Vec<Value>, commitment: proto::Commitment) -> Value {
+    let commitment = match commitment {
+        proto::Commitment::Processed => Commitment::Processed,
+        proto::Commitment::Confirmed => Commitment::Confirmed,
+        proto::Commitment::Finalized => Commitment::Finalized,
+    };
+    params.push(json!({ "commitment": commitment }));
+    Value::Array(params)
+}
+
+impl From<RpcError> for Status {
+    fn from(error: RpcError) -> Self {
+        let code = match error.code {
+            INVALID_PARAMS => Code::InvalidArgument,
+            METHOD_NOT_FOUND => Code::Unimplemented,
+            PRUNED => Code::OutOfRange,
+            RATE_LIMITED => Code::ResourceExhausted,
+            _ => Code::FailedPrecondition,
+        };
+        // Saying when to call again, as JSON-RPC does
+        let retry_after_ms = error.data.as_ref().and_then(|data| data.get("retryAfterMs")).and_then(Value::as_u64);
+        let mut status = Status::new(code, error.message);
+        if let Some(retry_after_ms) = retry_after_ms {
+            status.metadata_mut().insert("retry-after-ms", retry_after_ms.into());
+        }
+        status
+    }
+}
+
+// Hex in a JSON-RPC result, as bytes
+fn decode_hex(hex: &str) -> Result<Vec<u8>, RpcError> {
+    hex::decode(hex).map_err(|_| RpcError::new(SERVER_ERROR, "Unexpected RPC result"))
+}
+
+impl From<UserAccount> for proto::Account {
+    fn from(account: UserAccount) -> Self {
+        Self {
+            public_key: account.public_key.to_vec(),
+            balance: account.balance,
+            nonce: account.nonce,
+            locked: account.locked.iter().map(|vesting| proto::Vesting { amount: vesting.amount, unlock_height: vesting.unlock_height }).collect(),
+            data: account.data,
+            owner: account.owner.to_vec(),
+            frozen: account.frozen,
+        }
+    }
+}
+
+impl From<Block> for proto::Block {
+    fn from(block: Block) -> Self {
+        Self {
+            hash: block.hash.to_vec(),
+            prev_hash: block.header.prev_hash.to_vec(),
+            height: block.header.height,
+            slot: block.header.slot,
+            proposer: block.header.proposer.to_vec(),
+            transactions: block.transactions.iter().map(Transaction::to_bytes).collect(),
+            encoded: block.to_bytes(),
+        }
+    }
+}
+
+impl From<TransactionStatus> for proto::TransactionStatus {
+    fn from(status: TransactionStatus) -> Self {
+        let status = match status {
+            TransactionStatus::Pending => TxStatus::Pending(proto::Empty {}),
+            TransactionStatus::Included { block_hash, height } => TxStatus::Included(Included { block_hash: block_hash.to_vec(), height }),
+            TransactionStatus::Unknown => TxStatus::Unknown(proto::Empty {}),
+        };
+        Self { status: Some(status) }
+    }
+}
+
+impl From<ValidatorAccount> for proto::Validator {
+    fn from(validator: ValidatorAccount) -> Self {
+        Self {
+            public_key: validator.public_key.to_vec(),
+            stake: validator.stake,
+            commission: validator.commission.into(),
+            missed_slots: validator.missed_slots,
+            bls_key: validator.bls_key.map(|key| key.to_vec()),
+            jailed_since: validator.jailed_since,
+        }
+    }
+}
+
+impl From<EpochInfo> for proto::EpochInfo {
+    fn from(info: EpochInfo) -> Self {
+        Self {
+            epoch: info.epoch,
+            slot_index: info.slot_index,
+            slots_in_epoch: info.slots_in_epoch,
+            absolute_slot: info.absolute_slot,
+            block_height: info.block_height,
+        }
+    }
+}
+
+impl From<MerkleProof> for proto::MerkleProof {
+    fn from(proof: MerkleProof) -> Self {
+        Self { index: proof.index, leaf_count: proof.leaf_count, siblings: proof.siblings.iter().map(|sibling| sibling.to_vec()).collect() }
+    }
+}
+
+impl From<BlockHeader> for proto::BlockHeader {
+    fn from(header: BlockHeader) -> Self {
+        Self {
+            hash: header.hash().to_vec(),
+            prev_hash: header.prev_hash.to_vec(),
+            height: header.height,
+            slot: header.slot,
+            timestamp_ms: header.timestamp.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64),
+            tx_root: header.tx_root.to_vec(),
+            state_root: header.state_root.to_vec(),
+            proposer: header.proposer.to_vec(),
+            encoded: borsh::to_vec(&header).expect("Header encoding is infallible"),
+            certificate: header.certificate.map(Into::into),
+        }
+    }
+}
+
+impl From<QuorumCertificate> for proto::QuorumCertificate {
+    fn from(certificate: QuorumCertificate) -> Self {
+        Self {
+            block_hash: certificate.block_hash.to_vec(),
+            slot: certificate.slot,
+            signers: certificate.signers.iter().map(|signer| signer.to_vec()).collect(),
+            signature: certificate.signature.to_vec(),
+        }
+    }
+}
+
+impl From<Checkpoint> for proto::Checkpoint {
+    fn from(checkpoint: Checkpoint) -> Self {
+        Self {
+            block_hash: checkpoint.block_hash.to_vec(),
+            height: checkpoint.height,
+            slot: checkpoint.slot,
+            state_root: checkpoint.state_root.to_vec(),
+            certificate: Some(checkpoint.certificate.into()),
+        }
+    }
+}
+
+impl From<StateDiff> for proto::StateDiff {
+    fn from(diff: StateDiff) -> Self {
+        let changes = |changes: Vec<ValueChange>| {
+            changes.into_iter().map(|change| proto::ValueChange { pubkey: change.pubkey.to_vec(), old: change.old, new: change.new }).collect()
+        };
+        Self { balances: changes(diff.balances), stakes: changes(diff.stakes) }
+    }
+}
+
+impl From<SimulationResult> for proto::SimulationResult {
+    fn from(result: SimulationResult) -> Self {
+        Self { error: result.error, fee: result.fee, compute_units: result.compute_units, changes: Some(result.changes.into()) }
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    structures::{pubkey_from_address, Address, Seckey},
+    wallet::Wallet,
+};
+
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+// scrypt cost parameters, stored alongside the ciphertext so they can be raised later without
+// breaking existing files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self { log_n: 15, r: 8, p: 1 }
+    }
+}
+
+// A secret key encrypted under a passphrase: scrypt stretches the passphrase into an AES-256-GCM key.
+// The public key is kept in the clear (and authenticated as associated data) so a keystore can be
+// identified without unlocking it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub address: Address,
+    pub kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    pub fn encrypt(wallet: &Wallet, passphrase: &str) -> Result<Self, &'static str> {
+        Self::encrypt_with_params(wallet, passphrase, KdfParams::default())
+    }
+
+    pub fn encrypt_with_params(wallet: &Wallet, passphrase: &str, kdf: KdfParams) -> Result<Self, &'static str> {
+
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let cipher = cipher(passphrase, &salt, &kdf)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), aes_gcm::aead::Payload { msg: wallet.secret_key(), aad: &wallet.public_key })
+            .map_err(|_| "Failed to encrypt secret key")?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            address: wallet.address.clone(),
+            kdf,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    // Recover the wallet. A wrong passphrase or a tampered file both fail authentication.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Wallet, &'static str> {
+        if self.version != KEYSTORE_VERSION {
+            return Err("Unsupported keystore version")
+        }
+
+        let public_key = pubkey_from_address(&self.address)?;
+        let salt = hex::decode(&self.salt).map_err(|_| "Keystore salt is not valid hex")?;
+        let nonce = hex::decode(&self.nonce).map_err(|_| "Keystore nonce is not valid hex")?;
+        let ciphertext = hex::decode(&self.ciphertext).map_err(|_| "Keystore ciphertext is not valid hex")?;
+        if nonce.len() != NONCE_LENGTH {
+            return Err("Keystore nonce has the wrong length")
+        }
+
+        let cipher = cipher(passphrase, &salt, &self.kdf)?;
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), aes_gcm::aead::Payload { msg: &ciphertext, aad: &public_key })
+            .map_err(|_| "Incorrect passphrase or corrupted keystore")?;
+
+        let secret_key: Seckey = plaintext.try_into().map_err(|_| "Decrypted secret key has the wrong length")?;
+        let wallet = Wallet::from_secret_key(secret_key)?;
+        if wallet.public_key != public_key {
+            return Err("Decrypted secret key does not match keystore address")
+        }
+        Ok(wallet)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        write_private(path, contents.as_bytes())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+// Unlock the keystore at `path`, or create one holding a fresh wallet if there isn't one yet
+pub fn load_or_create(path: &Path, passphrase: &str) -> io::Result<Wallet> {
+    if path.exists() {
+        return Keystore::load(path)?
+            .decrypt(passphrase)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    let wallet = Wallet::generate();
+    Keystore::encrypt(&wallet, passphrase)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+        .save(path)?;
+    Ok(wallet)
+}
+
+fn cipher(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<Aes256Gcm, &'static str> {
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, 32).map_err(|_| "Invalid scrypt parameters")?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key).map_err(|_| "Key derivation failed")?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+// Keystores are only readable by their owner where the platform supports it
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    file.write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    fs::write(path, contents)
+}
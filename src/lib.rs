@@ -1,9 +1,92 @@
+mod bloom;
+mod bls;
 mod builder;
+mod chain;
+mod checkpoint;
+mod client;
+mod compute;
+mod config;
+mod contract;
 mod db;
+mod discovery;
+mod events;
+mod export;
+mod grpc;
+mod keystore;
+mod light_client;
+mod merkle;
+mod metrics;
+mod mnemonic;
+mod network;
+mod node;
+mod noise;
 mod structures;
 mod pool;
+mod plugin;
+mod program;
+mod pruning;
+mod quic;
+mod rate_limit;
+mod rest;
+mod rewards;
+mod rpc;
+mod scheduler;
+mod scoring;
+mod shred;
+mod snapshot;
+mod storage;
+mod sync;
+mod tower;
+mod validator;
+mod vote;
+mod vrf;
+mod wal;
+mod wallet;
+mod wire;
+#[cfg(test)]
 mod tests;
 
-pub use db::AccountsDB;
+pub use bloom::{AccountBloom, BLOOM_BYTES, BLOOM_HASHES};
+pub use bls::{aggregate as bls_aggregate, verify as bls_verify, verify_aggregate as bls_verify_aggregate, verify_possession as bls_verify_possession, BlsPubkey, BlsSignature};
+pub use builder::BlockBuilder;
+pub use chain::{Blockchain, Commitment};
+pub use checkpoint::Checkpoint;
+pub use client::{ClientError, LitechainClient, SendOutcome, DEFAULT_CLIENT_TIMEOUT, DEFAULT_REBROADCAST_INTERVAL, POLL_INTERVAL};
+pub use compute::{compute_units, next_base_fee, ACCOUNT_UNITS, BASE_FEE_CHANGE_DENOMINATOR, BYTE_UNITS, MAX_TRANSACTION_UNITS, SIGNATURE_UNITS};
+pub use contract::{check_code, contract_address, HOST_CALL_GAS, MAX_GAS, MAX_INPUT_BYTES, WASM_LOADER};
+pub use config::{ChainConfig, GenesisAccount, GenesisConfig, GenesisValidator};
+pub use discovery::{DiscoveryConfig, PeerRecord, MAX_KNOWN_PEERS, MAX_SHARED_PEERS, MDNS_SERVICE};
+pub use db::{account_leaf, verify_account_proof, AccountsDB, BlockUndo, EpochInfo, HistoryError, TransferError};
+pub use events::{Event, EventBus, EVENT_CAPACITY};
+pub use export::{ChainExport, ExportedBlock, EXPORT_FORMAT, EXPORT_VERSION};
+pub use grpc::{proto, GrpcServer};
+pub use keystore::{load_or_create, KdfParams, Keystore};
+pub use light_client::{LightClient, TrustedValidator};
+pub use merkle::{merkle_root, Hash, MerkleProof};
+pub use metrics::{Counter, Gauge, Histogram, Metrics};
+pub use mnemonic::{account_path, derive_account_key, derive_secret_key, generate_mnemonic, seed_from_mnemonic, COIN_TYPE};
+pub use network::{Message, Network, MAX_MESSAGE_SIZE};
+pub use node::{Node, NodeConfig};
+pub use noise::{HANDSHAKE_TIMEOUT, NOISE_PARAMS};
 pub use structures::*;
-pub use pool::{Mempool, MAX_TRANSACTIONS_PER_BLOCK};
\ No newline at end of file
+pub use pool::Mempool;
+pub use plugin::{PluginSet, StatePlugin};
+pub use program::{AccountMeta, AccountProgram, Instruction, Program, ProgramId, ProgramRegistry, StakeProgram, TransferProgram, ACCOUNT_PROGRAM, MAX_INSTRUCTION_ACCOUNTS, MAX_INSTRUCTION_DATA_BYTES, MAX_MESSAGE_INSTRUCTIONS, MAX_MESSAGE_SIGNERS, STAKE_PROGRAM, TRANSFER_PROGRAM};
+pub use pruning::PruningConfig;
+pub use quic::{QuicClient, QuicConfig, QuicServer, MAX_QUIC_TRANSACTION_SIZE, QUIC_ALPN};
+pub use rest::DEFAULT_REST_PAGE;
+pub use rate_limit::{Quota, RateLimitConfig, RateLimitKey, RateLimiter, MAX_TRACKED_CLIENTS};
+pub use rewards::RewardConfig;
+pub use rpc::{RpcError, RpcRequest, RpcResponse, RpcServer, API_KEY_HEADER, DEFAULT_MAX_BLOCK_PAGE, INVALID_PARAMS, MAX_ACCOUNTS_PAGE, MAX_HISTORY_PAGE, METHOD_NOT_FOUND, PARSE_ERROR, PRUNED, RATE_LIMITED, SERVER_ERROR};
+pub use scoring::{Misbehavior, ScoringConfig};
+pub use shred::{Shred, DATA_SHREDS_PER_SET, MAX_PENDING_BLOCKS, SHRED_PAYLOAD_SIZE};
+pub use scheduler::{execute_parallel, execute_parallel_results, execute_sequential, schedule};
+pub use snapshot::Snapshot;
+pub use storage::{compress, decompress, BlockArchive, Codec, FORMAT_VERSION};
+pub use sync::{load_snapshot, MAX_BLOCKS_PER_REQUEST};
+pub use tower::{Lockout, Tower, MAX_LOCKOUT_HISTORY};
+pub use validator::{Validator, ValidatorHandle};
+pub use vote::{is_quorum, total_voting_weight, voting_weight, Quorum, QuorumCertificate, Vote, VoteLock, VotePhase};
+pub use vrf::{leader_input, leader_score, prove as vrf_prove, verify as vrf_verify, VrfProof};
+pub use wal::{WalRecord, WriteAheadLog};
+pub use wallet::Wallet;
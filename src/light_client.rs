@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ed25519_dalek::Signature;
+
+use crate::{
+    bls::BlsPubkey,
+    checkpoint::Checkpoint,
+    config::GenesisConfig,
+    db::{verify_account_proof, AccountsDB},
+    merkle::MerkleProof,
+    structures::{bls_key_from_hex, pubkey_from_address, Block, BlockHeader, Blockhash, Pubkey, Transaction, UserAccount},
+    vote::QuorumCertificate,
+};
+
+// What a light client knows of a validator: the stake its votes count with & the BLS key they're
+// aggregated under, if it has one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrustedValidator {
+    pub stake: u64,
+    pub bls_key: Option<BlsPubkey>,
+}
+
+// Follows a chain from block headers & checkpoints alone, holding neither block bodies nor any account
+// state. It starts from a header it's told to trust & a validator set, takes a header once it extends one
+// already held & its proposer signed it, & counts a header as finalized once a certificate from a quorum
+// of the validators vouches for it. Transactions & accounts are then checked against the headers' roots
+// with Merkle proofs. The validator set is trusted as given, so following it across epochs is up to
+// whoever feeds the client.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    validators: HashMap<Pubkey, TrustedValidator>,
+    // One chain of headers, by height, with their hashes
+    headers: BTreeMap<u64, (Blockhash, BlockHeader)>,
+    // Height of the newest header a quorum vouched for, or the trusted one's
+    finalized: u64,
+    checkpoints: BTreeMap<u64, Checkpoint>,
+}
+
+impl LightClient {
+    pub fn new(trusted: BlockHeader, validators: HashMap<Pubkey, TrustedValidator>) -> Self {
+        let height = trusted.height;
+        Self {
+            validators,
+            headers: BTreeMap::from([(height, (trusted.hash(), trusted))]),
+            finalized: height,
+            checkpoints: BTreeMap::new(),
+        }
+    }
+
+    // A client trusting the genesis block & validators `config` describes
+    pub fn from_genesis(config: &GenesisConfig) -> Result<Self, &'static str> {
+        let genesis = Block::genesis(config, AccountsDB::from_genesis(config)?.state_root());
+        let mut validators = HashMap::new();
+        for validator in &config.validators {
+            let bls_key = validator.bls_key.as_deref().map(bls_key_from_hex).transpose()?;
+            validators.insert(pubkey_from_address(&validator.address)?, TrustedValidator { stake: validator.stake, bls_key });
+        }
+        Ok(Self::new(genesis.header, validators))
+    }
+
+    // Swap in the validator set of a new epoch, as learned from somewhere trusted
+    pub fn set_validators(&mut self, validators: HashMap<Pubkey, TrustedValidator>) {
+        self.validators = validators;
+    }
+
+    pub fn height(&self) -> u64 {
+        *self.headers.keys().next_back().expect("Client always holds its trusted header")
+    }
+
+    pub fn finalized_height(&self) -> u64 {
+        self.finalized
+    }
+
+    pub fn header(&self, height: u64) -> Option<&BlockHeader> {
+        self.headers.get(&height).map(|(_, header)| header)
+    }
+
+    pub fn latest_checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoints.values().next_back()
+    }
+
+    // Take the next header along with its proposer's signature. A header at a height already held
+    // replaces it & everything above it, unless that one is finalized. A certificate the header carries
+    // for its parent finalizes the parent.
+    pub fn add_header(&mut self, header: BlockHeader, signature: &Signature) -> Result<(), &'static str> {
+        let height = header.height;
+        let Some((parent_hash, parent)) = height.checked_sub(1).and_then(|parent| self.headers.get(&parent)) else {
+            return Err("Header does not extend a known header")
+        };
+        if header.prev_hash != *parent_hash || header.slot <= parent.slot {
+            return Err("Header does not extend a known header")
+        }
+        if height <= self.finalized {
+            return Err("Header conflicts with a finalized header")
+        }
+        if !self.validators.contains_key(&header.proposer) {
+            return Err("Header proposer is not a validator")
+        }
+        if !header.verify_proposer(signature) {
+            return Err("Invalid proposer signature")
+        }
+        if let Some(certificate) = &header.certificate {
+            if certificate.block_hash != header.prev_hash || certificate.slot != parent.slot {
+                return Err("Certificate is not for the header's parent")
+            }
+            self.verify_certificate(certificate)?;
+            self.finalized = height - 1;
+        }
+        self.headers.split_off(&height);
+        self.headers.insert(height, (header.hash(), header));
+        Ok(())
+    }
+
+    // Jump to a checkpoint's block, which needn't extend anything held. Headers that don't agree with it
+    // are dropped: everything above it, & everything below it back to the last finalized header unless
+    // the header held just below is its parent, since held headers only chain to each other.
+    pub fn add_checkpoint(&mut self, checkpoint: Checkpoint, header: BlockHeader) -> Result<(), &'static str> {
+        if !checkpoint.matches(&header) {
+            return Err("Header is not the checkpointed block")
+        }
+        if checkpoint.certificate.block_hash != checkpoint.block_hash || checkpoint.certificate.slot != checkpoint.slot {
+            return Err("Certificate is for a different block")
+        }
+        self.verify_certificate(&checkpoint.certificate)?;
+        let height = checkpoint.height;
+        match self.headers.get(&height) {
+            Some((hash, _)) if *hash == checkpoint.block_hash => {}
+            _ if height <= self.finalized => return Err("Checkpoint does not match the finalized headers"),
+            _ => {
+                let parent_held = height.checked_sub(1).and_then(|parent| self.headers.get(&parent)).is_some_and(|(hash, _)| *hash == header.prev_hash);
+                self.headers.split_off(&if parent_held { height } else { self.finalized + 1 });
+                self.headers.insert(height, (checkpoint.block_hash, header));
+            }
+        }
+        self.finalized = self.finalized.max(height);
+        self.checkpoints.insert(height, checkpoint);
+        Ok(())
+    }
+
+    // Whether `tx` is in the block at `height`, by its inclusion proof
+    pub fn verify_transaction(&self, height: u64, tx: &Transaction, proof: &MerkleProof) -> bool {
+        self.header(height).is_some_and(|header| header.verify_transaction(tx, proof))
+    }
+
+    // Whether `account` is as it stood in the state the block at `height` executes on top of, by a proof
+    // from `AccountsDB::get_account_with_proof`
+    pub fn verify_account(&self, height: u64, account: &UserAccount, proof: &MerkleProof) -> bool {
+        self.header(height).is_some_and(|header| verify_account_proof(account, proof, &header.state_root))
+    }
+
+    // Check a certificate's signature & quorum against the trusted validators, weighted the way
+    // `vote::voting_weight` weighs them
+    fn verify_certificate(&self, certificate: &QuorumCertificate) -> Result<u64, &'static str> {
+        let total_stake = self.validators.values().map(|validator| validator.stake).fold(0u64, u64::saturating_add);
+        let weight = |validator: &TrustedValidator| if total_stake == 0 { 1 } else { validator.stake };
+        let total_weight = if total_stake == 0 { self.validators.len() as u64 } else { total_stake };
+        certificate.verify_with(|signer| self.validators.get(signer).and_then(|validator| Some((validator.bls_key?, weight(validator)))), total_weight)
+    }
+}
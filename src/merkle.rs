@@ -0,0 +1,94 @@
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+// Leaves & interior nodes are hashed with distinct prefixes, so a node can't be passed off as a leaf
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+pub fn hash_leaf(data: &[u8]) -> Hash {
+    Sha256::new().chain_update([LEAF_PREFIX]).chain_update(data).finalize().into()
+}
+
+fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new().chain_update([NODE_PREFIX]).chain_update(left).chain_update(right).finalize().into()
+}
+
+// The next level up. A trailing node without a sibling is carried up as is rather than paired with
+// itself, so no two different leaf lists share a root.
+fn parent_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_node(left, right),
+            [lone] => *lone,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+// Root over already-hashed leaves. The root of no leaves is all zeroes.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0; 32]
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+    level[0]
+}
+
+// Evidence that a leaf sits at `index` in a tree of `leaf_count` leaves: the sibling at each level
+// where the path has one, from the bottom up.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct MerkleProof {
+    pub index: u64,
+    pub leaf_count: u64,
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    pub fn generate(leaves: &[Hash], index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None
+        }
+
+        let mut siblings = vec![];
+        let mut level = leaves.to_vec();
+        let mut position = index;
+        while level.len() > 1 {
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(*sibling);
+            }
+            level = parent_level(&level);
+            position /= 2;
+        }
+
+        Some(Self { index: index as u64, leaf_count: leaves.len() as u64, siblings })
+    }
+
+    // Whether `leaf` hashes up to `root` along this proof's path
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        if self.index >= self.leaf_count {
+            return false
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut hash = *leaf;
+        let mut position = self.index;
+        let mut width = self.leaf_count;
+        while width > 1 {
+            // The last node of an odd level has no sibling & is carried up unchanged
+            if position ^ 1 < width {
+                let Some(sibling) = siblings.next() else { return false };
+                hash = if position & 1 == 0 { hash_node(&hash, sibling) } else { hash_node(sibling, &hash) };
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+
+        siblings.next().is_none() && hash == *root
+    }
+}
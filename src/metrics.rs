@@ -0,0 +1,120 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+// Upper bounds, in seconds, of the buckets timings are sorted into
+const TIMING_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, amount: u64) {
+        self.0.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Timings counted into `TIMING_BUCKETS`, each bucket also counting everything faster than it
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; TIMING_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bound, bucket) in TIMING_BUCKETS.iter().zip(&self.buckets) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+// Everything a node reports about itself, shared by the mempool, the block builder & the validator loop.
+// Rates such as transactions per second are left to the scraper, from the counters.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub mempool_size: Gauge,
+    pub transactions_received: Counter,
+    pub transactions_finalized: Counter,
+    pub blocks_built: Counter,
+    pub blocks_finalized: Counter,
+    pub votes: Counter,
+    // Slots whose leader timed out, skipped over by blocks finalized here
+    pub slots_skipped: Counter,
+    // From starting to build a block to having one ready to propose
+    pub block_build_time: Histogram,
+    // From proposing a block to it being finalized
+    pub finalization_latency: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        gauge(&mut out, "litechain_mempool_size", "Transactions waiting in the mempool", &self.mempool_size);
+        counter(&mut out, "litechain_transactions_received_total", "Transactions admitted to the mempool", &self.transactions_received);
+        counter(&mut out, "litechain_transactions_finalized_total", "Transactions in finalized blocks", &self.transactions_finalized);
+        counter(&mut out, "litechain_blocks_built_total", "Blocks built by this node", &self.blocks_built);
+        counter(&mut out, "litechain_blocks_finalized_total", "Blocks finalized by this node", &self.blocks_finalized);
+        counter(&mut out, "litechain_votes_total", "Votes in quorums that finalized blocks", &self.votes);
+        counter(&mut out, "litechain_slots_skipped_total", "Leader slots skipped after timing out", &self.slots_skipped);
+        histogram(&mut out, "litechain_block_build_seconds", "Time spent building a block", &self.block_build_time);
+        histogram(&mut out, "litechain_finalization_seconds", "Time from proposing a block to finalizing it", &self.finalization_latency);
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}\n", counter.get());
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, gauge: &Gauge) {
+    let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {}\n", gauge.get());
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} histogram\n");
+    for (bound, bucket) in TIMING_BUCKETS.iter().zip(&histogram.buckets) {
+        let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {}", bucket.load(Ordering::Relaxed));
+    }
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", histogram.count());
+    let _ = writeln!(out, "{name}_sum {}", histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6);
+    let _ = writeln!(out, "{name}_count {}", histogram.count());
+}
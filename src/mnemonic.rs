@@ -0,0 +1,82 @@
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha512;
+
+use crate::structures::Seckey;
+
+// Keys are derived along m/44'/501'/{account}'/0', the same layout Solana wallets use
+pub const COIN_TYPE: u32 = 501;
+
+const HARDENED_OFFSET: u32 = 1 << 31;
+const ED25519_CURVE_SEED: &[u8] = b"ed25519 seed";
+
+// A fresh English mnemonic of 12 or 24 words
+pub fn generate_mnemonic(word_count: usize) -> Result<String, &'static str> {
+    let mut entropy = match word_count {
+        12 => vec![0u8; 16],
+        24 => vec![0u8; 32],
+        _ => return Err("Mnemonics must be 12 or 24 words"),
+    };
+    OsRng.fill_bytes(&mut entropy);
+
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|_| "Failed to encode mnemonic")?;
+    Ok(mnemonic.to_string())
+}
+
+// BIP39 seed for a mnemonic & optional passphrase. The mnemonic's checksum is verified.
+pub fn seed_from_mnemonic(phrase: &str, passphrase: &str) -> Result<[u8; 64], &'static str> {
+    let mnemonic = Mnemonic::parse(phrase).map_err(|_| "Invalid mnemonic")?;
+    Ok(mnemonic.to_seed(passphrase))
+}
+
+pub fn account_path(account: u32) -> String {
+    format!("m/44'/{}'/{}'/0'", COIN_TYPE, account)
+}
+
+// The secret key for account `account` of a mnemonic
+pub fn derive_account_key(phrase: &str, passphrase: &str, account: u32) -> Result<Seckey, &'static str> {
+    let seed = seed_from_mnemonic(phrase, passphrase)?;
+    derive_secret_key(&seed, &account_path(account))
+}
+
+// SLIP-0010 ed25519 derivation. Only hardened indices exist for ed25519, so every path segment must
+// end with a `'`.
+pub fn derive_secret_key(seed: &[u8], path: &str) -> Result<Seckey, &'static str> {
+    let mut segments = path.split('/');
+    if segments.next() != Some("m") {
+        return Err("Derivation path must start at m")
+    }
+
+    let (mut key, mut chain_code) = hmac_sha512(ED25519_CURVE_SEED, &[seed]);
+    for segment in segments {
+        let index = segment
+            .strip_suffix('\'')
+            .ok_or("Only hardened derivation is supported for ed25519")?
+            .parse::<u32>()
+            .map_err(|_| "Invalid derivation path index")?;
+        if index >= HARDENED_OFFSET {
+            return Err("Derivation path index out of range")
+        }
+
+        let hardened = (index + HARDENED_OFFSET).to_be_bytes();
+        (key, chain_code) = hmac_sha512(&chain_code, &[&[0], &key, &hardened]);
+    }
+
+    Ok(key)
+}
+
+// HMAC-SHA512 split into its left (key) & right (chain code) halves
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    let output = mac.finalize().into_bytes();
+
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
@@ -0,0 +1,655 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    io::{self, Error, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{
+    builder::BlockBuilder,
+    chain::Blockchain,
+    db::AccountsDB,
+    discovery::{self, DiscoveryConfig, PeerRecord, PeerTable, Source, MAX_SHARED_PEERS},
+    noise::{self, Sender, HANDSHAKE_TIMEOUT},
+    pool::Mempool,
+    scoring::{Misbehavior, PeerScores, ScoringConfig},
+    shred::{Shred, ShredAssembler, SHRED_PAYLOAD_SIZE},
+    snapshot::Snapshot,
+    structures::{Block, Blockhash, Pubkey, Transaction, TransactionSign},
+    sync::{self, MAX_BLOCKS_PER_REQUEST},
+    wallet::Wallet,
+};
+
+// Frames larger than this are treated as a misbehaving peer and the connection is dropped
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+// Most connections peers may have open to us at once, handshakes included. Any past it are closed as
+// soon as they're accepted.
+pub const MAX_INBOUND_PEERS: usize = 64;
+// Most gossip ids remembered. Past it the oldest are forgotten, so a message that old coming round
+// again is handled twice, which the mempool & chain turn away anyway.
+pub const MAX_SEEN_MESSAGES: usize = 65_536;
+
+// Everything nodes say to each other, borsh-encoded and sent encrypted, see `noise`
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub enum Message {
+    // Gossiped to every peer
+    Transaction(Box<Transaction>),
+    Block(Box<Block>),
+    // Point-to-point sync protocol
+    Status { height: u64 },
+    GetBlocks { start: u64, end: u64 },
+    Blocks(Vec<Block>),
+    // Fast sync: the state at the peer's tip, fetched instead of replaying blocks from genesis
+    GetSnapshot,
+    Snapshot(Box<Snapshot>),
+    // Discovery: the port the sender takes connections on, sent on connecting
+    Listening { port: u16 },
+    GetPeers,
+    Peers(Vec<PeerRecord>),
+    // Liveness checks. Anything a peer sends shows it's alive, these just make sure it sends something.
+    Ping,
+    Pong,
+    // Gossiped in place of `Block` for blocks too large for a single shred, see `shred`
+    Shred(Box<Shred>),
+}
+
+impl Message {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        borsh::to_vec(self).expect("Message encoding is infallible")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        borsh::from_slice(bytes).map_err(|_| "Invalid message encoding")
+    }
+
+    // Content hash used to avoid re-gossiping messages we've already handled
+    pub fn id(&self) -> Blockhash {
+        let mut id = [0u8; 32];
+        id.copy_from_slice(Sha256::digest(self.to_bytes()).as_slice());
+        id
+    }
+}
+
+// A gossip node: accepts peer connections, admits gossiped transactions into the local mempool,
+// applies gossiped blocks to the local chain & db, relays anything new to every other peer,
+// and catches up from peers whose tip is ahead of ours. Every connection starts with a Noise handshake
+// in which both sides prove their identity key, & everything after it is encrypted & authenticated.
+#[derive(Clone)]
+pub struct Network {
+    pub local_addr: SocketAddr,
+    // Identity proven to peers on connecting
+    wallet: Wallet,
+    mempool: Arc<RwLock<Mempool>>,
+    db: Arc<RwLock<AccountsDB>>,
+    chain: Arc<RwLock<Blockchain>>,
+    peers: Arc<DashMap<SocketAddr, Sender>>,
+    peer_info: Arc<DashMap<SocketAddr, PeerInfo>>,
+    // Nodes we could connect to, see `start_discovery`
+    known_peers: Arc<PeerTable>,
+    // Misbehavior of peers & who's banned for it
+    scores: Arc<PeerScores>,
+    // Blocks partway through arriving as shreds
+    shreds: Arc<ShredAssembler>,
+    peer_heights: Arc<DashMap<SocketAddr, u64>>,
+    seen: Arc<Mutex<Seen>>,
+    // Connections peers have open to us, see `MAX_INBOUND_PEERS`
+    inbound: Arc<AtomicUsize>,
+    // Start from a peer's snapshot rather than from genesis, set until we've synced once
+    fast_sync: Arc<AtomicBool>,
+    // Snapshot waiting on the block after it to check its state against
+    pending_snapshot: Arc<Mutex<Option<Snapshot>>>,
+    // Peers we've asked for a snapshot & not yet heard one from. Snapshots nobody asked for are ignored.
+    snapshot_requests: Arc<DashMap<SocketAddr, ()>>,
+}
+
+// What we know of a connected peer
+#[derive(Debug, Clone, Copy)]
+struct PeerInfo {
+    // Identity key it proved
+    identity: Pubkey,
+    // Where it takes connections, once it's said
+    listen_addr: Option<SocketAddr>,
+    last_seen: Instant,
+    // Start of the current second & messages received in it, to catch spam
+    window: (Instant, u32),
+}
+
+// Ids of gossip lately handled, kept to the newest `MAX_SEEN_MESSAGES`
+#[derive(Debug, Default)]
+pub(crate) struct Seen {
+    ids: HashSet<Blockhash>,
+    // Oldest first
+    order: VecDeque<Blockhash>,
+}
+
+impl Seen {
+    // Remember `id`, returning whether it's new
+    pub(crate) fn insert(&mut self, id: Blockhash) -> bool {
+        if !self.ids.insert(id) {
+            return false
+        }
+        if self.order.len() >= MAX_SEEN_MESSAGES {
+            let oldest = self.order.pop_front().expect("Seen ids aren't empty");
+            self.ids.remove(&oldest);
+        }
+        self.order.push_back(id);
+        true
+    }
+}
+
+// One of the `MAX_INBOUND_PEERS` inbound connections, given back when dropped
+struct InboundSlot(Arc<AtomicUsize>);
+
+impl InboundSlot {
+    fn take(inbound: &Arc<AtomicUsize>) -> Option<Self> {
+        inbound
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| (open < MAX_INBOUND_PEERS).then_some(open + 1))
+            .ok()
+            .map(|_| InboundSlot(Arc::clone(inbound)))
+    }
+}
+
+impl Drop for InboundSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Network")
+            .field("local_addr", &self.local_addr)
+            .field("identity", &self.wallet.address)
+            .field("peers", &self.peer_addrs())
+            .finish()
+    }
+}
+
+impl Network {
+    // Bind under a throwaway identity
+    pub fn bind(addr: &str, builder: &BlockBuilder) -> io::Result<Self> {
+        Self::bind_with_wallet(addr, builder, Wallet::generate())
+    }
+
+    pub fn bind_with_wallet(addr: &str, builder: &BlockBuilder, wallet: Wallet) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+
+        let network = Self {
+            local_addr: listener.local_addr()?,
+            wallet,
+            mempool: Arc::clone(&builder.mempool),
+            db: Arc::clone(&builder.db),
+            chain: Arc::clone(&builder.chain),
+            peers: Arc::new(DashMap::new()),
+            peer_info: Arc::new(DashMap::new()),
+            known_peers: Arc::default(),
+            scores: Arc::default(),
+            shreds: Arc::default(),
+            peer_heights: Arc::new(DashMap::new()),
+            seen: Arc::default(),
+            inbound: Arc::default(),
+            fast_sync: Arc::new(AtomicBool::new(false)),
+            pending_snapshot: Arc::new(Mutex::new(None)),
+            snapshot_requests: Arc::new(DashMap::new()),
+        };
+
+        let acceptor = network.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Some(slot) = InboundSlot::take(&acceptor.inbound) else { continue };
+                // Handshake off the accept loop, so a peer that stalls in it doesn't hold up the rest
+                let acceptor = acceptor.clone();
+                thread::spawn(move || {
+                    if let Err(e) = acceptor.add_peer(stream, None, None, Some(slot)) {
+                        warn!(error = ?e, "Failed to accept peer");
+                    }
+                });
+            }
+        });
+
+        Ok(network)
+    }
+
+    // Connect to whoever is at `addr`, whatever identity it proves
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        self.add_peer(TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?, Some(addr), None, None)
+    }
+
+    // Connect to `addr` only if it proves it holds `identity`
+    pub fn connect_to(&self, addr: SocketAddr, identity: &Pubkey) -> io::Result<()> {
+        self.add_peer(TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?, Some(addr), Some(identity), None)
+    }
+
+    // Keep up to `config.target_peers` connections from here on: every interval, drop peers that have
+    // gone quiet, ping the rest & ask them for the nodes they know, then dial nodes from the peer table,
+    // & from `bootstrap`, until there are enough. Addresses other peers pass on aren't checked, but a
+    // node is only taken as a peer if it proves the identity it was listed under, & hearsay never
+    // replaces an address a node gave us itself or that we've reached it at.
+    pub fn start_discovery(&self, bootstrap: Vec<SocketAddr>, config: DiscoveryConfig) -> io::Result<()> {
+        if config.mdns {
+            discovery::start_mdns(self.identity(), self.local_addr.port(), Arc::clone(&self.known_peers))?;
+        }
+        let network = self.clone();
+        thread::spawn(move || loop {
+            network.discover(&bootstrap, &config);
+            thread::sleep(Duration::from_millis(config.interval_ms));
+        });
+        Ok(())
+    }
+
+    fn discover(&self, bootstrap: &[SocketAddr], config: &DiscoveryConfig) {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(config.peer_timeout_ms);
+        let silent: Vec<SocketAddr> = self.peer_info
+            .iter()
+            .filter(|info| now.saturating_duration_since(info.last_seen) > timeout)
+            .map(|info| *info.key())
+            .collect();
+        for addr in silent {
+            warn!(peer = %addr, "Dropping unresponsive peer");
+            self.disconnect(&addr);
+        }
+        self.broadcast(&Message::Ping, None);
+        self.broadcast(&Message::GetPeers, None);
+
+        let connected: HashSet<Pubkey> = self.peer_info.iter().map(|info| info.identity).collect();
+        let listening: HashSet<SocketAddr> = self.peer_info.iter().filter_map(|info| info.listen_addr).collect();
+        let mut wanted = config.target_peers.saturating_sub(self.peers.len());
+        for (identity, addr) in self.known_peers.due(now) {
+            if wanted == 0 {
+                return
+            }
+            if connected.contains(&identity) || identity == self.identity() || self.scores.is_banned(Some(&identity), Some(addr.ip())) {
+                continue
+            }
+            let dialed = self.connect_to(addr, &identity);
+            self.known_peers.dialed(&identity, dialed.is_ok(), config);
+            wanted -= dialed.is_ok() as usize;
+        }
+        for addr in bootstrap.iter().filter(|addr| !listening.contains(addr)) {
+            if wanted == 0 {
+                return
+            }
+            wanted -= self.connect(*addr).is_ok() as usize;
+        }
+    }
+
+    // Change the policy peers are scored & banned by
+    pub fn set_scoring(&self, config: ScoringConfig) {
+        self.scores.set_config(config);
+    }
+
+    // Misbehavior score of the peer with `identity`, as decayed so far
+    pub fn peer_score(&self, identity: &Pubkey) -> f64 {
+        self.scores.score(identity)
+    }
+
+    pub fn is_banned(&self, identity: &Pubkey) -> bool {
+        self.scores.is_banned(Some(identity), None)
+    }
+
+    // Charge the peer at `origin` for `misbehavior`, & drop it if that gets it banned
+    fn report(&self, origin: SocketAddr, misbehavior: Misbehavior) {
+        let Some(identity) = self.peer_identity(&origin) else { return };
+        warn!(peer = %origin, ?misbehavior, "Peer misbehaved");
+        if !self.scores.report(&identity, origin.ip(), misbehavior) {
+            return
+        }
+        warn!(peer = %origin, identity = %hex::encode(identity), "Banning peer");
+        let ban_ip = self.scores.config().ban_ip;
+        let banned: Vec<SocketAddr> = self.peer_info
+            .iter()
+            .filter(|info| info.identity == identity || (ban_ip && info.key().ip() == origin.ip()))
+            .map(|info| *info.key())
+            .collect();
+        for addr in banned {
+            self.disconnect(&addr);
+        }
+    }
+
+    fn disconnect(&self, addr: &SocketAddr) {
+        if let Some((_, sender)) = self.peers.remove(addr) {
+            sender.shutdown();
+        }
+        self.peer_info.remove(addr);
+    }
+
+    // Nodes in the peer table, connected or not
+    pub fn known_peers(&self) -> Vec<PeerRecord> {
+        self.known_peers.records(usize::MAX)
+    }
+
+    pub fn identity(&self) -> Pubkey {
+        self.wallet.public_key
+    }
+
+    // Identity key the peer at `addr` proved on connecting
+    pub fn peer_identity(&self, addr: &SocketAddr) -> Option<Pubkey> {
+        self.peer_info.get(addr).map(|info| info.identity)
+    }
+
+    // Sync from a peer's snapshot if we're still at genesis. The snapshot is only taken once a quorum of
+    // the validators we start with certifies the block after it. Set before connecting to peers.
+    pub fn enable_fast_sync(&self) {
+        self.fast_sync.store(true, Ordering::SeqCst);
+    }
+
+    pub fn peer_addrs(&self) -> Vec<SocketAddr> {
+        self.peers.iter().map(|entry| *entry.key()).collect()
+    }
+
+    // Highest tip height any peer has announced to us
+    pub fn best_peer_height(&self) -> Option<u64> {
+        self.peer_heights.iter().map(|entry| *entry.value()).max()
+    }
+
+    pub fn is_synced(&self) -> bool {
+        let local_height = self.chain.read().unwrap().height();
+        self.best_peer_height().is_none_or(|peer_height| peer_height <= local_height)
+    }
+
+    // Admit a locally submitted transaction & gossip it to every peer
+    pub fn send_transaction(&self, tx: Transaction) -> Result<u64, &'static str> {
+        let id = self.mempool.read().unwrap().send_transaction(tx.clone())?;
+        self.gossip_transaction(tx);
+        Ok(id)
+    }
+
+    // `send_transaction`, skipping the mempool's preflight check
+    pub fn send_raw_transaction(&self, tx: Transaction) -> Result<u64, &'static str> {
+        let id = self.mempool.read().unwrap().send_raw_transaction(tx.clone())?;
+        self.gossip_transaction(tx);
+        Ok(id)
+    }
+
+    fn gossip_transaction(&self, tx: Transaction) {
+        let message = Message::Transaction(Box::new(tx));
+        self.seen.lock().unwrap().insert(message.id());
+        self.broadcast(&message, None);
+    }
+
+    // Gossip a block that has already been finalized locally
+    // Blocks that fit in one shred go out whole, larger ones as shreds peers relay as they arrive
+    pub fn broadcast_block(&self, block: &Block) {
+        let messages = if block.size as usize <= SHRED_PAYLOAD_SIZE {
+            vec![Message::Block(Box::new(block.clone()))]
+        } else {
+            Shred::split(block, &self.wallet).into_iter().map(|shred| Message::Shred(Box::new(shred))).collect()
+        };
+        for message in messages {
+            self.seen.lock().unwrap().insert(message.id());
+            self.broadcast(&message, None);
+        }
+    }
+
+    fn broadcast(&self, message: &Message, except: Option<SocketAddr>) {
+        let bytes = message.to_bytes();
+
+        // Peers that fail a write are assumed gone and dropped from the table
+        self.peers.retain(|addr, sender| Some(*addr) == except || sender.send(&bytes).is_ok());
+    }
+
+    fn send_to(&self, addr: SocketAddr, message: &Message) {
+        let failed = match self.peers.get_mut(&addr) {
+            Some(mut sender) => sender.value_mut().send(&message.to_bytes()).is_err(),
+            None => false,
+        };
+
+        if failed {
+            self.peers.remove(&addr);
+        }
+    }
+
+    // Handshake over `stream`, dialed to `dialed` or accepted if that's None, & start reading from it
+    // Inbound connections hold `slot` until they close
+    fn add_peer(&self, stream: TcpStream, dialed: Option<SocketAddr>, expected: Option<&Pubkey>, slot: Option<InboundSlot>) -> io::Result<()> {
+        let addr = stream.peer_addr()?;
+        if self.scores.is_banned(None, Some(addr.ip())) {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Peer is banned"))
+        }
+        let (identity, sender, mut receiver) = noise::handshake(stream, &self.wallet, dialed.is_some())?;
+        if expected.is_some_and(|expected| *expected != identity) {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Peer proved a different identity"))
+        }
+        if self.scores.is_banned(Some(&identity), None) {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Peer is banned"))
+        }
+        if identity == self.identity() {
+            return Err(Error::new(ErrorKind::AddrInUse, "Connected to ourselves"))
+        }
+        if let Some(dialed) = dialed {
+            self.known_peers.insert(identity, dialed, Source::Dialed);
+        }
+        let now = Instant::now();
+        self.peer_info.insert(addr, PeerInfo { identity, listen_addr: dialed, last_seen: now, window: (now, 0) });
+        self.peers.insert(addr, sender);
+
+        // Both sides announce their tip on connect so whoever is behind can start syncing, & where
+        // they listen so the other can pass it on
+        let height = self.chain.read().unwrap().height();
+        self.send_to(addr, &Message::Status { height });
+        self.send_to(addr, &Message::Listening { port: self.local_addr.port() });
+        self.send_to(addr, &Message::GetPeers);
+
+        let network = self.clone();
+        thread::spawn(move || {
+            while let Ok(bytes) = receiver.receive() {
+                if network.over_rate(&addr) {
+                    network.report(addr, Misbehavior::Spam);
+                }
+                match Message::from_bytes(&bytes) {
+                    Ok(message) => network.handle_message(message, addr),
+                    Err(_) => network.report(addr, Misbehavior::MalformedMessage),
+                }
+            }
+            network.peers.remove(&addr);
+            network.peer_info.remove(&addr);
+            network.peer_heights.remove(&addr);
+            network.snapshot_requests.remove(&addr);
+            drop(slot);
+        });
+
+        Ok(())
+    }
+
+    // Note a message from `addr`, & whether it's gone past the rate peers may send at
+    fn over_rate(&self, addr: &SocketAddr) -> bool {
+        let Some(mut info) = self.peer_info.get_mut(addr) else { return false };
+        let now = Instant::now();
+        info.last_seen = now;
+        if now.saturating_duration_since(info.window.0) >= Duration::from_secs(1) {
+            info.window = (now, 0);
+        }
+        info.window.1 += 1;
+        info.window.1 > self.scores.config().max_messages_per_second
+    }
+
+    fn handle_message(&self, message: Message, origin: SocketAddr) {
+        match message {
+            Message::Transaction(_) | Message::Block(_) | Message::Shred(_) => self.handle_gossip(message, origin),
+            Message::Status { height } => {
+                self.peer_heights.insert(origin, height);
+                let fresh = self.chain.read().unwrap().height() == 0;
+                if self.fast_sync.load(Ordering::SeqCst) && fresh && height > 0 {
+                    self.snapshot_requests.insert(origin, ());
+                    self.send_to(origin, &Message::GetSnapshot);
+                } else {
+                    self.request_missing_blocks(origin);
+                }
+            }
+            Message::GetBlocks { start, end } => {
+                let end = end.min(start.saturating_add(MAX_BLOCKS_PER_REQUEST - 1));
+                // Pruned blocks can't be served, & a batch that skips them wouldn't apply
+                let chain = self.chain.read().unwrap();
+                let blocks = if chain.ensure_unpruned(start).is_ok() { chain.range(start, end) } else { vec![] };
+                drop(chain);
+                self.send_to(origin, &Message::Blocks(blocks));
+            }
+            Message::GetSnapshot => {
+                let db = self.db.read().unwrap();
+                let snapshot = Snapshot::capture(&self.chain.read().unwrap(), &db);
+                drop(db);
+                self.send_to(origin, &Message::Snapshot(Box::new(snapshot)));
+            }
+            // Only as the answer to our own request, & only the first, so nobody can swap out the one pending
+            Message::Snapshot(snapshot) => {
+                if self.snapshot_requests.remove(&origin).is_none() {
+                    return
+                }
+                // The snapshot can only be trusted once we have the block after it & the one certifying
+                // that, so fetch those next
+                let local_height = self.chain.read().unwrap().height();
+                let mut pending = self.pending_snapshot.lock().unwrap();
+                if self.fast_sync.load(Ordering::SeqCst) && pending.is_none() && snapshot.height() > local_height {
+                    let start = snapshot.height() + 1;
+                    *pending = Some(*snapshot);
+                    drop(pending);
+                    self.send_to(origin, &Message::GetBlocks { start, end: start + MAX_BLOCKS_PER_REQUEST - 1 });
+                }
+            }
+            Message::Blocks(blocks) => {
+                self.load_pending_snapshot(&blocks, origin);
+                match sync::apply_blocks(&self.chain, &self.db, &self.mempool, &blocks) {
+                    Ok(applied) if applied > 0 => self.request_missing_blocks(origin),
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!(peer = %origin, error = e, "Failed to sync blocks");
+                        if is_invalid_block(e) {
+                            self.report(origin, Misbehavior::InvalidBlock);
+                        }
+                    }
+                }
+            }
+            Message::Listening { port } => {
+                let listen_addr = SocketAddr::new(origin.ip(), port);
+                let Some(mut info) = self.peer_info.get_mut(&origin) else { return };
+                info.listen_addr = Some(listen_addr);
+                let identity = info.identity;
+                drop(info);
+                self.known_peers.insert(identity, listen_addr, Source::Announced);
+            }
+            Message::GetPeers => self.send_to(origin, &Message::Peers(self.known_peers.records(MAX_SHARED_PEERS))),
+            Message::Peers(records) => {
+                for record in records.into_iter().take(MAX_SHARED_PEERS) {
+                    let Ok(addr) = record.addr.parse::<SocketAddr>() else { continue };
+                    if record.identity != self.identity() && !addr.ip().is_unspecified() {
+                        self.known_peers.insert(record.identity, addr, Source::Hearsay);
+                    }
+                }
+            }
+            Message::Ping => self.send_to(origin, &Message::Pong),
+            Message::Pong => {}
+        }
+    }
+
+    fn handle_gossip(&self, message: Message, origin: SocketAddr) {
+        if !self.seen.lock().unwrap().insert(message.id()) {
+            return
+        }
+
+        let result = match &message {
+            Message::Transaction(tx) if !tx.verify_signatures() => {
+                self.report(origin, Misbehavior::BadSignature);
+                Err("Invalid signature")
+            }
+            Message::Transaction(tx) => self.mempool.read().unwrap().send_transaction((**tx).clone()).map(|_| ()),
+            Message::Block(block) => self.receive_block(block, origin),
+            // Shreds of a block already rebuilt aren't needed, by us or by peers we relayed enough to
+            Message::Shred(shred) if self.shreds.is_reassembled(&shred.block_hash) => return,
+            // Relayed as soon as it checks out, except the one completing a block, which waits on the block
+            Message::Shred(shred) => match shred.verify() {
+                Err(e) => {
+                    self.report(origin, Misbehavior::MalformedMessage);
+                    Err(e)
+                }
+                // Signed by whoever shreds it, so it only takes a validator's key to be kept, unless there
+                // are none yet. A relay can't know who proposed, so it isn't charged for one that isn't.
+                Ok(()) if !self.is_shred_signer(&shred.signer) => Err("Shred signer is not a validator"),
+                // A verified shred that doesn't rebuild came from its signer, not the relay
+                Ok(()) => match self.shreds.insert((**shred).clone()) {
+                    Ok(Some(block)) => self.receive_block(&block, origin),
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                },
+            },
+            _ => Ok(()),
+        };
+
+        match result {
+            Ok(()) => self.broadcast(&message, Some(origin)),
+            Err(e) => warn!(peer = %origin, error = e, "Dropping message"),
+        }
+    }
+
+    // Whether shreds signed by `signer` are kept, which takes a validator once there are any
+    fn is_shred_signer(&self, signer: &Pubkey) -> bool {
+        let db = self.db.read().unwrap();
+        db.validator_count() == 0 || db.is_validator(signer)
+    }
+
+    // Apply a gossiped block if it extends our tip, otherwise sync up to it from the peer
+    fn receive_block(&self, block: &Block, origin: SocketAddr) -> Result<(), &'static str> {
+        let chain_lock = self.chain.read().unwrap();
+        let extends_tip = block.prev_hash() == chain_lock.tip().hash;
+        let local_height = chain_lock.height();
+        drop(chain_lock);
+
+        if extends_tip {
+            let result = sync::apply_block(&self.chain, &self.db, &self.mempool, block);
+            if result.is_err_and(is_invalid_block) {
+                self.report(origin, Misbehavior::InvalidBlock);
+            }
+            result
+        } else {
+            // We're missing the parent, ask the peer for everything after our tip (it clamps to its own)
+            let start = local_height + 1;
+            self.send_to(origin, &Message::GetBlocks { start, end: local_height + MAX_BLOCKS_PER_REQUEST });
+            Err("Block parent unknown, syncing")
+        }
+    }
+
+    // If `blocks` start right after the pending snapshot, check it against the first one, as certified by
+    // the second, & jump to it. A snapshot that doesn't check out is dropped & we sync from genesis instead.
+    fn load_pending_snapshot(&self, blocks: &[Block], origin: SocketAddr) {
+        let mut pending = self.pending_snapshot.lock().unwrap();
+        let Some(snapshot) = pending.as_ref() else { return };
+        let Some(child) = blocks.first().filter(|block| block.prev_hash() == snapshot.block.hash) else { return };
+
+        let result = match blocks.get(1).and_then(|block| block.header.certificate.as_ref()) {
+            Some(certificate) => sync::load_snapshot(&self.chain, &self.db, snapshot, child, certificate),
+            None => Err("No certificate for the block after the snapshot"),
+        };
+        *pending = None;
+        self.fast_sync.store(false, Ordering::SeqCst);
+        drop(pending);
+
+        if let Err(e) = result {
+            warn!(peer = %origin, error = e, "Rejecting snapshot");
+            self.request_missing_blocks(origin);
+        }
+    }
+
+    fn request_missing_blocks(&self, peer: SocketAddr) {
+        let peer_height = match self.peer_heights.get(&peer) {
+            Some(height) => *height,
+            None => return,
+        };
+
+        let range = sync::missing_range(&self.chain.read().unwrap(), peer_height);
+        if let Some((start, end)) = range {
+            self.send_to(peer, &Message::GetBlocks { start, end });
+        }
+    }
+}
+
+// Whether a block was refused for being invalid, rather than for arriving twice or before its parent
+fn is_invalid_block(error: &str) -> bool {
+    !matches!(error, "Block already in chain" | "Block parent unknown")
+}
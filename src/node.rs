@@ -0,0 +1,268 @@
+use std::{
+    fs,
+    future::Future,
+    io::{self, Error, ErrorKind},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tokio::{net::TcpListener, sync::watch};
+use tracing::{info, warn};
+
+use crate::{
+    builder::BlockBuilder,
+    chain::Blockchain,
+    config::{ChainConfig, GenesisConfig},
+    discovery::DiscoveryConfig,
+    events::EventBus,
+    grpc::GrpcServer,
+    metrics::Metrics,
+    network::Network,
+    plugin::StatePlugin,
+    program::Program,
+    pool::Mempool,
+    pruning::PruningConfig,
+    quic::{QuicConfig, QuicServer},
+    rate_limit::RateLimitConfig,
+    rewards::RewardConfig,
+    rpc::{RpcServer, DEFAULT_MAX_BLOCK_PAGE},
+    scoring::ScoringConfig,
+    validator::Validator,
+    wal::WriteAheadLog,
+    wallet::Wallet,
+};
+
+// Everything needed to stand up a node, loaded from a TOML file. Missing keys fall back to the defaults.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    pub rpc_addr: String,
+    pub p2p_addr: String,
+    // Bootstrap peers, connected to on start & redialed whenever the node is short of peers
+    pub peers: Vec<SocketAddr>,
+    pub discovery: DiscoveryConfig,
+    // How peers are scored for misbehaving & when they're banned for it
+    pub peer_scoring: ScoringConfig,
+    pub slot_interval_ms: u64,
+    // Most blocks a single RPC range query returns
+    pub rpc_max_block_page: u64,
+    // Quotas held to each RPC client. Without them the RPC server takes whatever it's sent.
+    pub rpc_rate_limit: Option<RateLimitConfig>,
+    // UDP address to take transactions on over QUIC, if any
+    pub quic_addr: Option<String>,
+    // Address to serve the gRPC API on, if any
+    pub grpc_addr: Option<String>,
+    pub quic: QuicConfig,
+    // Consensus parameters, when not taken from a genesis file
+    pub chain: ChainConfig,
+    pub rewards: RewardConfig,
+    // Genesis file of the chain to join. Without one the node starts a fresh, empty chain of its own.
+    pub genesis: Option<PathBuf>,
+    // Encrypted validator identity. Without one the node runs under a throwaway key.
+    pub keystore: Option<PathBuf>,
+    // Write-ahead log of every balance & stake change. Blocks aren't kept across restarts yet, so the
+    // node starts a new log each time it resyncs from genesis; `WriteAheadLog::recover` replays one.
+    pub wal: Option<PathBuf>,
+    // Keep enough history to answer queries about state at past heights
+    pub archive: bool,
+    pub pruning: PruningConfig,
+    // Start from a peer's state snapshot instead of replaying every block since genesis
+    pub fast_sync: bool,
+    // Run a development chain: RPC serves `requestAirdrop`, signed with the validator key, which is
+    // made the faucet of a fresh chain. A genesis file has to name that key as its faucet itself.
+    pub dev: bool,
+    // Check submitted transactions against current state before admitting them to the mempool
+    pub preflight: bool,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            rpc_addr: "127.0.0.1:8899".to_string(),
+            p2p_addr: "0.0.0.0:8900".to_string(),
+            peers: vec![],
+            discovery: DiscoveryConfig::default(),
+            peer_scoring: ScoringConfig::default(),
+            slot_interval_ms: 400,
+            rpc_max_block_page: DEFAULT_MAX_BLOCK_PAGE,
+            rpc_rate_limit: None,
+            quic_addr: None,
+            grpc_addr: None,
+            quic: QuicConfig::default(),
+            chain: ChainConfig::default(),
+            rewards: RewardConfig::default(),
+            genesis: None,
+            keystore: None,
+            wal: None,
+            archive: false,
+            pruning: PruningConfig::default(),
+            fast_sync: false,
+            dev: false,
+            preflight: true,
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+// A single validating node: local state, gossip, a validator loop & the RPC server
+pub struct Node {
+    pub config: NodeConfig,
+    pub builder: BlockBuilder,
+    pub network: Network,
+    pub validator: Validator,
+}
+
+impl Node {
+    pub fn new(config: NodeConfig) -> io::Result<Self> {
+        Self::with_wallet(config, Wallet::generate())
+    }
+
+    pub fn with_wallet(config: NodeConfig, wallet: Wallet) -> io::Result<Self> {
+        let genesis = match &config.genesis {
+            Some(path) => GenesisConfig::load(path)?,
+            None => GenesisConfig {
+                faucet: config.dev.then(|| wallet.address.clone()),
+                chain: config.chain,
+                ..GenesisConfig::default()
+            },
+        };
+        let (mut chain, mut db) = Blockchain::from_genesis(&genesis).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        db.archive = config.archive;
+        if let Some(path) = &config.wal {
+            chain = chain.with_wal(WriteAheadLog::create(path)?);
+        }
+
+        let (metrics, events) = (Arc::new(Metrics::new()), EventBus::default());
+        let db = Arc::new(RwLock::new(db));
+        let mut mempool = Mempool::new()
+            .with_metrics(Arc::clone(&metrics))
+            .with_events(events.clone())
+            .with_max_block_bytes(genesis.chain.max_block_bytes);
+        if config.preflight {
+            mempool = mempool.with_preflight(Arc::clone(&db));
+        }
+        let builder = BlockBuilder::new(Arc::new(RwLock::new(mempool)), db, Arc::new(RwLock::new(chain)))
+            .with_metrics(metrics)
+            .with_events(events)
+            .with_config(genesis.chain)
+            .with_rewards(config.rewards);
+        let network = Network::bind_with_wallet(&config.p2p_addr, &builder, wallet.clone())?;
+        let builder = builder.with_network(network.clone());
+        if config.fast_sync {
+            network.enable_fast_sync();
+        }
+
+        network.set_scoring(config.peer_scoring.clone());
+        for peer in &config.peers {
+            if let Err(e) = network.connect(*peer) {
+                warn!(peer = %peer, error = ?e, "Failed to connect to peer");
+            }
+        }
+        network.start_discovery(config.peers.clone(), config.discovery.clone())?;
+
+        // A chain with no validators at genesis is validated by this node alone
+        let validator = Validator::new(wallet, builder.clone());
+        let db_lock = builder.db.read().unwrap();
+        if db_lock.validators.is_empty() {
+            db_lock.add_validator(validator.wallet.public_key, validator.account());
+        }
+        drop(db_lock);
+
+        Ok(Self {
+            config,
+            builder,
+            network,
+            validator,
+        })
+    }
+
+    // Start streaming state to `plugin`: every account as it stands now, then each change from here on
+    pub fn load_plugin(&self, plugin: Arc<dyn StatePlugin>) {
+        let db_lock = self.builder.db.read().unwrap();
+        let mut chain_lock = self.builder.chain.write().unwrap();
+        let height = chain_lock.height();
+        for account in db_lock.accounts.iter() {
+            plugin.account_updated(account.key(), Some(account.value()), height);
+        }
+        info!(plugin = plugin.name(), "Plugin loaded");
+        chain_lock.add_plugin(plugin);
+    }
+
+    // Make a native program callable by transactions. Every node on the chain has to register it too.
+    pub fn register_program(&self, program: Arc<dyn Program>) -> Result<(), &'static str> {
+        self.builder.db.write().unwrap().register_program(program)
+    }
+
+    // Run the validator loop & RPC server until `shutdown` resolves, then stop both
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+        let (stop_tx, stop_rx) = watch::channel(false);
+
+        let listener = TcpListener::bind(&self.config.rpc_addr).await?;
+        info!(rpc = %listener.local_addr()?, p2p = %self.network.local_addr, validator = %self.validator.wallet.address, "Node started");
+
+        let mut rpc = RpcServer::new(self.builder.clone()).with_max_block_page(self.config.rpc_max_block_page);
+        if self.config.dev {
+            rpc = rpc.with_faucet(self.validator.wallet.clone());
+        }
+        if let Some(rate_limit) = self.config.rpc_rate_limit.clone() {
+            rpc = rpc.with_rate_limit(rate_limit);
+        }
+        let grpc = match &self.config.grpc_addr {
+            Some(addr) => {
+                let listener = TcpListener::bind(addr).await?;
+                info!(grpc = %listener.local_addr()?, "Serving gRPC");
+                Some(tokio::spawn(GrpcServer::new(rpc.clone()).serve(listener, stopped(stop_rx.clone()))))
+            }
+            None => None,
+        };
+        let rpc = tokio::spawn(rpc.serve(listener, stopped(stop_rx.clone())));
+
+        let quic = match &self.config.quic_addr {
+            Some(addr) => {
+                let socket = std::net::UdpSocket::bind(addr)?;
+                info!(quic = %socket.local_addr()?, "Taking transactions over QUIC");
+                let server = QuicServer::new(self.builder.clone()).with_config(self.config.quic.clone());
+                Some(tokio::spawn(server.serve(socket, stopped(stop_rx.clone()))))
+            }
+            None => None,
+        };
+
+        let pruner = self.config.pruning.keep_blocks.is_some()
+            .then(|| tokio::spawn(self.config.pruning.clone().run(self.builder.clone(), stopped(stop_rx.clone()))));
+
+        let validator = self.validator.clone();
+        let interval = Duration::from_millis(self.config.slot_interval_ms);
+        let validator_loop = tokio::spawn(async move { validator.run_async(interval, stopped(stop_rx)).await });
+
+        shutdown.await;
+        info!("Shutting down");
+        let _ = stop_tx.send(true);
+
+        rpc.await.map_err(Error::other)??;
+        if let Some(grpc) = grpc {
+            grpc.await.map_err(Error::other)??;
+        }
+        if let Some(quic) = quic {
+            quic.await.map_err(Error::other)??;
+        }
+        if let Some(pruner) = pruner {
+            pruner.await.map_err(Error::other)?;
+        }
+        validator_loop
+            .await
+            .map_err(Error::other)?
+            .map_err(Error::other)
+    }
+}
+
+async fn stopped(mut stop: watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stop| *stop).await;
+}
@@ -0,0 +1,153 @@
+use std::{
+    io::{self, Error, ErrorKind, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use ed25519_dalek::{PublicKey, Signature};
+use snow::{Builder, HandshakeState, StatelessTransportState};
+
+use crate::{network::MAX_MESSAGE_SIZE, structures::Pubkey, wallet::Wallet};
+
+// Both sides prove a static key, so each knows who it's talking to once the handshake is done
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+// How long a peer has to finish the handshake before it's dropped
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Largest Noise message, & the AEAD tag each one carries
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_SIZE: usize = 16;
+// Plaintext carried by each Noise message a frame is split into
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - TAG_SIZE;
+// Signed along with a Noise static key, so the signature can't be passed off as anything else
+const STATIC_KEY_CONTEXT: &[u8] = b"litechain-noise-static-key:";
+// Identity key & its signature over the Noise static key
+const PAYLOAD_SIZE: usize = 32 + Signature::BYTE_SIZE;
+
+// Sealing half of an encrypted connection
+pub(crate) struct Sender {
+    stream: TcpStream,
+    transport: Arc<StatelessTransportState>,
+    nonce: u64,
+}
+
+// Opening half, read from a thread of its own
+pub(crate) struct Receiver {
+    stream: TcpStream,
+    transport: Arc<StatelessTransportState>,
+    nonce: u64,
+}
+
+// Run the Noise handshake over `stream`, proving we hold `wallet`'s key & learning the peer's. Each side
+// uses a fresh static key for the connection & signs it with its identity key, so finishing the
+// handshake proves the peer holds the identity key it claims.
+pub(crate) fn handshake(mut stream: TcpStream, wallet: &Wallet, initiator: bool) -> io::Result<(Pubkey, Sender, Receiver)> {
+    let builder = Builder::new(NOISE_PARAMS.parse().expect("Noise parameters are valid"));
+    let keypair = builder.generate_keypair().map_err(noise_error)?;
+    let builder = builder.local_private_key(&keypair.private);
+    let mut state = if initiator { builder.build_initiator() } else { builder.build_responder() }.map_err(noise_error)?;
+
+    let mut payload = wallet.public_key.to_vec();
+    payload.extend_from_slice(&wallet.sign(&[STATIC_KEY_CONTEXT, &keypair.public].concat()).to_bytes());
+
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    // -> e; <- e, ee, s, es; -> s, se, with our identity riding on each side's static key message
+    let remote = if initiator {
+        write_handshake(&mut stream, &mut state, &[])?;
+        let remote = read_handshake(&mut stream, &mut state)?;
+        write_handshake(&mut stream, &mut state, &payload)?;
+        remote
+    } else {
+        read_handshake(&mut stream, &mut state)?;
+        write_handshake(&mut stream, &mut state, &payload)?;
+        read_handshake(&mut stream, &mut state)?
+    };
+    let identity = verify_payload(&remote, state.get_remote_static())?;
+    stream.set_read_timeout(None)?;
+
+    let transport = Arc::new(state.into_stateless_transport_mode().map_err(noise_error)?);
+    let sender = Sender { stream: stream.try_clone()?, transport: Arc::clone(&transport), nonce: 0 };
+    Ok((identity, sender, Receiver { stream, transport, nonce: 0 }))
+}
+
+// The identity key a peer vouched for its static key with
+fn verify_payload(payload: &[u8], remote_static: Option<&[u8]>) -> io::Result<Pubkey> {
+    let invalid = || Error::new(ErrorKind::PermissionDenied, "Peer failed to prove its identity");
+    let (Some(remote_static), true) = (remote_static, payload.len() == PAYLOAD_SIZE) else { return Err(invalid()) };
+    let identity: Pubkey = payload[..32].try_into().expect("Payload holds a key");
+    let public_key = PublicKey::from_bytes(&identity).map_err(|_| invalid())?;
+    let signature = Signature::from_bytes(&payload[32..]).map_err(|_| invalid())?;
+    public_key.verify_strict(&[STATIC_KEY_CONTEXT, remote_static].concat(), &signature).map_err(|_| invalid())?;
+    Ok(identity)
+}
+
+// Handshake messages are sent as is, each after its big-endian u16 length
+fn write_handshake(stream: &mut TcpStream, state: &mut HandshakeState, payload: &[u8]) -> io::Result<()> {
+    let mut message = vec![0u8; MAX_NOISE_MESSAGE];
+    let len = state.write_message(payload, &mut message).map_err(noise_error)?;
+    stream.write_all(&(len as u16).to_be_bytes())?;
+    stream.write_all(&message[..len])
+}
+
+fn read_handshake(stream: &mut TcpStream, state: &mut HandshakeState) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut message = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message)?;
+    let mut payload = vec![0u8; message.len()];
+    let len = state.read_message(&message, &mut payload).map_err(noise_error)?;
+    payload.truncate(len);
+    Ok(payload)
+}
+
+impl Sender {
+    // Seal `bytes` as one frame: its u32 length, then Noise messages of at most `MAX_CHUNK` plaintext
+    // bytes each. Every message takes the next nonce, so frames can't be dropped, replayed or
+    // reordered without the peer noticing.
+    pub(crate) fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0u8; 4];
+        for chunk in bytes.chunks(MAX_CHUNK) {
+            let start = frame.len();
+            frame.resize(start + chunk.len() + TAG_SIZE, 0);
+            self.transport.write_message(self.nonce, chunk, &mut frame[start..]).map_err(noise_error)?;
+            self.nonce += 1;
+        }
+        let len = (frame.len() - 4) as u32;
+        frame[..4].copy_from_slice(&len.to_le_bytes());
+        self.stream.write_all(&frame)
+    }
+
+    // Close the connection both ways, which ends the peer's reader too
+    pub(crate) fn shutdown(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl Receiver {
+    // Open the next frame, failing on anything tampered with or larger than a message can be
+    pub(crate) fn receive(&mut self) -> io::Result<Vec<u8>> {
+        let mut len = [0u8; 4];
+        self.stream.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_MESSAGE_SIZE + MAX_MESSAGE_SIZE.div_ceil(MAX_CHUNK) * TAG_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "Frame too large"))
+        }
+
+        let mut frame = vec![0u8; len];
+        self.stream.read_exact(&mut frame)?;
+        let mut bytes = Vec::with_capacity(len);
+        for message in frame.chunks(MAX_NOISE_MESSAGE) {
+            let start = bytes.len();
+            bytes.resize(start + message.len(), 0);
+            let opened = self.transport.read_message(self.nonce, message, &mut bytes[start..]).map_err(noise_error)?;
+            bytes.truncate(start + opened);
+            self.nonce += 1;
+        }
+        Ok(bytes)
+    }
+}
+
+fn noise_error(error: snow::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, error.to_string())
+}
@@ -0,0 +1,65 @@
+use std::{collections::BTreeSet, fmt, sync::Arc};
+
+use crate::{
+    db::AccountsDB,
+    structures::{Block, Pubkey, UserAccount},
+};
+
+// Hooks for mirroring chain state elsewhere, e.g. an indexer or an external database. Every change to
+// the canonical chain is reported: the accounts a block touched, then the block itself. Hooks run on the
+// thread applying the block while it holds the chain lock, so anything slow should be handed off.
+pub trait StatePlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    // `account` is the account as of `height`, `None` if it no longer exists
+    fn account_updated(&self, _pubkey: &Pubkey, _account: Option<&UserAccount>, _height: u64) {}
+
+    // `block` became canonical at `height`
+    fn block_finalized(&self, _block: &Block, _height: u64) {}
+
+    // `block` was rolled back by a reorg. The accounts it touched are reported again as they now stand.
+    fn block_reverted(&self, _block: &Block, _height: u64) {}
+}
+
+// The plugins registered with a node, called in the order they were added
+#[derive(Clone, Default)]
+pub struct PluginSet {
+    plugins: Vec<Arc<dyn StatePlugin>>,
+}
+
+impl fmt::Debug for PluginSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.plugins.iter().map(|plugin| plugin.name())).finish()
+    }
+}
+
+impl PluginSet {
+    pub fn register(&mut self, plugin: Arc<dyn StatePlugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn accounts_updated(&self, pubkeys: &BTreeSet<Pubkey>, db: &AccountsDB, height: u64) {
+        for pubkey in pubkeys {
+            let account = db.get_account(pubkey);
+            for plugin in &self.plugins {
+                plugin.account_updated(pubkey, account.as_ref(), height);
+            }
+        }
+    }
+
+    pub fn block_finalized(&self, block: &Block, height: u64) {
+        for plugin in &self.plugins {
+            plugin.block_finalized(block, height);
+        }
+    }
+
+    pub fn block_reverted(&self, block: &Block, height: u64) {
+        for plugin in &self.plugins {
+            plugin.block_reverted(block, height);
+        }
+    }
+}
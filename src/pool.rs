@@ -1,32 +1,158 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use dashmap::DashMap;
-use crate::structures::{Transaction, Pubkey, TransactionSign};
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Mutex,
+        RwLock,
+    },
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use crate::{
+    db::AccountsDB,
+    events::{Event, EventBus},
+    metrics::Metrics,
+    structures::{Block, Transaction, TransactionId, Pubkey, TransactionSign, Txhash},
+    vote::Vote,
+};
 
-pub const MAX_TRANSACTIONS_PER_BLOCK: usize = 2;
+// Priority key: highest bid (fee plus tip) first, then oldest first among equal bids
+type Priority = (Reverse<u64>, u64);
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct Mempool {
     pub pool: DashMap<u64, Transaction>,
     counter: AtomicU64,
+    // Ordered index over `pool`, kept in step by every method that inserts or removes
+    by_priority: Mutex<BTreeSet<Priority>>,
+    // The pending transaction for each (signer, nonce), so a resubmission replaces rather than duplicates it
+    by_sender: DashMap<(Pubkey, u64), u64>,
+    // Every pending transaction by content hash, so exact resubmissions are rejected
+    by_hash: DashMap<Txhash, u64>,
+    // Votes on finalized blocks, waiting to be recorded on chain. Kept out of `pool`: they ride along
+    // with the next block that's built, rather than waiting for room in one or causing one to be built.
+    votes: Mutex<Vec<Vote>>,
+    metrics: Arc<Metrics>,
+    events: EventBus,
+    // State to check submissions against before admitting them. Without it only signatures are checked.
+    preflight: Option<Arc<RwLock<AccountsDB>>>,
+    // Largest encoding admitted, beyond which a transaction could never fit in a block
+    max_transaction_bytes: usize,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Mempool {
     pub fn new() -> Self {
         Self {
             pool: DashMap::new(),
-            counter: AtomicU64::new(0)
+            counter: AtomicU64::new(0),
+            by_priority: Mutex::new(BTreeSet::new()),
+            by_sender: DashMap::new(),
+            by_hash: DashMap::new(),
+            votes: Mutex::new(vec![]),
+            metrics: Arc::default(),
+            events: EventBus::default(),
+            preflight: None,
+            max_transaction_bytes: usize::MAX,
         }
     }
 
+    // Refuse submissions that wouldn't execute against the current state of `db`, so they're turned
+    // away up front rather than dropped by whoever builds the next block. Anything relying on another
+    // pending transaction, say to fund it or to use up the nonce before its own, is refused too, so
+    // `send_raw_transaction` is there for those. Stale nonces are refused either way.
+    pub fn with_preflight(mut self, db: Arc<RwLock<AccountsDB>>) -> Self {
+        self.preflight = Some(db);
+        self
+    }
+
+    // Refuse transactions too big for a block of `max_block_bytes` to hold, even an otherwise empty one
+    pub fn with_max_block_bytes(mut self, max_block_bytes: usize) -> Self {
+        self.max_transaction_bytes = max_block_bytes.saturating_sub(Block::overhead());
+        self
+    }
+
+    // Announce admitted transactions on `events`
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    // Report admissions & the pool's size to `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    // Admit a transaction, once it passes preflight. If one with the same signer & nonce is already
+    // pending, this replaces it as long as it bids strictly more.
     pub fn send_transaction(&self, tx: Transaction) -> Result<u64, &'static str> {
+        self.admit(tx, true)
+    }
+
+    // Admit a transaction without the preflight check, only its signatures
+    pub fn send_raw_transaction(&self, tx: Transaction) -> Result<u64, &'static str> {
+        self.admit(tx, false)
+    }
+
+    fn admit(&self, tx: Transaction, preflight: bool) -> Result<u64, &'static str> {
+        if tx.vote().is_some() {
+            return Err("Votes are recorded by block proposers")
+        }
         let signer: Pubkey = tx.get_signer();
 
-        if !tx.verify_signature(&signer) {
+        if !tx.verify_signatures() {
            return Err("Signature invalid.")
         }
+        if tx.to_bytes().len() > self.max_transaction_bytes {
+            return Err("Transaction is too large for a block")
+        }
+
+        // A nonce the signer has already used can never run, whether or not the rest is checked
+        if let Some(db) = self.preflight.as_ref() {
+            let db = db.read().unwrap();
+            if tx.nonce() < db.nonce(&signer) {
+                return Err("Nonce already used")
+            }
+            if preflight && !tx.validate_state(&db) {
+                return Err("Transaction fails preflight against current state")
+            }
+        }
+
+        let hash = tx.hash();
+        if self.by_hash.contains_key(&hash) {
+            return Err("Transaction already in mempool")
+        }
 
-        let id = self.counter.fetch_add(1, Ordering::SeqCst);
+        // Holding the entry serializes concurrent submissions for the same (signer, nonce)
+        let id = match self.by_sender.entry((signer, tx.nonce())) {
+            Entry::Occupied(mut pending) => {
+                let pending_id = *pending.get();
+                if self.get_transaction(&pending_id).is_some_and(|pending_tx| tx.bid() <= pending_tx.bid()) {
+                    return Err("Replacement transaction must pay a higher fee")
+                }
+                self.unindex(&pending_id);
+
+                let id = self.counter.fetch_add(1, Ordering::SeqCst);
+                pending.insert(id);
+                id
+            }
+            Entry::Vacant(slot) => *slot.insert(self.counter.fetch_add(1, Ordering::SeqCst)),
+        };
+
+        let bid = tx.bid();
         self.pool.insert(id, tx);
+        self.by_priority.lock().unwrap().insert((Reverse(bid), id));
+        self.by_hash.insert(hash, id);
+        self.metrics.transactions_received.inc();
+        self.metrics.mempool_size.set(self.pool.len() as u64);
+        self.events.emit(Event::TransactionAdmitted { hash, id });
         Ok(id)
     }
 
@@ -35,16 +161,136 @@ impl Mempool {
     }
 
     pub fn remove_transaction(&self, id: &u64) {
-        self.pool.remove(id);
+        if let Some(tx) = self.unindex(id) {
+            self.by_sender.remove_if(&(tx.get_signer(), tx.nonce()), |_, pending| pending == id);
+        }
+    }
+
+    pub fn contains(&self, tx: &Transaction) -> bool {
+        self.by_hash.contains_key(&tx.hash())
+    }
+
+    // Whether a transaction is pending. Signatures aren't indexed, so looking one up scans the pool.
+    pub fn contains_id(&self, id: &TransactionId) -> bool {
+        match id {
+            TransactionId::Hash(hash) => self.by_hash.contains_key(hash),
+            TransactionId::Signature(_) => self.pool.iter().any(|tx| id.matches(&tx)),
+        }
+    }
+
+    // Remove a transaction from the pool, priority & hash indexes, leaving the sender index to the caller
+    fn unindex(&self, id: &u64) -> Option<Transaction> {
+        let (id, tx) = self.pool.remove(id)?;
+        self.by_priority.lock().unwrap().remove(&(Reverse(tx.bid()), id));
+        self.by_hash.remove(&tx.hash());
+        self.metrics.mempool_size.set(self.pool.len() as u64);
+        Some(tx)
     }
 
     pub fn remove_transactions(&mut self, transactions: &[u64]) {
         for tx in transactions {
-            self.pool.remove(tx);
+            self.remove_transaction(tx);
+        }
+    }
+
+    // Drop transactions that made it into a block
+    pub fn remove_included(&self, transactions: &[Transaction]) {
+        for tx in transactions {
+            // Copy the id out so the hash index isn't borrowed while it's being removed from
+            let id = self.by_hash.get(&tx.hash()).map(|id| *id);
+            if let Some(id) = id {
+                self.remove_transaction(&id);
+            }
         }
     }
 
-    pub fn get_transactions_for_block(&self) -> Vec<Transaction> {
-        self.pool.iter().take(MAX_TRANSACTIONS_PER_BLOCK).map(|tx| tx.clone()).collect()
+    pub fn clear(&self) {
+        self.pool.clear();
+        self.by_priority.lock().unwrap().clear();
+        self.by_sender.clear();
+        self.by_hash.clear();
+        self.votes.lock().unwrap().clear();
+        self.metrics.mempool_size.set(0);
+    }
+
+    // Queue votes to be recorded in the next block, skipping any we already hold
+    pub fn add_votes(&self, votes: Vec<Vote>) {
+        let mut pending = self.votes.lock().unwrap();
+        for vote in votes {
+            if !pending.contains(&vote) {
+                pending.push(vote);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    pub fn drain_votes(&self) -> Vec<Vote> {
+        std::mem::take(&mut *self.votes.lock().unwrap())
+    }
+
+    // Atomically take up to `max` of the highest-bidding transactions out of the pool, passing over any
+    // whose encoding would push the total past `max_bytes` for smaller ones behind it. Concurrent
+    // submissions either land before the drain & are considered, or after it & stay pending.
+    pub fn drain_for_block(&self, max: usize, max_bytes: usize) -> Vec<Transaction> {
+        let drained: Vec<(u64, Transaction)> = {
+            let mut by_priority = self.by_priority.lock().unwrap();
+            let mut drained = vec![];
+            let mut taken = vec![];
+            let mut bytes = 0;
+            for &(bid, id) in by_priority.iter() {
+                if drained.len() >= max {
+                    break
+                }
+                let Some(tx) = self.get_transaction(&id) else {
+                    taken.push((bid, id));
+                    continue
+                };
+
+                let size = tx.to_bytes().len();
+                if bytes + size > max_bytes {
+                    continue
+                }
+                bytes += size;
+
+                taken.push((bid, id));
+                if let Some(entry) = self.pool.remove(&id) {
+                    drained.push(entry);
+                }
+            }
+            for priority in taken {
+                by_priority.remove(&priority);
+            }
+            drained
+        };
+
+        // Sender & hash indexes are cleaned up outside the priority lock, which `send_transaction`
+        // takes while holding a sender entry
+        for (id, tx) in &drained {
+            self.by_hash.remove(&tx.hash());
+            self.by_sender.remove_if(&(tx.get_signer(), tx.nonce()), |_, pending| pending == id);
+        }
+        self.metrics.mempool_size.set(self.pool.len() as u64);
+
+        drained.into_iter().map(|(_, tx)| tx).collect()
+    }
+
+    // Put drained transactions back, e.g. when the block they were drained for didn't finalize.
+    // Anything that no longer fits (replaced or resubmitted meanwhile) is dropped. They passed
+    // preflight once already, & callers may be holding the state lock it would take.
+    pub fn requeue(&self, transactions: Vec<Transaction>) {
+        for tx in transactions {
+            match tx.vote() {
+                Some(vote) => self.add_votes(vec![*vote]),
+                None => { let _ = self.send_raw_transaction(tx); }
+            }
+        }
+    }
+
+    // The `max` highest-bidding transactions, left in the pool
+    pub fn get_transactions_for_block(&self, max: usize) -> Vec<Transaction> {
+        self.by_priority.lock().unwrap()
+            .iter()
+            .filter_map(|(_, id)| self.get_transaction(id))
+            .take(max)
+            .collect()
+    }
+}
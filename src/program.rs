@@ -0,0 +1,218 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    db::AccountsDB,
+    structures::{Pubkey, UserAccount},
+};
+
+// Identifies a native program, in the same space as account keys
+pub type ProgramId = Pubkey;
+
+pub const TRANSFER_PROGRAM: ProgramId = *b"litechain_transfer_program______";
+pub const STAKE_PROGRAM: ProgramId = *b"litechain_stake_program_________";
+pub const ACCOUNT_PROGRAM: ProgramId = *b"litechain_account_program_______";
+
+// Most accounts & bytes of data a single instruction may pass its program
+pub const MAX_INSTRUCTION_ACCOUNTS: usize = 64;
+pub const MAX_INSTRUCTION_DATA_BYTES: usize = 1024;
+
+// Most instructions & signers a single message may carry
+pub const MAX_MESSAGE_INSTRUCTIONS: usize = 16;
+pub const MAX_MESSAGE_SIGNERS: usize = 16;
+
+// An account handed to a program, & whether the transaction calling it carries that account's signature
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountMeta {
+    pub pubkey: Pubkey,
+    pub signer: bool,
+}
+
+// One call to a program, as a step of a message
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Instruction {
+    pub program: ProgramId,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+}
+
+impl Instruction {
+    pub fn new(program: ProgramId, accounts: Vec<Pubkey>, data: Vec<u8>) -> Self {
+        Self { program, accounts, data }
+    }
+
+    // Move `amt` from `from`, which has to sign, to `to`
+    pub fn transfer(from: Pubkey, to: Pubkey, amt: u64) -> Self {
+        Self::new(TRANSFER_PROGRAM, vec![from, to], amt.to_le_bytes().to_vec())
+    }
+
+    // Bond `amt` from `staker`, which has to sign, to `validator`
+    pub fn stake(staker: Pubkey, validator: Pubkey, amt: u64) -> Self {
+        Self::new(STAKE_PROGRAM, vec![staker, validator], amt.to_le_bytes().to_vec())
+    }
+
+    // Open `account`, funded with `amt` from `payer`. Both have to sign.
+    pub fn create_account(payer: Pubkey, account: Pubkey, amt: u64) -> Self {
+        Self::new(ACCOUNT_PROGRAM, vec![payer, account], amt.to_le_bytes().to_vec())
+    }
+
+    pub fn is_well_formed(&self) -> bool {
+        self.accounts.len() <= MAX_INSTRUCTION_ACCOUNTS && self.data.len() <= MAX_INSTRUCTION_DATA_BYTES
+    }
+}
+
+// On-chain logic compiled into the node & run by program transactions, so new behaviour doesn't need a
+// new transaction type. Every node on a chain has to register the same programs, or they'll disagree
+// about blocks that call them.
+pub trait Program: Send + Sync {
+    fn id(&self) -> ProgramId;
+
+    // Whether `process` would succeed against `db`, checked before a transaction is admitted
+    fn validate(&self, _accounts: &[AccountMeta], _data: &[u8], _db: &AccountsDB) -> bool {
+        true
+    }
+
+    // Carry out an instruction. Only `accounts` may be read or written, since transactions that don't
+    // share accounts run in parallel. The caller's fee has already been taken.
+    fn process(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> Result<(), &'static str>;
+}
+
+// Amount carried by the built-in programs' instructions
+fn amount(data: &[u8]) -> Result<u64, &'static str> {
+    data.try_into().map(u64::from_le_bytes).map_err(|_| "Instruction data is not an amount")
+}
+
+// The `[from, to]` accounts & amount of a built-in instruction, once `from` is known to have signed it
+fn debit<'a>(accounts: &'a [AccountMeta], data: &[u8]) -> Result<(&'a AccountMeta, &'a AccountMeta, u64), &'static str> {
+    let [from, to] = accounts else { return Err("Expected two accounts") };
+    if !from.signer {
+        return Err("Missing signature of the debited account")
+    }
+    Ok((from, to, amount(data)?))
+}
+
+// Moves `amount` from the first account to the second. Data is the amount, little endian.
+pub struct TransferProgram;
+
+impl Program for TransferProgram {
+    fn id(&self) -> ProgramId {
+        TRANSFER_PROGRAM
+    }
+
+    fn validate(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> bool {
+        let Ok((from, to, amt)) = debit(accounts, data) else { return false };
+        db.get_account(&to.pubkey).is_some() && db.get_account(&from.pubkey).is_some_and(|from| from.balance >= amt)
+    }
+
+    fn process(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> Result<(), &'static str> {
+        let (from, to, amt) = debit(accounts, data)?;
+        db.transfer(&from.pubkey, &to.pubkey, amt)?;
+        Ok(())
+    }
+}
+
+// Bonds `amount` from the first account to the validator that's second, as a delegation unless they're
+// the same account. Data is the amount, little endian.
+pub struct StakeProgram;
+
+impl Program for StakeProgram {
+    fn id(&self) -> ProgramId {
+        STAKE_PROGRAM
+    }
+
+    fn validate(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> bool {
+        let Ok((staker, validator, amt)) = debit(accounts, data) else { return false };
+        db.is_validator(&validator.pubkey) && db.get_account(&staker.pubkey).is_some_and(|staker| staker.balance >= amt)
+    }
+
+    fn process(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> Result<(), &'static str> {
+        let (staker, validator, amt) = debit(accounts, data)?;
+        if !db.is_validator(&validator.pubkey) {
+            return Err("Not a validator")
+        }
+        db.decrease_account_balance(&staker.pubkey, amt)
+            .map_err(|_| "Balance decrease failed")?;
+        db.increase_validator_stake(&validator.pubkey, amt)
+            .map_err(|_| "Stake increase failed")?;
+        if staker.pubkey != validator.pubkey {
+            db.delegate(&validator.pubkey, &staker.pubkey, amt);
+        }
+        Ok(())
+    }
+}
+
+// Opens the second account, funded with `amount` from the first. The new account signs too, so nobody
+// can open an account for a key they don't hold. Data is the amount, little endian.
+pub struct AccountProgram;
+
+impl Program for AccountProgram {
+    fn id(&self) -> ProgramId {
+        ACCOUNT_PROGRAM
+    }
+
+    fn validate(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> bool {
+        let Ok((payer, account, amt)) = debit(accounts, data) else { return false };
+        account.signer && db.get_account(&account.pubkey).is_none() && db.get_account(&payer.pubkey).is_some_and(|payer| payer.balance >= amt)
+    }
+
+    fn process(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> Result<(), &'static str> {
+        let (payer, account, amt) = debit(accounts, data)?;
+        if !account.signer {
+            return Err("Missing signature of the new account")
+        }
+        if db.get_account(&account.pubkey).is_some() {
+            return Err("Account already exists")
+        }
+        db.decrease_account_balance(&payer.pubkey, amt)
+            .map_err(|_| "Balance decrease failed")?;
+        db.add_account(account.pubkey, UserAccount { balance: amt, ..UserAccount::from_public_key(account.pubkey) });
+        Ok(())
+    }
+}
+
+// Programs by id. Starts out with the built-in ones, which can't be replaced.
+#[derive(Clone)]
+pub struct ProgramRegistry {
+    programs: HashMap<ProgramId, Arc<dyn Program>>,
+}
+
+impl Default for ProgramRegistry {
+    fn default() -> Self {
+        let mut registry = Self { programs: HashMap::new() };
+        for program in [Arc::new(TransferProgram) as Arc<dyn Program>, Arc::new(StakeProgram), Arc::new(AccountProgram)] {
+            registry.programs.insert(program.id(), program);
+        }
+        registry
+    }
+}
+
+impl fmt::Debug for ProgramRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.programs.keys().map(hex::encode)).finish()
+    }
+}
+
+impl ProgramRegistry {
+    pub fn register(&mut self, program: Arc<dyn Program>) -> Result<(), &'static str> {
+        if self.programs.contains_key(&program.id()) {
+            return Err("Program id already registered")
+        }
+        self.programs.insert(program.id(), program);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &ProgramId) -> Option<&Arc<dyn Program>> {
+        self.programs.get(id)
+    }
+
+    pub fn contains(&self, id: &ProgramId) -> bool {
+        self.programs.contains_key(id)
+    }
+
+    pub fn validate(&self, id: &ProgramId, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> bool {
+        self.get(id).is_some_and(|program| program.validate(accounts, data, db))
+    }
+
+    pub fn process(&self, id: &ProgramId, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> Result<(), &'static str> {
+        self.get(id).ok_or("Unknown program")?.process(accounts, data, db)
+    }
+}
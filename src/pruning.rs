@@ -0,0 +1,80 @@
+use std::{future::Future, path::PathBuf, time::Duration};
+
+use tracing::{error, warn};
+
+use crate::{
+    builder::BlockBuilder,
+    storage::{BlockArchive, Codec},
+};
+
+// How much history a node keeps. Nothing is pruned unless `keep_blocks` is set.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PruningConfig {
+    // Most recent blocks kept in full, which is also as far back as state can be rolled back or queried
+    pub keep_blocks: Option<u64>,
+    // Archive nodes snapshot the accounts at every multiple of this height as it's pruned
+    pub snapshot_interval: Option<u64>,
+    // How often the background pruner runs
+    pub interval_ms: u64,
+    // Where pruned blocks are written out in full, if anywhere, & how they're compressed
+    pub archive_dir: Option<PathBuf>,
+    pub codec: Codec,
+}
+
+impl Default for PruningConfig {
+    fn default() -> Self {
+        Self {
+            keep_blocks: None,
+            snapshot_interval: None,
+            interval_ms: 10_000,
+            archive_dir: None,
+            codec: Codec::default(),
+        }
+    }
+}
+
+impl PruningConfig {
+    // Oldest height kept in full once the chain is at `height`
+    pub fn horizon(&self, height: u64) -> Option<u64> {
+        self.keep_blocks.map(|keep| (height + 1).saturating_sub(keep))
+    }
+
+    // Prune the block store & the state history down to the horizon
+    pub fn prune(&self, builder: &BlockBuilder) {
+        let mut db_lock = builder.db.write().unwrap();
+        let mut chain_lock = builder.chain.write().unwrap();
+        let Some(horizon) = self.horizon(chain_lock.height()) else { return };
+
+        let pruned = chain_lock.prune(horizon);
+        db_lock.prune(chain_lock.horizon(), self.snapshot_interval);
+        drop(chain_lock);
+        drop(db_lock);
+
+        // Losing an archived copy doesn't affect consensus, so a failed write is only reported
+        if let Some(dir) = &self.archive_dir {
+            let stored = BlockArchive::open(dir, self.codec).and_then(|archive| pruned.iter().try_for_each(|block| archive.store(block)));
+            if let Err(e) = stored {
+                warn!(error = ?e, "Failed to archive pruned blocks");
+            }
+        }
+    }
+
+    // Prune every `interval_ms` until `shutdown` resolves
+    pub async fn run(self, builder: BlockBuilder, shutdown: impl Future<Output = ()>) {
+        let mut ticker = tokio::time::interval(Duration::from_millis(self.interval_ms));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = ticker.tick() => {}
+            }
+
+            let (config, builder) = (self.clone(), builder.clone());
+            if tokio::task::spawn_blocking(move || config.prune(&builder)).await.is_err() {
+                error!("Pruning task panicked");
+            }
+        }
+    }
+}
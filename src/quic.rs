@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{self, Error, ErrorKind},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ed25519_dalek::{PublicKey, Signature};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    ClientConfig, Connection, ConnectionError, Endpoint, EndpointConfig, Incoming, ServerConfig, TokioRuntime, TransportConfig, VarInt,
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, CryptoProvider},
+    pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+use tracing::debug;
+
+use crate::{
+    builder::BlockBuilder,
+    structures::{Pubkey, Transaction},
+    wallet::Wallet,
+};
+
+// Protocol both ends of an ingestion connection negotiate
+pub const QUIC_ALPN: &[u8] = b"litechain-tpu/1";
+// Largest transaction taken over a stream
+pub const MAX_QUIC_TRANSACTION_SIZE: usize = 64 * 1024;
+
+// Code a connection is closed with when it isn't admitted
+const REFUSED: u32 = 1;
+const SHUTDOWN: u32 = 2;
+// An `Option<Identity>`: its tag, key & signature
+const MAX_IDENTITY_SIZE: usize = 1 + 32 + Signature::BYTE_SIZE;
+// Label of the TLS keying material a client signs to prove its identity. It's unique to the
+// connection, so the signature can't be replayed on another.
+const IDENTITY_LABEL: &[u8] = b"litechain quic identity";
+// Server name clients ask for. The server's certificate is self-signed & not checked, see `AnyServerCert`.
+const SERVER_NAME: &str = "litechain";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct QuicConfig {
+    // Connections open to validators at once, shared out among them by active stake, with each
+    // getting at least one
+    pub staked_connections: usize,
+    // Connections open to everyone else at once, together
+    pub unstaked_connections: usize,
+    // Transactions a connection may have in flight, each on its own stream
+    pub max_streams: u32,
+    // How long a client has to identify itself once connected
+    pub handshake_timeout_ms: u64,
+}
+
+impl Default for QuicConfig {
+    fn default() -> Self {
+        Self {
+            staked_connections: 512,
+            unstaked_connections: 128,
+            max_streams: 128,
+            handshake_timeout_ms: 2000,
+        }
+    }
+}
+
+// Who a client says it is: its key, signed over the connection's keying material
+#[derive(Debug, borsh::BorshSerialize, borsh::BorshDeserialize)]
+struct Identity {
+    pubkey: Pubkey,
+    signature: [u8; Signature::BYTE_SIZE],
+}
+
+// Connections open per validator key, or per `None` for unstaked clients
+#[derive(Debug, Default)]
+struct Admissions(Mutex<HashMap<Option<Pubkey>, usize>>);
+
+impl Admissions {
+    fn admit(self: &Arc<Self>, key: Option<Pubkey>, limit: usize) -> Option<Admitted> {
+        let mut open = self.0.lock().unwrap();
+        let count = open.entry(key).or_default();
+        if *count >= limit {
+            return None
+        }
+        *count += 1;
+        Some(Admitted { admissions: Arc::clone(self), key })
+    }
+}
+
+// A connection's slot, given back when it closes
+struct Admitted {
+    admissions: Arc<Admissions>,
+    key: Option<Pubkey>,
+}
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        let mut open = self.admissions.0.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.key);
+            }
+        }
+    }
+}
+
+// Transaction ingestion over QUIC, apart from the query RPC server. A client opens a connection,
+// identifies itself on its first (bidirectional) stream & waits to be admitted, then sends each
+// transaction, as its canonical bytes, on a unidirectional stream of its own. Validators that prove
+// their key get slots in proportion to their stake, so a flood of unstaked connections can't crowd them
+// out. Transactions go into the mempool like ones sent over RPC, preflight & all.
+#[derive(Debug, Clone)]
+pub struct QuicServer {
+    builder: BlockBuilder,
+    config: QuicConfig,
+    admissions: Arc<Admissions>,
+}
+
+impl QuicServer {
+    pub fn new(builder: BlockBuilder) -> Self {
+        Self { builder, config: QuicConfig::default(), admissions: Arc::default() }
+    }
+
+    pub fn with_config(mut self, config: QuicConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Accept connections on `socket` until `shutdown` resolves
+    pub async fn serve(self, socket: UdpSocket, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+        let endpoint = Endpoint::new(EndpointConfig::default(), Some(self.server_config()?), socket, Arc::new(TokioRuntime))?;
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                incoming = endpoint.accept() => match incoming {
+                    Some(incoming) => { tokio::spawn(self.clone().handle(incoming)); }
+                    None => break,
+                },
+            }
+        }
+        endpoint.close(VarInt::from_u32(SHUTDOWN), b"Shutting down");
+        endpoint.wait_idle().await;
+        Ok(())
+    }
+
+    fn server_config(&self) -> io::Result<ServerConfig> {
+        let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).map_err(Error::other)?;
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(Error::other)?
+            .with_no_client_auth()
+            .with_single_cert(vec![certified.cert.der().clone()], key.into())
+            .map_err(Error::other)?;
+        tls.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+
+        // Only the identity stream until admitted, see `handle`
+        let mut transport = TransportConfig::default();
+        transport.max_concurrent_bidi_streams(VarInt::from_u32(1)).max_concurrent_uni_streams(VarInt::from_u32(0));
+        let mut config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls).map_err(Error::other)?));
+        config.transport_config(Arc::new(transport));
+        Ok(config)
+    }
+
+    async fn handle(self, incoming: Incoming) {
+        let Ok(connection) = incoming.await else { return };
+        let timeout = Duration::from_millis(self.config.handshake_timeout_ms);
+        let admitted = match tokio::time::timeout(timeout, self.admit(&connection)).await {
+            Ok(Ok(admitted)) => admitted,
+            Ok(Err(reason)) => return connection.close(VarInt::from_u32(REFUSED), reason.as_bytes()),
+            Err(_) => return connection.close(VarInt::from_u32(REFUSED), b"Handshake timed out"),
+        };
+        connection.set_max_concurrent_uni_streams(VarInt::from_u32(self.config.max_streams));
+
+        while let Ok(mut stream) = connection.accept_uni().await {
+            let server = self.clone();
+            tokio::spawn(async move {
+                let Ok(bytes) = stream.read_to_end(MAX_QUIC_TRANSACTION_SIZE).await else { return };
+                match Transaction::from_bytes(&bytes).and_then(|tx| server.submit(tx)) {
+                    Ok(id) => debug!(id, "Transaction received over QUIC"),
+                    Err(e) => debug!(error = e, "Transaction over QUIC refused"),
+                }
+            });
+        }
+        drop(admitted);
+    }
+
+    // Read the client's identity & take a slot for it, or say why there isn't one
+    async fn admit(&self, connection: &Connection) -> Result<Admitted, &'static str> {
+        let (mut send, mut recv) = connection.accept_bi().await.map_err(|_| "Connection lost")?;
+        let bytes = recv.read_to_end(MAX_IDENTITY_SIZE).await.map_err(|_| "Invalid identity")?;
+        let identity: Option<Identity> = borsh::from_slice(&bytes).map_err(|_| "Invalid identity")?;
+        let pubkey = match identity {
+            Some(identity) => Some(verify_identity(connection, &identity).then_some(identity.pubkey).ok_or("Invalid identity signature")?),
+            None => None,
+        };
+
+        let (key, limit) = match pubkey.and_then(|pubkey| self.staked_share(&pubkey).map(|share| (pubkey, share))) {
+            Some((pubkey, share)) => (Some(pubkey), share),
+            None => (None, self.config.unstaked_connections),
+        };
+        let admitted = self.admissions.admit(key, limit).ok_or(if key.is_some() { "Too many connections for stake" } else { "Too many unstaked connections" })?;
+        send.write_all(&[1]).await.map_err(|_| "Connection lost")?;
+        send.finish().map_err(|_| "Connection lost")?;
+        Ok(admitted)
+    }
+
+    // Connections `pubkey` may hold, if it's a validator leading & voting this epoch. While none has
+    // stake, every one of them gets an equal share.
+    fn staked_share(&self, pubkey: &Pubkey) -> Option<usize> {
+        let candidates = self.builder.db.read().unwrap().candidates();
+        let stake = candidates.iter().find(|(candidate, _)| candidate == pubkey)?.1 as u128;
+        let total: u128 = candidates.iter().map(|(_, stake)| *stake as u128).sum();
+        let slots = self.config.staked_connections as u128;
+        let share = (slots * stake).checked_div(total).unwrap_or(slots / candidates.len() as u128);
+        Some((share as usize).max(1))
+    }
+
+    fn submit(&self, tx: Transaction) -> Result<u64, &'static str> {
+        match &self.builder.network {
+            Some(network) => network.send_transaction(tx),
+            None => self.builder.mempool.read().unwrap().send_transaction(tx),
+        }
+    }
+}
+
+fn identity_message(connection: &Connection) -> Option<[u8; 32]> {
+    let mut message = [0u8; 32];
+    connection.export_keying_material(&mut message, IDENTITY_LABEL, b"").ok()?;
+    Some(message)
+}
+
+fn verify_identity(connection: &Connection, identity: &Identity) -> bool {
+    let (Some(message), Ok(public_key), Ok(signature)) =
+        (identity_message(connection), PublicKey::from_bytes(&identity.pubkey), Signature::from_bytes(&identity.signature))
+    else {
+        return false
+    };
+    public_key.verify_strict(&message, &signature).is_ok()
+}
+
+// A connection to a node's QUIC ingestion endpoint
+#[derive(Debug)]
+pub struct QuicClient {
+    endpoint: Endpoint,
+    connection: Connection,
+}
+
+impl QuicClient {
+    // Connect & identify as `wallet`, for a validator's share of connections, or anonymously. Fails with
+    // `ConnectionRefused` if the node has no room.
+    pub async fn connect(addr: SocketAddr, wallet: Option<&Wallet>) -> io::Result<Self> {
+        let local: SocketAddr = if addr.is_ipv4() { (Ipv4Addr::UNSPECIFIED, 0).into() } else { (Ipv6Addr::UNSPECIFIED, 0).into() };
+        let mut endpoint = Endpoint::client(local)?;
+        endpoint.set_default_client_config(client_config()?);
+        let connection = endpoint.connect(addr, SERVER_NAME).map_err(Error::other)?.await.map_err(refused)?;
+
+        let identity = match wallet {
+            Some(wallet) => {
+                let message = identity_message(&connection).ok_or_else(|| Error::other("No keying material to sign"))?;
+                Some(Identity { pubkey: wallet.public_key, signature: wallet.sign(&message).to_bytes() })
+            }
+            None => None,
+        };
+        let (mut send, mut recv) = connection.open_bi().await.map_err(refused)?;
+        send.write_all(&borsh::to_vec(&identity)?).await?;
+        send.finish().map_err(Error::other)?;
+        match recv.read_to_end(1).await {
+            Ok(answer) if answer == [1] => Ok(Self { endpoint, connection }),
+            _ => Err(connection.close_reason().map_or_else(|| Error::other("Handshake failed"), refused)),
+        }
+    }
+
+    // Send `tx` on a stream of its own. The node doesn't answer; watch for it over RPC.
+    pub async fn send_transaction(&self, tx: &Transaction) -> io::Result<()> {
+        let mut stream = self.connection.open_uni().await.map_err(refused)?;
+        stream.write_all(&tx.to_bytes()).await?;
+        stream.finish().map_err(Error::other)
+    }
+
+    // Close the connection once everything sent has been delivered
+    pub async fn close(self) {
+        self.connection.close(VarInt::from_u32(0), b"");
+        self.endpoint.wait_idle().await;
+    }
+}
+
+// A refusal as `ConnectionRefused` with the node's reason, any other failure as is
+fn refused(error: ConnectionError) -> Error {
+    match error {
+        ConnectionError::ApplicationClosed(close) if close.error_code == VarInt::from_u32(REFUSED) => {
+            Error::new(ErrorKind::ConnectionRefused, String::from_utf8_lossy(&close.reason).into_owned())
+        }
+        error => Error::other(error),
+    }
+}
+
+fn client_config() -> io::Result<ClientConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let mut tls = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyServerCert(provider)))
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls).map_err(Error::other)?)))
+}
+
+// Takes any server certificate. Nodes are reached by address & everything sent is signed, so there's
+// nothing for the server's identity to protect; the TLS handshake's own signatures are still checked.
+#[derive(Debug)]
+struct AnyServerCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyServerCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
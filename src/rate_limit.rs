@@ -0,0 +1,173 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Most clients tracked at once. Past that, whoever made a request least recently is forgotten, & starts
+// over with a full allowance if they come back.
+pub const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// How much one client may ask of the RPC server. Each allowance refills at its rate up to its burst;
+// a rate of 0 lifts that limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Quota {
+    pub requests_per_second: u64,
+    // Requests that can be made back to back after a quiet spell
+    pub request_burst: u64,
+    // Bytes of requests & responses together
+    pub bytes_per_second: u64,
+    pub byte_burst: u64,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 50,
+            request_burst: 100,
+            bytes_per_second: 1 << 20,
+            byte_burst: 4 << 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    // Quota of each IP address
+    pub per_ip: Quota,
+    // API keys, sent in the `x-api-key` header, each limited by its own quota rather than sharing its
+    // IP's. Keys not listed here are ignored.
+    pub api_keys: BTreeMap<String, Quota>,
+}
+
+// Who a request is charged to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    Ip(IpAddr),
+    ApiKey(String),
+}
+
+// What's left of a client's allowances. The byte allowance can go into debt, since a response's size
+// isn't known until after it's let through.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    requests: f64,
+    bytes: f64,
+    updated: Instant,
+    // When the client last made a request, counted in requests made of the limiter
+    used: u64,
+}
+
+impl Bucket {
+    fn full(quota: &Quota) -> Self {
+        Self { requests: quota.request_burst as f64, bytes: quota.byte_burst as f64, updated: Instant::now(), used: 0 }
+    }
+
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.requests = (self.requests + elapsed * quota.requests_per_second as f64).min(quota.request_burst as f64);
+        self.bytes = (self.bytes + elapsed * quota.bytes_per_second as f64).min(quota.byte_burst as f64);
+        self.updated = now;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_key: HashMap<RateLimitKey, Bucket>,
+    // Keys by when they were last used, least recently first
+    by_use: BTreeMap<u64, RateLimitKey>,
+    uses: u64,
+}
+
+impl Buckets {
+    // `key`'s bucket, marked as the most recently used. Making room for a new one forgets whichever
+    // was used least recently.
+    fn touch(&mut self, key: &RateLimitKey, quota: &Quota) -> &mut Bucket {
+        let used = self.uses;
+        self.uses += 1;
+        match self.by_key.get(key) {
+            Some(bucket) => {
+                self.by_use.remove(&bucket.used);
+            }
+            None => {
+                while self.by_key.len() >= MAX_TRACKED_CLIENTS {
+                    let Some((_, oldest)) = self.by_use.pop_first() else { break };
+                    self.by_key.remove(&oldest);
+                }
+            }
+        }
+        self.by_use.insert(used, key.clone());
+        let bucket = self.by_key.entry(key.clone()).or_insert_with(|| Bucket::full(quota));
+        bucket.used = used;
+        bucket
+    }
+}
+
+// Token buckets for each client of the RPC server, per IP address or per API key
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self { config, buckets: Arc::default() }
+    }
+
+    // The key a request is charged to: its API key if that's one we know, its IP otherwise
+    pub fn key(&self, ip: IpAddr, api_key: Option<&str>) -> RateLimitKey {
+        match api_key.filter(|api_key| self.config.api_keys.contains_key(*api_key)) {
+            Some(api_key) => RateLimitKey::ApiKey(api_key.to_string()),
+            None => RateLimitKey::Ip(ip),
+        }
+    }
+
+    fn quota(&self, key: &RateLimitKey) -> Quota {
+        match key {
+            RateLimitKey::ApiKey(api_key) => self.config.api_keys.get(api_key).copied().unwrap_or(self.config.per_ip),
+            RateLimitKey::Ip(_) => self.config.per_ip,
+        }
+    }
+
+    // Take a request carrying `bytes` out of `key`'s allowance, or say how long until it has room. A
+    // request is let through as long as the byte allowance isn't in debt, so one bigger than the
+    // burst isn't shut out for good.
+    pub fn check(&self, key: &RateLimitKey, bytes: u64) -> Result<(), Duration> {
+        let quota = self.quota(key);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.touch(key, &quota);
+        bucket.refill(&quota, now);
+
+        let wait = |missing: f64, rate: u64| if missing <= 0.0 || rate == 0 { 0.0 } else { missing / rate as f64 };
+        let wait = wait(1.0 - bucket.requests, quota.requests_per_second).max(wait(-bucket.bytes, quota.bytes_per_second));
+        if wait > 0.0 {
+            return Err(Duration::from_secs_f64(wait))
+        }
+        if quota.requests_per_second > 0 {
+            bucket.requests -= 1.0;
+        }
+        if quota.bytes_per_second > 0 {
+            bucket.bytes -= bytes as f64;
+        }
+        Ok(())
+    }
+
+    // Charge `key` for bytes sent back to it
+    pub fn charge(&self, key: &RateLimitKey, bytes: u64) {
+        if self.quota(key).bytes_per_second == 0 {
+            return
+        }
+        if let Some(bucket) = self.buckets.lock().unwrap().by_key.get_mut(key) {
+            bucket.bytes -= bytes as f64;
+        }
+    }
+
+    // Clients currently tracked, at most `MAX_TRACKED_CLIENTS`
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().unwrap().by_key.len()
+    }
+}
@@ -0,0 +1,150 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension,
+    Router,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    chain::Commitment,
+    rpc::{http_client, throttled, RpcServer, INVALID_PARAMS, METHOD_NOT_FOUND, PRUNED},
+};
+
+// Items a page holds when the request doesn't say
+pub const DEFAULT_REST_PAGE: u64 = 20;
+
+type ConnectedTo = Option<Extension<ConnectInfo<SocketAddr>>>;
+// A status & JSON body to answer with instead
+type Failure = (StatusCode, Value);
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ReadQuery {
+    commitment: Option<Commitment>,
+}
+
+// `?start=&limit=`, plus the commitment to read at
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct BlocksQuery {
+    start: u64,
+    limit: Option<u64>,
+    commitment: Option<Commitment>,
+}
+
+// `?limit=`, & `?before_height=&before_index=` from the last page's `next`
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct HistoryQuery {
+    limit: Option<u64>,
+    before_height: Option<u64>,
+    before_index: Option<u64>,
+    commitment: Option<Commitment>,
+}
+
+// Read-only REST endpoints for block explorers, alongside JSON-RPC on the same server. Each is answered
+// by the JSON-RPC method it mirrors, so both see the same state, commitment & quotas. Anything not found
+// is a 404; pages come back as `{ "items": [...], "next": ... }`, with `next` the query for the
+// following page, or null on the last one.
+pub fn routes() -> Router<RpcServer> {
+    Router::new()
+        .route("/blocks", get(blocks))
+        .route("/blocks/{height}", get(block))
+        .route("/accounts/{address}", get(account))
+        .route("/accounts/{address}/transactions", get(account_transactions))
+        .route("/transactions/{id}", get(transaction))
+}
+
+async fn blocks(State(server): State<RpcServer>, connect_info: ConnectedTo, headers: HeaderMap, Query(query): Query<BlocksQuery>) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_REST_PAGE).max(1);
+    let end = query.start.saturating_add(limit - 1);
+    respond(&server, connect_info, &headers, |server| {
+        let blocks = call(server, "getBlocks", vec![json!(query.start), json!(end)], query.commitment)?;
+        let height = call(server, "getBlockHeight", vec![], query.commitment)?.as_u64().unwrap_or(0);
+        // The server may have cut the page short, so carry on from whatever came last
+        let last = blocks.as_array().and_then(|blocks| blocks.last()).and_then(|block| block["header"]["height"].as_u64());
+        let next = last.filter(|last| *last < height).map(|last| json!({ "start": last + 1, "limit": limit }));
+        Ok(json!({ "items": blocks, "next": next }))
+    })
+}
+
+async fn block(State(server): State<RpcServer>, connect_info: ConnectedTo, headers: HeaderMap, Path(height): Path<u64>, Query(query): Query<ReadQuery>) -> Response {
+    respond(&server, connect_info, &headers, |server| call(server, "getBlock", vec![json!(height)], query.commitment))
+}
+
+async fn account(State(server): State<RpcServer>, connect_info: ConnectedTo, headers: HeaderMap, Path(address): Path<String>, Query(query): Query<ReadQuery>) -> Response {
+    respond(&server, connect_info, &headers, |server| call(server, "getAccount", vec![json!(address)], query.commitment))
+}
+
+async fn account_transactions(
+    State(server): State<RpcServer>,
+    connect_info: ConnectedTo,
+    headers: HeaderMap,
+    Path(address): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_REST_PAGE).max(1);
+    let before = query.before_height.map(|height| json!([height, query.before_index.unwrap_or(0)])).unwrap_or(Value::Null);
+    respond(&server, connect_info, &headers, |server| {
+        let entries = call(server, "getAccountHistory", vec![json!(address), json!(limit), before], query.commitment)?;
+        let page = entries.as_array().map_or(&[][..], Vec::as_slice);
+        let items: Vec<Value> = page.iter().map(|entry| json!({ "height": entry[0], "index": entry[1] })).collect();
+        let next = (items.len() as u64 == limit)
+            .then(|| items.last())
+            .flatten()
+            .map(|last| json!({ "limit": limit, "before_height": last["height"], "before_index": last["index"] }));
+        Ok(json!({ "items": items, "next": next }))
+    })
+}
+
+// A transaction by hash or signature, with its status & the transaction itself once it's in a block
+async fn transaction(State(server): State<RpcServer>, connect_info: ConnectedTo, headers: HeaderMap, Path(id): Path<String>, Query(query): Query<ReadQuery>) -> Response {
+    respond(&server, connect_info, &headers, |server| {
+        let status = call(server, "getTransactionStatus", vec![json!(id)], query.commitment)?;
+        if status == json!("Unknown") {
+            return Ok(Value::Null)
+        }
+        let included = call(server, "getTransaction", vec![json!(id)], query.commitment)?;
+        Ok(json!({ "status": status, "included": included }))
+    })
+}
+
+// Run a JSON-RPC method, reading at `commitment` if given
+fn call(server: &RpcServer, method: &str, mut params: Vec<Value>, commitment: Option<Commitment>) -> Result<Value, Failure> {
+    if let Some(commitment) = commitment {
+        params.push(json!({ "commitment": commitment }));
+    }
+    server.dispatch(method, &Value::Array(params)).map_err(|error| {
+        let status = match error.code {
+            INVALID_PARAMS => StatusCode::BAD_REQUEST,
+            METHOD_NOT_FOUND => StatusCode::NOT_FOUND,
+            PRUNED => StatusCode::GONE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, json!({ "error": error.message }))
+    })
+}
+
+// Answer with `read`'s JSON, or a 404 if it found nothing, charging the bytes to the client's quota
+fn respond(server: &RpcServer, connect_info: ConnectedTo, headers: &HeaderMap, read: impl FnOnce(&RpcServer) -> Result<Value, Failure>) -> Response {
+    let (addr, api_key) = http_client(connect_info, headers);
+    let limit = match server.rate_limit(addr, api_key, 0) {
+        Ok(limit) => limit,
+        Err(wait) => return throttled(wait),
+    };
+    let (status, value) = match read(server) {
+        Ok(Value::Null) => (StatusCode::NOT_FOUND, json!({ "error": "Not found" })),
+        Ok(value) => (StatusCode::OK, value),
+        Err(failure) => failure,
+    };
+    let body = serde_json::to_vec(&value).expect("JSON values always encode");
+    if let Some((limiter, key)) = limit {
+        limiter.charge(&key, body.len() as u64);
+    }
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
@@ -0,0 +1,59 @@
+use crate::{
+    db::AccountsDB,
+    structures::Pubkey,
+    vote::{voting_weight, Quorum},
+};
+
+// Per-block issuance: `initial_reward` halving every `halving_interval` blocks, with `proposer_percent`
+// going to the block's proposer and the rest split between voters by voting weight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RewardConfig {
+    pub initial_reward: u64,
+    pub halving_interval: u64,
+    pub proposer_percent: u64,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        Self {
+            initial_reward: 1_000,
+            halving_interval: 100_000,
+            proposer_percent: 20,
+        }
+    }
+}
+
+impl RewardConfig {
+    pub fn block_reward(&self, height: u64) -> u64 {
+        if self.halving_interval == 0 {
+            return self.initial_reward
+        }
+
+        let halvings = height / self.halving_interval;
+        self.initial_reward.checked_shr(halvings as u32).unwrap_or(0)
+    }
+
+    // How the reward for a block at `height` is split. Rounding dust goes to the proposer.
+    // Must be called against the same state the quorum was aggregated with.
+    pub fn split(&self, height: u64, proposer: &Pubkey, quorum: &Quorum, db: &AccountsDB) -> Vec<(Pubkey, u64)> {
+        let reward = self.block_reward(height);
+        let voter_pool = reward - reward * self.proposer_percent.min(100) / 100;
+
+        let mut rewards: Vec<(Pubkey, u64)> = vec![];
+        let mut distributed = 0u64;
+
+        if quorum.weight > 0 {
+            for vote in &quorum.votes {
+                let weight = voting_weight(db, &vote.validator) as u128;
+                let share = (voter_pool as u128 * weight / quorum.weight as u128) as u64;
+                if share > 0 {
+                    rewards.push((vote.validator, share));
+                    distributed += share;
+                }
+            }
+        }
+
+        rewards.push((*proposer, reward.saturating_sub(distributed)));
+        rewards
+    }
+}
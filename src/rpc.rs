@@ -0,0 +1,496 @@
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::Duration,
+};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension,
+    Router,
+};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::{
+    builder::BlockBuilder,
+    chain::{Blockchain, Commitment},
+    db::{AccountsDB, HistoryError},
+    rate_limit::{RateLimitConfig, RateLimitKey, RateLimiter},
+    rest,
+    structures::{pubkey_from_address, AirdropTransaction, Blockhash, Pubkey, Transaction, TransactionBody, TransactionId, TransactionSign, TransactionStatus},
+    wallet::Wallet,
+};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_ERROR: i64 = -32000;
+// What was asked for is older than the node keeps
+pub const PRUNED: i64 = -32001;
+// The client is over its quota. The error's data says when to retry.
+pub const RATE_LIMITED: i64 = -32005;
+
+// Header naming the API key a request is charged to
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// Most account history entries returned per request
+pub const MAX_HISTORY_PAGE: usize = 1000;
+// Most accounts or validators returned per page
+pub const MAX_ACCOUNTS_PAGE: usize = 1000;
+// Default for the most blocks or headers a range query returns
+pub const DEFAULT_MAX_BLOCK_PAGE: u64 = 100;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: &str) -> Self {
+        Self { code, message: message.to_string(), data: None }
+    }
+
+    // Over quota, with how long until the client may ask again
+    pub fn rate_limited(wait: Duration) -> Self {
+        let retry_after_ms = wait.as_millis().max(1) as u64;
+        Self { code: RATE_LIMITED, message: "Rate limit exceeded".to_string(), data: Some(json!({ "retryAfterMs": retry_after_ms })) }
+    }
+}
+
+impl From<HistoryError> for RpcError {
+    fn from(error: HistoryError) -> Self {
+        match error {
+            HistoryError::Pruned { horizon } => Self { code: PRUNED, message: format!("Pruned, the oldest height kept is {}", horizon), data: None },
+            error => Self::new(SERVER_ERROR, error.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+// JSON-RPC 2.0 over HTTP POST, serving reads from & submitting transactions to a node's builder state.
+// Params are positional, pubkeys are hex addresses & transactions are hex-encoded canonical bytes.
+// Queries may end their params with a `{ "commitment": "confirmed" }` object to read from the newest
+// block at that commitment rather than the tip. `sendTransaction` takes `{ "skipPreflight": true }` after
+// the transaction to skip the mempool's check against current state.
+#[derive(Debug, Clone)]
+pub struct RpcServer {
+    pub(crate) builder: BlockBuilder,
+    // Signs `requestAirdrop`s on dev chains
+    faucet: Option<Wallet>,
+    airdrop_nonce: Arc<AtomicU64>,
+    // Most blocks or headers `getBlocks` & `getBlockHeaders` return
+    max_block_page: u64,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl RpcServer {
+    pub fn new(builder: BlockBuilder) -> Self {
+        Self { builder, faucet: None, airdrop_nonce: Arc::default(), max_block_page: DEFAULT_MAX_BLOCK_PAGE, rate_limiter: None }
+    }
+
+    // Hold each client to a quota of requests & bytes, turning away whatever goes over it with HTTP 429
+    // & a `RATE_LIMITED` error
+    pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(config));
+        self
+    }
+
+    pub fn with_max_block_page(mut self, max_block_page: u64) -> Self {
+        self.max_block_page = max_block_page.max(1);
+        self
+    }
+
+    // Serve `requestAirdrop`, minting through airdrop transactions signed by `faucet`
+    pub fn with_faucet(mut self, faucet: Wallet) -> Self {
+        self.faucet = Some(faucet);
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/", post(handle_http))
+            .route("/metrics", get(handle_metrics))
+            .merge(rest::routes())
+            .with_state(self)
+    }
+
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> io::Result<()> {
+        axum::serve(listener, self.router().into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(shutdown)
+            .await
+    }
+
+    pub fn handle(&self, request: RpcRequest) -> RpcResponse {
+        let (result, error) = match self.dispatch(&request.method, &request.params) {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result,
+            error,
+        }
+    }
+
+    // An inclusive [start, end] height range, cut down to at most `max_block_page` blocks
+    fn page_params(&self, params: &Value) -> Result<(u64, u64), RpcError> {
+        let start = u64_param(params, 0)?;
+        let end = u64_param(params, 1)?;
+        Ok((start, end.min(start.saturating_add(self.max_block_page - 1))))
+    }
+
+    // Height of the newest canonical block at `commitment`
+    fn committed_height(&self, commitment: Commitment) -> u64 {
+        let db = self.builder.db.read().unwrap();
+        self.builder.chain.read().unwrap().commitment_height(commitment, &db)
+    }
+
+    // Run `read` against state as of the newest block at `commitment`
+    fn read_state<T>(&self, commitment: Commitment, read: impl FnOnce(&AccountsDB) -> T) -> Result<T, RpcError> {
+        let db = self.builder.db.read().unwrap();
+        let height = self.builder.chain.read().unwrap().commitment_height(commitment, &db);
+        Ok(read(db.state_at(height)?.as_ref()))
+    }
+
+    // The limiter & key to charge a request of `bytes` from `addr` to, if this server rate limits &
+    // knows who's asking (which it does when served through `serve` or over gRPC), or how long until it
+    // may ask again if the client is over its quota
+    pub(crate) fn rate_limit(&self, addr: Option<SocketAddr>, api_key: Option<&str>, bytes: u64) -> Result<Option<(&RateLimiter, RateLimitKey)>, Duration> {
+        let Some((limiter, addr)) = self.rate_limiter.as_ref().zip(addr) else { return Ok(None) };
+        let key = limiter.key(addr.ip(), api_key);
+        limiter.check(&key, bytes)?;
+        Ok(Some((limiter, key)))
+    }
+
+    pub(crate) fn dispatch(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        let commitment = commitment_param(params)?;
+        match method {
+            "getAccount" => {
+                let pubkey = pubkey_param(params, 0)?;
+                self.read_state(commitment, |db| json!(db.get_account(&pubkey)))
+            }
+            // The account with a Merkle path to the state root as of the block at the commitment, which the
+            // next block's header commits to
+            "getAccountWithProof" => {
+                let pubkey = pubkey_param(params, 0)?;
+                self.read_state(commitment, |db| {
+                    db.get_account_with_proof(&pubkey).map_or(Value::Null, |(account, proof)| json!({
+                        "height": db.latest_height,
+                        "state_root": hex::encode(db.state_root()),
+                        "account": account,
+                        "proof": proof,
+                    }))
+                })
+            }
+            "getAccountAt" => {
+                let pubkey = pubkey_param(params, 0)?;
+                let height = u64_param(params, 1)?;
+                let account = self.builder.db.read().unwrap().get_account_at(&pubkey, height)?;
+                Ok(json!(account))
+            }
+            "getMultipleAccounts" => {
+                let addresses = param(params, 0)?.as_array().ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected an array of addresses"))?;
+                let pubkeys = addresses
+                    .iter()
+                    .map(|address| address.as_str().ok_or("Expected a string address").and_then(pubkey_from_address))
+                    .collect::<Result<Vec<Pubkey>, _>>()
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                self.read_state(commitment, |db| json!(db.get_multiple_accounts(&pubkeys)))
+            }
+            "getBalance" => {
+                let pubkey = pubkey_param(params, 0)?;
+                self.read_state(commitment, |db| json!(db.get_account(&pubkey).map_or(0, |account| account.balance)))
+            }
+            "getBalanceAt" => {
+                let pubkey = pubkey_param(params, 0)?;
+                let height = u64_param(params, 1)?;
+                let balance = self.builder.db.read().unwrap().get_balance_at(&pubkey, height)?;
+                Ok(json!(balance))
+            }
+            "getAccountCount" => self.read_state(commitment, |db| json!(db.account_count())),
+            "getAccounts" => {
+                let (offset, limit) = account_page_params(params)?;
+                self.read_state(commitment, |db| json!(db.iter_accounts(offset, limit)))
+            }
+            "getValidators" => {
+                let (offset, limit) = account_page_params(params)?;
+                self.read_state(commitment, |db| json!(db.iter_validators(offset, limit)))
+            }
+            "getValidator" => {
+                let pubkey = pubkey_param(params, 0)?;
+                self.read_state(commitment, |db| json!(db.get_validator(&pubkey)))
+            }
+            "getPendingWithdrawals" => {
+                let pubkey = pubkey_param(params, 0)?;
+                self.read_state(commitment, |db| json!(db.pending_withdrawals(&pubkey)))
+            }
+            "getAllowance" => {
+                let owner = pubkey_param(params, 0)?;
+                let delegate = pubkey_param(params, 1)?;
+                self.read_state(commitment, |db| json!(db.allowance(&owner, &delegate)))
+            }
+            "getMint" => {
+                let id = pubkey_param(params, 0)?;
+                self.read_state(commitment, |db| json!(db.get_mint(&id)))
+            }
+            "getTokenBalance" => {
+                let mint = pubkey_param(params, 0)?;
+                let owner = pubkey_param(params, 1)?;
+                self.read_state(commitment, |db| json!(db.token_balance(&mint, &owner)))
+            }
+            "getEpochInfo" => self.read_state(commitment, |db| json!(db.epoch_info())),
+            "getTotalSupply" => self.read_state(commitment, |db| json!(db.total_supply)),
+            "getBlockHeight" => Ok(json!(self.committed_height(commitment))),
+            "getLatestBlockhash" => {
+                let height = self.committed_height(commitment);
+                let chain = self.builder.chain.read().unwrap();
+                Ok(json!(chain.block_at(height).map(|block| hex::encode(block.hash))))
+            }
+            "getAccountHistory" => {
+                let pubkey = pubkey_param(params, 0)?;
+                let limit = u64_param(params, 1)?.min(MAX_HISTORY_PAGE as u64) as usize;
+                let before: Option<(u64, u64)> = serde_json::from_value(params.get(2).cloned().unwrap_or(Value::Null))
+                    .map_err(|_| RpcError::new(INVALID_PARAMS, "Expected a [height, index] cursor"))?;
+                // Nothing from blocks above the commitment
+                let first_uncommitted = (self.committed_height(commitment) + 1, 0);
+                let before = before.map_or(first_uncommitted, |before| before.min(first_uncommitted));
+                Ok(json!(self.builder.chain.read().unwrap().account_history(&pubkey, limit, Some(before))))
+            }
+            "getTransactionStatus" => {
+                let id = transaction_id_param(params, 0)?;
+                let committed = self.committed_height(commitment);
+                match self.builder.get_transaction_status(&id) {
+                    TransactionStatus::Included { height, .. } if height > committed => Ok(json!(TransactionStatus::Pending)),
+                    status => Ok(json!(status)),
+                }
+            }
+            // The transaction itself & where it sits, once it's in a block at the commitment
+            "getTransaction" => {
+                let id = transaction_id_param(params, 0)?;
+                let committed = self.committed_height(commitment);
+                let chain = self.builder.chain.read().unwrap();
+                let Some(block) = chain.find_transaction(&id).and_then(|hash| chain.get(&hash)).filter(|block| block.height() <= committed) else {
+                    return Ok(Value::Null)
+                };
+                let found = block.transactions.iter().enumerate().find(|(_, tx)| id.matches(tx));
+                Ok(json!(found.map(|(index, tx)| json!({
+                    "transaction": tx,
+                    "block_hash": hex::encode(block.hash),
+                    "height": block.height(),
+                    "index": index,
+                }))))
+            }
+            "getBlock" => {
+                let height = u64_param(params, 0)?;
+                let committed = self.committed_height(commitment);
+                let chain = self.builder.chain.read().unwrap();
+                chain.ensure_unpruned(height)?;
+                Ok(json!(chain.block_at(height).filter(|_| height <= committed)))
+            }
+            "getBlockByHash" => {
+                let hash = hash_param(params, 0)?;
+                let committed = self.committed_height(commitment);
+                let chain = self.builder.chain.read().unwrap();
+                if let Some(height) = chain.height_of(&hash) {
+                    chain.ensure_unpruned(height)?;
+                }
+                Ok(json!(chain.get(&hash).filter(|_| is_committed(&chain, &hash, commitment, committed))))
+            }
+            "getBlocks" => {
+                let (start, end) = self.page_params(params)?;
+                let committed = self.committed_height(commitment);
+                let chain = self.builder.chain.read().unwrap();
+                chain.ensure_unpruned(start)?;
+                Ok(json!(chain.range(start, end.min(committed))))
+            }
+            "getBlockHeaders" => {
+                let (start, end) = self.page_params(params)?;
+                let committed = self.committed_height(commitment);
+                let headers = self.builder.chain.read().unwrap().headers(start, end.min(committed));
+                Ok(json!(headers.into_iter().map(|(hash, header)| json!({ "hash": hex::encode(hash), "header": header })).collect::<Vec<_>>()))
+            }
+            // At the given height, or the latest one
+            "getCheckpoint" => {
+                let chain = self.builder.chain.read().unwrap();
+                match params.get(0).and_then(Value::as_u64) {
+                    Some(height) => Ok(json!(chain.checkpoint(height))),
+                    None => Ok(json!(chain.latest_checkpoint())),
+                }
+            }
+            "getStateDiff" => {
+                let hash = hash_param(params, 0)?;
+                let committed = self.committed_height(commitment);
+                let chain = self.builder.chain.read().unwrap();
+                Ok(json!(chain.state_diff(&hash).filter(|_| is_committed(&chain, &hash, commitment, committed))))
+            }
+            "sendTransaction" => {
+                let bytes = hex::decode(str_param(params, 0)?)
+                    .map_err(|_| RpcError::new(INVALID_PARAMS, "Transaction is not valid hex"))?;
+                let tx = Transaction::from_bytes(&bytes).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                let skip_preflight = params.get(1).and_then(|config| config.get("skipPreflight")).and_then(Value::as_bool).unwrap_or(false);
+                self.submit(tx, skip_preflight)
+            }
+            // Run against state at the commitment without submitting it
+            "simulateTransaction" => {
+                let bytes = hex::decode(str_param(params, 0)?)
+                    .map_err(|_| RpcError::new(INVALID_PARAMS, "Transaction is not valid hex"))?;
+                let tx = Transaction::from_bytes(&bytes).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                self.read_state(commitment, |db| json!(db.simulate_transaction(&tx)))
+            }
+            "requestAirdrop" => {
+                let faucet = self.faucet.as_ref()
+                    .ok_or_else(|| RpcError::new(SERVER_ERROR, "Airdrops are only available on dev chains"))?;
+                let to = pubkey_param(params, 0)?;
+                let amt = u64_param(params, 1)?;
+
+                // Past both the faucet's nonce on chain & every airdrop already handed out, in case the
+                // faucet's key signed anything else meanwhile
+                let next = self.builder.db.read().unwrap().nonce(&faucet.public_key);
+                let nonce = self.airdrop_nonce
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nonce| Some(nonce.max(next) + 1))
+                    .unwrap_or_else(|nonce| nonce)
+                    .max(next);
+                let mut tx = Transaction::new(TransactionBody::Airdrop(AirdropTransaction::new(to, faucet.public_key, amt, nonce)));
+                tx.sign(faucet);
+                // Its nonce may follow airdrops still pending, which preflight would refuse it for
+                self.submit(tx, true)
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+
+    // Into the mempool, & out to peers if we have any
+    fn submit(&self, tx: Transaction, skip_preflight: bool) -> Result<Value, RpcError> {
+        let id = match (&self.builder.network, skip_preflight) {
+            (Some(network), false) => network.send_transaction(tx),
+            (Some(network), true) => network.send_raw_transaction(tx),
+            (None, false) => self.builder.mempool.read().unwrap().send_transaction(tx),
+            (None, true) => self.builder.mempool.read().unwrap().send_raw_transaction(tx),
+        };
+        id.map(|id| json!(id)).map_err(|e| RpcError::new(SERVER_ERROR, e))
+    }
+}
+
+// Prometheus scrape endpoint
+async fn handle_metrics(State(server): State<RpcServer>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], server.builder.metrics.render())
+}
+
+async fn handle_http(
+    State(server): State<RpcServer>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let (addr, api_key) = http_client(connect_info, &headers);
+    let limit = match server.rate_limit(addr, api_key, body.len() as u64) {
+        Ok(limit) => limit,
+        Err(wait) => return throttled(wait),
+    };
+
+    let response = match serde_json::from_str::<RpcRequest>(&body) {
+        Ok(request) => server.handle(request),
+        Err(_) => RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: Value::Null,
+            result: None,
+            error: Some(RpcError::new(PARSE_ERROR, "Invalid JSON-RPC request")),
+        },
+    };
+    let bytes = serde_json::to_vec(&response).expect("Responses always encode");
+    if let Some((limiter, key)) = &limit {
+        limiter.charge(key, bytes.len() as u64);
+    }
+    ([(header::CONTENT_TYPE, "application/json")], bytes).into_response()
+}
+
+// Who's asking over HTTP: their address, known when served through `serve`, & the API key they sent
+pub(crate) fn http_client(connect_info: Option<Extension<ConnectInfo<SocketAddr>>>, headers: &HeaderMap) -> (Option<SocketAddr>, Option<&str>) {
+    let addr = connect_info.map(|Extension(ConnectInfo(addr))| addr);
+    (addr, headers.get(API_KEY_HEADER).and_then(|api_key| api_key.to_str().ok()))
+}
+
+pub(crate) fn throttled(wait: Duration) -> Response {
+    let retry_after_ms = wait.as_millis().max(1) as u64;
+    let response = RpcResponse { jsonrpc: "2.0".to_string(), id: Value::Null, result: None, error: Some(RpcError::rate_limited(wait)) };
+    let headers = [(header::CONTENT_TYPE, "application/json".to_string()), (header::RETRY_AFTER, retry_after_ms.div_ceil(1000).to_string())];
+    (StatusCode::TOO_MANY_REQUESTS, headers, serde_json::to_vec(&response).expect("Responses always encode")).into_response()
+}
+
+fn param(params: &Value, index: usize) -> Result<&Value, RpcError> {
+    params.get(index).ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing parameter"))
+}
+
+fn str_param(params: &Value, index: usize) -> Result<&str, RpcError> {
+    param(params, index)?.as_str().ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a string parameter"))
+}
+
+fn u64_param(params: &Value, index: usize) -> Result<u64, RpcError> {
+    param(params, index)?.as_u64().ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected an integer parameter"))
+}
+
+// Whether the block is at `commitment`, given the height of the newest block that is. Processed covers
+// every known block, forks included; anything stronger only canonical ones.
+fn is_committed(chain: &Blockchain, hash: &Blockhash, commitment: Commitment, committed: u64) -> bool {
+    commitment == Commitment::Processed || chain.height_of(hash).is_some_and(|height| height <= committed)
+}
+
+// The `{ "commitment": ... }` object queries may end their params with, processed if there isn't one
+fn commitment_param(params: &Value) -> Result<Commitment, RpcError> {
+    let config = params.as_array().and_then(|params| params.last()).and_then(Value::as_object);
+    match config.and_then(|config| config.get("commitment")) {
+        Some(commitment) => serde_json::from_value(commitment.clone())
+            .map_err(|_| RpcError::new(INVALID_PARAMS, "Commitment must be processed, confirmed or finalized")),
+        None => Ok(Commitment::default()),
+    }
+}
+
+// An [offset, limit] page, with the limit capped at `MAX_ACCOUNTS_PAGE`
+fn account_page_params(params: &Value) -> Result<(usize, usize), RpcError> {
+    let offset = u64_param(params, 0)? as usize;
+    let limit = u64_param(params, 1)?.min(MAX_ACCOUNTS_PAGE as u64) as usize;
+    Ok((offset, limit))
+}
+
+fn hash_param(params: &Value, index: usize) -> Result<Blockhash, RpcError> {
+    let bytes = hex::decode(str_param(params, index)?).map_err(|_| RpcError::new(INVALID_PARAMS, "Hash is not valid hex"))?;
+    bytes.try_into().map_err(|_| RpcError::new(INVALID_PARAMS, "Hash must be 32 bytes"))
+}
+
+fn transaction_id_param(params: &Value, index: usize) -> Result<TransactionId, RpcError> {
+    let bytes = hex::decode(str_param(params, index)?).map_err(|_| RpcError::new(INVALID_PARAMS, "Transaction id is not valid hex"))?;
+    TransactionId::from_bytes(&bytes).ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a transaction hash or signature"))
+}
+
+fn pubkey_param(params: &Value, index: usize) -> Result<Pubkey, RpcError> {
+    pubkey_from_address(str_param(params, index)?).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
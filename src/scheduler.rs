@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::{
+    db::AccountsDB,
+    structures::{Pubkey, Transaction, TransactionSign},
+};
+
+// Group a block's transactions into batches whose members share no accounts, by index, in the order
+// the batches must run. Every account a transaction touches is also written (a debit, a credit or a
+// stake change), so its read & write sets are both `accounts()` and any shared account is a conflict.
+// A transaction goes in the batch after the last one that touched any of its accounts, which keeps
+// conflicting transactions in block order & makes running the batches equivalent to running the block
+// sequentially.
+pub fn schedule(transactions: &[Transaction]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = vec![];
+    let mut last_batch: HashMap<Pubkey, usize> = HashMap::new();
+
+    for (index, tx) in transactions.iter().enumerate() {
+        let accounts = tx.accounts();
+        let batch = accounts
+            .iter()
+            .filter_map(|pubkey| last_batch.get(pubkey))
+            .max()
+            .map_or(0, |batch| batch + 1);
+
+        if batch == batches.len() {
+            batches.push(vec![]);
+        }
+        batches[batch].push(index);
+        for pubkey in accounts {
+            last_batch.insert(pubkey, batch);
+        }
+    }
+
+    batches
+}
+
+// Execute transactions batch by batch, each batch's transactions concurrently on the rayon pool.
+// `AccountsDB` is a pair of DashMaps, so transactions on disjoint accounts only ever contend for a
+// shard lock. Fees, tips & burns are taken out of the supply & airdrops minted once a batch is done. On error, the other
+// transactions have still been applied; callers revert through the block's undo record.
+pub fn execute_parallel(transactions: &[Transaction], db: &mut AccountsDB) -> Result<(), &'static str> {
+    execute_parallel_results(transactions, db).into_iter().collect()
+}
+
+// `execute_parallel`, keeping each transaction's outcome, in block order. A failed transaction
+// isn't charged its fee.
+pub fn execute_parallel_results(transactions: &[Transaction], db: &mut AccountsDB) -> Vec<Result<(), &'static str>> {
+    let mut results = vec![Ok(()); transactions.len()];
+
+    for batch in schedule(transactions) {
+        let shared: &AccountsDB = db;
+        let outcomes: Vec<Result<(), &'static str>> = batch
+            .par_iter()
+            .map(|&index| transactions[index].apply(shared))
+            .collect();
+
+        let (mut burned, mut minted) = (0u64, 0u64);
+        for (&index, outcome) in batch.iter().zip(outcomes) {
+            if outcome.is_ok() {
+                let tx = &transactions[index];
+                burned = burned.saturating_add(tx.bid()).saturating_add(tx.burned());
+                minted = minted.saturating_add(tx.minted());
+            }
+            results[index] = outcome;
+        }
+        db.burn(burned);
+        db.mint(minted);
+    }
+
+    results
+}
+
+// One transaction at a time, in block order. The baseline `execute_parallel` must agree with.
+pub fn execute_sequential(transactions: &[Transaction], db: &mut AccountsDB) -> Result<(), &'static str> {
+    for tx in transactions {
+        tx.execute(db)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::structures::Pubkey;
+
+// What a peer can do wrong, each adding its penalty to the peer's score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    // A block that fails validation
+    InvalidBlock,
+    // A transaction whose signatures don't check out
+    BadSignature,
+    // A message that doesn't decode
+    MalformedMessage,
+    // A message past the rate a peer may send at
+    Spam,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    pub invalid_block_penalty: u64,
+    pub bad_signature_penalty: u64,
+    pub malformed_message_penalty: u64,
+    pub spam_penalty: u64,
+    // Messages a peer may send each second before every further one counts as spam
+    pub max_messages_per_second: u32,
+    // Score at which a peer is banned
+    pub ban_threshold: u64,
+    // How long it takes a score to decay to half. 0 keeps scores from decaying at all.
+    pub half_life_ms: u64,
+    pub ban_duration_ms: u64,
+    // Ban the peer's IP address along with its identity, since a new identity costs nothing
+    pub ban_ip: bool,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            invalid_block_penalty: 50,
+            bad_signature_penalty: 20,
+            malformed_message_penalty: 20,
+            spam_penalty: 1,
+            max_messages_per_second: 1000,
+            ban_threshold: 100,
+            half_life_ms: 300_000,
+            ban_duration_ms: 3_600_000,
+            ban_ip: true,
+        }
+    }
+}
+
+impl ScoringConfig {
+    pub fn penalty(&self, misbehavior: Misbehavior) -> u64 {
+        match misbehavior {
+            Misbehavior::InvalidBlock => self.invalid_block_penalty,
+            Misbehavior::BadSignature => self.bad_signature_penalty,
+            Misbehavior::MalformedMessage => self.malformed_message_penalty,
+            Misbehavior::Spam => self.spam_penalty,
+        }
+    }
+
+    // What `score` has decayed to after `elapsed`
+    fn decay(&self, score: f64, elapsed: Duration) -> f64 {
+        if self.half_life_ms == 0 {
+            return score
+        }
+        score * 0.5f64.powf(elapsed.as_millis() as f64 / self.half_life_ms as f64)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Bans {
+    identities: HashMap<Pubkey, Instant>,
+    ips: HashMap<IpAddr, Instant>,
+}
+
+// Misbehavior scores of peers by identity, & who's banned until when
+#[derive(Debug, Default)]
+pub(crate) struct PeerScores {
+    config: RwLock<ScoringConfig>,
+    scores: Mutex<HashMap<Pubkey, (f64, Instant)>>,
+    bans: Mutex<Bans>,
+}
+
+impl PeerScores {
+    pub(crate) fn set_config(&self, config: ScoringConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    pub(crate) fn config(&self) -> ScoringConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub(crate) fn score(&self, identity: &Pubkey) -> f64 {
+        let config = self.config();
+        self.scores.lock().unwrap().get(identity).map_or(0.0, |(score, updated)| config.decay(*score, updated.elapsed()))
+    }
+
+    // Add `misbehavior`'s penalty to `identity`'s score. Returns true if that crosses the threshold,
+    // in which case the peer is banned, from `ip` too if the policy says so, & its score starts over.
+    pub(crate) fn report(&self, identity: &Pubkey, ip: IpAddr, misbehavior: Misbehavior) -> bool {
+        let config = self.config();
+        let now = Instant::now();
+        let mut scores = self.scores.lock().unwrap();
+        let (score, updated) = scores.entry(*identity).or_insert((0.0, now));
+        *score = config.decay(*score, now.saturating_duration_since(*updated)) + config.penalty(misbehavior) as f64;
+        *updated = now;
+        if *score < config.ban_threshold as f64 {
+            return false
+        }
+        scores.remove(identity);
+        drop(scores);
+
+        let until = now + Duration::from_millis(config.ban_duration_ms);
+        let mut bans = self.bans.lock().unwrap();
+        bans.identities.insert(*identity, until);
+        if config.ban_ip {
+            bans.ips.insert(ip, until);
+        }
+        true
+    }
+
+    // Whether `identity`, or `ip` if given, is banned, forgetting bans that have run out
+    pub(crate) fn is_banned(&self, identity: Option<&Pubkey>, ip: Option<IpAddr>) -> bool {
+        let now = Instant::now();
+        let mut bans = self.bans.lock().unwrap();
+        bans.identities.retain(|_, until| *until > now);
+        bans.ips.retain(|_, until| *until > now);
+        identity.is_some_and(|identity| bans.identities.contains_key(identity)) || ip.is_some_and(|ip| bans.ips.contains_key(&ip))
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use ed25519_dalek::{PublicKey, Signature};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use crate::{
+    merkle::{hash_leaf, merkle_root, Hash, MerkleProof},
+    network::MAX_MESSAGE_SIZE,
+    structures::{Block, Blockhash, Pubkey},
+    wallet::Wallet,
+    wire,
+};
+
+// Block bytes each shred carries, so a shred fits a single datagram with room to spare
+pub const SHRED_PAYLOAD_SIZE: usize = 1024;
+// Data shreds per erasure set. Each set also gets as many coding shreds, so any half of a set's shreds
+// rebuild it, & a set stays well inside the 256 shards a Reed-Solomon code over bytes allows.
+pub const DATA_SHREDS_PER_SET: usize = 32;
+// Most blocks reassembled at once. Starting another drops the oldest.
+pub const MAX_PENDING_BLOCKS: usize = 32;
+
+// Signed along with a shred root, so the signature can't be passed off as anything else
+const SHRED_ROOT_CONTEXT: &[u8] = b"litechain-shred-root:";
+
+// One fixed-size piece of an erasure-coded block. The block's encoding is split into sets of up to
+// `DATA_SHREDS_PER_SET` data shreds, the last padded out with zeros, & each set gets as many coding
+// shreds. Within its set, `index` counts the data shreds first, then the coding ones. Each carries the
+// block hash & encoding length, so shreds of different blocks can arrive interleaved & any of them
+// tells how the block was split.
+// Whoever shreds a block commits to every shred of it under one Merkle root & signs the root along with
+// the block hash & length, so each shred can be checked on its own before it's relayed or kept.
+#[derive(Debug, Clone, PartialEq, Eq, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Shred {
+    pub block_hash: Blockhash,
+    pub block_size: u32,
+    pub set: u32,
+    pub index: u32,
+    pub payload: Vec<u8>,
+    pub root: Hash,
+    pub proof: MerkleProof,
+    pub signer: Pubkey,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    pub signature: Signature,
+}
+
+impl Shred {
+    // Split `block`'s encoding into data shreds, add the coding shreds for each set, & sign them all
+    // as `wallet`
+    pub fn split(block: &Block, wallet: &Wallet) -> Vec<Shred> {
+        let mut bytes = borsh::to_vec(block).expect("Block encoding is infallible");
+        let block_size = bytes.len() as u32;
+        bytes.resize(bytes.len().next_multiple_of(SHRED_PAYLOAD_SIZE), 0);
+
+        let data: Vec<&[u8]> = bytes.chunks(SHRED_PAYLOAD_SIZE).collect();
+        let mut pieces = Vec::with_capacity(data.len() * 2);
+        for (set, data) in data.chunks(DATA_SHREDS_PER_SET).enumerate() {
+            let mut shards: Vec<Vec<u8>> = data.iter().map(|payload| payload.to_vec()).collect();
+            shards.resize(data.len() * 2, vec![0; SHRED_PAYLOAD_SIZE]);
+            coder(data.len()).encode(&mut shards).expect("Shards are all the same size");
+            pieces.extend(shards.into_iter().enumerate().map(|(index, payload)| (set as u32, index as u32, payload)));
+        }
+        Self::commit(block.hash, block_size, pieces, wallet)
+    }
+
+    // Commit to every shred of a block, as (set, index, payload) in order, under one root & sign it
+    pub(crate) fn commit(block_hash: Blockhash, block_size: u32, pieces: Vec<(u32, u32, Vec<u8>)>, wallet: &Wallet) -> Vec<Shred> {
+        let leaves: Vec<Hash> = pieces.iter().map(|(set, index, payload)| shred_leaf(*set, *index, payload)).collect();
+        let root = merkle_root(&leaves);
+        let signature = wallet.sign(&signed_message(&block_hash, block_size, &root));
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(position, (set, index, payload))| Shred {
+                block_hash,
+                block_size,
+                set,
+                index,
+                payload,
+                root,
+                proof: MerkleProof::generate(&leaves, position).expect("Every shred has a leaf"),
+                signer: wallet.public_key,
+                signature,
+            })
+            .collect()
+    }
+
+    // Whether this is one of its set's data shreds rather than a coding shred
+    pub fn is_data(&self) -> bool {
+        (self.index as usize) < set_data_shreds(self.block_size, self.set)
+    }
+
+    // Check the shred is where it says in its block, is under the root & the signer signed the root
+    pub fn verify(&self) -> Result<(), &'static str> {
+        self.check()?;
+        let position = self.set as u64 * 2 * DATA_SHREDS_PER_SET as u64 + self.index as u64;
+        if self.proof.index != position || self.proof.leaf_count != 2 * data_shreds(self.block_size) as u64 {
+            return Err("Shred proof is for another position")
+        }
+        if !self.proof.verify(&shred_leaf(self.set, self.index, &self.payload), &self.root) {
+            return Err("Shred is not under its root")
+        }
+        let message = signed_message(&self.block_hash, self.block_size, &self.root);
+        match PublicKey::from_bytes(&self.signer) {
+            Ok(public_key) if public_key.verify_strict(&message, &self.signature).is_ok() => Ok(()),
+            _ => Err("Invalid shred signature"),
+        }
+    }
+
+    fn check(&self) -> Result<(), &'static str> {
+        if self.block_size == 0 || self.block_size as usize > MAX_MESSAGE_SIZE {
+            return Err("Shred block size out of range")
+        }
+        if self.set >= set_count(self.block_size) || self.index as usize >= set_data_shreds(self.block_size, self.set) * 2 {
+            return Err("Shred index out of range")
+        }
+        if self.payload.len() != SHRED_PAYLOAD_SIZE {
+            return Err("Shred payload has the wrong size")
+        }
+        Ok(())
+    }
+}
+
+fn shred_leaf(set: u32, index: u32, payload: &[u8]) -> Hash {
+    hash_leaf(&[&set.to_le_bytes()[..], &index.to_le_bytes(), payload].concat())
+}
+
+fn signed_message(block_hash: &Blockhash, block_size: u32, root: &Hash) -> Vec<u8> {
+    [SHRED_ROOT_CONTEXT, &block_hash[..], &block_size.to_le_bytes(), &root[..]].concat()
+}
+
+// Data shreds a block of `block_size` bytes is split into, its sets, & the data shreds in set `set`
+fn data_shreds(block_size: u32) -> usize {
+    (block_size as usize).div_ceil(SHRED_PAYLOAD_SIZE)
+}
+
+fn set_count(block_size: u32) -> u32 {
+    data_shreds(block_size).div_ceil(DATA_SHREDS_PER_SET) as u32
+}
+
+fn set_data_shreds(block_size: u32, set: u32) -> usize {
+    data_shreds(block_size).saturating_sub(set as usize * DATA_SHREDS_PER_SET).min(DATA_SHREDS_PER_SET)
+}
+
+fn coder(data_shreds: usize) -> ReedSolomon {
+    ReedSolomon::new(data_shreds, data_shreds).expect("Set sizes are within the code's limits")
+}
+
+#[derive(Debug)]
+struct PendingSet {
+    shards: Vec<Option<Vec<u8>>>,
+    received: usize,
+    // Set once enough shreds are in to rebuild every data shred
+    recovered: bool,
+}
+
+#[derive(Debug)]
+struct PendingBlock {
+    block_size: u32,
+    sets: Vec<PendingSet>,
+    recovered: u32,
+}
+
+// A block's shreds as one signer committed to them, so shreds under another root can't mix in
+type ShredKey = (Blockhash, Hash);
+
+#[derive(Debug, Default)]
+struct Shreds {
+    pending: HashMap<ShredKey, PendingBlock>,
+    // Oldest pending block first
+    order: VecDeque<ShredKey>,
+    // Blocks lately rebuilt, whose remaining shreds aren't needed
+    reassembled: VecDeque<Blockhash>,
+}
+
+// Shreds received so far of blocks not yet rebuilt, by block hash & root
+#[derive(Debug, Default)]
+pub(crate) struct ShredAssembler(Mutex<Shreds>);
+
+impl ShredAssembler {
+    // Add `shred`, already verified, returning its block once every set has enough shreds to be rebuilt.
+    // Shreds that don't rebuild the block they name are all dropped, so it can be tried again.
+    pub(crate) fn insert(&self, shred: Shred) -> Result<Option<Block>, &'static str> {
+        shred.check()?;
+        let key = (shred.block_hash, shred.root);
+        let mut shreds = self.0.lock().unwrap();
+        let Shreds { pending, order, reassembled } = &mut *shreds;
+        if reassembled.contains(&shred.block_hash) {
+            return Ok(None)
+        }
+        if !pending.contains_key(&key) {
+            if order.len() >= MAX_PENDING_BLOCKS {
+                let oldest = order.pop_front().expect("Pending blocks aren't empty");
+                pending.remove(&oldest);
+            }
+            order.push_back(key);
+            let sets = (0..set_count(shred.block_size))
+                .map(|set| PendingSet { shards: vec![None; set_data_shreds(shred.block_size, set) * 2], received: 0, recovered: false })
+                .collect();
+            pending.insert(key, PendingBlock { block_size: shred.block_size, sets, recovered: 0 });
+        }
+
+        let block = pending.get_mut(&key).expect("Block was just added");
+        if block.block_size != shred.block_size {
+            return Err("Shred block size differs from the block's other shreds")
+        }
+        let set = &mut block.sets[shred.set as usize];
+        let shard = &mut set.shards[shred.index as usize];
+        if set.recovered || shard.is_some() {
+            return Ok(None)
+        }
+        *shard = Some(shred.payload);
+        set.received += 1;
+        let data_shreds = set.shards.len() / 2;
+        if set.received < data_shreds {
+            return Ok(None)
+        }
+        // Rebuild whichever shreds are missing from the ones in, coding shreds too, so the whole set can be
+        // held to the root. Erasure coding alone can't tell a corrupt shard from a good one.
+        if coder(data_shreds).reconstruct(&mut set.shards).is_err() {
+            pending.remove(&key);
+            order.retain(|pending| *pending != key);
+            return Err("Shreds don't rebuild their set")
+        }
+        set.recovered = true;
+        block.recovered += 1;
+        if block.recovered < block.sets.len() as u32 {
+            return Ok(None)
+        }
+
+        let block = pending.remove(&key).expect("Block is pending");
+        order.retain(|pending| *pending != key);
+        drop(shreds);
+
+        let shards: Vec<(u32, u32, Vec<u8>)> = block
+            .sets
+            .into_iter()
+            .enumerate()
+            .flat_map(|(set, pending)| {
+                pending.shards.into_iter().enumerate().map(move |(index, shard)| (set as u32, index as u32, shard.expect("Sets are rebuilt")))
+            })
+            .collect();
+        // Shreds the signer committed to that aren't one consistent encoding rebuild differently
+        // depending on which arrive, so the rebuilt shreds have to be the very ones committed to
+        let leaves: Vec<Hash> = shards.iter().map(|(set, index, payload)| shred_leaf(*set, *index, payload)).collect();
+        if merkle_root(&leaves) != shred.root {
+            return Err("Shreds don't rebuild the ones their root commits to")
+        }
+        let mut bytes: Vec<u8> = shards
+            .into_iter()
+            .filter(|(set, index, _)| (*index as usize) < set_data_shreds(block.block_size, *set))
+            .flat_map(|(_, _, payload)| payload)
+            .collect();
+        bytes.truncate(block.block_size as usize);
+        let rebuilt: Block = borsh::from_slice(&bytes).map_err(|_| "Shreds don't decode to a block")?;
+        if rebuilt.hash != shred.block_hash {
+            return Err("Shreds don't match their block")
+        }
+
+        let reassembled = &mut self.0.lock().unwrap().reassembled;
+        if reassembled.len() >= MAX_PENDING_BLOCKS {
+            reassembled.pop_front();
+        }
+        reassembled.push_back(shred.block_hash);
+        Ok(Some(rebuilt))
+    }
+
+    // Whether `block_hash` was lately rebuilt, so its remaining shreds can be ignored
+    pub(crate) fn is_reassembled(&self, block_hash: &Blockhash) -> bool {
+        self.0.lock().unwrap().reassembled.contains(block_hash)
+    }
+}
@@ -0,0 +1,139 @@
+use std::{io, path::Path};
+
+use crate::{
+    chain::Blockchain,
+    db::AccountsDB,
+    merkle::Hash,
+    storage::{self, Codec},
+    structures::{Allowance, Block, Delegation, Mint, MintId, Pubkey, TokenAccount, Unbonding, UserAccount, ValidatorAccount},
+    vote::QuorumCertificate,
+};
+
+// Every piece of consensus state as it stood once `block` & its rewards were applied, so a new node can
+// start from it instead of replaying from genesis. Nothing in here is trusted by itself: the state is
+// checked against the root the next block's header commits to, & that block has to be certified by a
+// quorum of a validator set the node already trusts, not one the snapshot names.
+#[derive(Debug, Clone, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Snapshot {
+    pub block: Block,
+    pub total_supply: u64,
+    pub base_fee: u64,
+    pub epoch_seed: Hash,
+    pub active_set: Vec<(Pubkey, u64)>,
+    pub accounts: Vec<(Pubkey, UserAccount)>,
+    pub validators: Vec<(Pubkey, ValidatorAccount)>,
+    pub unbonding: Vec<(Pubkey, Vec<Unbonding>)>,
+    pub delegations: Vec<(Pubkey, Vec<Delegation>)>,
+    pub pending_rewards: Vec<(Pubkey, u64)>,
+    pub mints: Vec<(MintId, Mint)>,
+    pub token_accounts: Vec<(Pubkey, TokenAccount)>,
+    pub allowances: Vec<(Pubkey, Allowance)>,
+}
+
+impl Snapshot {
+    // State at the chain's tip
+    pub fn capture(chain: &Blockchain, db: &AccountsDB) -> Self {
+        Self {
+            block: chain.tip().clone(),
+            total_supply: db.total_supply,
+            base_fee: db.base_fee,
+            epoch_seed: db.epoch_seed,
+            active_set: db.active_set.iter().map(|(pubkey, stake)| (*pubkey, *stake)).collect(),
+            accounts: db.accounts.iter().map(|entry| (*entry.key(), entry.value().clone())).collect(),
+            validators: db.validators.iter().map(|entry| (*entry.key(), entry.value().clone())).collect(),
+            unbonding: db.unbonding.iter().map(|entry| (*entry.key(), entry.value().clone())).collect(),
+            delegations: db.delegations.iter().map(|entry| (*entry.key(), entry.value().clone())).collect(),
+            pending_rewards: db.pending_rewards.iter().map(|entry| (*entry.key(), *entry.value())).collect(),
+            mints: db.mints.iter().map(|entry| (*entry.key(), *entry.value())).collect(),
+            token_accounts: db.token_accounts.iter().map(|entry| (*entry.key(), *entry.value())).collect(),
+            allowances: db.allowances.iter().map(|entry| (*entry.key(), *entry.value())).collect(),
+        }
+    }
+
+    // Write the snapshot to `path`, compressed with `codec`
+    pub fn save(&self, path: &Path, codec: Codec) -> io::Result<()> {
+        storage::save(path, self, codec)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        storage::load(path)
+    }
+
+    pub fn height(&self) -> u64 {
+        self.block.height()
+    }
+
+    // The snapshot's state, with the chain parameters taken from `config` (e.g. the genesis state)
+    pub fn restore(&self, config: &AccountsDB) -> AccountsDB {
+        let mut db = AccountsDB::new();
+        db.latest_blockhash = self.block.hash;
+        db.latest_height = self.height();
+        db.latest_slot = self.block.slot();
+        db.total_supply = self.total_supply;
+        db.base_fee = self.base_fee;
+        db.epoch_seed = self.epoch_seed;
+        db.active_set = self.active_set.iter().copied().collect();
+        db.faucet = config.faucet;
+        db.freeze_authority = config.freeze_authority;
+        db.min_validator_stake = config.min_validator_stake;
+        db.unbonding_blocks = config.unbonding_blocks;
+        db.max_missed_slots = config.max_missed_slots;
+        db.jail_cooldown_blocks = config.jail_cooldown_blocks;
+        db.max_block_compute_units = config.max_block_compute_units;
+        db.epoch_slots = config.epoch_slots;
+        db.rent_exempt_minimum = config.rent_exempt_minimum;
+        db.rent_per_epoch = config.rent_per_epoch;
+        db.archive = config.archive;
+        db.programs = config.programs.clone();
+
+        for (pubkey, account) in &self.accounts {
+            db.add_account(*pubkey, account.clone());
+        }
+        for (pubkey, validator) in &self.validators {
+            db.add_validator(*pubkey, validator.clone());
+        }
+        for (pubkey, pending) in &self.unbonding {
+            db.unbonding.insert(*pubkey, pending.clone());
+        }
+        for (pubkey, delegations) in &self.delegations {
+            db.delegations.insert(*pubkey, delegations.clone());
+        }
+        for (pubkey, owed) in &self.pending_rewards {
+            db.pending_rewards.insert(*pubkey, *owed);
+        }
+        for (mint_id, mint) in &self.mints {
+            db.add_mint(*mint_id, *mint);
+        }
+        for (pubkey, account) in &self.token_accounts {
+            db.token_accounts.insert(*pubkey, *account);
+        }
+        for (pubkey, allowance) in &self.allowances {
+            db.allowances.insert(*pubkey, *allowance);
+        }
+
+        db
+    }
+
+    // Check `state`, the snapshot as restored, against `child`: the block after the snapshot's, committing
+    // to the state it builds on. `certificate` has to finalize `child` with a quorum of `trusted`, state
+    // whose validators we already rely on, e.g. our own genesis.
+    pub fn verify(&self, state: &AccountsDB, child: &Block, certificate: &QuorumCertificate, trusted: &AccountsDB) -> Result<(), &'static str> {
+        if !self.block.is_consistent() {
+            return Err("Snapshot block hash does not match contents")
+        }
+        if child.prev_hash() != self.block.hash || child.height() != self.height() + 1 {
+            return Err("Block does not follow the snapshot")
+        }
+        if !child.is_consistent() || !child.verify_proposer() {
+            return Err("Block is not signed by its proposer")
+        }
+        if certificate.block_hash != child.hash || certificate.slot != child.slot() {
+            return Err("Certificate is for a different block")
+        }
+        certificate.verify(trusted)?;
+        if child.header.state_root != state.state_root() {
+            return Err("Snapshot state does not match the block's state root")
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::structures::Block;
+
+// Every stored file starts with this, the format version & the codec its payload is compressed with
+const MAGIC: [u8; 4] = *b"LCST";
+pub const FORMAT_VERSION: u8 = 1;
+const HEADER_LENGTH: usize = MAGIC.len() + 2;
+const ZSTD_LEVEL: i32 = 3;
+
+// How snapshots & archived blocks are compressed on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    None,
+    Lz4,
+    #[default]
+    Zstd,
+}
+
+impl Codec {
+    fn tag(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Lz4 => 1,
+            Codec::Zstd => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Codec::None),
+            1 => Some(Codec::Lz4),
+            2 => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+}
+
+// `payload` compressed with `codec`, behind the format header
+pub fn compress(codec: Codec, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend([FORMAT_VERSION, codec.tag()]);
+    match codec {
+        Codec::None => bytes.extend(payload),
+        Codec::Lz4 => bytes.extend(lz4_flex::compress_prepend_size(payload)),
+        Codec::Zstd => bytes.extend(zstd::encode_all(payload, ZSTD_LEVEL)?),
+    }
+    Ok(bytes)
+}
+
+// The payload of something written by `compress`, whichever codec it used
+pub fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    if bytes.len() < HEADER_LENGTH || bytes[..MAGIC.len()] != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Missing storage header"))
+    }
+    if bytes[MAGIC.len()] != FORMAT_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, "Unsupported storage format version"))
+    }
+    let codec = Codec::from_tag(bytes[MAGIC.len() + 1]).ok_or_else(|| Error::new(ErrorKind::InvalidData, "Unknown codec"))?;
+
+    let payload = &bytes[HEADER_LENGTH..];
+    match codec {
+        Codec::None => Ok(payload.to_vec()),
+        Codec::Lz4 => lz4_flex::decompress_size_prepended(payload).map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+        Codec::Zstd => zstd::decode_all(payload),
+    }
+}
+
+// Write `value` borsh-encoded & compressed to `path`
+pub fn save<T: borsh::BorshSerialize>(path: &Path, value: &T, codec: Codec) -> io::Result<()> {
+    fs::write(path, compress(codec, &borsh::to_vec(value)?)?)
+}
+
+pub fn load<T: borsh::BorshDeserialize>(path: &Path) -> io::Result<T> {
+    borsh::from_slice(&decompress(&fs::read(path)?)?)
+}
+
+// Canonical blocks pruned from memory, one compressed file per height
+#[derive(Debug, Clone)]
+pub struct BlockArchive {
+    dir: PathBuf,
+    codec: Codec,
+}
+
+impl BlockArchive {
+    pub fn open(dir: &Path, codec: Codec) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf(), codec })
+    }
+
+    pub fn store(&self, block: &Block) -> io::Result<()> {
+        save(&self.path(block.height()), block, self.codec)
+    }
+
+    // The archived block at `height`, if there is one
+    pub fn load(&self, height: u64) -> io::Result<Option<Block>> {
+        match load(&self.path(height)) {
+            Ok(block) => Ok(Some(block)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn path(&self, height: u64) -> PathBuf {
+        self.dir.join(format!("{height}.block"))
+    }
+}
@@ -1,525 +1,3932 @@
-use std::{
-    thread,
-    time::{Duration, SystemTime},
-};
+use std::time::{Duration, SystemTime};
 
 use ed25519_dalek::{
-    Keypair, 
     PublicKey, 
     PUBLIC_KEY_LENGTH, 
     SECRET_KEY_LENGTH, 
-    SecretKey,
     Signature, 
-    Signer as DalekSigner,
     Verifier
 };
-use hex;
-use rand::rngs::OsRng;
 use sha2::{Sha256, Digest};
 
 use crate::{
-    builder::BlockBuilder,
+    bloom::AccountBloom,
+    bls::{self, BlsPubkey, BlsSignature},
+    compute::{self, MAX_TRANSACTION_UNITS},
+    config::GenesisConfig,
+    contract,
     db::AccountsDB,
+    merkle::{hash_leaf, merkle_root, Hash, MerkleProof},
+    program::{
+        AccountMeta,
+        Instruction,
+        ProgramId,
+        MAX_INSTRUCTION_ACCOUNTS,
+        MAX_INSTRUCTION_DATA_BYTES,
+        MAX_MESSAGE_INSTRUCTIONS,
+        MAX_MESSAGE_SIGNERS,
+        STAKE_PROGRAM,
+        TRANSFER_PROGRAM,
+    },
+    vote::{QuorumCertificate, Vote},
+    vrf::{self, VrfProof},
+    wallet::Wallet,
+    wire,
 };
 
 // Primitives for accounts / blocks / transactions
 pub type Blockhash = [u8; 32];
+pub type Txhash = [u8; 32];
 pub type Pubkey = [u8; PUBLIC_KEY_LENGTH];
 pub type Seckey = [u8; SECRET_KEY_LENGTH];
 pub type Address = String;
+// Identifies a token other than the native one
+pub type MintId = [u8; 32];
+
+// Parse a hex address back into the pubkey it was derived from
+pub fn pubkey_from_address(address: &str) -> Result<Pubkey, &'static str> {
+    let bytes = hex::decode(address).map_err(|_| "Address is not valid hex")?;
+    bytes.try_into().map_err(|_| "Address is not 32 bytes")
+}
+
+pub fn bls_key_from_hex(key: &str) -> Result<BlsPubkey, &'static str> {
+    let bytes = hex::decode(key).map_err(|_| "BLS key is not valid hex")?;
+    bytes.try_into().map_err(|_| "BLS key is not 48 bytes")
+}
+
+// Most bytes of user data a memo transaction may carry
+pub const MAX_MEMO_BYTES: usize = 512;
+
+// Most recipients a batch transfer may pay
+pub const MAX_BATCH_RECIPIENTS: usize = 64;
+
+// Most keys a multisig account may have
+pub const MAX_MULTISIG_SIGNERS: usize = 16;
+
+// Owner of every account until it's assigned elsewhere. System accounts are controlled by their own key.
+pub const SYSTEM_OWNER: Pubkey = [0; PUBLIC_KEY_LENGTH];
+
+// Largest `data` an account may hold
+pub const MAX_ACCOUNT_DATA_BYTES: usize = 10 * 1024;
 
 const DEFAULT_SIGNATURE_BYTES: [u8; Signature::BYTE_SIZE] = [0; Signature::BYTE_SIZE];
 
 // Enums defining types of accounts & transactions
+#[derive(serde::Serialize, serde::Deserialize)]
 pub enum Account {
     UserAccount(UserAccount),
     ValidatorAccount(ValidatorAccount),
+    MultisigAccount(MultisigAccount),
+    Mint(Mint),
+    TokenAccount(TokenAccount),
 }
 
-pub trait Signer {
-    fn public_key(&self) -> &Pubkey;
-    fn secret_key(&self) -> &Pubkey;
+// Version byte of the V1 encoding. Legacy encodings start with a `TransactionBody` tag, which is always
+// below 0x80, so a set high bit marks a versioned transaction.
+pub const TRANSACTION_V1: u8 = 0x80 | 1;
+// Version byte of the V2 encoding, which adds a fee payer
+pub const TRANSACTION_V2: u8 = 0x80 | 2;
+// Version byte of the V3 encoding, which adds a priority tip
+pub const TRANSACTION_V3: u8 = 0x80 | 3;
+
+// A transaction in one of the encodings we accept. New fields go in new versions, so payloads signed &
+// blocks stored under an older version keep their exact bytes.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Transaction {
+    // The original, unversioned encoding: just the body. Signatures cover the body's payload.
+    Legacy(TransactionBody),
+    // `TRANSACTION_V1` followed by the body. The version byte is part of the signed payload too.
+    V1(TransactionBody),
+    // `TRANSACTION_V2` followed by the body & a fee payer. The payer signs the same payload as the body's signers.
+    V2(Sponsored),
+    // `TRANSACTION_V3` followed by the body & a tip, which the signer pays on top of the fee
+    V3(Tipped),
 }
 
-impl Signer for Account {
-    fn public_key(&self) -> &Pubkey {
-        match self {
-            Account::UserAccount(user) => user.public_key(),
-            Account::ValidatorAccount(validator) => validator.public_key(),
-        }
-    }
+// A body whose fee is paid by `fee_payer` instead of its signer, so a service can cover its users' fees
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Sponsored {
+    pub body: TransactionBody,
+    pub fee_payer: Pubkey,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    payer_signature: Signature,
+}
 
-    fn secret_key(&self) -> &Pubkey {
-        match self {
-            Account::UserAccount(user) => user.secret_key(),
-            Account::ValidatorAccount(validator) => validator.secret_key(),
-        }
-    }
+// A body that pays the block's proposer `tip` on top of its fee, for priority when blocks are full
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Tipped {
+    pub body: TransactionBody,
+    pub tip: u64,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Transaction {
+// What a transaction does, independent of how it's encoded
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub enum TransactionBody {
     Stake(StakeTransaction),
     Transfer(TransferTransaction),
+    Airdrop(AirdropTransaction),
+    Memo(MemoTransaction),
+    BatchTransfer(BatchTransferTransaction),
+    MultisigTransfer(MultisigTransferTransaction),
+    RegisterValidator(RegisterValidatorTransaction),
+    Unstake(UnstakeTransaction),
+    Burn(BurnTransaction),
+    CreateMint(CreateMintTransaction),
+    MintTo(MintToTransaction),
+    TokenTransfer(TokenTransferTransaction),
+    TimeLockedTransfer(TimeLockedTransferTransaction),
+    Approve(ApproveTransaction),
+    DelegatedTransfer(DelegatedTransferTransaction),
+    Assign(AssignTransaction),
+    Freeze(FreezeTransaction),
+    Thaw(ThawTransaction),
+    Deploy(DeployTransaction),
+    Invoke(InvokeTransaction),
+    Program(ProgramTransaction),
+    Message(MessageTransaction),
+    Unjail(UnjailTransaction),
+    // A validator's vote on an earlier block, recorded so finality can be read off the chain
+    Vote(Vote),
 }
 
-impl Transaction {
+impl TransactionBody {
     pub fn get_signer(&self) -> Pubkey {
         match self {
-            Transaction::Stake(tx) => tx.staker,
-            Transaction::Transfer(tx) => tx.from
+            TransactionBody::Stake(tx) => tx.staker,
+            TransactionBody::Transfer(tx) => tx.from,
+            TransactionBody::Airdrop(tx) => tx.authority,
+            TransactionBody::Memo(tx) => tx.from,
+            TransactionBody::BatchTransfer(tx) => tx.from,
+            TransactionBody::MultisigTransfer(tx) => tx.multisig.address(),
+            TransactionBody::RegisterValidator(tx) => tx.validator,
+            TransactionBody::Unstake(tx) => tx.validator,
+            TransactionBody::Burn(tx) => tx.from,
+            TransactionBody::CreateMint(tx) => tx.authority,
+            TransactionBody::MintTo(tx) => tx.authority,
+            TransactionBody::TokenTransfer(tx) => tx.from,
+            TransactionBody::TimeLockedTransfer(tx) => tx.from,
+            TransactionBody::Approve(tx) => tx.owner,
+            TransactionBody::DelegatedTransfer(tx) => tx.delegate,
+            TransactionBody::Assign(tx) => tx.account,
+            TransactionBody::Freeze(tx) => tx.authority,
+            TransactionBody::Thaw(tx) => tx.authority,
+            TransactionBody::Deploy(tx) => tx.authority,
+            TransactionBody::Invoke(tx) => tx.caller,
+            TransactionBody::Program(tx) => tx.signer,
+            TransactionBody::Message(tx) => tx.payer(),
+            TransactionBody::Unjail(tx) => tx.validator,
+            TransactionBody::Vote(vote) => vote.validator,
         }
     }
-}
 
-impl TransactionSign for Transaction {
-    fn get_signature(&self) -> &Signature {
+    pub fn nonce(&self) -> u64 {
         match self {
-            Transaction::Stake(tx) => &tx.signature,
-            Transaction::Transfer(tx) => &tx.signature
+            TransactionBody::Stake(tx) => tx.nonce,
+            TransactionBody::Transfer(tx) => tx.nonce,
+            TransactionBody::Airdrop(tx) => tx.nonce,
+            TransactionBody::Memo(tx) => tx.nonce,
+            TransactionBody::BatchTransfer(tx) => tx.nonce,
+            TransactionBody::MultisigTransfer(tx) => tx.nonce,
+            TransactionBody::RegisterValidator(tx) => tx.nonce,
+            TransactionBody::Unstake(tx) => tx.nonce,
+            TransactionBody::Burn(tx) => tx.nonce,
+            TransactionBody::CreateMint(tx) => tx.nonce,
+            TransactionBody::MintTo(tx) => tx.nonce,
+            TransactionBody::TokenTransfer(tx) => tx.nonce,
+            TransactionBody::TimeLockedTransfer(tx) => tx.nonce,
+            TransactionBody::Approve(tx) => tx.nonce,
+            TransactionBody::DelegatedTransfer(tx) => tx.nonce,
+            TransactionBody::Assign(tx) => tx.nonce,
+            TransactionBody::Freeze(tx) => tx.nonce,
+            TransactionBody::Thaw(tx) => tx.nonce,
+            TransactionBody::Deploy(tx) => tx.nonce,
+            TransactionBody::Invoke(tx) => tx.nonce,
+            TransactionBody::Program(tx) => tx.nonce,
+            TransactionBody::Message(tx) => tx.nonce,
+            TransactionBody::Unjail(tx) => tx.nonce,
+            // One vote per validator per slot
+            TransactionBody::Vote(vote) => vote.slot,
         }
     }
 
-    fn get_mut_signature(&mut self) -> &mut Signature {
+    // What the sender is paying for inclusion
+    pub fn fee(&self) -> u64 {
         match self {
-            Transaction::Stake(tx) => &mut tx.signature,
-            Transaction::Transfer(tx) => &mut tx.signature
+            TransactionBody::Stake(tx) => tx.fee,
+            TransactionBody::Transfer(tx) => tx.fee,
+            TransactionBody::Airdrop(_) => 0,
+            TransactionBody::Memo(tx) => tx.fee,
+            TransactionBody::BatchTransfer(tx) => tx.fee,
+            TransactionBody::MultisigTransfer(tx) => tx.fee,
+            TransactionBody::RegisterValidator(tx) => tx.fee,
+            TransactionBody::Unstake(tx) => tx.fee,
+            TransactionBody::Burn(tx) => tx.fee,
+            TransactionBody::CreateMint(tx) => tx.fee,
+            TransactionBody::MintTo(tx) => tx.fee,
+            TransactionBody::TokenTransfer(tx) => tx.fee,
+            TransactionBody::TimeLockedTransfer(tx) => tx.fee,
+            TransactionBody::Approve(tx) => tx.fee,
+            TransactionBody::DelegatedTransfer(tx) => tx.fee,
+            TransactionBody::Assign(tx) => tx.fee,
+            TransactionBody::Freeze(tx) => tx.fee,
+            TransactionBody::Thaw(tx) => tx.fee,
+            TransactionBody::Deploy(tx) => tx.fee,
+            TransactionBody::Invoke(tx) => tx.fee,
+            TransactionBody::Program(tx) => tx.fee,
+            TransactionBody::Message(tx) => tx.fee,
+            TransactionBody::Unjail(tx) => tx.fee,
+            TransactionBody::Vote(_) => 0,
         }
     }
 
-    fn validate(&self, db: &AccountsDB) -> bool {
+    // New supply this transaction creates once executed
+    pub fn minted(&self) -> u64 {
         match self {
-            Transaction::Stake(tx) => tx.validate(db),
-            Transaction::Transfer(tx) => tx.validate(db),
+            TransactionBody::Airdrop(tx) => tx.amt,
+            _ => 0,
         }
     }
 
-    fn serialize(&self) -> Vec<u8> {
+    // Supply this transaction destroys once executed, on top of its fee
+    pub fn burned(&self) -> u64 {
         match self {
-            Transaction::Stake(tx) => tx.serialize(),
-            Transaction::Transfer(tx) => tx.serialize(),
+            TransactionBody::Burn(tx) => tx.amt,
+            _ => 0,
         }
     }
 
-    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+    // Every account or validator this transaction may read or write
+    pub fn accounts(&self) -> Vec<Pubkey> {
         match self {
-            Transaction::Stake(tx) => tx.execute(db),
-            Transaction::Transfer(tx) => tx.execute(db),
+            TransactionBody::Stake(tx) => vec![tx.staker, tx.validator],
+            TransactionBody::Transfer(tx) => vec![tx.from, tx.to],
+            TransactionBody::Airdrop(tx) => vec![tx.authority, tx.to],
+            TransactionBody::Memo(tx) => vec![tx.from],
+            TransactionBody::BatchTransfer(tx) => {
+                std::iter::once(tx.from).chain(tx.transfers.iter().map(|(to, _)| *to)).collect()
+            }
+            TransactionBody::MultisigTransfer(tx) => vec![tx.multisig.address(), tx.to],
+            TransactionBody::RegisterValidator(tx) => vec![tx.validator],
+            TransactionBody::Unstake(tx) => vec![tx.validator],
+            TransactionBody::Burn(tx) => vec![tx.from],
+            TransactionBody::CreateMint(tx) => vec![tx.authority, tx.mint_id()],
+            TransactionBody::MintTo(tx) => vec![tx.authority, tx.mint, TokenAccount::address(&tx.mint, &tx.to)],
+            TransactionBody::TokenTransfer(tx) => {
+                vec![tx.from, TokenAccount::address(&tx.mint, &tx.from), TokenAccount::address(&tx.mint, &tx.to)]
+            }
+            TransactionBody::TimeLockedTransfer(tx) => vec![tx.from, tx.to],
+            TransactionBody::Approve(tx) => vec![tx.owner, Allowance::address(&tx.owner, &tx.delegate)],
+            TransactionBody::DelegatedTransfer(tx) => {
+                vec![tx.delegate, tx.owner, tx.to, Allowance::address(&tx.owner, &tx.delegate)]
+            }
+            TransactionBody::Assign(tx) => vec![tx.account],
+            TransactionBody::Freeze(tx) => vec![tx.authority, tx.account],
+            TransactionBody::Thaw(tx) => vec![tx.authority, tx.account],
+            TransactionBody::Deploy(tx) => vec![tx.authority, tx.address()],
+            TransactionBody::Invoke(tx) => {
+                [tx.caller, tx.program].into_iter().chain(tx.accounts.iter().copied()).collect()
+            }
+            TransactionBody::Program(tx) => std::iter::once(tx.signer).chain(tx.accounts.iter().copied()).collect(),
+            TransactionBody::Message(tx) => tx.keys(),
+            TransactionBody::Unjail(tx) => vec![tx.validator],
+            TransactionBody::Vote(vote) => vec![vote.validator],
         }
     }
-}
-
-pub trait TransactionSign {
-    fn get_signature(&self) -> &Signature;
-    fn get_mut_signature(&mut self) -> &mut Signature;
-    fn validate(&self, db: &AccountsDB) -> bool;
-    fn serialize(&self) -> Vec<u8>;
-    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str>;
-
-    fn sign(&mut self, signer: &Account) {
-        let keypair = Keypair {
-            public: PublicKey::from_bytes(signer.public_key()).expect("Invalid public key"),
-            secret: SecretKey::from_bytes(signer.secret_key()).expect("Invalid secret key"),
-        };
 
-        let tx_data = self.serialize();
-        let sig = keypair.sign(&tx_data);
+    // Accounts this transaction debits or changes. Each one's owner has to allow it, & none may be frozen.
+    pub fn debited(&self) -> Vec<Pubkey> {
+        match self {
+            TransactionBody::Stake(tx) => vec![tx.staker],
+            TransactionBody::Transfer(tx) => vec![tx.from],
+            TransactionBody::Airdrop(_) => vec![],
+            TransactionBody::Memo(tx) => vec![tx.from],
+            TransactionBody::BatchTransfer(tx) => vec![tx.from],
+            TransactionBody::MultisigTransfer(tx) => vec![tx.multisig.address()],
+            TransactionBody::RegisterValidator(tx) => vec![tx.validator],
+            TransactionBody::Unstake(tx) => vec![tx.validator],
+            TransactionBody::Burn(tx) => vec![tx.from],
+            TransactionBody::CreateMint(tx) => vec![tx.authority],
+            TransactionBody::MintTo(tx) => vec![tx.authority],
+            TransactionBody::TokenTransfer(tx) => vec![tx.from],
+            TransactionBody::TimeLockedTransfer(tx) => vec![tx.from],
+            TransactionBody::Approve(tx) => vec![tx.owner],
+            TransactionBody::DelegatedTransfer(tx) => vec![tx.delegate, tx.owner],
+            TransactionBody::Assign(tx) => vec![tx.account],
+            TransactionBody::Freeze(tx) => vec![tx.authority],
+            TransactionBody::Thaw(tx) => vec![tx.authority],
+            TransactionBody::Deploy(tx) => vec![tx.authority],
+            TransactionBody::Invoke(tx) => vec![tx.caller],
+            TransactionBody::Program(tx) => vec![tx.signer],
+            TransactionBody::Message(tx) => tx.signers.clone(),
+            TransactionBody::Unjail(tx) => vec![tx.validator],
+            TransactionBody::Vote(_) => vec![],
+        }
+    }
 
-        *self.get_mut_signature() = sig;
+    fn debits_allowed(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        let signer = self.get_signer();
+        for account in self.debited().iter().filter_map(|pubkey| db.get_account(pubkey)) {
+            if !account.is_authorized(&signer) {
+                return Err("Account owner has not authorized this transaction")
+            }
+            if account.frozen {
+                return Err("Account is frozen")
+            }
+        }
+        Ok(())
     }
 
-    fn verify_signature(&self, signer: &Pubkey) -> bool {
-        let public_key = PublicKey::from_bytes(signer).expect("Invalid public key");
-        let tx_data = self.serialize();
+    // Each transaction has to carry its signer's next nonce, so none can run twice or out of order.
+    // Votes are numbered by their slot instead.
+    fn nonce_matches(&self, db: &AccountsDB) -> bool {
+        matches!(self, TransactionBody::Vote(_)) || self.nonce() == db.nonce(&self.get_signer())
+    }
 
-        match public_key.verify(&tx_data, self.get_signature()) {
-            Ok(_) => true,
-            Err(_) => false,
+    // Move the signer on to its next nonce, once the transaction has run
+    fn advance_nonce(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        match self {
+            TransactionBody::Vote(_) => Ok(()),
+            _ => db.increment_nonce(&self.get_signer()),
         }
     }
-}
 
-pub struct Block {
-    pub transactions: Vec<Transaction>,
-    pub hash: Blockhash,
-    pub prev_hash: Blockhash,
-    timestamp: SystemTime,
 }
 
-impl Block {
-    pub fn new(transactions: Vec<Transaction>, prev_hash: Blockhash) -> Self {
-        let mut block = Block {
-            transactions,
-            hash: [0; 32],
-            prev_hash,
-            timestamp: SystemTime::now(),
-        };
-        // Derive the hash for the new block
-        block.hash = block.get_hash(prev_hash);
-        block
+impl Transaction {
+    // A new transaction in the current encoding. Sign it as a whole, after wrapping the body.
+    pub fn new(body: TransactionBody) -> Self {
+        Transaction::V1(body)
     }
 
-    pub fn create_genesis() -> Self {
-        Self {
-            transactions: vec![],
-            hash: [1; 32],
-            prev_hash: [1; 32],
-            timestamp: SystemTime::now(),
-        }
+    // A transaction whose fee `fee_payer` pays. Both the payer & the body's signers have to sign it.
+    pub fn sponsored(body: TransactionBody, fee_payer: Pubkey) -> Self {
+        Transaction::V2(Sponsored {
+            body,
+            fee_payer,
+            payer_signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        })
     }
 
-    pub fn get_hash(&self, prev_hash: Blockhash) -> Blockhash {
-        let mut hasher = Sha256::new();
+    // A transaction that tips the proposer of the block including it, on top of its fee
+    pub fn tipped(body: TransactionBody, tip: u64) -> Self {
+        Transaction::V3(Tipped { body, tip })
+    }
 
-        // Hash the previous blockhash
-        hasher.update(prev_hash);
+    pub fn body(&self) -> &TransactionBody {
+        match self {
+            Transaction::Legacy(body) | Transaction::V1(body) => body,
+            Transaction::V2(sponsored) => &sponsored.body,
+            Transaction::V3(tipped) => &tipped.body,
+        }
+    }
 
-        // Hash the timestamp
-        if let Ok(duration) = self.timestamp.duration_since(SystemTime::UNIX_EPOCH) {
-            let timestamp = duration.as_secs();
-            hasher.update(&timestamp.to_le_bytes());
+    fn body_mut(&mut self) -> &mut TransactionBody {
+        match self {
+            Transaction::Legacy(body) | Transaction::V1(body) => body,
+            Transaction::V2(sponsored) => &mut sponsored.body,
+            Transaction::V3(tipped) => &mut tipped.body,
         }
+    }
 
-        // Hash all the transactions in the block
-        for tx in &self.transactions {
-            let tx_data = tx.serialize();
-            hasher.update(tx_data);
+    // Who pays the fee: the sponsor if there is one, otherwise the signer
+    pub fn fee_payer(&self) -> Pubkey {
+        match self {
+            Transaction::V2(sponsored) => sponsored.fee_payer,
+            _ => self.get_signer(),
         }
+    }
 
-        let hash = hasher.finalize();
+    // The version byte this transaction is encoded with, if any
+    pub fn version(&self) -> Option<u8> {
+        match self {
+            Transaction::Legacy(_) => None,
+            Transaction::V1(_) => Some(TRANSACTION_V1),
+            Transaction::V2(_) => Some(TRANSACTION_V2),
+            Transaction::V3(_) => Some(TRANSACTION_V3),
+        }
+    }
 
-        let mut new_hash = [0u8; 32];
+    pub fn get_signer(&self) -> Pubkey {
+        self.body().get_signer()
+    }
 
-        new_hash.copy_from_slice(hash.as_slice());
+    pub fn nonce(&self) -> u64 {
+        self.body().nonce()
+    }
 
-        new_hash
+    pub fn fee(&self) -> u64 {
+        self.body().fee()
     }
-}
 
-#[derive(Default, Debug, PartialEq, Eq, Clone)]
-pub struct UserAccount {
-    pub address: Address, // Derived from public key to string
-    pub public_key: Pubkey, // Derived from secret key
-    pub balance: u64,
-    pub nonce: u64,
-    secret_key: Seckey,
-}
+    pub fn tip(&self) -> u64 {
+        match self {
+            Transaction::V3(tipped) => tipped.tip,
+            _ => 0,
+        }
+    }
 
-impl UserAccount {
-    pub fn new() -> Self {
-        // Generate a new random keypair for our new account
-        let mut csprng = OsRng;
-        let keypair: Keypair = Keypair::generate(&mut csprng);
+    // Everything the signer offers for inclusion. The base fee is the same for every transaction, so
+    // ranking by this ranks by what the proposer earns.
+    pub fn bid(&self) -> u64 {
+        self.fee().saturating_add(self.tip())
+    }
 
-        let public_key: Pubkey = keypair.public.to_bytes();
-        let secret_key: Seckey = keypair.secret.to_bytes();
+    pub fn minted(&self) -> u64 {
+        self.body().minted()
+    }
 
-        let address = hex::encode(&public_key);
+    pub fn burned(&self) -> u64 {
+        self.body().burned()
+    }
 
-        UserAccount {
-            address,
-            balance: 0,
-            nonce: 0,
-            public_key,
-            secret_key,
+    pub fn accounts(&self) -> Vec<Pubkey> {
+        let mut accounts = self.body().accounts();
+        if let Transaction::V2(sponsored) = self {
+            accounts.push(sponsored.fee_payer);
         }
+        accounts
     }
 
-    pub fn sign_transaction(&self, transaction: &mut TransferTransaction) {
-        let keypair = Keypair {
-            public: PublicKey::from_bytes(&self.public_key).expect("Invalid public key"),
-            secret: SecretKey::from_bytes(&self.secret_key).expect("Invalid secret key")
-        };
+    // The sponsor covers the fee by handing it to the signer, who pays it as usual. Checked against a copy
+    // of the accounts, since the body's own checks need the fee to be there already.
+    fn sponsor_fee(&self, sponsored: &Sponsored, db: &AccountsDB) -> Result<(), &'static str> {
+        let payer = db.get_account(&sponsored.fee_payer).ok_or("Account not found.")?;
+        if !payer.is_authorized(&sponsored.fee_payer) {
+            return Err("Account owner has not authorized this transaction")
+        }
+        if payer.frozen {
+            return Err("Account is frozen")
+        }
+        db.transfer(&sponsored.fee_payer, &self.get_signer(), self.fee())?;
+        Ok(())
+    }
 
-        let tx_data = transaction.serialize();
+    // Taken from the signer up front, like the fee, & paid to the proposer once the block has run.
+    // Debits `db` as given, so `validate_state` hands it a staged copy to check the body against what's left.
+    fn pay_tip(&self, tipped: &Tipped, db: &AccountsDB) -> Result<(), &'static str> {
+        db.decrease_account_balance(&self.get_signer(), tipped.tip)
+            .map_err(|_| "Insufficient balance.")
+    }
 
-        let sig = keypair.sign(&tx_data);
+    pub fn compute_units(&self) -> u64 {
+        compute::compute_units(self)
+    }
 
-        transaction.signature = sig;
+    pub fn vote(&self) -> Option<&Vote> {
+        match self.body() {
+            TransactionBody::Vote(vote) => Some(vote),
+            _ => None,
+        }
     }
-}
 
-impl Signer for UserAccount {
-    fn public_key(&self) -> &Pubkey {
-        &self.public_key
+    // Votes & faucet airdrops are free, so they're never priced out of a block
+    pub fn pays_base_fee(&self) -> bool {
+        !matches!(self.body(), TransactionBody::Vote(_) | TransactionBody::Airdrop(_))
     }
 
-    fn secret_key(&self) -> &Pubkey {
-        &self.secret_key
+    // Canonical borsh encoding, including the signature, used on the wire and on disk
+    pub fn to_bytes(&self) -> Vec<u8> {
+        borsh::to_vec(self).expect("Transaction encoding is infallible")
     }
-}
 
-#[derive(Default, Debug, Clone)]
-pub struct ValidatorAccount {
-    pub address: Address,
-    pub public_key: Pubkey,
-    pub stake: u64,
-    pub builder: BlockBuilder,
-    last_finalized_hash: Blockhash,
-    secret_key: Seckey,
-}
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        borsh::from_slice(bytes).map_err(|_| "Invalid transaction encoding")
+    }
 
-impl ValidatorAccount {
-    pub fn new(builder: BlockBuilder) -> Self {
-        let mut csprng = OsRng;
-        let keypair = Keypair::generate(&mut csprng);
+    // Content hash of the full signed encoding, identifying this exact transaction
+    pub fn hash(&self) -> Txhash {
+        Sha256::digest(self.to_bytes()).into()
+    }
 
-        let public_key: Pubkey = keypair.public.to_bytes();
-        let secret_key: Seckey = keypair.secret.to_bytes();
+    // This transaction's leaf in its block's transaction Merkle tree
+    pub fn leaf(&self) -> Hash {
+        hash_leaf(&self.to_bytes())
+    }
 
-        let address = hex::encode(&public_key);
+    // Check every transaction's signatures in a single batched verification, which is much cheaper
+    // than verifying a full block one signature at a time
+    pub fn verify_batch(transactions: &[Transaction]) -> bool {
+        if !transactions.iter().all(|tx| tx.authorized()) {
+            return false
+        }
 
-        ValidatorAccount {
-            address,
-            public_key,
-            stake: 0,
-            builder,
-            last_finalized_hash: [1; 32], // Genesis blockhash
-            secret_key,
-        }
-    }
-
-    pub fn start(&self, interval: Duration) -> Result<(), &'static str> {
-        loop {
-            thread::sleep(interval);
-    
-            let leader = self.builder.get_leader();
-            if leader.public_key == self.public_key {
-                match self.builder.build(self.last_finalized_hash) {
-                    Ok(proposed_block) => {
-
-                        if proposed_block.hash == [1; 32] {
-                            println!("Shutting down validator as no more transactions are in the mempool.");
-                            break Ok(());
-                        }
-
-                        let db_lock = self.builder.db.read().unwrap();
-                        let min_votes = db_lock.validators.len() / 2 + 1;  
-                        let votes = db_lock.validators.iter()
-                            .filter(|validator| validator.vote(&proposed_block))
-                            .count();
-    
-                        drop(db_lock);
-    
-                        if votes >= min_votes {
-                            let mut db_lock = self.builder.db.write().unwrap();
-                            db_lock.finalize_block(&proposed_block)?;
-                            
-                            let mempool_lock = self.builder.mempool.write().unwrap();
-
-                            for tx_in_block in &proposed_block.transactions {
-                                mempool_lock.pool.retain(|_, tx_in_mempool| tx_in_mempool != tx_in_block);
-                            }
-
-
-                            println!("Block {:?} finalized", proposed_block.hash);
-
-                            for mut entry in db_lock.validators.iter_mut() {
-                                let validator = entry.value_mut();
-                                validator.update_last_finalized_hash(proposed_block.hash);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("An error occurred: {:?}", e);
-                    }
-                }
-            }
+        let mut messages: Vec<Vec<u8>> = vec![];
+        let mut signers = vec![];
+        for (index, tx) in transactions.iter().enumerate() {
+            messages.push(tx.serialize());
+            signers.extend(tx.signatures().into_iter().map(|(signer, signature)| (index, signer, signature)));
+        }
+        if signers.is_empty() {
+            return true
+        }
+
+        let public_keys: Option<Vec<PublicKey>> = signers.iter()
+            .map(|(_, signer, _)| PublicKey::from_bytes(signer).ok())
+            .collect();
+        let public_keys = match public_keys {
+            Some(public_keys) => public_keys,
+            None => return false,
+        };
+
+        let messages: Vec<&[u8]> = signers.iter().map(|(index, _, _)| messages[*index].as_slice()).collect();
+        let signatures: Vec<Signature> = signers.iter().map(|(_, _, signature)| *signature).collect();
+
+        ed25519_dalek::verify_batch(&messages, &signatures, &public_keys).is_ok()
+    }
+}
+
+impl TransactionSign for TransactionBody {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        match self {
+            TransactionBody::Stake(tx) => tx.signatures(),
+            TransactionBody::Transfer(tx) => tx.signatures(),
+            TransactionBody::Airdrop(tx) => tx.signatures(),
+            TransactionBody::Memo(tx) => tx.signatures(),
+            TransactionBody::BatchTransfer(tx) => tx.signatures(),
+            TransactionBody::MultisigTransfer(tx) => tx.signatures(),
+            TransactionBody::RegisterValidator(tx) => tx.signatures(),
+            TransactionBody::Unstake(tx) => tx.signatures(),
+            TransactionBody::Burn(tx) => tx.signatures(),
+            TransactionBody::CreateMint(tx) => tx.signatures(),
+            TransactionBody::MintTo(tx) => tx.signatures(),
+            TransactionBody::TokenTransfer(tx) => tx.signatures(),
+            TransactionBody::TimeLockedTransfer(tx) => tx.signatures(),
+            TransactionBody::Approve(tx) => tx.signatures(),
+            TransactionBody::DelegatedTransfer(tx) => tx.signatures(),
+            TransactionBody::Assign(tx) => tx.signatures(),
+            TransactionBody::Freeze(tx) => tx.signatures(),
+            TransactionBody::Thaw(tx) => tx.signatures(),
+            TransactionBody::Deploy(tx) => tx.signatures(),
+            TransactionBody::Invoke(tx) => tx.signatures(),
+            TransactionBody::Program(tx) => tx.signatures(),
+            TransactionBody::Message(tx) => tx.signatures(),
+            TransactionBody::Unjail(tx) => tx.signatures(),
+            TransactionBody::Vote(vote) => vote.signatures(),
         }
     }
 
-    pub fn vote(&self, block: &Block) -> bool {
-        self.builder.validate_block(block).is_ok()
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        match self {
+            TransactionBody::Stake(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Transfer(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Airdrop(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Memo(tx) => tx.sign_message(wallet, message),
+            TransactionBody::BatchTransfer(tx) => tx.sign_message(wallet, message),
+            TransactionBody::MultisigTransfer(tx) => tx.sign_message(wallet, message),
+            TransactionBody::RegisterValidator(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Unstake(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Burn(tx) => tx.sign_message(wallet, message),
+            TransactionBody::CreateMint(tx) => tx.sign_message(wallet, message),
+            TransactionBody::MintTo(tx) => tx.sign_message(wallet, message),
+            TransactionBody::TokenTransfer(tx) => tx.sign_message(wallet, message),
+            TransactionBody::TimeLockedTransfer(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Approve(tx) => tx.sign_message(wallet, message),
+            TransactionBody::DelegatedTransfer(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Assign(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Freeze(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Thaw(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Deploy(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Invoke(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Program(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Message(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Unjail(tx) => tx.sign_message(wallet, message),
+            TransactionBody::Vote(vote) => vote.sign_message(wallet, message),
+        }
     }
 
-    pub fn update_last_finalized_hash(&mut self, new_hash: Blockhash) {
-        self.last_finalized_hash = new_hash;
+    fn authorized(&self) -> bool {
+        match self {
+            TransactionBody::Stake(tx) => tx.authorized(),
+            TransactionBody::Transfer(tx) => tx.authorized(),
+            TransactionBody::Airdrop(tx) => tx.authorized(),
+            TransactionBody::Memo(tx) => tx.authorized(),
+            TransactionBody::BatchTransfer(tx) => tx.authorized(),
+            TransactionBody::MultisigTransfer(tx) => tx.authorized(),
+            TransactionBody::RegisterValidator(tx) => tx.authorized(),
+            TransactionBody::Unstake(tx) => tx.authorized(),
+            TransactionBody::Burn(tx) => tx.authorized(),
+            TransactionBody::CreateMint(tx) => tx.authorized(),
+            TransactionBody::MintTo(tx) => tx.authorized(),
+            TransactionBody::TokenTransfer(tx) => tx.authorized(),
+            TransactionBody::TimeLockedTransfer(tx) => tx.authorized(),
+            TransactionBody::Approve(tx) => tx.authorized(),
+            TransactionBody::DelegatedTransfer(tx) => tx.authorized(),
+            TransactionBody::Assign(tx) => tx.authorized(),
+            TransactionBody::Freeze(tx) => tx.authorized(),
+            TransactionBody::Thaw(tx) => tx.authorized(),
+            TransactionBody::Deploy(tx) => tx.authorized(),
+            TransactionBody::Invoke(tx) => tx.authorized(),
+            TransactionBody::Program(tx) => tx.authorized(),
+            TransactionBody::Message(tx) => tx.authorized(),
+            TransactionBody::Unjail(tx) => tx.authorized(),
+            TransactionBody::Vote(vote) => vote.authorized(),
+        }
     }
 
-}
+    fn validate(&self, db: &AccountsDB) -> bool {
+        if self.debits_allowed(db).is_err() || !self.nonce_matches(db) {
+            return false
+        }
+        match self {
+            TransactionBody::Stake(tx) => tx.validate(db),
+            TransactionBody::Transfer(tx) => tx.validate(db),
+            TransactionBody::Airdrop(tx) => tx.validate(db),
+            TransactionBody::Memo(tx) => tx.validate(db),
+            TransactionBody::BatchTransfer(tx) => tx.validate(db),
+            TransactionBody::MultisigTransfer(tx) => tx.validate(db),
+            TransactionBody::RegisterValidator(tx) => tx.validate(db),
+            TransactionBody::Unstake(tx) => tx.validate(db),
+            TransactionBody::Burn(tx) => tx.validate(db),
+            TransactionBody::CreateMint(tx) => tx.validate(db),
+            TransactionBody::MintTo(tx) => tx.validate(db),
+            TransactionBody::TokenTransfer(tx) => tx.validate(db),
+            TransactionBody::TimeLockedTransfer(tx) => tx.validate(db),
+            TransactionBody::Approve(tx) => tx.validate(db),
+            TransactionBody::DelegatedTransfer(tx) => tx.validate(db),
+            TransactionBody::Assign(tx) => tx.validate(db),
+            TransactionBody::Freeze(tx) => tx.validate(db),
+            TransactionBody::Thaw(tx) => tx.validate(db),
+            TransactionBody::Deploy(tx) => tx.validate(db),
+            TransactionBody::Invoke(tx) => tx.validate(db),
+            TransactionBody::Program(tx) => tx.validate(db),
+            TransactionBody::Message(tx) => tx.validate(db),
+            TransactionBody::Unjail(tx) => tx.validate(db),
+            TransactionBody::Vote(vote) => vote.validate(db),
+        }
+    }
 
-impl Signer for ValidatorAccount {
-    fn public_key(&self) -> &Pubkey {
-        &self.public_key
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.debits_allowed(db).is_err() || !self.nonce_matches(db) {
+            return false
+        }
+        match self {
+            TransactionBody::Stake(tx) => tx.validate_state(db),
+            TransactionBody::Transfer(tx) => tx.validate_state(db),
+            TransactionBody::Airdrop(tx) => tx.validate_state(db),
+            TransactionBody::Memo(tx) => tx.validate_state(db),
+            TransactionBody::BatchTransfer(tx) => tx.validate_state(db),
+            TransactionBody::MultisigTransfer(tx) => tx.validate_state(db),
+            TransactionBody::RegisterValidator(tx) => tx.validate_state(db),
+            TransactionBody::Unstake(tx) => tx.validate_state(db),
+            TransactionBody::Burn(tx) => tx.validate_state(db),
+            TransactionBody::CreateMint(tx) => tx.validate_state(db),
+            TransactionBody::MintTo(tx) => tx.validate_state(db),
+            TransactionBody::TokenTransfer(tx) => tx.validate_state(db),
+            TransactionBody::TimeLockedTransfer(tx) => tx.validate_state(db),
+            TransactionBody::Approve(tx) => tx.validate_state(db),
+            TransactionBody::DelegatedTransfer(tx) => tx.validate_state(db),
+            TransactionBody::Assign(tx) => tx.validate_state(db),
+            TransactionBody::Freeze(tx) => tx.validate_state(db),
+            TransactionBody::Thaw(tx) => tx.validate_state(db),
+            TransactionBody::Deploy(tx) => tx.validate_state(db),
+            TransactionBody::Invoke(tx) => tx.validate_state(db),
+            TransactionBody::Program(tx) => tx.validate_state(db),
+            TransactionBody::Message(tx) => tx.validate_state(db),
+            TransactionBody::Unjail(tx) => tx.validate_state(db),
+            TransactionBody::Vote(vote) => vote.validate_state(db),
+        }
     }
 
-    fn secret_key(&self) -> &Pubkey {
-        &self.secret_key
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            TransactionBody::Stake(tx) => tx.serialize(),
+            TransactionBody::Transfer(tx) => tx.serialize(),
+            TransactionBody::Airdrop(tx) => tx.serialize(),
+            TransactionBody::Memo(tx) => tx.serialize(),
+            TransactionBody::BatchTransfer(tx) => tx.serialize(),
+            TransactionBody::MultisigTransfer(tx) => tx.serialize(),
+            TransactionBody::RegisterValidator(tx) => tx.serialize(),
+            TransactionBody::Unstake(tx) => tx.serialize(),
+            TransactionBody::Burn(tx) => tx.serialize(),
+            TransactionBody::CreateMint(tx) => tx.serialize(),
+            TransactionBody::MintTo(tx) => tx.serialize(),
+            TransactionBody::TokenTransfer(tx) => tx.serialize(),
+            TransactionBody::TimeLockedTransfer(tx) => tx.serialize(),
+            TransactionBody::Approve(tx) => tx.serialize(),
+            TransactionBody::DelegatedTransfer(tx) => tx.serialize(),
+            TransactionBody::Assign(tx) => tx.serialize(),
+            TransactionBody::Freeze(tx) => tx.serialize(),
+            TransactionBody::Thaw(tx) => tx.serialize(),
+            TransactionBody::Deploy(tx) => tx.serialize(),
+            TransactionBody::Invoke(tx) => tx.serialize(),
+            TransactionBody::Program(tx) => tx.serialize(),
+            TransactionBody::Message(tx) => tx.serialize(),
+            TransactionBody::Unjail(tx) => tx.serialize(),
+            TransactionBody::Vote(vote) => vote.serialize(),
+        }
     }
-}
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct StakeTransaction {
-    pub validator: Pubkey,
-    pub staker: Pubkey,
-    pub amt: u64,
-    nonce: u64,
-    signature: Signature
-}
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        self.debits_allowed(db)?;
+        if !self.nonce_matches(db) {
+            return Err("Transaction nonce is not the signer's next")
+        }
+        match self {
+            TransactionBody::Stake(tx) => tx.apply(db),
+            TransactionBody::Transfer(tx) => tx.apply(db),
+            TransactionBody::Airdrop(tx) => tx.apply(db),
+            TransactionBody::Memo(tx) => tx.apply(db),
+            TransactionBody::BatchTransfer(tx) => tx.apply(db),
+            TransactionBody::MultisigTransfer(tx) => tx.apply(db),
+            TransactionBody::RegisterValidator(tx) => tx.apply(db),
+            TransactionBody::Unstake(tx) => tx.apply(db),
+            TransactionBody::Burn(tx) => tx.apply(db),
+            TransactionBody::CreateMint(tx) => tx.apply(db),
+            TransactionBody::MintTo(tx) => tx.apply(db),
+            TransactionBody::TokenTransfer(tx) => tx.apply(db),
+            TransactionBody::TimeLockedTransfer(tx) => tx.apply(db),
+            TransactionBody::Approve(tx) => tx.apply(db),
+            TransactionBody::DelegatedTransfer(tx) => tx.apply(db),
+            TransactionBody::Assign(tx) => tx.apply(db),
+            TransactionBody::Freeze(tx) => tx.apply(db),
+            TransactionBody::Thaw(tx) => tx.apply(db),
+            TransactionBody::Deploy(tx) => tx.apply(db),
+            TransactionBody::Invoke(tx) => tx.apply(db),
+            TransactionBody::Program(tx) => tx.apply(db),
+            TransactionBody::Message(tx) => tx.apply(db),
+            TransactionBody::Unjail(tx) => tx.apply(db),
+            TransactionBody::Vote(vote) => vote.apply(db),
+        }?;
+        self.advance_nonce(db)
+    }
 
-impl StakeTransaction {
-    pub fn new(validator: Pubkey, staker: Pubkey, amt: u64, nonce: u64) -> Self {
-        StakeTransaction {
-            validator,
-            staker,
-            amt,
-            nonce,
-            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap()
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        self.debits_allowed(db)?;
+        if !self.nonce_matches(db) {
+            return Err("Transaction nonce is not the signer's next")
         }
+        match self {
+            TransactionBody::Stake(tx) => tx.apply_state(db),
+            TransactionBody::Transfer(tx) => tx.apply_state(db),
+            TransactionBody::Airdrop(tx) => tx.apply_state(db),
+            TransactionBody::Memo(tx) => tx.apply_state(db),
+            TransactionBody::BatchTransfer(tx) => tx.apply_state(db),
+            TransactionBody::MultisigTransfer(tx) => tx.apply_state(db),
+            TransactionBody::RegisterValidator(tx) => tx.apply_state(db),
+            TransactionBody::Unstake(tx) => tx.apply_state(db),
+            TransactionBody::Burn(tx) => tx.apply_state(db),
+            TransactionBody::CreateMint(tx) => tx.apply_state(db),
+            TransactionBody::MintTo(tx) => tx.apply_state(db),
+            TransactionBody::TokenTransfer(tx) => tx.apply_state(db),
+            TransactionBody::TimeLockedTransfer(tx) => tx.apply_state(db),
+            TransactionBody::Approve(tx) => tx.apply_state(db),
+            TransactionBody::DelegatedTransfer(tx) => tx.apply_state(db),
+            TransactionBody::Assign(tx) => tx.apply_state(db),
+            TransactionBody::Freeze(tx) => tx.apply_state(db),
+            TransactionBody::Thaw(tx) => tx.apply_state(db),
+            TransactionBody::Deploy(tx) => tx.apply_state(db),
+            TransactionBody::Invoke(tx) => tx.apply_state(db),
+            TransactionBody::Program(tx) => tx.apply_state(db),
+            TransactionBody::Message(tx) => tx.apply_state(db),
+            TransactionBody::Unjail(tx) => tx.apply_state(db),
+            TransactionBody::Vote(vote) => vote.apply_state(db),
+        }?;
+        self.advance_nonce(db)
     }
-}
 
-impl TransactionSign for StakeTransaction {
-    fn get_signature(&self) -> &Signature {
-        &self.signature
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        if !self.nonce_matches(db) {
+            return Err("Transaction nonce is not the signer's next")
+        }
+        match self {
+            TransactionBody::Stake(tx) => tx.execute(db),
+            TransactionBody::Transfer(tx) => tx.execute(db),
+            TransactionBody::Airdrop(tx) => tx.execute(db),
+            TransactionBody::Memo(tx) => tx.execute(db),
+            TransactionBody::BatchTransfer(tx) => tx.execute(db),
+            TransactionBody::MultisigTransfer(tx) => tx.execute(db),
+            TransactionBody::RegisterValidator(tx) => tx.execute(db),
+            TransactionBody::Unstake(tx) => tx.execute(db),
+            TransactionBody::Burn(tx) => tx.execute(db),
+            TransactionBody::CreateMint(tx) => tx.execute(db),
+            TransactionBody::MintTo(tx) => tx.execute(db),
+            TransactionBody::TokenTransfer(tx) => tx.execute(db),
+            TransactionBody::TimeLockedTransfer(tx) => tx.execute(db),
+            TransactionBody::Approve(tx) => tx.execute(db),
+            TransactionBody::DelegatedTransfer(tx) => tx.execute(db),
+            TransactionBody::Assign(tx) => tx.execute(db),
+            TransactionBody::Freeze(tx) => tx.execute(db),
+            TransactionBody::Thaw(tx) => tx.execute(db),
+            TransactionBody::Deploy(tx) => tx.execute(db),
+            TransactionBody::Invoke(tx) => tx.execute(db),
+            TransactionBody::Program(tx) => tx.execute(db),
+            TransactionBody::Message(tx) => tx.execute(db),
+            TransactionBody::Unjail(tx) => tx.execute(db),
+            TransactionBody::Vote(vote) => vote.execute(db),
+        }?;
+        self.advance_nonce(db)
     }
+}
 
-    fn get_mut_signature(&mut self) -> &mut Signature {
-        &mut self.signature
+impl TransactionSign for Transaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        let mut signatures = self.body().signatures();
+        if let Transaction::V2(sponsored) = self {
+            signatures.push((sponsored.fee_payer, sponsored.payer_signature));
+        }
+        signatures
     }
 
-    fn validate(&self, db: &AccountsDB) -> bool {
-        // Make sure `validator`` is a validator
-        if !db.is_validator(&self.validator) {
-            return false
+    // A sponsor's wallet only signs as the payer, unless it's the body's signer too
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        let signer = self.get_signer();
+        if let Transaction::V2(sponsored) = self {
+            if sponsored.fee_payer == wallet.public_key {
+                sponsored.payer_signature = wallet.sign(message);
+                if signer != wallet.public_key {
+                    return
+                }
+            }
         }
+        self.body_mut().sign_message(wallet, message)
+    }
 
-        let staker = match db.get_account(&self.staker) {
-            Some(account) => account,
-            None => return false,
-        };
+    fn authorized(&self) -> bool {
+        self.body().authorized()
+    }
+
+    // Checked against this transaction's own payload, which includes the version
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
 
-        if !self.verify_signature(&staker.public_key()) {
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.compute_units() > MAX_TRANSACTION_UNITS {
             return false
         }
-
-        if staker.balance.lt(&self.amt) {
+        if self.pays_base_fee() && self.fee() < db.base_fee {
             return false
         }
-
-        true
+        match self {
+            Transaction::V2(sponsored) => {
+                let staged = db.stage(&self.accounts());
+                self.sponsor_fee(sponsored, &staged).is_ok() && sponsored.body.validate_state(&staged)
+            }
+            Transaction::V3(tipped) => {
+                let staged = db.stage(&self.accounts());
+                self.pay_tip(tipped, &staged).is_ok() && tipped.body.validate_state(&staged)
+            }
+            _ => self.body().validate_state(db),
+        }
     }
 
     fn serialize(&self) -> Vec<u8> {
         let mut data = vec![];
 
-        data.extend(&self.validator.to_vec());
-        data.extend(&self.staker.to_vec());
-        data.extend(&self.nonce.to_le_bytes());
-        data.extend(&self.amt.to_le_bytes());
+        data.extend(self.version());
+        data.extend(self.body().serialize());
+        match self {
+            Transaction::V2(sponsored) => data.extend(&sponsored.fee_payer),
+            Transaction::V3(tipped) => data.extend(tipped.tip.to_le_bytes()),
+            _ => {}
+        }
 
         data
     }
 
-    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
-        if !self.validate(&db) {
-            return Err("Invalid transaction in Stake execute")
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in execute")
         }
+        self.apply_state(db)
+    }
 
-        // If a transaction has gotten this far we can assume that the accounts are in the db
-        let staker = db.get_account(&self.staker).unwrap();
-
-        if staker.balance.lt(&self.amt) {
-            return Err("Staker balance less than amount")
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        match self {
+            Transaction::V2(sponsored) => {
+                if !self.validate_state(db) {
+                    return Err("Invalid transaction in execute")
+                }
+                self.sponsor_fee(sponsored, db)?;
+            }
+            Transaction::V3(tipped) => {
+                if !self.validate_state(db) {
+                    return Err("Invalid transaction in execute")
+                }
+                self.pay_tip(tipped, db)?;
+            }
+            _ => {}
         }
+        self.body().apply_state(db)
+    }
 
-        db.decrease_account_balance(&self.staker, self.amt)
-            .map_err(|_| "Balance decrease failed")?;
-
-
-        db.increase_validator_stake(&self.validator, self.amt)
-            .map_err(|_| "Stake increase failed")?;
-
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.bid().saturating_add(self.burned()));
+        db.mint(self.minted());
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct TransferTransaction {
-    pub to: Pubkey,
-    pub from: Pubkey,
-    pub amt: u64,
-    nonce: u64,
-    signature: Signature,
+// A body signed on its own, through its type's `sign`, is a legacy transaction: that signature covers
+// the unversioned payload
+macro_rules! legacy_from {
+    ($($variant:ident($transaction:ty)),*) => {
+        $(impl From<$transaction> for Transaction {
+            fn from(tx: $transaction) -> Self {
+                Transaction::Legacy(TransactionBody::$variant(tx))
+            }
+        })*
+    };
 }
 
-impl TransferTransaction {
-    pub fn new(to: Pubkey, from: Pubkey, amt: u64, nonce: u64) -> Self {
-        TransferTransaction {
-            to,
-            from,
-            amt,
-            nonce,
-            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+legacy_from!(
+    Stake(StakeTransaction),
+    Transfer(TransferTransaction),
+    Airdrop(AirdropTransaction),
+    Memo(MemoTransaction),
+    BatchTransfer(BatchTransferTransaction),
+    MultisigTransfer(MultisigTransferTransaction),
+    RegisterValidator(RegisterValidatorTransaction),
+    Unstake(UnstakeTransaction),
+    Burn(BurnTransaction),
+    CreateMint(CreateMintTransaction),
+    MintTo(MintToTransaction),
+    TokenTransfer(TokenTransferTransaction),
+    TimeLockedTransfer(TimeLockedTransferTransaction),
+    Approve(ApproveTransaction),
+    DelegatedTransfer(DelegatedTransferTransaction),
+    Assign(AssignTransaction),
+    Freeze(FreezeTransaction),
+    Thaw(ThawTransaction),
+    Deploy(DeployTransaction),
+    Invoke(InvokeTransaction),
+    Program(ProgramTransaction),
+    Message(MessageTransaction),
+    Unjail(UnjailTransaction),
+    Vote(Vote)
+);
+
+// Legacy transactions encode as their bare body, exactly as before versioning
+impl borsh::BorshSerialize for Transaction {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        if let Some(version) = self.version() {
+            writer.write_all(&[version])?;
+        }
+        match self {
+            Transaction::V2(sponsored) => borsh::BorshSerialize::serialize(sponsored, writer),
+            Transaction::V3(tipped) => borsh::BorshSerialize::serialize(tipped, writer),
+            _ => borsh::BorshSerialize::serialize(self.body(), writer),
         }
     }
 }
 
-impl TransactionSign for TransferTransaction {
-    fn get_signature(&self) -> &Signature {
-        &self.signature
-    }
-
-    fn get_mut_signature(&mut self) -> &mut Signature {
-        &mut self.signature
+impl borsh::BorshDeserialize for Transaction {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut first = [0; 1];
+        reader.read_exact(&mut first)?;
+        match first[0] {
+            TRANSACTION_V1 => Ok(Transaction::V1(TransactionBody::deserialize_reader(reader)?)),
+            TRANSACTION_V2 => Ok(Transaction::V2(Sponsored::deserialize_reader(reader)?)),
+            TRANSACTION_V3 => Ok(Transaction::V3(Tipped::deserialize_reader(reader)?)),
+            version if version & 0x80 != 0 => {
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Unsupported transaction version"))
+            }
+            // No version byte, so the byte we read was the body's own tag
+            tag => Ok(Transaction::Legacy(TransactionBody::deserialize_reader(&mut std::io::Read::chain(&[tag][..], reader))?)),
+        }
     }
+}
 
-    fn serialize(&self) -> Vec<u8> {
+pub trait TransactionSign {
+    // Every (signer, signature) pair carried by this transaction. Just the one for most transactions.
+    fn signatures(&self) -> Vec<(Pubkey, Signature)>;
+    // Add `wallet`'s signature over `message`, this transaction's payload in whatever encoding wraps it
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]);
+    fn validate(&self, db: &AccountsDB) -> bool;
+    // Everything `validate` checks except the signatures, for callers that verify signatures in bulk
+    fn validate_state(&self, db: &AccountsDB) -> bool;
+    fn serialize(&self) -> Vec<u8>;
+    // The balance & stake changes of `execute` without burning the fee, which needs exclusive access to
+    // the supply. Only touches this transaction's own accounts, so it can run alongside others that don't share them.
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str>;
+    // `apply` without the signature check, for simulating transactions whose signatures are already verified
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str>;
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str>;
+
+    fn sign(&mut self, wallet: &Wallet) {
+        let tx_data = self.serialize();
+        self.sign_message(wallet, &tx_data);
+    }
+
+    // Whether the signatures carried are enough to authorize this transaction, if they all check out.
+    // A single-signature transaction always carries its one.
+    fn authorized(&self) -> bool {
+        true
+    }
+
+    // The transaction is authorized & every signature it carries is valid
+    fn verify_signatures(&self) -> bool {
+        let tx_data = self.serialize();
+        self.authorized() && self.signatures().iter().all(|(signer, signature)| verify(signer, &tx_data, signature))
+    }
+
+    // Whether `signer` validly signed this transaction
+    fn verify_signature(&self, signer: &Pubkey) -> bool {
+        let tx_data = self.serialize();
+        self.signatures().iter().any(|(pubkey, signature)| pubkey == signer && verify(signer, &tx_data, signature))
+    }
+}
+
+fn verify(signer: &Pubkey, message: &[u8], signature: &Signature) -> bool {
+    PublicKey::from_bytes(signer).is_ok_and(|public_key| public_key.verify(message, signature).is_ok())
+}
+
+// Everything a block commits to. Its hash is the block's hash, & the transaction root lets a single
+// transaction be proven part of the block without the rest of the body.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct BlockHeader {
+    pub prev_hash: Blockhash,
+    // Distance from genesis, one more than the parent
+    pub height: u64,
+    // Leader slot the block was proposed in. Increases along a chain but may skip slots nobody filled.
+    pub slot: u64,
+    // Wall-clock time as claimed by the proposer. Nothing reads the clock while building or hashing a
+    // block, so the same inputs always give the same hash.
+    #[borsh(serialize_with = "wire::serialize_timestamp", deserialize_with = "wire::deserialize_timestamp")]
+    pub timestamp: SystemTime,
+    // Merkle root over the block's transactions, in order
+    pub tx_root: Hash,
+    // Accounts the block's transactions touch, for skipping blocks without fetching them
+    pub account_bloom: AccountBloom,
+    // `AccountsDB::state_root` of the state the block executes on top of
+    pub state_root: Hash,
+    // Validator that built the block & signed its hash
+    pub proposer: Pubkey,
+    // The proposer's VRF proof over the epoch seed & slot, showing what it drew for the slot
+    pub vrf_proof: VrfProof,
+    // The votes that finalized the parent, aggregated, if enough of its voters have BLS keys
+    pub certificate: Option<QuorumCertificate>,
+}
+
+impl BlockHeader {
+    pub fn hash(&self) -> Blockhash {
+        Sha256::digest(borsh::to_vec(self).expect("Header encoding is infallible")).into()
+    }
+
+    // Whether `signature` is the proposer's over this header's hash, see `Block::verify_proposer`
+    pub fn verify_proposer(&self, signature: &Signature) -> bool {
+        PublicKey::from_bytes(&self.proposer).is_ok_and(|public_key| public_key.verify_strict(&self.hash(), signature).is_ok())
+    }
+
+    // False if no transaction in the block touches `pubkey`; true if one may
+    pub fn may_touch(&self, pubkey: &Pubkey) -> bool {
+        self.account_bloom.contains(pubkey)
+    }
+
+    // Check a transaction's inclusion proof against this header alone
+    pub fn verify_transaction(&self, tx: &Transaction, proof: &MerkleProof) -> bool {
+        proof.verify(&tx.leaf(), &self.tx_root)
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Block {
+    pub transactions: Vec<Transaction>,
+    pub hash: Blockhash,
+    pub header: BlockHeader,
+    // The proposer's signature over the block hash
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+    // Length of the block's canonical encoding in bytes
+    pub size: u64,
+}
+
+impl Block {
+    pub fn new(transactions: Vec<Transaction>, prev_hash: Blockhash) -> Self {
+        let header = BlockHeader {
+            prev_hash,
+            height: 0,
+            slot: 0,
+            timestamp: SystemTime::UNIX_EPOCH,
+            tx_root: Self::tx_root(&transactions),
+            account_bloom: Self::account_bloom(&transactions),
+            state_root: [0; 32],
+            proposer: [0; 32],
+            vrf_proof: VrfProof::default(),
+            certificate: None,
+        };
+        let mut block = Block {
+            transactions,
+            hash: [0; 32],
+            header,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+            size: 0,
+        };
+        block.seal();
+        block
+    }
+
+    // A block directly on top of `parent`, one height & one slot above it
+    pub fn extending(parent: &Block, transactions: Vec<Transaction>) -> Self {
+        Self::new(transactions, parent.hash)
+            .with_height(parent.height() + 1)
+            .with_slot(parent.slot() + 1)
+    }
+
+    pub fn with_height(mut self, height: u64) -> Self {
+        self.header.height = height;
+        self.seal();
+        self
+    }
+
+    pub fn with_slot(mut self, slot: u64) -> Self {
+        self.header.slot = slot;
+        self.seal();
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.header.timestamp = timestamp;
+        self.seal();
+        self
+    }
+
+    pub fn with_state_root(mut self, state_root: Hash) -> Self {
+        self.header.state_root = state_root;
+        self.seal();
+        self
+    }
+
+    pub fn with_certificate(mut self, certificate: Option<QuorumCertificate>) -> Self {
+        self.header.certificate = certificate;
+        self.seal();
+        self
+    }
+
+    // Prove what `wallet` drew for the block's slot under `epoch_seed`, so goes after the slot is set
+    pub fn with_leader_proof(mut self, wallet: &Wallet, epoch_seed: &Hash) -> Self {
+        self.header.vrf_proof = vrf::prove(wallet, &vrf::leader_input(epoch_seed, self.slot())).1;
+        self.seal();
+        self
+    }
+
+    // Sign the block as proposed by `wallet`. Changing the header afterwards voids the signature, so
+    // this goes last.
+    pub fn with_proposer(mut self, wallet: &Wallet) -> Self {
+        self.header.proposer = wallet.public_key;
+        self.seal();
+        self.signature = wallet.sign(&self.hash);
+        self
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    // Whether the named proposer signed this block. Strict verification rejects the small-order keys
+    // an unsigned block's all-zero proposer & signature would otherwise pass with.
+    pub fn verify_proposer(&self) -> bool {
+        match PublicKey::from_bytes(&self.header.proposer) {
+            Ok(public_key) => public_key.verify_strict(&self.hash, &self.signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // What the proposer drew for the block's slot under `epoch_seed`, if the header's proof is theirs
+    pub fn leader_output(&self, epoch_seed: &Hash) -> Option<Hash> {
+        vrf::verify(&self.header.proposer, &vrf::leader_input(epoch_seed, self.slot()), &self.header.vrf_proof)
+    }
+
+    // Derive the hash & size from the header & body
+    fn seal(&mut self) {
+        self.hash = self.header.hash();
+        self.size = self.encoded_len() as u64;
+    }
+
+    // First block of the chain `config` describes, on top of its initial state. Genesis has no parent,
+    // so its prev hash commits to the config instead & the block hash follows from the config alone.
+    pub fn genesis(config: &GenesisConfig, state_root: Hash) -> Self {
+        let header = BlockHeader {
+            prev_hash: config.hash(),
+            height: 0,
+            slot: 0,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(config.timestamp),
+            tx_root: merkle_root(&[]),
+            account_bloom: AccountBloom::default(),
+            state_root,
+            proposer: [0; 32],
+            vrf_proof: VrfProof::default(),
+            certificate: None,
+        };
+        let mut block = Block {
+            transactions: vec![],
+            hash: [0; 32],
+            header,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+            size: 0,
+        };
+        block.seal();
+        block
+    }
+
+    // Genesis of the default, empty chain
+    pub fn create_genesis() -> Self {
+        Self::genesis(&GenesisConfig::default(), merkle_root(&[]))
+    }
+
+    pub fn prev_hash(&self) -> Blockhash {
+        self.header.prev_hash
+    }
+
+    pub fn height(&self) -> u64 {
+        self.header.height
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.header.slot
+    }
+
+    // Serialized size of an empty block, the fixed overhead every block pays before its transactions
+    pub fn overhead() -> usize {
+        Self::create_genesis().encoded_len()
+    }
+
+    // The size is a fixed-width field, so the encoding is the same length whatever it holds
+    pub fn encoded_len(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    // Compute units of every transaction in the block, votes included
+    pub fn compute_units(&self) -> u64 {
+        self.transactions.iter().map(Transaction::compute_units).fold(0, u64::saturating_add)
+    }
+
+    // What the block's transactions paid over `base_fee` between them, tips included, owed to its proposer
+    pub fn tips(&self, base_fee: u64) -> u64 {
+        self.transactions
+            .iter()
+            .filter(|tx| tx.pays_base_fee())
+            .map(|tx| tx.fee().saturating_sub(base_fee).saturating_add(tx.tip()))
+            .fold(0, u64::saturating_add)
+    }
+
+    pub fn tx_root(transactions: &[Transaction]) -> Hash {
+        merkle_root(&transactions.iter().map(Transaction::leaf).collect::<Vec<_>>())
+    }
+
+    pub fn account_bloom(transactions: &[Transaction]) -> AccountBloom {
+        AccountBloom::from_accounts(&transactions.iter().flat_map(Transaction::accounts).collect::<Vec<_>>())
+    }
+
+    // Proof that the transaction at `index` is in this block, checkable against the header alone
+    pub fn prove_transaction(&self, index: usize) -> Option<MerkleProof> {
+        let leaves: Vec<Hash> = self.transactions.iter().map(Transaction::leaf).collect();
+        MerkleProof::generate(&leaves, index)
+    }
+
+    // Whether the hash commits to the header & the header to the transactions
+    pub fn is_consistent(&self) -> bool {
+        self.hash == self.header.hash()
+            && self.header.tx_root == Self::tx_root(&self.transactions)
+            && self.header.account_bloom == Self::account_bloom(&self.transactions)
+    }
+
+    // Canonical borsh encoding of the full block
+    pub fn to_bytes(&self) -> Vec<u8> {
+        borsh::to_vec(self).expect("Block encoding is infallible")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let block: Block = borsh::from_slice(bytes).map_err(|_| "Invalid block encoding")?;
+
+        if !block.is_consistent() {
+            return Err("Block hash does not match contents")
+        }
+        if block.size != bytes.len() as u64 {
+            return Err("Block size does not match its encoding")
+        }
+
+        Ok(block)
+    }
+}
+
+// A transaction as a client knows it: by content hash, or by one of its signatures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionId {
+    Hash(Txhash),
+    Signature([u8; Signature::BYTE_SIZE]),
+}
+
+impl TransactionId {
+    // Tell the two apart by length
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if let Ok(hash) = bytes.try_into() {
+            return Some(TransactionId::Hash(hash))
+        }
+        bytes.try_into().ok().map(TransactionId::Signature)
+    }
+
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        match self {
+            TransactionId::Hash(hash) => tx.hash() == *hash,
+            TransactionId::Signature(signature) => tx.signatures().iter().any(|(_, carried)| carried.to_bytes() == *signature),
+        }
+    }
+}
+
+// Where a transaction is, as far as this node knows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TransactionStatus {
+    // Waiting in the mempool, or over RPC, in a block that isn't yet at the commitment asked for
+    Pending,
+    // In a canonical block
+    Included { block_hash: Blockhash, height: u64 },
+    Unknown,
+}
+
+// What one transaction did when its block was executed. A block only commits if every transaction in
+// it succeeds, so each receipt is for one that did.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TransactionReceipt {
+    pub tx_hash: Txhash,
+    pub block_hash: Blockhash,
+    // Position in the block
+    pub index: u64,
+    // Fee taken from the signer
+    pub fee: u64,
+    // Compute units the transaction cost the block
+    #[serde(default)]
+    pub compute_units: u64,
+}
+
+impl TransactionReceipt {
+    pub fn new(block: &Block, index: u64, tx: &Transaction) -> Self {
+        TransactionReceipt { tx_hash: tx.hash(), block_hash: block.hash, index, fee: tx.fee(), compute_units: tx.compute_units() }
+    }
+}
+
+// What a transaction would do if it ran now, from `AccountsDB::simulate_transaction`
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SimulationResult {
+    // Why it would fail, if it would
+    pub error: Option<String>,
+    // Fee it would pay, nothing if it would fail
+    pub fee: u64,
+    pub compute_units: u64,
+    // Balances & stakes it would change, nothing if it would fail
+    pub changes: StateDiff,
+}
+
+// A balance or stake a block changed. `None` means the account or validator didn't exist on that side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ValueChange {
+    pub pubkey: Pubkey,
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+}
+
+// Balances & stakes a block changed when it was executed, rewards included, each ordered by key
+#[derive(Default, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StateDiff {
+    pub balances: Vec<ValueChange>,
+    pub stakes: Vec<ValueChange>,
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct UserAccount {
+    pub address: Address, // Derived from public key to string
+    pub public_key: Pubkey, // Owner's wallet public key
+    pub balance: u64,
+    pub nonce: u64,
+    // Funds received through time-locked transfers, not spendable until they unlock
+    #[serde(default)]
+    pub locked: Vec<Vesting>,
+    // State kept by whatever program owns the account, at most `MAX_ACCOUNT_DATA_BYTES`
+    #[serde(default)]
+    pub data: Vec<u8>,
+    // Who may debit or change the account, `SYSTEM_OWNER` by default
+    #[serde(default)]
+    pub owner: Pubkey,
+    // Set by the chain's freeze authority. Nothing can debit a frozen account until it's thawed.
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+impl UserAccount {
+    pub fn from_public_key(public_key: Pubkey) -> Self {
+        UserAccount {
+            address: hex::encode(public_key),
+            public_key,
+            ..Default::default()
+        }
+    }
+
+    // Whether a transaction signed by `signer` may debit or change this account. System accounts leave
+    // that to the transaction's own signature checks; any other owner has to sign itself.
+    pub fn is_authorized(&self, signer: &Pubkey) -> bool {
+        self.owner == SYSTEM_OWNER || self.owner == *signer
+    }
+
+    pub fn locked_balance(&self) -> u64 {
+        self.locked.iter().map(|vesting| vesting.amount).fold(0u64, u64::saturating_add)
+    }
+}
+
+// Part of an account's funds that moves into its balance once the chain reaches `unlock_height`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Vesting {
+    pub amount: u64,
+    pub unlock_height: u64,
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct ValidatorAccount {
+    pub address: Address,
+    pub public_key: Pubkey,
+    // Self-bond plus everything delegated to the validator
+    pub stake: u64,
+    // Percent of the rewards earned on delegated stake the validator keeps, set when it registers
+    pub commission: u8,
+    // Leader slots missed since the validator last proposed a block
+    pub missed_slots: u64,
+    // Height the validator was jailed at for missing too many slots, until it unjails itself
+    pub jailed_since: Option<u64>,
+    // Key the validator's votes are aggregated under, if it registered one
+    #[serde(default, with = "wire::optional_byte_array")]
+    pub bls_key: Option<BlsPubkey>,
+    last_finalized_hash: Blockhash,
+}
+
+// A token other than the native one. Only `authority` can issue more of it; `supply` is everything
+// issued so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Mint {
+    pub authority: Pubkey,
+    pub decimals: u8,
+    pub supply: u64,
+}
+
+impl Mint {
+    // Mint ids come from the creating key & the nonce it created the mint with, so they can't collide
+    pub fn id(authority: &Pubkey, nonce: u64) -> MintId {
+        Sha256::new().chain_update(b"mint").chain_update(authority).chain_update(nonce.to_le_bytes()).finalize().into()
+    }
+}
+
+// One owner's balance of one token. It's stored at `address(mint, owner)`, apart from the owner's
+// native `UserAccount`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct TokenAccount {
+    pub mint: MintId,
+    pub owner: Pubkey,
+    pub balance: u64,
+}
+
+impl TokenAccount {
+    pub fn address(mint: &MintId, owner: &Pubkey) -> Pubkey {
+        Sha256::new().chain_update(b"token").chain_update(mint).chain_update(owner).finalize().into()
+    }
+}
+
+// How much of `owner`'s balance `delegate` may still move. It's stored at `address(owner, delegate)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Allowance {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub amount: u64,
+}
+
+impl Allowance {
+    pub fn address(owner: &Pubkey, delegate: &Pubkey) -> Pubkey {
+        Sha256::new().chain_update(b"allowance").chain_update(owner).chain_update(delegate).finalize().into()
+    }
+}
+
+// Stake bonded to a validator by another account, kept under the validator's key so its share of the
+// validator's rewards can be paid out to `delegator`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Delegation {
+    pub delegator: Pubkey,
+    pub amount: u64,
+}
+
+// Stake withdrawn by a validator, held back until the chain reaches `release_height`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Unbonding {
+    pub amount: u64,
+    pub release_height: u64,
+}
+
+impl ValidatorAccount {
+    pub fn new(public_key: Pubkey) -> Self {
+        ValidatorAccount {
+            address: hex::encode(public_key),
+            public_key,
+            stake: 0,
+            commission: 0,
+            missed_slots: 0,
+            jailed_since: None,
+            bls_key: None,
+            last_finalized_hash: [0; 32], // Nothing finalized yet
+        }
+    }
+
+    pub fn with_bls_key(mut self, bls_key: BlsPubkey) -> Self {
+        self.bls_key = Some(bls_key);
+        self
+    }
+
+    pub fn is_jailed(&self) -> bool {
+        self.jailed_since.is_some()
+    }
+
+    pub fn update_last_finalized_hash(&mut self, new_hash: Blockhash) {
+        self.last_finalized_hash = new_hash;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct StakeTransaction {
+    pub validator: Pubkey,
+    pub staker: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature
+}
+
+impl StakeTransaction {
+    pub fn new(validator: Pubkey, staker: Pubkey, amt: u64, nonce: u64) -> Self {
+        StakeTransaction {
+            validator,
+            staker,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap()
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // Accounts of the stake program instruction this executes as
+    fn instruction_accounts(&self) -> [AccountMeta; 2] {
+        [AccountMeta { pubkey: self.staker, signer: true }, AccountMeta { pubkey: self.validator, signer: false }]
+    }
+}
+
+impl TransactionSign for StakeTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.staker, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        // State first, so we only ever parse the key of an account that exists
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        // Make sure `validator`` is a validator
+        if !db.is_validator(&self.validator) {
+            return false
+        }
+
+        let staker = match db.get_account(&self.staker) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        // The staker pays the fee on top of the amount staked
+        match self.amt.checked_add(self.fee) {
+            Some(total) => staker.balance >= total,
+            None => false,
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.validator.to_vec());
+        data.extend(&self.staker.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Stake execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Stake execute")
+        }
+
+        // If a transaction has gotten this far we can assume that the accounts are in the db
+        let staker = db.get_account(&self.staker).unwrap();
+        let total = self.amt.checked_add(self.fee).ok_or("Amount plus fee overflows")?;
+
+        if staker.balance.lt(&total) {
+            return Err("Staker balance less than amount")
+        }
+
+        db.decrease_account_balance(&self.staker, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+
+        db.programs.process(&STAKE_PROGRAM, &self.instruction_accounts(), &self.amt.to_le_bytes(), db)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct TransferTransaction {
+    pub to: Pubkey,
+    pub from: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl TransferTransaction {
+    pub fn new(to: Pubkey, from: Pubkey, amt: u64, nonce: u64) -> Self {
+        TransferTransaction {
+            to,
+            from,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // Accounts of the transfer program instruction this executes as
+    fn instruction_accounts(&self) -> [AccountMeta; 2] {
+        [AccountMeta { pubkey: self.from, signer: true }, AccountMeta { pubkey: self.to, signer: false }]
+    }
+}
+
+impl TransactionSign for TransferTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.from, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.to.to_vec());
+        data.extend(&self.from.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        // Make sure that the `from` account is actually the signer once we know it exists
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        // First we'll make sure that `to` and `from` actually exist
+        let from = match db.get_account(&self.from) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        let _to = match db.get_account(&self.to) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        // "Simulate" the transaction, `from` pays the fee on top of the amount
+        match self.amt.checked_add(self.fee) {
+            Some(total) => from.balance >= total,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Transfer execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Transfer execute")
+        }
+
+        // If a transaction has gotten this far we can assume that the accounts are in the db
+        let from = db.get_account(&self.from).unwrap();
+        let total = self.amt.checked_add(self.fee).ok_or("Amount plus fee overflows")?;
+
+        if from.balance.lt(&total) {
+            return Err("Staker balance less than amount")
+        }
+
+        db.decrease_account_balance(&self.from, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+
+        db.programs.process(&TRANSFER_PROGRAM, &self.instruction_accounts(), &self.amt.to_le_bytes(), db)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Dev chains only: mints `amt` to `to`, opening the account if needed. Only the chain's faucet, set at
+// genesis, may sign one, so on a chain without a faucet every airdrop is invalid.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct AirdropTransaction {
+    pub to: Pubkey,
+    pub authority: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl AirdropTransaction {
+    pub fn new(to: Pubkey, authority: Pubkey, amt: u64, nonce: u64) -> Self {
+        AirdropTransaction {
+            to,
+            authority,
+            amt,
+            nonce,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+}
+
+impl TransactionSign for AirdropTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.authority, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.to.to_vec());
+        data.extend(&self.authority.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        db.faucet == Some(self.authority) && self.amt > 0
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Airdrop execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Airdrop execute")
+        }
+
+        db.deposit(&self.to, self.amt);
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.mint(self.amt);
+        Ok(())
+    }
+}
+
+// Anchors up to `MAX_MEMO_BYTES` of arbitrary data on chain. Moves no funds; the sender only pays the fee.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct MemoTransaction {
+    pub from: Pubkey,
+    pub memo: Vec<u8>,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl MemoTransaction {
+    pub fn new(from: Pubkey, memo: Vec<u8>, nonce: u64) -> Self {
+        MemoTransaction {
+            from,
+            memo,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for MemoTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.from, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.from.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+        // Length-prefixed, so the memo can't be shifted into or out of the fields around it
+        data.extend(&(self.memo.len() as u64).to_le_bytes());
+        data.extend(&self.memo);
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.memo.len() > MAX_MEMO_BYTES {
+            return false
+        }
+
+        match db.get_account(&self.from) {
+            Some(from) => from.balance >= self.fee,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Memo execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Memo execute")
+        }
+
+        db.decrease_account_balance(&self.from, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Pays several recipients out of one account, all or nothing: either every credit happens or none do
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct BatchTransferTransaction {
+    pub from: Pubkey,
+    pub transfers: Vec<(Pubkey, u64)>,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl BatchTransferTransaction {
+    pub fn new(from: Pubkey, transfers: Vec<(Pubkey, u64)>, nonce: u64) -> Self {
+        BatchTransferTransaction {
+            from,
+            transfers,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // Everything `from` pays: each amount plus the fee, or `None` on overflow
+    pub fn total(&self) -> Option<u64> {
+        self.transfers.iter().try_fold(self.fee, |total, (_, amt)| total.checked_add(*amt))
+    }
+}
+
+impl TransactionSign for BatchTransferTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.from, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.from.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+        data.extend(&(self.transfers.len() as u64).to_le_bytes());
+        for (to, amt) in &self.transfers {
+            data.extend(&to.to_vec());
+            data.extend(&amt.to_le_bytes());
+        }
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.transfers.is_empty() || self.transfers.len() > MAX_BATCH_RECIPIENTS {
+            return false
+        }
+
+        // Every recipient has to exist up front, so no credit can fail halfway through
+        if !self.transfers.iter().all(|(to, _)| db.get_account(to).is_some()) {
+            return false
+        }
+
+        let from = match db.get_account(&self.from) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        match self.total() {
+            Some(total) => from.balance >= total,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in BatchTransfer execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in BatchTransfer execute")
+        }
+
+        // The whole debit comes first; once it succeeds the credits can't fail
+        let total = self.total().ok_or("Amount plus fee overflows")?;
+        db.decrease_account_balance(&self.from, total)
+            .map_err(|_| "Balance decrease failed")?;
+
+        for (to, amt) in &self.transfers {
+            db.increase_account_balance(to, *amt)
+                .map_err(|_| "Balance increase failed")?;
+        }
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// An M-of-N account: spending from it takes signatures from `threshold` of its `signers`. Its funds
+// sit in an ordinary account at `address()`, which no single key can sign for.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct MultisigAccount {
+    pub threshold: u8,
+    pub signers: Vec<Pubkey>,
+}
+
+impl MultisigAccount {
+    pub fn new(threshold: u8, signers: Vec<Pubkey>) -> Self {
+        MultisigAccount { threshold, signers }
+    }
+
+    // Hash of the configuration, so changing the threshold or any signer makes a different account
+    pub fn address(&self) -> Pubkey {
+        let encoding = borsh::to_vec(self).expect("Multisig encoding is infallible");
+        Sha256::new().chain_update(b"multisig").chain_update(encoding).finalize().into()
+    }
+
+    // At least one & at most all signers required, with no signer listed twice
+    pub fn is_valid(&self) -> bool {
+        let signers = self.signers.len();
+        if self.threshold == 0 || self.threshold as usize > signers || signers > MAX_MULTISIG_SIGNERS {
+            return false
+        }
+        self.signers.iter().enumerate().all(|(i, signer)| !self.signers[..i].contains(signer))
+    }
+}
+
+// A transfer out of a multisig account. It carries the account's configuration, so its signatures
+// can be checked without looking anything up, & the signatures collected so far, each tagged with
+// its signer's index in the configuration.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct MultisigTransferTransaction {
+    pub multisig: MultisigAccount,
+    pub to: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature_set", deserialize_with = "wire::deserialize_signature_set")]
+    signatures: Vec<(u8, Signature)>,
+}
+
+impl MultisigTransferTransaction {
+    pub fn new(multisig: MultisigAccount, to: Pubkey, amt: u64, nonce: u64) -> Self {
+        MultisigTransferTransaction {
+            multisig,
+            to,
+            amt,
+            nonce,
+            fee: 0,
+            signatures: vec![],
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for MultisigTransferTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        self.signatures
+            .iter()
+            .filter_map(|(index, signature)| self.multisig.signers.get(*index as usize).map(|signer| (*signer, *signature)))
+            .collect()
+    }
+
+    // Add (or replace) `wallet`'s signature. Wallets that aren't signers of the account are ignored.
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        let Some(index) = self.multisig.signers.iter().position(|signer| signer == &wallet.public_key) else { return };
+        let signature = wallet.sign(message);
+
+        self.signatures.retain(|(signed, _)| *signed as usize != index);
+        self.signatures.push((index as u8, signature));
+    }
+
+    // Enough distinct signers of a well-formed account, & no signature from outside it
+    fn authorized(&self) -> bool {
+        let distinct = self.signatures.iter().enumerate().all(|(i, (index, _))| {
+            !self.signatures[..i].iter().any(|(other, _)| other == index)
+        });
+
+        self.multisig.is_valid()
+            && distinct
+            && self.signatures.iter().all(|(index, _)| (*index as usize) < self.multisig.signers.len())
+            && self.signatures.len() >= self.multisig.threshold as usize
+    }
+
+    // Signatures aren't part of the payload, so each signer signs the same bytes
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.multisig.address().to_vec());
+        data.extend(&self.to.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        let from = match db.get_account(&self.multisig.address()) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        if db.get_account(&self.to).is_none() {
+            return false
+        }
+
+        match self.amt.checked_add(self.fee) {
+            Some(total) => from.balance >= total,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in MultisigTransfer execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in MultisigTransfer execute")
+        }
+
+        let address = self.multisig.address();
+        db.decrease_account_balance(&address, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+
+        db.transfer(&address, &self.to, self.amt)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Turns a funded account into a validator, moving `stake` out of its balance as a self-bond of at
+// least the chain's minimum. The account's key becomes the validator's identity.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct RegisterValidatorTransaction {
+    pub validator: Pubkey,
+    pub stake: u64,
+    pub commission: u8,
+    // BLS key to vote with & the proof of possession that has to come with it
+    #[serde(default, with = "wire::optional_byte_array")]
+    pub bls_key: Option<BlsPubkey>,
+    #[serde(default, with = "wire::optional_byte_array")]
+    pub bls_proof: Option<BlsSignature>,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl RegisterValidatorTransaction {
+    pub fn new(validator: Pubkey, stake: u64, nonce: u64) -> Self {
+        RegisterValidatorTransaction {
+            validator,
+            stake,
+            commission: 0,
+            bls_key: None,
+            bls_proof: None,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // Keep `commission` percent of the rewards earned on stake delegated to us. Signed, like the fee.
+    pub fn with_commission(mut self, commission: u8) -> Self {
+        self.commission = commission;
+        self
+    }
+
+    // Register `wallet`'s BLS key so our votes can be aggregated. Signed, like the fee.
+    pub fn with_bls_key(mut self, wallet: &Wallet) -> Self {
+        self.bls_key = Some(wallet.bls_public_key());
+        self.bls_proof = Some(wallet.bls_proof_of_possession());
+        self
+    }
+}
+
+impl TransactionSign for RegisterValidatorTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.validator, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.validator.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.stake.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+        data.push(self.commission);
+        if let (Some(key), Some(proof)) = (&self.bls_key, &self.bls_proof) {
+            data.extend(key);
+            data.extend(proof);
+        }
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if db.is_validator(&self.validator) || self.stake < db.min_validator_stake || self.commission > 100 {
+            return false
+        }
+        match (&self.bls_key, &self.bls_proof) {
+            (None, None) => {}
+            (Some(key), Some(proof)) if bls::verify_possession(key, proof) => {}
+            _ => return false,
+        }
+
+        let account = match db.get_account(&self.validator) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        match self.stake.checked_add(self.fee) {
+            Some(total) => account.balance >= total,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in RegisterValidator execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in RegisterValidator execute")
+        }
+
+        let total = self.stake.checked_add(self.fee).ok_or("Amount plus fee overflows")?;
+        db.decrease_account_balance(&self.validator, total)
+            .map_err(|_| "Balance decrease failed")?;
+
+        let mut validator = ValidatorAccount::new(self.validator);
+        validator.stake = self.stake;
+        validator.commission = self.commission;
+        validator.bls_key = self.bls_key;
+        db.add_validator(self.validator, validator);
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Withdraws `amt` of a validator's self-bond. It leaves the stake at once but is only paid out to the
+// validator's account after the chain's unbonding period. The fee comes from the account's balance.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct UnstakeTransaction {
+    pub validator: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl UnstakeTransaction {
+    pub fn new(validator: Pubkey, amt: u64, nonce: u64) -> Self {
+        UnstakeTransaction {
+            validator,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for UnstakeTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.validator, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.validator.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        let validator = match db.get_validator(&self.validator) {
+            Some(validator) => validator,
+            None => return false,
+        };
+        // Delegated stake isn't the validator's to withdraw
+        let self_stake = db.self_stake(&validator);
+        if self.amt == 0 || self_stake < self.amt {
+            return false
+        }
+
+        // Either exit entirely or stay above the minimum bond
+        let remaining = self_stake - self.amt;
+        if remaining != 0 && remaining < db.min_validator_stake {
+            return false
+        }
+
+        match db.get_account(&self.validator) {
+            Some(account) => account.balance >= self.fee,
+            None => self.fee == 0,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Unstake execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Unstake execute")
+        }
+
+        if self.fee > 0 {
+            db.decrease_account_balance(&self.validator, self.fee)
+                .map_err(|_| "Balance decrease failed")?;
+        }
+        db.decrease_validator_stake(&self.validator, self.amt)?;
+        db.queue_unbonding(&self.validator, self.amt);
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Lets a validator jailed for missing slots back into the leader schedule & quorums, once the chain's
+// cooldown has passed. Signed by the validator.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct UnjailTransaction {
+    pub validator: Pubkey,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl UnjailTransaction {
+    pub fn new(validator: Pubkey, nonce: u64) -> Self {
+        UnjailTransaction {
+            validator,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for UnjailTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.validator, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.validator.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        let Some(jailed_since) = db.get_validator(&self.validator).and_then(|validator| validator.jailed_since) else { return false };
+        if db.latest_height < jailed_since.saturating_add(db.jail_cooldown_blocks) {
+            return false
+        }
+        db.get_account(&self.validator).is_some_and(|account| account.balance >= self.fee)
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Unjail execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Unjail execute")
+        }
+
+        db.decrease_account_balance(&self.validator, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.unjail(&self.validator)
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Destroys `amt` of the sender's balance, taking it out of the total supply along with the fee
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct BurnTransaction {
+    pub from: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl BurnTransaction {
+    pub fn new(from: Pubkey, amt: u64, nonce: u64) -> Self {
+        BurnTransaction {
+            from,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for BurnTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.from, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.from.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.amt == 0 {
+            return false
+        }
+
+        let from = match db.get_account(&self.from) {
+            Some(from) => from,
+            None => return false,
+        };
+
+        match self.amt.checked_add(self.fee) {
+            Some(total) => from.balance >= total,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Burn execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Burn execute")
+        }
+
+        let total = self.amt.checked_add(self.fee).ok_or("Amount plus fee overflows")?;
+        db.decrease_account_balance(&self.from, total)
+            .map_err(|_| "Balance decrease failed")?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.amt.saturating_add(self.fee));
+        Ok(())
+    }
+}
+
+// Creates a new token with `authority` as the only key that can issue it. Its id is
+// `Mint::id(authority, nonce)` and it starts with no supply.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct CreateMintTransaction {
+    pub authority: Pubkey,
+    pub decimals: u8,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl CreateMintTransaction {
+    pub fn new(authority: Pubkey, decimals: u8, nonce: u64) -> Self {
+        CreateMintTransaction {
+            authority,
+            decimals,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn mint_id(&self) -> MintId {
+        Mint::id(&self.authority, self.nonce)
+    }
+}
+
+impl TransactionSign for CreateMintTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.authority, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.authority.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.push(self.decimals);
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if db.get_mint(&self.mint_id()).is_some() {
+            return false
+        }
+
+        match db.get_account(&self.authority) {
+            Some(authority) => authority.balance >= self.fee,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in CreateMint execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in CreateMint execute")
+        }
+
+        db.decrease_account_balance(&self.authority, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.add_mint(self.mint_id(), Mint { authority: self.authority, decimals: self.decimals, supply: 0 });
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Issues `amt` of a token to `to`, opening its token account if needed. Signed by the mint's authority.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct MintToTransaction {
+    pub mint: MintId,
+    pub authority: Pubkey,
+    pub to: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl MintToTransaction {
+    pub fn new(mint: MintId, authority: Pubkey, to: Pubkey, amt: u64, nonce: u64) -> Self {
+        MintToTransaction {
+            mint,
+            authority,
+            to,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for MintToTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.authority, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.mint.to_vec());
+        data.extend(&self.authority.to_vec());
+        data.extend(&self.to.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        let mint = match db.get_mint(&self.mint) {
+            Some(mint) => mint,
+            None => return false,
+        };
+        if mint.authority != self.authority || self.amt == 0 || mint.supply.checked_add(self.amt).is_none() {
+            return false
+        }
+
+        match db.get_account(&self.authority) {
+            Some(authority) => authority.balance >= self.fee,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in MintTo execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in MintTo execute")
+        }
+
+        db.decrease_account_balance(&self.authority, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.issue_tokens(&self.mint, self.amt)?;
+        db.increase_token_balance(&self.mint, &self.to, self.amt);
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Moves `amt` of a token between owners, opening the recipient's token account if needed. The fee is
+// paid in the native token.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct TokenTransferTransaction {
+    pub mint: MintId,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl TokenTransferTransaction {
+    pub fn new(mint: MintId, from: Pubkey, to: Pubkey, amt: u64, nonce: u64) -> Self {
+        TokenTransferTransaction {
+            mint,
+            from,
+            to,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for TokenTransferTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.from, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.mint.to_vec());
+        data.extend(&self.from.to_vec());
+        data.extend(&self.to.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.amt == 0 || db.token_balance(&self.mint, &self.from) < self.amt {
+            return false
+        }
+
+        match db.get_account(&self.from) {
+            Some(from) => from.balance >= self.fee,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in TokenTransfer execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in TokenTransfer execute")
+        }
+
+        db.decrease_account_balance(&self.from, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.decrease_token_balance(&self.mint, &self.from, self.amt)?;
+        db.increase_token_balance(&self.mint, &self.to, self.amt);
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// A transfer the recipient can't spend until the block at `unlock_height` has been finalized. Until
+// then the amount sits in the recipient's `locked` funds.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct TimeLockedTransferTransaction {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amt: u64,
+    pub unlock_height: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl TimeLockedTransferTransaction {
+    pub fn new(from: Pubkey, to: Pubkey, amt: u64, unlock_height: u64, nonce: u64) -> Self {
+        TimeLockedTransferTransaction {
+            from,
+            to,
+            amt,
+            unlock_height,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for TimeLockedTransferTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.from, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.from.to_vec());
+        data.extend(&self.to.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.unlock_height.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.amt == 0 || db.get_account(&self.to).is_none() {
+            return false
+        }
+
+        let from = match db.get_account(&self.from) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        match self.amt.checked_add(self.fee) {
+            Some(total) => from.balance >= total,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in TimeLockedTransfer execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in TimeLockedTransfer execute")
+        }
+
+        let total = self.amt.checked_add(self.fee).ok_or("Amount plus fee overflows")?;
+        db.decrease_account_balance(&self.from, total)
+            .map_err(|_| "Balance decrease failed")?;
+        db.lock_funds(&self.to, Vesting { amount: self.amt, unlock_height: self.unlock_height })?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Lets `delegate` move up to `amt` of the owner's balance, replacing any earlier allowance. Approving
+// zero revokes it.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct ApproveTransaction {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl ApproveTransaction {
+    pub fn new(owner: Pubkey, delegate: Pubkey, amt: u64, nonce: u64) -> Self {
+        ApproveTransaction {
+            owner,
+            delegate,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for ApproveTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.owner, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.owner.to_vec());
+        data.extend(&self.delegate.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.owner == self.delegate {
+            return false
+        }
+
+        match db.get_account(&self.owner) {
+            Some(owner) => owner.balance >= self.fee,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Approve execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Approve execute")
+        }
+
+        db.decrease_account_balance(&self.owner, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.set_allowance(&self.owner, &self.delegate, self.amt);
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Moves `amt` from `owner` to `to` on the owner's behalf, spending the allowance they gave `delegate`.
+// Signed by the delegate, who also pays the fee.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct DelegatedTransferTransaction {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub to: Pubkey,
+    pub amt: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl DelegatedTransferTransaction {
+    pub fn new(owner: Pubkey, delegate: Pubkey, to: Pubkey, amt: u64, nonce: u64) -> Self {
+        DelegatedTransferTransaction {
+            owner,
+            delegate,
+            to,
+            amt,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for DelegatedTransferTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.delegate, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.owner.to_vec());
+        data.extend(&self.delegate.to_vec());
+        data.extend(&self.to.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.amt == 0 || db.allowance(&self.owner, &self.delegate) < self.amt || db.get_account(&self.to).is_none() {
+            return false
+        }
+
+        let owner = match db.get_account(&self.owner) {
+            Some(account) => account,
+            None => return false,
+        };
+        let delegate = match db.get_account(&self.delegate) {
+            Some(account) => account,
+            None => return false,
+        };
+
+        owner.balance >= self.amt && delegate.balance >= self.fee
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in DelegatedTransfer execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in DelegatedTransfer execute")
+        }
+
+        db.decrease_account_balance(&self.delegate, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.spend_allowance(&self.owner, &self.delegate, self.amt)?;
+        db.transfer(&self.owner, &self.to, self.amt)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Hands an account to a new owner, signed by the account's own key while it's still a system account.
+// From then on only the new owner can debit or change it.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct AssignTransaction {
+    pub account: Pubkey,
+    pub owner: Pubkey,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl AssignTransaction {
+    pub fn new(account: Pubkey, owner: Pubkey, nonce: u64) -> Self {
+        AssignTransaction {
+            account,
+            owner,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for AssignTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.account, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.account.to_vec());
+        data.extend(&self.owner.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        match db.get_account(&self.account) {
+            Some(account) => account.owner == SYSTEM_OWNER && account.balance >= self.fee,
+            None => false,
+        }
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Assign execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Assign execute")
+        }
+
+        db.decrease_account_balance(&self.account, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.assign_owner(&self.account, self.owner)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Stops every debit from `account` until it's thawed. Only the chain's freeze authority, set at genesis,
+// may sign one, & it can't freeze itself.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct FreezeTransaction {
+    pub authority: Pubkey,
+    pub account: Pubkey,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl FreezeTransaction {
+    pub fn new(authority: Pubkey, account: Pubkey, nonce: u64) -> Self {
+        FreezeTransaction {
+            authority,
+            account,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for FreezeTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.authority, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.authority.to_vec());
+        data.extend(&self.account.to_vec());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if db.freeze_authority != Some(self.authority) {
+            return false
+        }
+        let Some(account) = db.get_account(&self.account) else { return false };
+        !account.frozen && self.account != self.authority && db.get_account(&self.authority).is_some_and(|authority| authority.balance >= self.fee)
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Freeze execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Freeze execute")
+        }
+
+        db.decrease_account_balance(&self.authority, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.set_frozen(&self.account, true)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Lifts a freeze on `account`. Signed by the chain's freeze authority.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct ThawTransaction {
+    pub authority: Pubkey,
+    pub account: Pubkey,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl ThawTransaction {
+    pub fn new(authority: Pubkey, account: Pubkey, nonce: u64) -> Self {
+        ThawTransaction {
+            authority,
+            account,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for ThawTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.authority, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
         let mut data = vec![];
 
-        data.extend(&self.to.to_vec());
-        data.extend(&self.from.to_vec());
+        data.extend(&self.authority.to_vec());
+        data.extend(&self.account.to_vec());
         data.extend(&self.nonce.to_le_bytes());
-        data.extend(&self.amt.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
 
         data
     }
 
     fn validate(&self, db: &AccountsDB) -> bool {
-        // First we'll make sure that `to` and `from` actually exist
-        let from = match db.get_account(&self.from) {
-            Some(account) => account,
-            None => return false,
-        };
+        self.validate_state(db) && self.verify_signatures()
+    }
 
-        let _to = match db.get_account(&self.to) {
-            Some(account) => account,
-            None => return false,
-        };
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if db.freeze_authority != Some(self.authority) {
+            return false
+        }
+        let Some(account) = db.get_account(&self.account) else { return false };
+        account.frozen && db.get_account(&self.authority).is_some_and(|authority| authority.balance >= self.fee)
+    }
 
-        // Now we'll go ahead and make sure that the `from` account is actually the signer 
-        if !self.verify_signature(from.public_key()) {
-            return false;
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Thaw execute")
         }
+        self.apply_state(db)
+    }
 
-        // "Simulate" the transaction
-        if from.balance.lt(&self.amt) {
-            return false;
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Thaw execute")
         }
 
-        // Now we can say that for our purposes, the transaction is valid (`from` has balance gte amt & tx is signed by `from`)
-        true
+        db.decrease_account_balance(&self.authority, self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        db.set_frozen(&self.account, false)?;
+
+        Ok(())
     }
 
     fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
-        if !self.validate(&db) {
-            return Err("Invalid transaction in Transfer execute")
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Stores WASM bytecode in a new program account at `contract_address(authority, nonce)`, owned by the
+// loader so it can never change. Invoke transactions run it from then on.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct DeployTransaction {
+    pub authority: Pubkey,
+    pub code: Vec<u8>,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl DeployTransaction {
+    pub fn new(authority: Pubkey, code: Vec<u8>, nonce: u64) -> Self {
+        DeployTransaction {
+            authority,
+            code,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
         }
+    }
 
-        // If a transaction has gotten this far we can assume that the accounts are in the db
-        let from = db.get_account(&self.from).unwrap();
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
 
-        if from.balance.lt(&self.amt) {
-            return Err("Staker balance less than amount")
+    // Account the program is deployed to
+    pub fn address(&self) -> Pubkey {
+        contract::contract_address(&self.authority, self.nonce)
+    }
+}
+
+impl TransactionSign for DeployTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.authority, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.authority.to_vec());
+        data.extend(&(self.code.len() as u64).to_le_bytes());
+        data.extend(&self.code);
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.code.len() > MAX_ACCOUNT_DATA_BYTES || db.get_account(&self.address()).is_some() {
+            return false
+        }
+        db.get_account(&self.authority).is_some_and(|authority| authority.balance >= self.fee) && contract::check_code(&self.code).is_ok()
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Deploy execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Deploy execute")
         }
 
-        db.decrease_account_balance(&self.from, self.amt)
+        db.decrease_account_balance(&self.authority, self.fee)
             .map_err(|_| "Balance decrease failed")?;
-        
-        db.increase_account_balance(&self.to, self.amt)
+        let address = self.address();
+        db.add_account(address, UserAccount {
+            data: self.code.clone(),
+            owner: contract::WASM_LOADER,
+            ..UserAccount::from_public_key(address)
+        });
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Runs a deployed program with `data` as its input, able to read `accounts` & change those the program
+// owns. Execution stops once `gas_limit` is spent, & a program that fails changes nothing.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct InvokeTransaction {
+    pub caller: Pubkey,
+    pub program: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+    pub gas_limit: u64,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl InvokeTransaction {
+    pub fn new(caller: Pubkey, program: Pubkey, accounts: Vec<Pubkey>, data: Vec<u8>, gas_limit: u64, nonce: u64) -> Self {
+        InvokeTransaction {
+            caller,
+            program,
+            accounts,
+            data,
+            gas_limit,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+}
+
+impl TransactionSign for InvokeTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.caller, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.caller.to_vec());
+        data.extend(&self.program.to_vec());
+        data.extend(&(self.accounts.len() as u64).to_le_bytes());
+        for account in &self.accounts {
+            data.extend(account);
+        }
+        data.extend(&(self.data.len() as u64).to_le_bytes());
+        data.extend(&self.data);
+        data.extend(&self.gas_limit.to_le_bytes());
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    // Whether the program runs successfully is only known once it's applied
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.gas_limit > contract::MAX_GAS || self.data.len() > contract::MAX_INPUT_BYTES {
+            return false
+        }
+        if db.get_account(&self.program).is_none_or(|program| program.owner != contract::WASM_LOADER) {
+            return false
+        }
+        self.accounts.iter().all(|account| db.get_account(account).is_some())
+            && db.get_account(&self.caller).is_some_and(|caller| caller.balance >= self.fee)
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Invoke execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Invoke execute")
+        }
+
+        contract::invoke(db, self)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// Calls a native program registered with the node, so downstream crates can add on-chain logic without
+// a transaction type of their own. `signer` pays the fee & is the only account marked as signed.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct ProgramTransaction {
+    pub signer: Pubkey,
+    pub program: ProgramId,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+}
+
+impl ProgramTransaction {
+    pub fn new(signer: Pubkey, program: ProgramId, accounts: Vec<Pubkey>, data: Vec<u8>, nonce: u64) -> Self {
+        ProgramTransaction {
+            signer,
+            program,
+            accounts,
+            data,
+            nonce,
+            fee: 0,
+            signature: Signature::from_bytes(&DEFAULT_SIGNATURE_BYTES).unwrap(),
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // `accounts` as the program sees them
+    fn instruction_accounts(&self) -> Vec<AccountMeta> {
+        self.accounts.iter().map(|pubkey| AccountMeta { pubkey: *pubkey, signer: *pubkey == self.signer }).collect()
+    }
+}
+
+impl TransactionSign for ProgramTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.signer, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&self.signer.to_vec());
+        data.extend(&self.program.to_vec());
+        data.extend(&(self.accounts.len() as u64).to_le_bytes());
+        for account in &self.accounts {
+            data.extend(account);
+        }
+        data.extend(&(self.data.len() as u64).to_le_bytes());
+        data.extend(&self.data);
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        if self.accounts.len() > MAX_INSTRUCTION_ACCOUNTS || self.data.len() > MAX_INSTRUCTION_DATA_BYTES {
+            return false
+        }
+        db.get_account(&self.signer).is_some_and(|signer| signer.balance >= self.fee)
+            && db.programs.validate(&self.program, &self.instruction_accounts(), &self.data, db)
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Program execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Program execute")
+        }
+
+        db.decrease_account_balance(&self.signer, self.fee)
             .map_err(|_| "Balance decrease failed")?;
+        db.programs.process(&self.program, &self.instruction_accounts(), &self.data, db)?;
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
+
+// A list of instructions run in order as one transaction: one fee, paid by the first signer, & one set
+// of signatures. Either every instruction succeeds or none of them take effect.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct MessageTransaction {
+    // Keys that have to sign, the first one paying the fee
+    pub signers: Vec<Pubkey>,
+    pub instructions: Vec<Instruction>,
+    nonce: u64,
+    pub fee: u64,
+    #[borsh(serialize_with = "wire::serialize_signature_set", deserialize_with = "wire::deserialize_signature_set")]
+    signatures: Vec<(u8, Signature)>,
+}
+
+impl MessageTransaction {
+    pub fn new(signers: Vec<Pubkey>, instructions: Vec<Instruction>, nonce: u64) -> Self {
+        MessageTransaction {
+            signers,
+            instructions,
+            nonce,
+            fee: 0,
+            signatures: vec![],
+        }
+    }
+
+    // Offer a fee for priority in the mempool. It's part of the signed payload, so set it before signing.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.signers.first().copied().unwrap_or_default()
+    }
+
+    // Every account the message's instructions name, & its signers
+    fn keys(&self) -> Vec<Pubkey> {
+        let mut keys: Vec<Pubkey> = self.signers.iter().chain(self.instructions.iter().flat_map(|ix| &ix.accounts)).copied().collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    fn is_well_formed(&self) -> bool {
+        let distinct = self.signers.iter().enumerate().all(|(i, signer)| !self.signers[..i].contains(signer));
+        !self.signers.is_empty()
+            && self.signers.len() <= MAX_MESSAGE_SIGNERS
+            && distinct
+            && self.instructions.len() <= MAX_MESSAGE_INSTRUCTIONS
+            && self.instructions.iter().all(Instruction::is_well_formed)
+    }
 
+    // Take the fee & run every instruction against `db`, stopping at the first that fails
+    fn run(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        db.decrease_account_balance(&self.payer(), self.fee)
+            .map_err(|_| "Balance decrease failed")?;
+        for instruction in &self.instructions {
+            let accounts: Vec<AccountMeta> = instruction.accounts
+                .iter()
+                .map(|pubkey| AccountMeta { pubkey: *pubkey, signer: self.signers.contains(pubkey) })
+                .collect();
+            db.programs.process(&instruction.program, &accounts, &instruction.data, db)?;
+        }
         Ok(())
     }
 }
 
+impl TransactionSign for MessageTransaction {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        self.signatures
+            .iter()
+            .filter_map(|(index, signature)| self.signers.get(*index as usize).map(|signer| (*signer, *signature)))
+            .collect()
+    }
+
+    // Add (or replace) `wallet`'s signature. Wallets that aren't signers of the message are ignored.
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        let Some(index) = self.signers.iter().position(|signer| signer == &wallet.public_key) else { return };
+        let signature = wallet.sign(message);
+
+        self.signatures.retain(|(signed, _)| *signed as usize != index);
+        self.signatures.push((index as u8, signature));
+    }
+
+    // One signature from each signer
+    fn authorized(&self) -> bool {
+        let distinct = self.signatures.iter().enumerate().all(|(i, (index, _))| {
+            !self.signatures[..i].iter().any(|(other, _)| other == index)
+        });
+
+        distinct
+            && self.signatures.iter().all(|(index, _)| (*index as usize) < self.signers.len())
+            && self.signatures.len() == self.signers.len()
+    }
+
+    // Signatures aren't part of the payload, so each signer signs the same bytes
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(&(self.signers.len() as u64).to_le_bytes());
+        for signer in &self.signers {
+            data.extend(signer);
+        }
+        data.extend(&(self.instructions.len() as u64).to_le_bytes());
+        for instruction in &self.instructions {
+            data.extend(&instruction.program);
+            data.extend(&(instruction.accounts.len() as u64).to_le_bytes());
+            for account in &instruction.accounts {
+                data.extend(account);
+            }
+            data.extend(&(instruction.data.len() as u64).to_le_bytes());
+            data.extend(&instruction.data);
+        }
+        data.extend(&self.nonce.to_le_bytes());
+        data.extend(&self.fee.to_le_bytes());
+
+        data
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    // Later instructions can depend on earlier ones, so the whole message is run against a copy of its accounts
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        self.is_well_formed() && self.run(&db.stage(&self.keys())).is_ok()
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Message execute")
+        }
+        self.apply_state(db)
+    }
+
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.is_well_formed() {
+            return Err("Invalid transaction in Message execute")
+        }
+
+        let keys = self.keys();
+        let staged = db.stage(&keys);
+        self.run(&staged)?;
+        db.commit(&staged, &keys);
+
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)?;
+        db.burn(self.fee);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    chain::Blockchain,
+    db::AccountsDB,
+    pool::Mempool,
+    snapshot::Snapshot,
+    structures::Block,
+    vote::QuorumCertificate,
+};
+
+// Upper bound on how many blocks a peer will serve in response to a single request
+pub const MAX_BLOCKS_PER_REQUEST: u64 = 64;
+
+// The next height range to request from a peer whose tip is at `peer_height`, if we're behind it
+pub fn missing_range(chain: &Blockchain, peer_height: u64) -> Option<(u64, u64)> {
+    let local_height = chain.height();
+    if peer_height <= local_height {
+        return None
+    }
+
+    let start = local_height + 1;
+    let end = peer_height.min(local_height + MAX_BLOCKS_PER_REQUEST);
+    Some((start, end))
+}
+
+// Apply a block received from a peer: extend the tip or track it as a fork & re-run fork choice.
+// Transactions from blocks rolled back by a reorg go back into the mempool.
+pub fn apply_block(
+    chain: &Arc<RwLock<Blockchain>>,
+    db: &Arc<RwLock<AccountsDB>>,
+    mempool: &Arc<RwLock<Mempool>>,
+    block: &Block,
+) -> Result<(), &'static str> {
+    let mut db_lock = db.write().unwrap();
+    let mut chain_lock = chain.write().unwrap();
+
+    let reverted = chain_lock.apply(block.clone(), &mut db_lock)?;
+
+    // Everything that became canonical: just this block, or the whole new branch after a reorg
+    let fork_height = match reverted.first() {
+        Some(first) => chain_lock.height_of(&first.prev_hash()).expect("Fork point is canonical"),
+        None => chain_lock.height().saturating_sub(1),
+    };
+    let included: Vec<Block> = chain_lock.range(fork_height + 1, chain_lock.height());
+
+    let mempool_lock = mempool.write().unwrap();
+    // Without preflight, since we're holding the state lock it would take
+    for tx in reverted.iter().flat_map(|block| &block.transactions) {
+        let _ = mempool_lock.send_raw_transaction(tx.clone());
+    }
+    for block in &included {
+        mempool_lock.remove_included(&block.transactions);
+    }
+
+    Ok(())
+}
+
+// Fast sync: replace our chain & state with `snapshot` once `child`, the block after it, vouches for
+// its state & `certificate` shows our validators finalized `child`. Blocks from `child` on are then
+// applied as usual.
+pub fn load_snapshot(
+    chain: &Arc<RwLock<Blockchain>>,
+    db: &Arc<RwLock<AccountsDB>>,
+    snapshot: &Snapshot,
+    child: &Block,
+    certificate: &QuorumCertificate,
+) -> Result<(), &'static str> {
+    let mut db_lock = db.write().unwrap();
+    let mut chain_lock = chain.write().unwrap();
+
+    // The validators we start out with are the ones trusted to vouch for the snapshot
+    let state = snapshot.restore(&db_lock);
+    snapshot.verify(&state, child, certificate, &db_lock)?;
+
+    *db_lock = state;
+    chain_lock.reset_to(snapshot.block.clone());
+    Ok(())
+}
+
+// Replay a batch of consecutive blocks, stopping at the first one that fails. Returns how many were applied.
+pub fn apply_blocks(
+    chain: &Arc<RwLock<Blockchain>>,
+    db: &Arc<RwLock<AccountsDB>>,
+    mempool: &Arc<RwLock<Mempool>>,
+    blocks: &[Block],
+) -> Result<usize, &'static str> {
+    let mut applied = 0;
+    for block in blocks {
+        // Peers may resend blocks we already have, skip over those
+        if chain.read().unwrap().contains(&block.hash) {
+            continue
+        }
+        apply_block(chain, db, mempool, block)?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
@@ -1,44 +1,120 @@
 use std::{
+    collections::BTreeMap,
     mem::drop,
     sync::{Arc, RwLock}, 
     thread,
-    time::Duration
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
+    bloom::AccountBloom,
     builder::BlockBuilder,
-    db::AccountsDB,
+    chain::{Blockchain, Commitment},
+    checkpoint::Checkpoint,
+    client::{ClientError, LitechainClient, SendOutcome},
+    compute::{next_base_fee, ACCOUNT_UNITS, BYTE_UNITS, MAX_TRANSACTION_UNITS, SIGNATURE_UNITS},
+    config::{ChainConfig, GenesisAccount, GenesisConfig, GenesisValidator},
+    contract::{MAX_GAS, WASM_LOADER},
+    discovery::{DiscoveryConfig, PeerRecord, PeerTable, Source, MAX_KNOWN_PEERS},
+    db::{verify_account_proof, AccountsDB, EpochInfo, HistoryError, TransferError},
+    events::{Event, EventBus},
+    grpc::{proto::{self, node_client::NodeClient}, GrpcServer},
+    keystore::{KdfParams, Keystore},
+    light_client::LightClient,
+    merkle::{merkle_root, Hash, MerkleProof},
+    metrics::Metrics,
+    mnemonic::{derive_secret_key, generate_mnemonic, seed_from_mnemonic},
+    network::{Message, Network, Seen, MAX_INBOUND_PEERS, MAX_SEEN_MESSAGES},
+    node::{Node, NodeConfig},
+    noise,
+    scoring::ScoringConfig,
+    shred::{Shred, ShredAssembler, DATA_SHREDS_PER_SET, SHRED_PAYLOAD_SIZE},
     structures::{
-        Account, 
+        AirdropTransaction,
+        ApproveTransaction,
+        AssignTransaction,
+        BatchTransferTransaction,
+        Block,
+        BurnTransaction,
+        CreateMintTransaction,
+        DelegatedTransferTransaction,
+        Delegation,
+        DeployTransaction,
+        FreezeTransaction,
+        InvokeTransaction,
+        MemoTransaction,
+        MessageTransaction,
+        Mint,
+        MintToTransaction,
+        MultisigAccount,
+        MultisigTransferTransaction,
+        ProgramTransaction,
+        Pubkey,
+        RegisterValidatorTransaction,
+        SimulationResult,
+        StateDiff,
         StakeTransaction,
+        ThawTransaction,
+        TimeLockedTransferTransaction,
+        TokenTransferTransaction,
         Transaction,
+        TransactionBody,
+        TransactionId,
+        TransactionStatus,
         TransferTransaction, 
         TransactionSign,
+        Unbonding,
+        UnjailTransaction,
+        UnstakeTransaction,
         UserAccount,
         ValidatorAccount,
+        ValueChange,
+        TRANSACTION_V1,
+        TRANSACTION_V2,
+        TRANSACTION_V3,
+        MAX_ACCOUNT_DATA_BYTES,
+        MAX_MEMO_BYTES,
+        SYSTEM_OWNER,
     }, 
+    plugin::StatePlugin,
     pool::Mempool, 
+    program::{AccountMeta, Instruction, Program, ProgramId, TransferProgram, TRANSFER_PROGRAM},
+    pruning::PruningConfig,
+    quic::{QuicClient, QuicConfig, QuicServer},
+    rate_limit::{Quota, RateLimitConfig, RateLimiter, MAX_TRACKED_CLIENTS},
+    rewards::RewardConfig,
+    rpc::{RpcError, RpcRequest, RpcServer, INVALID_PARAMS, METHOD_NOT_FOUND, PRUNED, RATE_LIMITED, SERVER_ERROR},
+    scheduler::{execute_parallel, execute_parallel_results, execute_sequential, schedule},
+    snapshot::Snapshot,
+    storage::{compress, decompress, BlockArchive, Codec},
+    sync::load_snapshot,
+    tower::{Tower, MAX_LOCKOUT_HISTORY},
+    validator::Validator,
+    vote::{total_voting_weight, voting_weight, Quorum, Vote, VotePhase},
+    vrf::{self, leader_input, leader_score},
+    wal::WriteAheadLog,
+    wallet::Wallet,
 };
 
-fn setup_accounts(db: &AccountsDB) -> (UserAccount, UserAccount) {
-    let account1 = UserAccount::new();
-    let account2 = UserAccount::new();
-    db.add_account(account1.public_key, account1.clone());
-    db.add_account(account2.public_key, account2.clone());
+fn setup_accounts(db: &AccountsDB) -> (Wallet, Wallet) {
+    let account1 = Wallet::generate();
+    let account2 = Wallet::generate();
+    db.add_account(account1.public_key, account1.account());
+    db.add_account(account2.public_key, account2.account());
     (account1, account2)
 }
 
-fn setup_validators() -> (ValidatorAccount, ValidatorAccount, Arc<RwLock<AccountsDB>>, Arc<RwLock<Mempool>>) {
+fn setup_validators() -> (Validator, Validator, Arc<RwLock<AccountsDB>>, Arc<RwLock<Mempool>>) {
     let mempool = Arc::new(RwLock::new(Mempool::new()));
     let db = Arc::new(RwLock::new(AccountsDB::new()));
-    let builder1 = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db));
-    let builder2 = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db));
-    let validator1 = ValidatorAccount::new(builder1);
-    let validator2 = ValidatorAccount::new(builder2);
+    let chain = Arc::new(RwLock::new(Blockchain::new()));
+    let builder = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db), Arc::clone(&chain));
+    let validator1 = Validator::new(Wallet::generate(), builder.clone());
+    let validator2 = Validator::new(Wallet::generate(), builder);
     let db_lock = db.write().unwrap();
 
-    db_lock.add_validator(validator1.public_key, validator1.clone());
-    db_lock.add_validator(validator2.public_key, validator2.clone());
+    db_lock.add_validator(validator1.wallet.public_key, validator1.account());
+    db_lock.add_validator(validator2.wallet.public_key, validator2.account());
     (validator1, validator2, Arc::clone(&db), Arc::clone(&mempool))
 }
 
@@ -70,9 +146,9 @@ fn test_transfer_transaction_validation() {
 
     let _ = db.increase_account_balance(&account1.public_key, 1000);
 
-    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, account1.nonce);
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
 
-    tx.sign(&Account::UserAccount(account1));
+    tx.sign(&account1);
 
     assert!(tx.validate(&db)); 
 }
@@ -83,8 +159,8 @@ fn test_validator_creation() {
 
     let db_lock = db.read().unwrap();
 
-    assert!(db_lock.is_validator(&validator1.public_key), "Validator 1 should exist");
-    assert!(db_lock.is_validator(&validator2.public_key), "Validator 2 should exist");
+    assert!(db_lock.is_validator(&validator1.wallet.public_key), "Validator 1 should exist");
+    assert!(db_lock.is_validator(&validator2.wallet.public_key), "Validator 2 should exist");
 }
 
 #[test]
@@ -96,9 +172,9 @@ fn test_stake_transaction_validation() {
 
     let _ = db_lock.increase_account_balance(&account1.public_key, 1000);
 
-    let mut tx = StakeTransaction::new(validator1.public_key, account1.public_key, 500, account1.nonce);
+    let mut tx = StakeTransaction::new(validator1.wallet.public_key, account1.public_key, 500, 0);
 
-    tx.sign(&Account::UserAccount(account1));
+    tx.sign(&account1);
 
     assert!(tx.validate(&db_lock));
 }
@@ -112,13 +188,13 @@ fn test_send_transaction() {
 
     let _ = db.increase_account_balance(&account1.public_key, 1000);
 
-    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, account1.nonce);
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
 
-    tx.sign(&Account::UserAccount(account1));
+    tx.sign(&account1);
 
     assert!(tx.validate(&db), "Transaction validation failed");
 
-    let sig = mempool.send_transaction(Transaction::Transfer(tx));
+    let sig = mempool.send_transaction(Transaction::from(tx));
 
     assert!(sig.is_ok(), "Transaction send failed");
 }
@@ -134,18 +210,18 @@ fn test_build_block() {
     let genesis_block = validator1.builder.build_genesis();
 
     assert!(genesis_block.transactions.is_empty(), "Genesis block should have no transactions");
-    assert_eq!(genesis_block.hash, [1; 32], "Genesis block hash should be predefined");
+    assert_eq!(genesis_block.hash, Block::create_genesis().hash, "Genesis block hash should be deterministic");
 
     let _ = db_lock.increase_account_balance(&account1.public_key, 1000);
 
-    let mut transfer_tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, account1.nonce);
-    let mut stake_tx = StakeTransaction::new(validator1.public_key, account1.public_key, 500, account1.nonce);
+    let mut transfer_tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
+    let mut stake_tx = StakeTransaction::new(validator1.wallet.public_key, account1.public_key, 500, 1);
 
-    transfer_tx.sign(&Account::UserAccount(account1.clone()));
-    stake_tx.sign(&Account::UserAccount(account1.clone()));
+    transfer_tx.sign(&account1);
+    stake_tx.sign(&account1);
 
-    let signed_transfer_tx = Transaction::Transfer(transfer_tx.clone());
-    let signed_stake_tx: Transaction = Transaction::Stake(stake_tx.clone());
+    let signed_transfer_tx = Transaction::from(transfer_tx);
+    let signed_stake_tx: Transaction = Transaction::from(stake_tx);
 
     let transfer_sig = mempool_lock.send_transaction(signed_transfer_tx.clone());
     let stake_sig = mempool_lock.send_transaction(signed_stake_tx.clone());
@@ -156,9 +232,9 @@ fn test_build_block() {
     drop(db_lock);
     drop(mempool_lock);
 
-    let new_block = validator1.builder.build(genesis_block.hash);
+    let new_block = validator1.builder.build(genesis_block.hash, &validator1.wallet);
 
-    let block = new_block.unwrap();
+    let block = new_block.unwrap().expect("A full mempool should produce a block");
 
     assert!(block.transactions.contains(&signed_transfer_tx), "Block should contain the transfer transaction");
     assert!(block.transactions.contains(&signed_stake_tx), "Block should contain the stake transaction");
@@ -177,28 +253,28 @@ fn test_run_blockchain() {
     let genesis_block = validator1.builder.build_genesis();
 
     assert!(genesis_block.transactions.is_empty(), "Genesis block should have no transactions");
-    assert_eq!(genesis_block.hash, [1; 32], "Genesis block hash should be predefined");
+    assert_eq!(genesis_block.hash, Block::create_genesis().hash, "Genesis block hash should be deterministic");
 
     let _ = db_lock.increase_account_balance(&account1.public_key, 10000);
     let _ = db_lock.increase_account_balance(&account2.public_key, 10000);
 
-    let mut transfer_tx1 = TransferTransaction::new(account2.public_key, account1.public_key, 1500, account1.nonce);
-    let mut transfer_tx2 = TransferTransaction::new(account1.public_key, account2.public_key, 2000, account2.nonce);
+    let mut transfer_tx1 = TransferTransaction::new(account2.public_key, account1.public_key, 1500, 0);
+    let mut transfer_tx2 = TransferTransaction::new(account1.public_key, account2.public_key, 2000, 0);
 
-    let mut stake_tx1 = StakeTransaction::new(validator1.public_key, account1.public_key, 500, account1.nonce);
-    let mut stake_tx2 = StakeTransaction::new(validator2.public_key, account2.public_key, 750, account2.nonce);
+    let mut stake_tx1 = StakeTransaction::new(validator1.wallet.public_key, account1.public_key, 500, 1);
+    let mut stake_tx2 = StakeTransaction::new(validator2.wallet.public_key, account2.public_key, 750, 1);
 
-    transfer_tx1.sign(&Account::UserAccount(account1.clone()));
-    transfer_tx2.sign(&Account::UserAccount(account2.clone()));
+    transfer_tx1.sign(&account1);
+    transfer_tx2.sign(&account2);
 
-    stake_tx1.sign(&Account::UserAccount(account1.clone()));
-    stake_tx2.sign(&Account::UserAccount(account2.clone()));
+    stake_tx1.sign(&account1);
+    stake_tx2.sign(&account2);
 
-    let signed_transfer1 = Transaction::Transfer(transfer_tx1);
-    let signed_transfer2 = Transaction::Transfer(transfer_tx2);
+    let signed_transfer1 = Transaction::from(transfer_tx1);
+    let signed_transfer2 = Transaction::from(transfer_tx2);
 
-    let signed_stake1 = Transaction::Stake(stake_tx1);
-    let signed_stake2 = Transaction::Stake(stake_tx2);
+    let signed_stake1 = Transaction::from(stake_tx1);
+    let signed_stake2 = Transaction::from(stake_tx2);
 
     let transfer1_sig = mempool_lock.send_transaction(signed_transfer1);
     let transfer2_sig = mempool_lock.send_transaction(signed_transfer2);
@@ -215,18 +291,4291 @@ fn test_run_blockchain() {
     drop(db_lock);
     drop(mempool_lock);
 
-    let validator1_handle = thread::spawn(move || {
-        let _ = validator1.start(Duration::from_millis(100));
+    let validator1_handle = validator1.start(Duration::from_millis(100));
+    let validator2_handle = validator2.start(Duration::from_millis(100));
+
+    assert!(validator1_handle.is_running() && validator2_handle.is_running(), "Validators should be running");
+
+    let drained = wait_until(Duration::from_secs(10), || mempool.read().unwrap().pool.is_empty());
+
+    validator1_handle.stop();
+    assert!(!validator1_handle.is_running(), "Stopped validator should report not running");
+
+    assert!(validator1_handle.join().is_ok(), "Validator 1 should shut down cleanly");
+    assert!(validator2_handle.join().is_ok(), "Validator 2 should shut down cleanly");
+    assert!(drained, "Validators should have drained the mempool");
+
+    let mempool_lock = mempool.read().unwrap();
+
+    assert_eq!(mempool_lock.pool.len(), 0, "Leftover transactions in mempool");
+}
+#[test]
+fn test_serde_roundtrip() {
+    let db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+
+    let _ = db.increase_account_balance(&account1.public_key, 1000);
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
+    tx.sign(&account1);
+
+    let block = Block::new(vec![Transaction::from(tx)], [1; 32]);
+
+    let json = serde_json::to_string(&block).expect("Block should encode to JSON");
+    let decoded: Block = serde_json::from_str(&json).expect("Block should decode from JSON");
+    assert_eq!(decoded.hash, block.hash, "Block hash should survive a JSON roundtrip");
+    assert_eq!(decoded.transactions, block.transactions, "Transactions should survive a JSON roundtrip");
+    assert!(decoded.transactions[0].verify_signature(&account1.public_key), "Signature should survive a JSON roundtrip");
+
+    let bytes = bincode::serialize(&db).expect("AccountsDB should encode to bincode");
+    let decoded_db: AccountsDB = bincode::deserialize(&bytes).expect("AccountsDB should decode from bincode");
+    let decoded_account = decoded_db.get_account(&account1.public_key).expect("Account 1 should exist");
+    assert_eq!(decoded_account.balance, 1000, "Balance should survive a bincode roundtrip");
+    assert_eq!(decoded_account.address, account1.address, "Address should survive a bincode roundtrip");
+}
+
+#[test]
+fn test_borsh_roundtrip() {
+    let (validator1, _v, db, _) = setup_validators();
+    let db_lock = db.read().unwrap();
+    let (account1, account2) = setup_accounts(&db_lock);
+
+    let mut transfer_tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
+    let mut stake_tx = StakeTransaction::new(validator1.wallet.public_key, account1.public_key, 500, 0);
+    transfer_tx.sign(&account1);
+    stake_tx.sign(&account1);
+
+    let transfer = Transaction::from(transfer_tx);
+    let bytes = transfer.to_bytes();
+    let decoded = Transaction::from_bytes(&bytes).expect("Transaction should decode");
+    assert_eq!(decoded, transfer, "Transaction should survive a borsh roundtrip");
+    assert!(decoded.verify_signature(&account1.public_key), "Decoded signature should verify");
+
+    let block = Block::new(vec![transfer, Transaction::from(stake_tx)], [1; 32]);
+    let bytes = block.to_bytes();
+    let decoded = Block::from_bytes(&bytes).expect("Block should decode");
+    assert_eq!(decoded.to_bytes(), bytes, "Block encoding should be byte-for-byte stable");
+    assert_eq!(decoded.hash, block.hash, "Block hash should survive a borsh roundtrip");
+
+    assert!(Transaction::from_bytes(&bytes[..10]).is_err(), "Truncated bytes should not decode");
+
+    let mut tampered = block.to_bytes();
+    let amt_offset = 4 + 1 + 32 + 32; // vec length, variant tag, `to`, `from`
+    tampered[amt_offset] ^= 0xff;
+    assert!(Block::from_bytes(&tampered).is_err(), "Tampered block should not decode");
+}
+
+fn wait_until(timeout: Duration, condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+fn setup_node(accounts: &[&Wallet]) -> (Network, BlockBuilder) {
+    let mempool = Arc::new(RwLock::new(Mempool::new()));
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let chain = Arc::new(RwLock::new(Blockchain::new()));
+    for account in accounts {
+        db.read().unwrap().add_account(account.public_key, account.account());
+        let _ = db.read().unwrap().increase_account_balance(&account.public_key, 1000);
+    }
+    let builder = BlockBuilder::new(mempool, db, chain);
+    let network = Network::bind("127.0.0.1:0", &builder).unwrap();
+    (network.clone(), builder.with_network(network))
+}
+
+#[test]
+fn test_network_gossip() {
+    let (account1, account2) = (Wallet::generate(), Wallet::generate());
+
+    // Two independent nodes, each with their own mempool & copy of the same state
+    let (network_a, node_a) = setup_node(&[&account1, &account2]);
+    let (network_b, node_b) = setup_node(&[&account1, &account2]);
+
+    network_a.connect(network_b.local_addr).expect("Node A should connect to node B");
+    assert!(wait_until(Duration::from_secs(5), || network_b.peer_addrs().len() == 1), "Node B should see node A as a peer");
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
+    tx.sign(&account1);
+    let tx = Transaction::from(tx);
+
+    assert!(network_a.send_transaction(tx.clone()).is_ok(), "Transaction send failed");
+    assert!(
+        wait_until(Duration::from_secs(5), || node_b.mempool.read().unwrap().pool.len() == 1),
+        "Transaction should be gossiped to node B's mempool"
+    );
+
+    // Node A finalizes a block locally & gossips it, node B should apply it & clear its mempool
+    let block = Block::extending(&Block::create_genesis(), vec![tx]);
+    node_a.chain.write().unwrap().apply(block.clone(), &mut node_a.db.write().unwrap()).unwrap();
+    node_a.mempool.read().unwrap().clear();
+    network_a.broadcast_block(&block);
+
+    assert!(
+        wait_until(Duration::from_secs(5), || node_b.chain.read().unwrap().tip().hash == block.hash),
+        "Block should be gossiped to & applied by node B"
+    );
+    let account2_on_b = node_b.db.read().unwrap().get_account(&account2.public_key).unwrap();
+    assert_eq!(account2_on_b.balance, 1500, "Node B should have applied the transfer");
+    assert!(node_b.mempool.read().unwrap().pool.is_empty(), "Node B should drop included transactions from its mempool");
+}
+
+#[test]
+fn test_network_limits() {
+    // Gossip ids are remembered up to a cap, the oldest forgotten first
+    let id = |n: usize| {
+        let mut id = [0; 32];
+        id[..8].copy_from_slice(&(n as u64).to_le_bytes());
+        id
+    };
+    let mut seen = Seen::default();
+    for n in 0..=MAX_SEEN_MESSAGES {
+        assert!(seen.insert(id(n)));
+    }
+    assert!(!seen.insert(id(MAX_SEEN_MESSAGES)), "Recent ids should be remembered");
+    assert!(seen.insert(id(0)), "The oldest id should have been forgotten");
+
+    // Connections past the inbound cap are closed as soon as they're accepted, & their slots come back
+    // once connections close
+    let (network_a, _) = setup_node(&[]);
+    let (network_b, _) = setup_node(&[]);
+    let stalled: Vec<std::net::TcpStream> = (0..MAX_INBOUND_PEERS).map(|_| std::net::TcpStream::connect(network_a.local_addr).unwrap()).collect();
+    assert!(network_b.connect(network_a.local_addr).is_err(), "A connection past the cap should be refused");
+    drop(stalled);
+    assert!(wait_until(Duration::from_secs(5), || network_b.connect(network_a.local_addr).is_ok()), "Closed connections should free their slots");
+}
+
+#[test]
+fn test_encrypted_peers() {
+    let (network_a, _) = setup_node(&[]);
+    let (network_b, _) = setup_node(&[]);
+    let (network_c, _) = setup_node(&[]);
+
+    network_a.connect_to(network_b.local_addr, &network_b.identity()).expect("B should prove the identity asked for");
+    assert!(wait_until(Duration::from_secs(5), || network_b.peer_addrs().len() == 1), "B should take A as a peer");
+    assert_eq!(network_a.peer_identity(&network_b.local_addr), Some(network_b.identity()));
+    assert_eq!(network_b.peer_identity(&network_b.peer_addrs()[0]), Some(network_a.identity()));
+
+    let error = network_c.connect_to(network_b.local_addr, &Wallet::generate().public_key).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied, "A peer proving another identity should be refused");
+    assert!(network_c.peer_addrs().is_empty());
+
+    // A peer speaking plaintext never gets past the handshake
+    let mut plaintext = std::net::TcpStream::connect(network_b.local_addr).unwrap();
+    let status = Message::Status { height: 0 }.to_bytes();
+    std::io::Write::write_all(&mut plaintext, &[&(status.len() as u32).to_le_bytes()[..], &status].concat()).unwrap();
+    drop(plaintext);
+    assert!(wait_until(Duration::from_secs(5), || network_b.peer_addrs().len() == 1), "Only A should remain a peer of B");
+
+    // Frames are sealed, so one tampered with in transit is refused
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (wallet_a, wallet_b) = (Wallet::generate(), Wallet::generate());
+    let responder_wallet = wallet_b.clone();
+    let responder = thread::spawn(move || noise::handshake(listener.accept().unwrap().0, &responder_wallet, false).unwrap());
+    let stream = std::net::TcpStream::connect(addr).unwrap();
+    let mut raw = stream.try_clone().unwrap();
+    let (identity_b, mut sender, _) = noise::handshake(stream, &wallet_a, true).unwrap();
+    let (identity_a, _, mut receiver) = responder.join().unwrap();
+    assert_eq!((identity_a, identity_b), (wallet_a.public_key, wallet_b.public_key));
+
+    sender.send(b"sealed").unwrap();
+    assert_eq!(receiver.receive().unwrap(), b"sealed");
+    let large = vec![7u8; 200_000];
+    sender.send(&large).unwrap();
+    assert_eq!(receiver.receive().unwrap(), large, "Frames past one Noise message should be split & rejoined");
+    std::io::Write::write_all(&mut raw, &[&20u32.to_le_bytes()[..], &[0u8; 20]].concat()).unwrap();
+    assert!(receiver.receive().is_err());
+}
+
+#[test]
+fn test_peer_discovery() {
+    let (hub, _) = setup_node(&[]);
+    let (network_b, _) = setup_node(&[]);
+    let (network_c, _) = setup_node(&[]);
+    network_c.connect(hub.local_addr).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || hub.known_peers().iter().any(|record| record.identity == network_c.identity())), "Hub should learn where C listens");
+    assert!(hub.connect(hub.local_addr).is_err(), "A node shouldn't take itself as a peer");
+
+    // B only knows the hub, but hears of C from it & dials C too
+    let config = DiscoveryConfig { interval_ms: 20, peer_timeout_ms: 500, ..Default::default() };
+    network_b.start_discovery(vec![hub.local_addr], config).unwrap();
+    let is_peer = |network: &Network, identity: Pubkey| network.peer_addrs().iter().any(|addr| network.peer_identity(addr) == Some(identity));
+    assert!(wait_until(Duration::from_secs(5), || is_peer(&network_b, hub.identity())), "B should dial its bootstrap node");
+    assert!(wait_until(Duration::from_secs(5), || is_peer(&network_b, network_c.identity())), "B should dial the peer the hub told it of");
+    let record = network_b.known_peers().into_iter().find(|record| record.identity == network_c.identity()).unwrap();
+    assert_eq!(record.addr, network_c.local_addr.to_string());
+
+    // A peer that stops answering pings is dropped
+    let silent = Wallet::generate();
+    let (_, _sender, _receiver) = noise::handshake(std::net::TcpStream::connect(network_b.local_addr).unwrap(), &silent, true).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || is_peer(&network_b, silent.public_key)));
+    assert!(wait_until(Duration::from_secs(5), || !is_peer(&network_b, silent.public_key)), "Silent peer should time out");
+    assert!(is_peer(&network_b, network_c.identity()), "Live peers should be kept");
+
+    // A peer can tell of new nodes, but can't move ones already known
+    let (liar, stranger) = (Wallet::generate(), Wallet::generate());
+    let (_, mut sender, _receiver) = noise::handshake(std::net::TcpStream::connect(network_b.local_addr).unwrap(), &liar, true).unwrap();
+    let records = vec![
+        PeerRecord { addr: "127.0.0.1:1".to_string(), identity: network_c.identity() },
+        PeerRecord { addr: "127.0.0.1:2".to_string(), identity: stranger.public_key },
+    ];
+    sender.send(&Message::Peers(records).to_bytes()).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || network_b.known_peers().iter().any(|record| record.identity == stranger.public_key)));
+    let record = network_b.known_peers().into_iter().find(|record| record.identity == network_c.identity()).unwrap();
+    assert_eq!(record.addr, network_c.local_addr.to_string(), "Hearsay shouldn't replace an address C was reached at");
+
+    // Backoff doesn't overflow however many dials are allowed to fail
+    let table = PeerTable::default();
+    let config = DiscoveryConfig { max_dial_failures: 40, ..Default::default() };
+    table.insert(stranger.public_key, "127.0.0.1:2".parse().unwrap(), Source::Hearsay);
+    for _ in 0..40 {
+        table.dialed(&stranger.public_key, false, &config);
+    }
+    assert!(table.records(MAX_KNOWN_PEERS).is_empty(), "Node should be forgotten after too many failed dials");
+}
+
+#[test]
+fn test_peer_scoring() {
+    let (network_a, _) = setup_node(&[]);
+    let (honest, _) = setup_node(&[]);
+    // Banning by IP would lock out every node on localhost
+    network_a.set_scoring(ScoringConfig { bad_signature_penalty: 30, malformed_message_penalty: 20, ban_threshold: 40, ban_ip: false, ..Default::default() });
+
+    let (sender_wallet, receiver_wallet) = (Wallet::generate(), Wallet::generate());
+    let bad = Wallet::generate();
+    let stream = std::net::TcpStream::connect(network_a.local_addr).unwrap();
+    let (_, mut sender, mut receiver) = noise::handshake(stream, &bad, true).unwrap();
+    let is_peer = |identity: Pubkey| network_a.peer_addrs().iter().any(|addr| network_a.peer_identity(addr) == Some(identity));
+    assert!(wait_until(Duration::from_secs(5), || is_peer(bad.public_key)));
+
+    sender.send(b"not a message").unwrap();
+    assert!(wait_until(Duration::from_secs(5), || network_a.peer_score(&bad.public_key) > 0.0), "Malformed messages should count against a peer");
+    assert!(!network_a.is_banned(&bad.public_key));
+    assert!(is_peer(bad.public_key), "A peer under the threshold should be kept");
+
+    // An unsigned transfer takes it over the threshold
+    let tx = Transaction::from(TransferTransaction::new(receiver_wallet.public_key, sender_wallet.public_key, 500, 0));
+    sender.send(&Message::Transaction(Box::new(tx)).to_bytes()).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || network_a.is_banned(&bad.public_key)), "Peer should be banned");
+    assert!(wait_until(Duration::from_secs(5), || !is_peer(bad.public_key)), "Banned peer should be dropped");
+    while receiver.receive().is_ok() {}
+
+    // It's turned away when it comes back, while others still get in
+    let stream = std::net::TcpStream::connect(network_a.local_addr).unwrap();
+    let (_, _, mut receiver) = noise::handshake(stream, &bad, true).unwrap();
+    assert!(receiver.receive().is_err(), "Banned peer should be refused");
+    assert!(!is_peer(bad.public_key));
+    honest.connect_to(network_a.local_addr, &network_a.identity()).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || is_peer(honest.identity())), "Honest peers should still be taken");
+    assert_eq!(network_a.peer_score(&honest.identity()), 0.0);
+}
+
+#[test]
+fn test_block_shreds() {
+    let (account1, account2) = (Wallet::generate(), Wallet::generate());
+    let (network_a, node_a) = setup_node(&[&account1, &account2]);
+    let (network_b, node_b) = setup_node(&[&account1, &account2]);
+    let (network_c, node_c) = setup_node(&[&account1, &account2]);
+
+    let transactions: Vec<Transaction> = (0..12)
+        .map(|nonce| {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+            tx.sign(&account1);
+            Transaction::from(tx)
+        })
+        .collect();
+    let block = Block::extending(&Block::create_genesis(), transactions);
+    let shreds = Shred::split(&block, &account1);
+    let data: Vec<&Shred> = shreds.iter().filter(|shred| shred.is_data()).collect();
+    assert!(data.len() > 1, "Block should take more than one shred");
+    assert_eq!(shreds.len(), data.len() * 2, "Each data shred should get a coding shred");
+    assert!(shreds.iter().all(|shred| shred.block_hash == block.hash && shred.payload.len() == SHRED_PAYLOAD_SIZE));
+    assert!(shreds.iter().all(|shred| shred.verify().is_ok() && shred.signer == account1.public_key));
+
+    // Each shred is checked on its own against the root its signer signed
+    let mut bad = shreds[1].clone();
+    bad.payload[0] ^= 1;
+    assert!(bad.verify().is_err(), "Altered shred should fail its proof");
+    let mut bad = shreds[1].clone();
+    bad.index = 0;
+    assert!(bad.verify().is_err(), "Shred moved in its set should fail its proof");
+    assert!(Shred { block_hash: [7; 32], ..shreds[0].clone() }.verify().is_err(), "Shred of another block should fail its signature");
+    assert!(Shred { signer: account2.public_key, ..shreds[0].clone() }.verify().is_err(), "Shred should fail under another signer");
+    let forged = Shred::split(&Block::extending(&Block::create_genesis(), vec![]), &account2);
+    assert!(Shred { root: forged[0].root, ..shreds[0].clone() }.verify().is_err(), "Shred should fail under another root");
+
+    // Any half of the shreds rebuild the block, whatever order they arrive in & whichever are lost
+    for kept in [shreds.iter().filter(|shred| !shred.is_data()).collect::<Vec<_>>(), shreds.iter().rev().step_by(2).collect()] {
+        let assembler = ShredAssembler::default();
+        for shred in &kept[..kept.len() - 1] {
+            assert!(matches!(assembler.insert((*shred).clone()), Ok(None)));
+        }
+        let rebuilt = assembler.insert((*kept.last().unwrap()).clone()).unwrap().expect("Half the shreds should rebuild the block");
+        assert_eq!(rebuilt.hash, block.hash);
+        assert!(matches!(assembler.insert(shreds[0].clone()), Ok(None)), "Shreds of a rebuilt block should be ignored");
+    }
+    let assembler = ShredAssembler::default();
+    for shred in &shreds[..data.len() - 1] {
+        assembler.insert(shred.clone()).unwrap();
+    }
+    assert!(matches!(assembler.insert(shreds[0].clone()), Ok(None)), "Repeated shreds shouldn't count twice");
+
+    let mut bad = shreds[0].clone();
+    bad.index = shreds.len() as u32;
+    assert!(assembler.insert(bad).is_err(), "Shred past its set should be refused");
+    let mut bad = shreds[0].clone();
+    bad.payload.pop();
+    assert!(assembler.insert(bad).is_err(), "Short shred should be refused");
+    let results: Vec<_> = shreds.iter().map(|shred| assembler.insert(Shred { block_hash: [7; 32], ..shred.clone() })).collect();
+    assert!(results.iter().any(Result::is_err), "Shreds of a block other than the one they name should be refused");
+    // Shreds that failed to rebuild are dropped, so the same ones are taken again rather than ignored
+    assert!(matches!(assembler.insert(Shred { block_hash: [7; 32], ..shreds[0].clone() }), Ok(None)));
+    // & they don't hold up the honest shreds of the block they claimed
+    let rebuilt = assembler.insert(shreds[data.len() - 1].clone()).unwrap().expect("Honest shreds should still rebuild the block");
+    assert_eq!(rebuilt.hash, block.hash);
+
+    // Over the network, B rejoins the block A shreds & relays the shreds on to C
+    network_a.connect(network_b.local_addr).unwrap();
+    network_b.connect(network_c.local_addr).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || network_b.peer_addrs().len() == 2 && network_c.peer_addrs().len() == 1));
+    node_a.chain.write().unwrap().apply(block.clone(), &mut node_a.db.write().unwrap()).unwrap();
+    network_a.broadcast_block(&block);
+    for node in [&node_b, &node_c] {
+        assert!(wait_until(Duration::from_secs(5), || node.chain.read().unwrap().tip().hash == block.hash), "Shredded block should be applied");
+    }
+}
+
+#[test]
+fn test_block_shred_sets() {
+    let (account1, account2) = (Wallet::generate(), Wallet::generate());
+    let transactions: Vec<Transaction> = (0..400)
+        .map(|nonce| Transaction::from(TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce)))
+        .collect();
+    let block = Block::extending(&Block::create_genesis(), transactions);
+    let shreds = Shred::split(&block, &account1);
+    let sets = shreds.iter().map(|shred| shred.set).max().unwrap() + 1;
+    let last: Vec<&Shred> = shreds.iter().filter(|shred| shred.set == sets - 1).collect();
+    assert!(sets > 1, "Block should take more than one set");
+    assert!(last.len() < DATA_SHREDS_PER_SET * 2, "Last set should be short");
+    assert!(shreds.iter().all(|shred| shred.verify().is_ok()));
+
+    // Half of each set rebuilds the block, each from a different mix of data & coding shreds
+    for (offset, step) in [(0, 2), (1, 2)] {
+        let assembler = ShredAssembler::default();
+        let mut rebuilt = None;
+        for set in 0..sets {
+            let shreds: Vec<&Shred> = shreds.iter().filter(|shred| shred.set == set).collect();
+            for shred in shreds.iter().skip(offset).step_by(step) {
+                rebuilt = assembler.insert((*shred).clone()).unwrap();
+            }
+        }
+        assert_eq!(rebuilt.expect("Half of each set should rebuild the block").hash, block.hash);
+    }
+    let assembler = ShredAssembler::default();
+    for shred in shreds.iter().filter(|shred| shred.set > 0) {
+        assert!(matches!(assembler.insert(shred.clone()), Ok(None)), "Block shouldn't rebuild before every set has");
+    }
+
+    // A tampered coding shred is caught before it's kept
+    let mut bad = (*last.last().unwrap()).clone();
+    bad.payload[0] ^= 1;
+    assert!(bad.verify().is_err(), "Tampered coding shred should fail its proof");
+
+    // Even signed, a corrupt shard that decodes fine is caught against the root once its set is rebuilt
+    let corrupt = |corrupt_index: u32| {
+        let pieces = shreds
+            .iter()
+            .map(|shred| {
+                let mut payload = shred.payload.clone();
+                if shred.set == sets - 1 && shred.index == corrupt_index {
+                    payload[0] ^= 1;
+                }
+                (shred.set, shred.index, payload)
+            })
+            .collect();
+        Shred::commit(block.hash, shreds[0].block_size, pieces, &account1)
+    };
+    let forged = corrupt(last.len() as u32 - 1);
+    assert!(forged.iter().all(|shred| shred.verify().is_ok()), "Signer's own shreds should verify");
+    let assembler = ShredAssembler::default();
+    let mut results = Vec::new();
+    for shred in forged.iter().filter(|shred| shred.set < sets - 1 || !shred.is_data()) {
+        results.push(assembler.insert(shred.clone()));
+    }
+    assert!(results.last().unwrap().is_err(), "Corrupt coding shred should be caught against the root");
+    // Rebuilt from its data shreds instead, the block decodes, but the coding shreds don't match the root
+    let assembler = ShredAssembler::default();
+    let results: Vec<_> = forged.iter().filter(|shred| shred.is_data()).map(|shred| assembler.insert(shred.clone())).collect();
+    assert!(results.last().unwrap().is_err(), "Set holding a corrupt shard should be refused however it's rebuilt");
+}
+
+#[test]
+fn test_block_sync() {
+    let (account1, account2) = (Wallet::generate(), Wallet::generate());
+    let (network_a, node_a) = setup_node(&[&account1, &account2]);
+
+    // Node A produces a few blocks before node B comes online
+    for (nonce, amt) in [100, 200, 300].into_iter().enumerate() {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce as u64);
+        tx.sign(&account1);
+
+        let tip = node_a.chain.read().unwrap().tip().clone();
+        let block = Block::extending(&tip, vec![Transaction::from(tx)]);
+        node_a.chain.write().unwrap().apply(block, &mut node_a.db.write().unwrap()).unwrap();
+    }
+    assert_eq!(node_a.chain.read().unwrap().height(), 3, "Node A should be at height 3");
+
+    let (network_b, node_b) = setup_node(&[&account1, &account2]);
+    network_b.connect(network_a.local_addr).expect("Node B should connect to node A");
+
+    assert!(
+        wait_until(Duration::from_secs(5), || node_b.chain.read().unwrap().height() == 3),
+        "Node B should catch up to node A's tip"
+    );
+    assert!(network_b.is_synced(), "Node B should report itself as synced");
+    assert_eq!(node_b.chain.read().unwrap().tip().hash, node_a.chain.read().unwrap().tip().hash, "Tips should match");
+
+    let account2_on_b = node_b.db.read().unwrap().get_account(&account2.public_key).unwrap();
+    assert_eq!(account2_on_b.balance, 1600, "Node B should have replayed every synced block");
+}
+
+#[test]
+fn test_fork_choice_reorg() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 1000);
+    let mut chain = Blockchain::new();
+    let genesis = chain.tip().clone();
+
+    let transfer = |amt: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    // Two competing blocks on top of genesis, the first one seen wins until the second gets more votes
+    let block_a = Block::extending(&genesis, vec![transfer(100, 0)]);
+    let block_b = Block::extending(&genesis, vec![transfer(300, 0)]);
+
+    chain.add_votes(&block_a.hash, 10);
+    chain.apply(block_a.clone(), &mut db).expect("Block A should apply");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 100, "Block A should have executed");
+
+    let reverted = chain.apply(block_b.clone(), &mut db).expect("Block B should be tracked as a fork");
+    assert!(reverted.is_empty(), "A lighter fork shouldn't trigger a reorg");
+    assert_eq!(chain.tip().hash, block_a.hash, "Block A should still be the tip");
+    assert!(chain.contains(&block_b.hash) && !chain.is_canonical(&block_b.hash), "Block B should be a known fork");
+
+    chain.add_votes(&block_b.hash, 20);
+    let reverted = chain.apply_fork_choice(&mut db).expect("Reorg should succeed");
+    assert_eq!(reverted.len(), 1, "Block A should have been rolled back");
+    assert_eq!(chain.tip().hash, block_b.hash, "Heavier block B should be the new tip");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 300, "Only block B should be reflected in state");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 700, "Block A's debit should be undone");
+
+    // Extending A past B's weight flips the chain back
+    let block_a2 = Block::extending(&block_a, vec![transfer(50, 1)]);
+    chain.add_votes(&block_a2.hash, 15);
+    let reverted = chain.apply(block_a2.clone(), &mut db).expect("Reorg back to A should succeed");
+    assert_eq!(reverted.len(), 1, "Block B should have been rolled back");
+    assert_eq!(chain.height(), 2, "Chain should be A -> A2");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 150, "State should reflect A & A2");
+}
+
+#[test]
+fn test_vote_quorum() {
+    let (validator1, validator2, db, _) = setup_validators();
+    let db_lock = db.read().unwrap();
+    let leader = validator1.builder.get_leader(1).unwrap();
+    let block = Block::extending(&Block::create_genesis(), vec![])
+        .with_state_root(db_lock.state_root())
+        .with_leader_proof(&leader, &db_lock.epoch_seed)
+        .with_proposer(&leader);
+
+    let vote1 = validator1.prevote(&block).expect("Validator 1 should vote for an empty block");
+    let vote2 = validator2.prevote(&block).expect("Validator 2 should vote for an empty block");
+    assert!(vote1.verify() && vote2.verify(), "Votes should carry valid signatures");
+
+    // With no stake anywhere each validator counts equally, so one of two isn't a quorum
+    assert!(Quorum::aggregate(block.hash, 1, vec![vote1], &db_lock).is_err(), "Half the validators isn't a quorum");
+    let quorum = Quorum::aggregate(block.hash, 1, vec![vote1, vote2], &db_lock).expect("Both validators should be a quorum");
+    assert_eq!(quorum.weight, 2, "Quorum should count both validators");
+
+    assert!(Quorum::aggregate(block.hash, 1, vec![vote1, vote1], &db_lock).is_err(), "Duplicate votes should be rejected");
+    assert!(Quorum::aggregate(block.hash, 2, vec![vote1, vote2], &db_lock).is_err(), "Votes for another slot should be rejected");
+
+    // Once stake exists, a single validator holding over two thirds of it is enough
+    let _ = db_lock.increase_validator_stake(&validator1.wallet.public_key, 700);
+    let _ = db_lock.increase_validator_stake(&validator2.wallet.public_key, 300);
+    let quorum = Quorum::aggregate(block.hash, 1, vec![vote1], &db_lock).expect("Two thirds of the stake should be a quorum");
+    assert_eq!(quorum.weight, 700, "Quorum weight should be stake-weighted");
+    assert!(Quorum::aggregate(block.hash, 1, vec![vote2], &db_lock).is_err(), "Minority stake isn't a quorum");
+}
+
+#[test]
+fn test_two_phase_voting() {
+    let (mut validator1, mut validator2, db, _) = setup_validators();
+    // Let the leaders of the first few slots time out, so blocks for later ones are votable
+    for validator in [&mut validator1, &mut validator2] {
+        validator.builder.config.leader_timeout_ms = 1;
+    }
+    validator1.builder.start_slot_clock();
+    thread::sleep(Duration::from_millis(10));
+    let (state_root, epoch_seed) = (db.read().unwrap().state_root(), db.read().unwrap().epoch_seed);
+    let propose = |slot: u64, timestamp: SystemTime| {
+        let leader = validator1.builder.get_leader(slot).unwrap();
+        Block::extending(&Block::create_genesis(), vec![])
+            .with_slot(slot)
+            .with_timestamp(timestamp)
+            .with_state_root(state_root)
+            .with_leader_proof(&leader, &epoch_seed)
+            .with_proposer(&leader)
+    };
+    let now = SystemTime::now();
+    let block = propose(1, now);
+    let prevotes = vec![validator1.prevote(&block).unwrap(), validator2.prevote(&block).unwrap()];
+    let polka = Quorum::aggregate(block.hash, 1, prevotes.clone(), &db.read().unwrap()).unwrap();
+    assert_eq!(polka.phase, VotePhase::Prevote);
+    assert_eq!(polka.certificate(&db.read().unwrap()).err(), Some("Only precommits finalize a block"));
+
+    // Phases are signed & can't be mixed
+    let mut relabeled = prevotes[0];
+    relabeled.phase = VotePhase::Precommit;
+    assert!(!relabeled.verify(), "A prevote shouldn't pass as a precommit");
+    let precommit = validator1.precommit(&block, &polka).expect("A quorum of prevotes should allow a precommit");
+    assert_eq!(precommit.phase, VotePhase::Precommit);
+    assert_eq!(Quorum::aggregate(block.hash, 1, vec![prevotes[1], precommit], &db.read().unwrap()).err(), Some("Votes are from different phases"));
+    let short = Quorum { votes: vec![prevotes[0]], ..polka.clone() };
+    assert!(validator2.precommit(&block, &short).is_none(), "Precommitting should take a real quorum of prevotes");
+
+    // Locked on the block, validator 1 won't vote for a rival at the same height
+    let rival = propose(1, now + Duration::from_secs(1));
+    assert!(validator1.prevote(&rival).is_none(), "A locked validator shouldn't prevote another block");
+    assert!(validator2.prevote(&rival).is_some(), "Unlocked validators can still prevote");
+    let rival_polka = Quorum { block_hash: rival.hash, votes: vec![validator2.prevote(&rival).unwrap(), Vote::prevote(rival.hash, 1, &validator1.wallet)], ..polka.clone() };
+    assert!(validator1.precommit(&rival, &rival_polka).is_none(), "A polka from the same slot shouldn't move a lock");
+
+    // A polka in a later slot does, once the tower lockout on the first precommit has run out
+    let polka_for = |block: &Block| Quorum::aggregate(block.hash, block.slot(), vec![validator2.prevote(block).unwrap(), Vote::prevote(block.hash, block.slot(), &validator1.wallet)], &db.read().unwrap()).unwrap();
+    let early = propose(2, now);
+    assert!(validator1.precommit(&early, &polka_for(&early)).is_none(), "The lockout should still bind in slot 2");
+    let later = propose(4, now);
+    let later_polka = polka_for(&later);
+    assert!(validator1.precommit(&later, &later_polka).is_some(), "A later polka should relock");
+    assert_eq!(validator1.builder.locks.get(&validator1.wallet.public_key).unwrap().block_hash, later.hash);
+
+    // A quorum is more than two thirds of the weight, not just a majority
+    let db_lock = db.read().unwrap();
+    let _ = db_lock.increase_validator_stake(&validator1.wallet.public_key, 650);
+    let _ = db_lock.increase_validator_stake(&validator2.wallet.public_key, 350);
+    assert_eq!(Quorum::aggregate(block.hash, 1, vec![prevotes[0]], &db_lock).err(), Some("Not enough votes for quorum"), "65% of stake isn't a quorum");
+    assert!(Quorum::aggregate(block.hash, 1, prevotes, &db_lock).is_ok());
+}
+
+#[test]
+fn test_leader_timeout() {
+    let (mut validator1, mut validator2, db, mempool) = setup_validators();
+    let mut validator3 = Validator::new(Wallet::generate(), validator1.builder.clone());
+    db.read().unwrap().add_validator(validator3.wallet.public_key, validator3.account());
+    for validator in [&mut validator1, &mut validator2, &mut validator3] {
+        let _ = db.read().unwrap().increase_validator_stake(&validator.wallet.public_key, 100);
+        validator.builder.config.leader_timeout_ms = 20;
+    }
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let transfer = |nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    // Whoever leads slot 1 never runs its loop, so nothing is proposed until the slot times out
+    let dead = validator1.builder.get_leader(1).unwrap().public_key;
+    let live: Vec<&Validator> = [&validator1, &validator2, &validator3].into_iter().filter(|validator| validator.wallet.public_key != dead).collect();
+    assert_eq!(validator1.builder.current_slot(), 1, "The clock shouldn't run without transactions waiting");
+    mempool.read().unwrap().send_transaction(transfer(0)).unwrap();
+    mempool.read().unwrap().send_transaction(transfer(1)).unwrap();
+    let handles: Vec<_> = live.iter().map(|validator| validator.start(Duration::from_millis(5))).collect();
+    let chain = Arc::clone(&validator1.builder.chain);
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 1), "Another validator should take over");
+    for handle in handles {
+        assert!(handle.join().is_ok());
+    }
+
+    let tip = chain.read().unwrap().tip().clone();
+    assert!(tip.slot() > 1, "The dead leader's slot should be skipped");
+    assert_ne!(tip.header.proposer, dead);
+    assert_eq!(validator1.builder.metrics.slots_skipped.get(), tip.slot() - 1);
+    assert!(db.read().unwrap().get_validator(&dead).unwrap().missed_slots >= 1, "The skipped slot should count against its leader");
+    assert_eq!(validator1.builder.current_slot(), tip.slot() + 1, "The clock should restart with the new tip");
+}
+
+#[test]
+fn test_tower_lockouts() {
+    // Each vote stacked on top doubles the lockouts under it
+    let mut tower = Tower::default();
+    let fork_a = [[1; 32], [2; 32], [3; 32]];
+    for (slot, hash) in (1..=3).zip(fork_a) {
+        assert!(tower.can_vote(slot, |voted| fork_a.contains(voted)));
+        tower.record(slot, hash);
+    }
+    assert_eq!(tower.votes().iter().map(|vote| vote.lockout()).collect::<Vec<_>>(), vec![8, 4, 2]);
+    assert!(!tower.can_vote(3, |_| true), "Slots already voted past can't be voted on");
+
+    // Partitioned onto fork B, the validator has to wait out every lockout on fork A
+    let on_fork_b = |voted: &[u8; 32]| !fork_a.contains(voted);
+    assert!(!tower.can_vote(4, on_fork_b), "A vote on another fork should be refused while fork A is locked in");
+    assert!(!tower.can_vote(9, on_fork_b), "The deepest vote on fork A binds through slot 9");
+    assert!(tower.can_vote(10, on_fork_b));
+    assert!(tower.can_vote(4, |voted| fork_a.contains(voted)), "Building on fork A is always allowed");
+
+    // Switching pops the expired votes
+    tower.record(10, [4; 32]);
+    assert_eq!(tower.votes().len(), 1);
+    assert_eq!(tower.last_voted_slot(), Some(10));
+
+    // A full tower roots its oldest vote, which binds for good
+    let mut tower = Tower::default();
+    for slot in 1..=MAX_LOCKOUT_HISTORY as u64 + 1 {
+        tower.record(slot, [slot as u8; 32]);
+    }
+    assert_eq!(tower.votes().len(), MAX_LOCKOUT_HISTORY);
+    assert_eq!(tower.root().map(|root| root.slot), Some(1));
+    assert!(!tower.can_vote(u64::MAX, |voted| *voted != [1; 32]), "Nothing off the root is ever votable");
+
+    // Validators keep a tower of their precommits & check it before voting
+    let (validator1, validator2, db, _) = setup_validators();
+    let (state_root, epoch_seed) = (db.read().unwrap().state_root(), db.read().unwrap().epoch_seed);
+    let leader = validator1.builder.get_leader(1).unwrap();
+    let block = Block::extending(&Block::create_genesis(), vec![])
+        .with_state_root(state_root)
+        .with_leader_proof(&leader, &epoch_seed)
+        .with_proposer(&leader);
+    let prevotes = vec![validator1.prevote(&block).unwrap(), validator2.prevote(&block).unwrap()];
+    let polka = Quorum::aggregate(block.hash, 1, prevotes, &db.read().unwrap()).unwrap();
+    validator1.precommit(&block, &polka).unwrap();
+    assert_eq!(validator1.builder.towers.get(&validator1.wallet.public_key).unwrap().last_voted_slot(), Some(1));
+    assert!(validator1.precommit(&block, &polka).is_none(), "A validator shouldn't vote twice in a slot");
+}
+
+#[test]
+fn test_validator_eligibility() {
+    let (validator1, validator2, db, _) = setup_validators();
+    let db_lock = db.read().unwrap();
+    let leader = validator1.builder.get_leader(1).unwrap();
+    let block = Block::extending(&Block::create_genesis(), vec![])
+        .with_state_root(db_lock.state_root())
+        .with_leader_proof(&leader, &db_lock.epoch_seed)
+        .with_proposer(&leader);
+    let (vote1, vote2) = (validator1.prevote(&block).unwrap(), validator2.prevote(&block).unwrap());
+
+    // Neither holds the minimum yet, so both lead & vote as equals
+    let _ = db_lock.increase_validator_stake(&validator2.wallet.public_key, db_lock.min_validator_stake - 1);
+    assert_eq!(voting_weight(&db_lock, &validator1.wallet.public_key), 1);
+    assert_eq!(db_lock.candidates().len(), 2);
+
+    // Once one does, the other stops counting
+    let _ = db_lock.increase_validator_stake(&validator1.wallet.public_key, db_lock.min_validator_stake);
+    assert_eq!(voting_weight(&db_lock, &validator2.wallet.public_key), 0, "Stake below the minimum shouldn't count");
+    assert_eq!(total_voting_weight(&db_lock), db_lock.min_validator_stake);
+    assert_eq!(validator1.builder.get_leader(1).unwrap().public_key, validator1.wallet.public_key, "Only eligible validators should lead");
+    assert!(Quorum::aggregate(block.hash, 1, vec![vote2], &db_lock).is_err(), "An ineligible validator can't make a quorum");
+    let quorum = Quorum::aggregate(block.hash, 1, vec![vote1, vote2], &db_lock).unwrap();
+    assert_eq!(quorum.weight, db_lock.min_validator_stake);
+}
+
+#[test]
+fn test_validator_jailing() {
+    let mut db = AccountsDB::new();
+    let (validator1, validator2) = setup_accounts(&db);
+    for (wallet, stake) in [(&validator1, 200), (&validator2, 150)] {
+        let mut account = ValidatorAccount::new(wallet.public_key);
+        account.stake = stake;
+        db.add_validator(wallet.public_key, account);
+    }
+    (db.max_missed_slots, db.jail_cooldown_blocks) = (3, 2);
+    assert_eq!(db.candidates().len(), 2);
+
+    // Nobody filled slots 1 to 4, so both candidates missed them, but validator 2 has since proposed
+    let genesis = Block::create_genesis();
+    let block1 = Block::extending(&genesis, vec![]).with_slot(5).with_proposer(&validator2);
+    let (undo, _) = db.finalize_block_with_undo(&block1).unwrap();
+    let jailed = db.get_validator(&validator1.public_key).unwrap();
+    assert_eq!((jailed.missed_slots, jailed.jailed_since), (4, Some(1)), "Missing too many slots should jail a validator");
+    assert_eq!(db.get_validator(&validator2.public_key).unwrap().missed_slots, 0, "Proposing should clear the count");
+    assert_eq!(db.candidates().iter().map(|(pubkey, _)| *pubkey).collect::<Vec<_>>(), vec![validator2.public_key], "Jailed validators shouldn't lead");
+    assert_eq!(voting_weight(&db, &validator1.public_key), 0, "Jailed validators shouldn't vote");
+
+    let unjail = |nonce: u64| {
+        let mut tx = UnjailTransaction::new(validator1.public_key, nonce);
+        tx.sign(&validator1);
+        Transaction::from(tx)
+    };
+    assert!(!unjail(0).validate(&db), "Unjailing should wait out the cooldown");
+
+    db.revert_block(undo);
+    assert!(!db.get_validator(&validator1.public_key).unwrap().is_jailed(), "Reverting should lift the jailing");
+    db.finalize_block(&block1).unwrap();
+
+    let block2 = Block::extending(&block1, vec![]).with_proposer(&validator2);
+    let block3 = Block::extending(&block2, vec![unjail(0)]).with_proposer(&validator2);
+    db.finalize_block(&block2).unwrap();
+    db.finalize_block(&block3).expect("Unjailing should execute once the cooldown has passed");
+    let unjailed = db.get_validator(&validator1.public_key).unwrap();
+    assert_eq!((unjailed.missed_slots, unjailed.jailed_since), (0, None));
+    assert_eq!(db.candidates().len(), 2);
+    assert!(!unjail(1).validate(&db), "Only jailed validators can unjail");
+}
+
+#[test]
+fn test_epoch_active_set() {
+    let mut db = AccountsDB::new();
+    let (validator1, validator2) = setup_accounts(&db);
+    let mut account = ValidatorAccount::new(validator1.public_key);
+    account.stake = 200;
+    db.add_validator(validator1.public_key, account);
+    db.epoch_slots = 4;
+
+    // The first epoch boundary takes the set over from the live validators
+    let genesis = Block::create_genesis();
+    let block1 = Block::extending(&genesis, vec![]).with_slot(4).with_proposer(&validator1);
+    db.finalize_block(&block1).unwrap();
+    assert_eq!(db.active_set, BTreeMap::from([(validator1.public_key, 200)]));
+
+    // A validator joining & stake added mid-epoch wait for the next one
+    let mut account = ValidatorAccount::new(validator2.public_key);
+    account.stake = 1000;
+    db.add_validator(validator2.public_key, account);
+    db.increase_validator_stake(&validator1.public_key, 100).unwrap();
+    assert_eq!(voting_weight(&db, &validator2.public_key), 0, "A validator joining mid-epoch shouldn't vote in it");
+    assert_eq!(total_voting_weight(&db), 200, "Stake added mid-epoch shouldn't count in it");
+    assert!(db.draw_leader(&validator2, 5).is_none(), "A validator joining mid-epoch shouldn't lead in it");
+
+    let block2 = Block::extending(&block1, vec![]).with_slot(8).with_proposer(&validator1);
+    let (undo, _) = db.finalize_block_with_undo(&block2).unwrap();
+    assert_eq!(db.active_set, BTreeMap::from([(validator1.public_key, 300), (validator2.public_key, 1000)]));
+    assert_eq!(voting_weight(&db, &validator2.public_key), 1000);
+    assert!(db.draw_leader(&validator2, 9).is_some());
+
+    db.revert_block(undo);
+    assert_eq!(db.active_set, BTreeMap::from([(validator1.public_key, 200)]), "Reverting should restore the epoch's set");
+}
+
+#[test]
+fn test_vrf_leader_election() {
+    let wallet = Wallet::generate();
+    let input = leader_input(&[7; 32], 1);
+    let (output, proof) = vrf::prove(&wallet, &input);
+    assert_eq!(vrf::prove(&wallet, &input), (output, proof), "The output should be fixed by the key & input");
+    assert_eq!(vrf::verify(&wallet.public_key, &input, &proof), Some(output));
+    assert_eq!(vrf::verify(&wallet.public_key, &leader_input(&[7; 32], 2), &proof), None, "A proof shouldn't carry over to another slot");
+    assert_eq!(vrf::verify(&Wallet::generate().public_key, &input, &proof), None, "A proof should only verify under its own key");
+    let mut forged = proof;
+    forged.gamma = vrf::prove(&Wallet::generate(), &input).1.gamma;
+    assert_eq!(vrf::verify(&wallet.public_key, &input, &forged), None, "Another key's output shouldn't pass");
+    assert!(leader_score(&output, 200) < leader_score(&output, 100), "More stake should mean a lower score");
+
+    // Voters turn down a block from a validator that drew higher than they did
+    let (validator1, validator2, db, _) = setup_validators();
+    let leader = validator1.builder.get_leader(1).unwrap();
+    let (leader, other) = if leader.public_key == validator1.wallet.public_key { (&validator1, &validator2) } else { (&validator2, &validator1) };
+    let (state_root, epoch_seed) = (db.read().unwrap().state_root(), db.read().unwrap().epoch_seed);
+    let propose = |validator: &Validator| Block::extending(&Block::create_genesis(), vec![])
+        .with_state_root(state_root)
+        .with_leader_proof(&validator.wallet, &epoch_seed)
+        .with_proposer(&validator.wallet);
+    assert!(other.prevote(&propose(leader)).is_some());
+    assert!(leader.prevote(&propose(other)).is_none(), "The slot's leader shouldn't back another proposer");
+    assert!(other.prevote(&propose(other)).is_some());
+    let unproven = Block::extending(&Block::create_genesis(), vec![]).with_state_root(state_root).with_proposer(&leader.wallet);
+    assert!(other.prevote(&unproven).is_none(), "Blocks without a proof of the draw shouldn't get votes");
+
+    // The seed takes in the last block of each epoch
+    let mut db = AccountsDB::new();
+    db.epoch_slots = 2;
+    let block1 = Block::extending(&Block::create_genesis(), vec![]);
+    let block2 = Block::extending(&block1, vec![]);
+    db.finalize_block(&block1).unwrap();
+    assert_eq!(db.epoch_seed, Hash::default());
+    let (undo, _) = db.finalize_block_with_undo(&block2).unwrap();
+    assert_ne!(db.epoch_seed, Hash::default(), "Ending an epoch should roll the seed");
+    db.revert_block(undo);
+    assert_eq!(db.epoch_seed, Hash::default(), "Reverting should restore the seed");
+}
+
+#[test]
+fn test_block_rewards() {
+    let (validator1, validator2, db, _) = setup_validators();
+    let db_lock = db.read().unwrap();
+    let mut chain = Blockchain::new();
+    let (account1, account2) = setup_accounts(&db_lock);
+    let _ = db_lock.increase_account_balance(&account1.public_key, 1000);
+
+    let config = RewardConfig { initial_reward: 1000, halving_interval: 10, proposer_percent: 20 };
+    assert_eq!(config.block_reward(0), 1000, "First halving period pays the full reward");
+    assert_eq!(config.block_reward(25), 250, "Reward should halve every interval");
+
+    let _ = db_lock.increase_validator_stake(&validator1.wallet.public_key, 300);
+    let _ = db_lock.increase_validator_stake(&validator2.wallet.public_key, 100);
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 100, 0);
+    tx.sign(&account1);
+    let block = Block::extending(chain.tip(), vec![Transaction::from(tx)])
+        .with_state_root(db_lock.state_root())
+        .with_proposer(&validator1.wallet);
+
+    // Whoever drew lowest, both validators back the block
+    drop(db_lock);
+    let votes = vec![
+        Vote::new(block.hash, 1, &validator1.wallet),
+        Vote::new(block.hash, 1, &validator2.wallet),
+    ];
+    let mut db_lock = db.write().unwrap();
+    let quorum = Quorum::aggregate(block.hash, 1, votes, &db_lock).unwrap();
+    let rewards = config.split(1, &validator1.wallet.public_key, &quorum, &db_lock);
+
+    chain.add_votes(&block.hash, quorum.weight);
+    chain.apply(block.clone(), &mut db_lock).unwrap();
+    chain.credit_rewards(&block.hash, rewards, &mut db_lock).unwrap();
+
+    // 200 to the proposer, then 800 split 3:1 by stake between the voters, all held until the epoch ends
+    assert_eq!(db_lock.pending_reward(&validator1.wallet.public_key), 200 + 600, "Proposer should get its cut plus its voter share");
+    assert_eq!(db_lock.pending_reward(&validator2.wallet.public_key), 200, "Voter should get its stake-weighted share");
+    assert_eq!(db_lock.total_supply, 1000, "Issuance should be tracked in total supply");
+
+    // Rewards roll back with their block when a heavier fork wins
+    let competing = Block::extending(chain.block_at(0).unwrap(), vec![]);
+    chain.add_votes(&competing.hash, 1_000);
+    chain.apply(competing.clone(), &mut db_lock).unwrap();
+    assert_eq!(chain.tip().hash, competing.hash, "Heavier fork should win");
+    assert_eq!(db_lock.total_supply, 0, "Reorged-out rewards should be unminted");
+    assert_eq!(db_lock.pending_reward(&validator2.wallet.public_key), 0, "Reorged-out rewards should no longer be owed");
+
+    // And come back if the original block becomes canonical again
+    chain.add_votes(&block.hash, 1_000);
+    chain.apply_fork_choice(&mut db_lock).unwrap();
+    assert_eq!(chain.tip().hash, block.hash, "Original block should be canonical again");
+    assert_eq!(db_lock.total_supply, 1000, "Rewards should be re-credited with their block");
+    assert_eq!(db_lock.pending_reward(&validator2.wallet.public_key), 200, "Voter reward should be restored");
+}
+
+#[test]
+fn test_delegator_rewards() {
+    let mut db = AccountsDB::new();
+    db.epoch_slots = 2;
+    let (validator, delegator) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&validator.public_key, 1000);
+    let _ = db.increase_account_balance(&delegator.public_key, 1000);
+
+    let mut register = RegisterValidatorTransaction::new(validator.public_key, 100, 0).with_commission(10);
+    register.sign(&validator);
+    let mut delegate = StakeTransaction::new(validator.public_key, delegator.public_key, 300, 0);
+    delegate.sign(&delegator);
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(register), Transaction::from(delegate)]);
+    db.apply_block(&block1).expect("Registration & delegation should execute");
+    assert_eq!(db.delegations(&validator.public_key), vec![Delegation { delegator: delegator.public_key, amount: 300 }]);
+    let account = db.get_validator(&validator.public_key).unwrap();
+    assert_eq!((account.stake, db.self_stake(&account), account.commission), (400, 100, 10));
+
+    let mut withdraw = UnstakeTransaction::new(validator.public_key, 200, 1);
+    withdraw.sign(&validator);
+    assert!(!Transaction::from(withdraw).validate(&db), "A validator can't withdraw delegated stake");
+
+    db.credit_block_rewards(&block1.hash, &[(validator.public_key, 1000)]).unwrap();
+    assert_eq!(db.pending_reward(&validator.public_key), 1000, "Rewards should wait for the end of the epoch");
+    assert_eq!(db.get_account(&validator.public_key).unwrap().balance, 900);
+
+    // Delegated stake earned 750 of the 1000, less 10% commission
+    let block2 = Block::extending(&block1, vec![]);
+    db.apply_block(&block2).expect("Epoch boundary should execute");
+    assert_eq!(db.get_account(&delegator.public_key).unwrap().balance, 700 + 675);
+    assert_eq!(db.get_account(&validator.public_key).unwrap().balance, 900 + 325);
+    assert_eq!(db.pending_reward(&validator.public_key), 0);
+
+    db.rollback_to(1).unwrap();
+    assert_eq!(db.pending_reward(&validator.public_key), 1000, "Rolling back the boundary should owe the rewards again");
+    assert_eq!(db.get_account(&delegator.public_key).unwrap().balance, 700);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_run_blockchain_async() {
+    let (validator1, validator2, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 10000);
+
+    for (nonce, amt) in [100, 200].into_iter().enumerate() {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce as u64);
+        tx.sign(&account1);
+        assert!(mempool.read().unwrap().send_transaction(Transaction::from(tx)).is_ok(), "Transaction send failed");
+    }
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let handles: Vec<_> = [validator1, validator2].into_iter().map(|validator| {
+        let mut shutdown = shutdown_rx.clone();
+        tokio::spawn(async move {
+            validator.run_async(Duration::from_millis(50), async move { let _ = shutdown.wait_for(|stop| *stop).await; }).await
+        })
+    }).collect();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !mempool.read().unwrap().pool.is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    shutdown_tx.send(true).unwrap();
+    for handle in handles {
+        assert!(handle.await.unwrap().is_ok(), "Validator should shut down cleanly");
+    }
+
+    assert!(mempool.read().unwrap().pool.is_empty(), "Validators should have drained the mempool");
+    assert_eq!(db.read().unwrap().get_account(&account2.public_key).unwrap().balance, 300, "Both transfers should be finalized");
+}
+
+fn rpc_request(method: &str, params: serde_json::Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: serde_json::json!(1),
+        method: method.to_string(),
+        params,
+    }
+}
+
+#[test]
+fn test_rpc_server() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let rpc = RpcServer::new(validator1.builder.clone());
+
+    let response = rpc.handle(rpc_request("getBalance", serde_json::json!([account1.address])));
+    assert_eq!(response.result, Some(serde_json::json!(1000)), "getBalance should return the account balance");
+
+    let response = rpc.handle(rpc_request("getBlockHeight", serde_json::Value::Null));
+    assert_eq!(response.result, Some(serde_json::json!(0)), "Fresh chain should be at genesis");
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
+    tx.sign(&account1);
+    let encoded = hex::encode(Transaction::from(tx).to_bytes());
+    let response = rpc.handle(rpc_request("sendTransaction", serde_json::json!([encoded])));
+    assert!(response.error.is_none(), "sendTransaction should succeed");
+    assert_eq!(mempool.read().unwrap().pool.len(), 1, "Transaction should land in the mempool");
+
+    let response = rpc.handle(rpc_request("getBalance", serde_json::json!(["not hex"])));
+    assert_eq!(response.error.map(|e| e.code), Some(INVALID_PARAMS), "Bad addresses should be rejected");
+
+    let response = rpc.handle(rpc_request("getNothing", serde_json::Value::Null));
+    assert_eq!(response.error.map(|e| e.code), Some(METHOD_NOT_FOUND), "Unknown methods should be rejected");
+}
+
+#[test]
+fn test_simulate_transaction() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+    let supply = db.read().unwrap().total_supply;
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 40, 0).with_fee(5);
+    tx.sign(&account1);
+    let tx = Transaction::from(tx);
+    let simulated = db.read().unwrap().simulate_transaction(&tx);
+    let mut balances = vec![
+        ValueChange { pubkey: account1.public_key, old: Some(100), new: Some(55) },
+        ValueChange { pubkey: account2.public_key, old: Some(0), new: Some(40) },
+    ];
+    balances.sort_by_key(|change| change.pubkey);
+    assert_eq!(simulated, SimulationResult {
+        error: None,
+        fee: 5,
+        compute_units: tx.compute_units(),
+        changes: StateDiff { balances, stakes: vec![] },
+    });
+    assert_eq!(db.read().unwrap().get_account(&account1.public_key).unwrap().balance, 100, "Simulating should change nothing");
+    assert_eq!(db.read().unwrap().total_supply, supply);
+
+    let mut overdraft = TransferTransaction::new(account2.public_key, account1.public_key, 1000, 1);
+    overdraft.sign(&account1);
+    let failed = db.read().unwrap().simulate_transaction(&Transaction::from(overdraft));
+    assert!(failed.error.is_some());
+    assert_eq!((failed.fee, failed.changes), (0, StateDiff::default()), "A failing transaction pays & changes nothing");
+
+    let rpc = RpcServer::new(validator1.builder.clone());
+    let response = rpc.handle(rpc_request("simulateTransaction", serde_json::json!([hex::encode(tx.to_bytes())])));
+    assert_eq!(response.result.map(|result| serde_json::from_value::<SimulationResult>(result).unwrap()), Some(simulated));
+    assert!(mempool.read().unwrap().pool.is_empty(), "Simulated transactions aren't submitted");
+}
+
+#[test]
+fn test_commitment_levels() {
+    let (alice, bob) = (Wallet::generate(), Wallet::generate());
+    let mut config = GenesisConfig {
+        accounts: vec![
+            GenesisAccount { address: alice.address.clone(), balance: 1000 },
+            GenesisAccount { address: bob.address.clone(), balance: 0 },
+        ],
+        ..GenesisConfig::default()
+    };
+    config.chain.finality_depth = 1;
+    let (mut chain, mut db) = Blockchain::from_genesis(&config).unwrap();
+    let mut transfers = vec![];
+    for nonce in 0..3 {
+        let mut tx = TransferTransaction::new(bob.public_key, alice.public_key, 100, nonce);
+        tx.sign(&alice);
+        let block = Block::extending(chain.tip(), vec![Transaction::from(tx)]);
+        // The newest block hasn't been voted on yet
+        if nonce < 2 {
+            chain.add_votes(&block.hash, 1);
+        }
+        chain.apply(block.clone(), &mut db).unwrap();
+        transfers.push(block.transactions[0].hash());
+    }
+    let heights = [Commitment::Processed, Commitment::Confirmed, Commitment::Finalized].map(|commitment| chain.commitment_height(commitment, &db));
+    assert_eq!(heights, [3, 2, 1]);
+
+    let builder = BlockBuilder::new(Arc::default(), Arc::new(RwLock::new(db)), Arc::new(RwLock::new(chain)));
+    let rpc = RpcServer::new(builder.clone());
+    let query = |method: &str, mut params: Vec<serde_json::Value>, commitment: &str| {
+        params.push(serde_json::json!({ "commitment": commitment }));
+        rpc.handle(rpc_request(method, serde_json::Value::Array(params)))
+    };
+    for (commitment, balance) in [("processed", 300), ("confirmed", 200), ("finalized", 100)] {
+        assert_eq!(query("getBalance", vec![serde_json::json!(bob.address)], commitment).result, Some(serde_json::json!(balance)), "Balances should be read at the {} block", commitment);
+    }
+    assert_eq!(builder.db.read().unwrap().get_account(&bob.public_key).unwrap().balance, 300, "Reading past state shouldn't touch the live state");
+    assert_eq!(query("getBlockHeight", vec![], "confirmed").result, Some(serde_json::json!(2)));
+    assert_eq!(query("getBlock", vec![serde_json::json!(3)], "confirmed").result, Some(serde_json::Value::Null), "Blocks above the commitment shouldn't be served");
+    assert!(query("getBlock", vec![serde_json::json!(3)], "processed").result.is_some_and(|block| !block.is_null()));
+    let status = |commitment: &str| query("getTransactionStatus", vec![serde_json::json!(hex::encode(transfers[1]))], commitment).result;
+    assert_eq!(status("finalized"), Some(serde_json::json!(TransactionStatus::Pending)));
+    assert!(matches!(serde_json::from_value(status("confirmed").unwrap()), Ok(TransactionStatus::Included { height: 2, .. })));
+    assert_eq!(query("getBalance", vec![serde_json::json!(bob.address)], "eventually").error.map(|e| e.code), Some(INVALID_PARAMS));
+}
+
+#[test]
+fn test_finality_checkpoints() {
+    let validators = [Wallet::generate(), Wallet::generate()];
+    let mut config = GenesisConfig {
+        validators: validators
+            .iter()
+            .map(|wallet| GenesisValidator { address: wallet.address.clone(), stake: 100, commission: 0, bls_key: Some(hex::encode(wallet.bls_public_key())) })
+            .collect(),
+        ..GenesisConfig::default()
+    };
+    config.chain.checkpoint_interval = 2;
+    let (mut chain, mut db) = Blockchain::from_genesis(&config).unwrap();
+    let certify = |block: &Block, db: &AccountsDB| {
+        let votes = validators.iter().map(|wallet| Vote::new(block.hash, block.slot(), wallet)).collect();
+        Quorum::aggregate(block.hash, block.slot(), votes, db).unwrap().certificate(db).unwrap()
+    };
+
+    // Only blocks at multiples of the interval are checkpointed, once they have a certificate
+    let block1 = Block::extending(chain.tip(), vec![]);
+    let block2 = Block::extending(&block1, vec![]);
+    chain.apply(block1.clone(), &mut db).unwrap();
+    chain.add_certificate(certify(&block1, &db));
+    chain.apply(block2.clone(), &mut db).unwrap();
+    assert!(chain.latest_checkpoint().is_none());
+    chain.add_certificate(certify(&block2, &db));
+    let checkpoint = chain.checkpoint(2).unwrap().clone();
+    assert!(checkpoint.matches(&block2.header));
+    assert!(!checkpoint.matches(&block1.header));
+    assert_eq!(checkpoint.verify(&db), Ok(()));
+    let mut moved = checkpoint.clone();
+    moved.block_hash = block1.hash;
+    assert_eq!(moved.verify(&db), Err("Certificate is for a different block"));
+
+    // A certificate carried in the next block's header counts too
+    let block3 = Block::extending(&block2, vec![]);
+    let block4 = Block::extending(&block3, vec![]);
+    chain.apply(block3.clone(), &mut db).unwrap();
+    chain.apply(block4.clone(), &mut db).unwrap();
+    let block5 = Block::extending(&block4, vec![]).with_certificate(Some(certify(&block4, &db)));
+    chain.apply(block5, &mut db).unwrap();
+    assert_eq!(chain.latest_checkpoint().map(|checkpoint| checkpoint.height), Some(4));
+
+    let rpc = RpcServer::new(BlockBuilder::new(Arc::default(), Arc::new(RwLock::new(db)), Arc::new(RwLock::new(chain))));
+    let latest = rpc.handle(rpc_request("getCheckpoint", serde_json::json!([]))).result.unwrap();
+    assert_eq!(latest["height"], serde_json::json!(4));
+    let second = rpc.handle(rpc_request("getCheckpoint", serde_json::json!([2]))).result.unwrap();
+    assert_eq!(serde_json::from_value::<Checkpoint>(second).unwrap(), checkpoint);
+    assert_eq!(rpc.handle(rpc_request("getCheckpoint", serde_json::json!([3]))).result, Some(serde_json::Value::Null));
+}
+
+#[test]
+fn test_light_client() {
+    let validators = [Wallet::generate(), Wallet::generate()];
+    let (alice, bob) = (Wallet::generate(), Wallet::generate());
+    let mut config = GenesisConfig {
+        accounts: vec![
+            GenesisAccount { address: alice.address.clone(), balance: 1000 },
+            GenesisAccount { address: bob.address.clone(), balance: 0 },
+        ],
+        validators: validators
+            .iter()
+            .map(|wallet| GenesisValidator { address: wallet.address.clone(), stake: 100, commission: 0, bls_key: Some(hex::encode(wallet.bls_public_key())) })
+            .collect(),
+        ..GenesisConfig::default()
+    };
+    config.chain.checkpoint_interval = 2;
+    let (mut chain, mut db) = Blockchain::from_genesis(&config).unwrap();
+    let mut client = LightClient::from_genesis(&config).unwrap();
+    assert_eq!(client.header(0).map(|header| header.hash()), Some(chain.tip().hash), "The client should start from the same genesis");
+    let certify = |block: &Block, db: &AccountsDB| {
+        let votes = validators.iter().map(|wallet| Vote::new(block.hash, block.slot(), wallet)).collect();
+        Quorum::aggregate(block.hash, block.slot(), votes, db).unwrap().certificate(db).unwrap()
+    };
+
+    let mut tx = TransferTransaction::new(bob.public_key, alice.public_key, 100, 0);
+    tx.sign(&alice);
+    let block1 = Block::extending(chain.tip(), vec![Transaction::from(tx)]).with_state_root(db.state_root()).with_proposer(&validators[0]);
+    chain.apply(block1.clone(), &mut db).unwrap();
+    client.add_header(block1.header.clone(), block1.signature()).unwrap();
+    let proof = block1.prove_transaction(0).unwrap();
+    assert!(client.verify_transaction(1, &block1.transactions[0], &proof));
+    assert!(!client.verify_transaction(0, &block1.transactions[0], &proof), "Proofs should only check against their own block");
+
+    // The next header finalizes the first & commits to the state after it
+    let block2 = Block::extending(&block1, vec![])
+        .with_state_root(db.state_root())
+        .with_certificate(Some(certify(&block1, &db)))
+        .with_proposer(&validators[1]);
+    let outsider = Block::extending(&block1, vec![]).with_state_root(db.state_root()).with_proposer(&alice);
+    assert_eq!(client.add_header(outsider.header.clone(), outsider.signature()), Err("Header proposer is not a validator"));
+    assert_eq!(client.add_header(block2.header.clone(), block1.signature()), Err("Invalid proposer signature"));
+    client.add_header(block2.header.clone(), block2.signature()).unwrap();
+    assert_eq!((client.height(), client.finalized_height()), (2, 1));
+    let account = db.get_account(&bob.public_key).unwrap();
+    let proof = db.prove_account(&bob.public_key).unwrap();
+    assert!(client.verify_account(2, &account, &proof), "Bob's balance should check against the header's state root");
+    assert!(!client.verify_account(1, &account, &proof), "Bob's balance was different before the transfer");
+    assert!(!client.verify_account(2, &UserAccount { balance: 1000, ..account.clone() }, &proof), "A forged balance shouldn't check out");
+
+    // A fresh client can start from a checkpoint rather than walk every header
+    chain.apply(block2.clone(), &mut db).unwrap();
+    let block3 = Block::extending(&block2, vec![]).with_state_root(db.state_root()).with_certificate(Some(certify(&block2, &db))).with_proposer(&validators[0]);
+    chain.apply(block3.clone(), &mut db).unwrap();
+    let checkpoint = chain.latest_checkpoint().unwrap().clone();
+    let mut fresh = LightClient::from_genesis(&config).unwrap();
+    assert_eq!(fresh.add_checkpoint(checkpoint.clone(), block1.header.clone()), Err("Header is not the checkpointed block"));
+
+    // Unfinalized headers of another fork below the checkpoint don't become finalized with it
+    let fork1 = Block::extending(&Block::genesis(&config, AccountsDB::from_genesis(&config).unwrap().state_root()), vec![]).with_proposer(&validators[1]);
+    let mut forked = LightClient::from_genesis(&config).unwrap();
+    forked.add_header(fork1.header.clone(), fork1.signature()).unwrap();
+    forked.add_checkpoint(checkpoint.clone(), block2.header.clone()).unwrap();
+    assert!(forked.header(1).is_none(), "A header the checkpoint doesn't build on should be dropped");
+    assert_eq!(forked.header(2).map(|header| header.hash()), Some(block2.hash));
+    let mut following = LightClient::from_genesis(&config).unwrap();
+    following.add_header(block1.header.clone(), block1.signature()).unwrap();
+    following.add_checkpoint(checkpoint.clone(), block2.header.clone()).unwrap();
+    assert!(following.verify_transaction(1, &block1.transactions[0], &block1.prove_transaction(0).unwrap()), "The checkpoint's ancestors should be kept");
+
+    fresh.add_checkpoint(checkpoint, block2.header.clone()).unwrap();
+    assert_eq!((fresh.height(), fresh.finalized_height()), (2, 2));
+    assert_eq!(fresh.add_header(block1.header.clone(), block1.signature()), Err("Header conflicts with a finalized header"));
+    fresh.add_header(block3.header.clone(), block3.signature()).unwrap();
+    assert_eq!(fresh.height(), 3);
+}
+
+#[test]
+fn test_account_proofs() {
+    let (alice, bob) = (Wallet::generate(), Wallet::generate());
+    let config = GenesisConfig {
+        accounts: vec![
+            GenesisAccount { address: alice.address.clone(), balance: 1000 },
+            GenesisAccount { address: bob.address.clone(), balance: 0 },
+        ],
+        ..GenesisConfig::default()
+    };
+    let (mut chain, mut db) = Blockchain::from_genesis(&config).unwrap();
+    for nonce in 0..2 {
+        let mut tx = TransferTransaction::new(bob.public_key, alice.public_key, 100, nonce);
+        tx.sign(&alice);
+        let block = Block::extending(chain.tip(), vec![Transaction::from(tx)]);
+        // Only the first block is voted on
+        if nonce == 0 {
+            chain.add_votes(&block.hash, 1);
+        }
+        chain.apply(block, &mut db).unwrap();
+    }
+    let (account, proof) = db.get_account_with_proof(&bob.public_key).unwrap();
+    assert!(verify_account_proof(&account, &proof, &db.state_root()));
+    assert!(!verify_account_proof(&UserAccount { balance: 1000, ..account }, &proof, &db.state_root()), "A forged balance shouldn't check out");
+    assert!(db.get_account_with_proof(&Wallet::generate().public_key).is_none());
+
+    let rpc = RpcServer::new(BlockBuilder::new(Arc::default(), Arc::new(RwLock::new(db)), Arc::new(RwLock::new(chain))));
+    let query = |commitment: &str| {
+        let params = serde_json::json!([bob.address, { "commitment": commitment }]);
+        rpc.handle(rpc_request("getAccountWithProof", params)).result.unwrap()
+    };
+    for (commitment, height, balance) in [("processed", 2, 200), ("confirmed", 1, 100)] {
+        let result = query(commitment);
+        assert_eq!(result["height"], serde_json::json!(height));
+        let account: UserAccount = serde_json::from_value(result["account"].clone()).unwrap();
+        let proof: MerkleProof = serde_json::from_value(result["proof"].clone()).unwrap();
+        let state_root: Hash = hex::decode(result["state_root"].as_str().unwrap()).unwrap().try_into().unwrap();
+        assert_eq!(account.balance, balance);
+        assert!(verify_account_proof(&account, &proof, &state_root), "The {} proof should check against its root", commitment);
+    }
+    let stranger = serde_json::json!([Wallet::generate().address]);
+    assert_eq!(rpc.handle(rpc_request("getAccountWithProof", stranger)).result, Some(serde_json::Value::Null));
+}
+
+#[tokio::test]
+async fn test_node_startup_and_shutdown() {
+    let config: NodeConfig = toml::from_str(r#"
+        rpc_addr = "127.0.0.1:0"
+        p2p_addr = "127.0.0.1:0"
+        slot_interval_ms = 10
+
+        [rewards]
+        initial_reward = 50
+        halving_interval = 1000
+        proposer_percent = 100
+    "#).expect("Config should parse");
+    assert_eq!(config.rewards.initial_reward, 50, "Nested reward config should parse");
+    assert!(config.peers.is_empty(), "Unset keys should fall back to defaults");
+
+    let node = Node::new(config).expect("Node should start");
+    assert!(node.builder.db.read().unwrap().is_validator(&node.validator.wallet.public_key), "Node should register its validator");
+
+    let result = tokio::time::timeout(Duration::from_secs(5), node.run(tokio::time::sleep(Duration::from_millis(100)))).await;
+    assert!(matches!(result, Ok(Ok(()))), "Node should shut down cleanly");
+}
+
+#[test]
+fn test_keystore_roundtrip() {
+    let kdf = KdfParams { log_n: 4, r: 8, p: 1 }; // Cheap parameters keep the test fast
+    let wallet = Wallet::generate();
+
+    let keystore = Keystore::encrypt_with_params(&wallet, "hunter2", kdf).expect("Encryption should succeed");
+    assert_eq!(keystore.address, wallet.address, "Keystore should be labelled with the wallet address");
+
+    let path = std::env::temp_dir().join(format!("litechain-keystore-{}.json", wallet.address));
+    keystore.save(&path).expect("Keystore should save");
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains(&hex::encode(wallet.secret_key())), "Secret key must not be stored in plaintext");
+
+    let loaded = Keystore::load(&path).expect("Keystore should load");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, keystore, "Keystore should survive a save & load");
+
+    let restored = loaded.decrypt("hunter2").expect("Correct passphrase should unlock");
+    assert_eq!(restored, wallet, "Restored wallet should match the original");
+    assert!(loaded.decrypt("hunter3").is_err(), "Wrong passphrase should be rejected");
+}
+
+#[test]
+fn test_mnemonic_derivation() {
+    // SLIP-0010 ed25519 test vector 1
+    let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+    assert_eq!(
+        hex::encode(derive_secret_key(&seed, "m").unwrap()),
+        "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+    );
+    assert_eq!(
+        hex::encode(derive_secret_key(&seed, "m/0'").unwrap()),
+        "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+    );
+    assert!(derive_secret_key(&seed, "m/0").is_err(), "Non-hardened derivation should be rejected");
+
+    let phrase = generate_mnemonic(24).unwrap();
+    assert_eq!(phrase.split_whitespace().count(), 24);
+    assert!(generate_mnemonic(13).is_err(), "Only 12 & 24 word mnemonics are supported");
+
+    let first = Wallet::from_mnemonic(&phrase, "", 0).unwrap();
+    let again = Wallet::from_mnemonic(&phrase, "", 0).unwrap();
+    let second = Wallet::from_mnemonic(&phrase, "", 1).unwrap();
+    let protected = Wallet::from_mnemonic(&phrase, "extra words", 0).unwrap();
+    assert_eq!(first, again, "The same phrase & index should regenerate the same wallet");
+    assert_ne!(first.public_key, second.public_key, "Each index should derive a distinct wallet");
+    assert_ne!(first.public_key, protected.public_key, "The BIP39 passphrase should change the seed");
+
+    let valid = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    assert!(seed_from_mnemonic(valid, "").is_ok());
+    assert!(seed_from_mnemonic(&valid.replace("about", "abandon"), "").is_err(), "Bad checksums should be rejected");
+}
+
+#[test]
+fn test_wallet_keeps_secrets_off_chain() {
+    let (validator1, _v, db, _) = setup_validators();
+    let db_lock = db.read().unwrap();
+    let (account1, _) = setup_accounts(&db_lock);
+
+    let state = bincode::serialize(&*db_lock).unwrap();
+    for secret in [account1.secret_key(), validator1.wallet.secret_key()] {
+        assert!(!state.windows(secret.len()).any(|window| window == secret), "Ledger state must not contain secret keys");
+    }
+    assert!(!format!("{:?}", account1).contains(&format!("{:?}", account1.secret_key())), "Wallet debug output should redact the secret key");
+
+    assert_eq!(db_lock.get_account(&account1.public_key), Some(account1.account()), "Stored account should only carry public state");
+}
+
+#[test]
+fn test_batch_signature_verification() {
+    let (validator1, _v, db, _) = setup_validators();
+    let db_lock = db.read().unwrap();
+    let (account1, account2) = setup_accounts(&db_lock);
+    let _ = db_lock.increase_account_balance(&account1.public_key, 1000);
+    drop(db_lock);
+
+    let transactions: Vec<Transaction> = (1..=8u64)
+        .map(|amt| {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, amt - 1);
+            tx.sign(&account1);
+            Transaction::from(tx)
+        })
+        .collect();
+    assert!(Transaction::verify_batch(&transactions), "A batch of valid signatures should verify");
+    assert!(Transaction::verify_batch(&[]), "An empty batch trivially verifies");
+
+    let builder = validator1.builder.clone().with_config(ChainConfig { max_transactions_per_block: 16, ..Default::default() });
+    let block = Block::extending(&Block::create_genesis(), transactions.clone()).with_state_root(db.read().unwrap().state_root()).with_proposer(&validator1.wallet);
+    assert!(builder.validate_block(&block).is_ok(), "Block of validly signed transactions should pass");
+
+    // One transaction signed by the wrong key spoils the whole batch
+    let mut forged = TransferTransaction::new(account1.public_key, account2.public_key, 1, 0);
+    forged.sign(&account1);
+    let mut tampered = transactions;
+    tampered.push(Transaction::from(forged));
+    assert!(!Transaction::verify_batch(&tampered), "A forged signature should fail the batch");
+    let block = Block::extending(&Block::create_genesis(), tampered).with_proposer(&validator1.wallet);
+    assert_eq!(builder.validate_block(&block), Err("Invalid transaction signature"));
+}
+
+#[test]
+fn test_mempool_fee_priority() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let mempool = Mempool::new();
+
+    // Each amount doubles as the nonce so none of these replace one another
+    let transfer = |amt: u64, fee: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, amt).with_fee(fee);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    let cheap = transfer(1, 1);
+    let pricey = transfer(2, 50);
+    let free = transfer(3, 0);
+    let tied = transfer(4, 50);
+    for tx in [cheap.clone(), pricey.clone(), free, tied.clone()] {
+        mempool.send_transaction(tx).unwrap();
+    }
+
+    // Highest fee wins, & equal fees go in arrival order
+    assert_eq!(mempool.get_transactions_for_block(2), vec![pricey.clone(), tied.clone()], "Block should take the highest-paying transactions");
+
+    mempool.remove_included(&[pricey]);
+    assert_eq!(mempool.get_transactions_for_block(2), vec![tied.clone(), cheap], "Removed transactions should leave the index");
+    assert_eq!(mempool.pool.len(), 3);
+
+    // Fees are signed over & paid on top of the amount
+    let first = |amt: u64, fee: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, 0).with_fee(fee);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    assert!(!first(60, 50).validate(&db), "Sender must cover amount plus fee");
+    first(4, 50).execute(&mut db).unwrap();
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 46, "Fee should be debited from the sender");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 4, "Recipient receives only the amount");
+}
+
+#[test]
+fn test_mempool_replace_by_fee() {
+    let db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let mempool = Mempool::new();
+
+    let transfer = |amt: u64, nonce: u64, fee: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce).with_fee(fee);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    let original = transfer(100, 0, 5);
+    let other_nonce = transfer(100, 1, 1);
+    let original_id = mempool.send_transaction(original).unwrap();
+    mempool.send_transaction(other_nonce.clone()).unwrap();
+
+    assert!(mempool.send_transaction(transfer(50, 0, 5)).is_err(), "Same-fee resubmission should be rejected");
+    assert!(mempool.send_transaction(transfer(50, 0, 4)).is_err(), "Lower-fee resubmission should be rejected");
+    assert_eq!(mempool.pool.len(), 2, "Rejected replacements shouldn't be admitted");
+
+    let replacement = transfer(50, 0, 6);
+    let replacement_id = mempool.send_transaction(replacement.clone()).expect("Higher fee should replace the pending transaction");
+    assert_eq!(mempool.pool.len(), 2, "Replacement shouldn't create a duplicate");
+    assert!(mempool.get_transaction(&original_id).is_none(), "Original should be evicted");
+    assert_eq!(mempool.get_transaction(&replacement_id), Some(replacement.clone()));
+    assert_eq!(mempool.get_transactions_for_block(2), vec![replacement.clone(), other_nonce], "Priority index should follow the replacement");
+
+    // Once the pending transaction leaves the pool its nonce is free again
+    mempool.remove_included(&[replacement]);
+    assert!(mempool.send_transaction(transfer(10, 0, 0)).is_ok(), "Nonce should be reusable once its transaction is gone");
+}
+
+#[test]
+fn test_transaction_nonces() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let transfer = |nonce: u64, fee: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce).with_fee(fee);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    // Each nonce runs once, in order
+    let first = transfer(0, 1);
+    assert!(!transfer(1, 1).validate(&db.read().unwrap()), "A nonce can't be skipped");
+    first.execute(&mut db.write().unwrap()).unwrap();
+    assert_eq!(db.read().unwrap().nonce(&account1.public_key), 1);
+    assert!(first.execute(&mut db.write().unwrap()).is_err(), "A transaction can't be replayed");
+
+    let preflighted = Mempool::new().with_preflight(Arc::clone(&db));
+    assert_eq!(preflighted.send_raw_transaction(transfer(0, 5)), Err("Nonce already used"));
+    assert!(preflighted.send_raw_transaction(transfer(2, 1)).is_ok(), "A later nonce can wait on an earlier one");
+
+    // A later nonce bidding more is still built after the one it follows
+    let (second, third) = (transfer(1, 1), transfer(2, 9));
+    mempool.read().unwrap().send_transaction(third.clone()).unwrap();
+    mempool.read().unwrap().send_transaction(second.clone()).unwrap();
+    let builder = validator1.builder.clone().with_config(ChainConfig { max_transactions_per_block: 2, ..Default::default() });
+    let block = builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("Both transfers should be built");
+    assert_eq!(block.transactions, vec![second, third], "A signer's transactions should run in nonce order");
+    assert!(builder.validate_block(&block).is_ok());
+}
+
+#[test]
+fn test_mempool_rejects_duplicates() {
+    let db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let mempool = Mempool::new();
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 100, 0);
+    tx.sign(&account1);
+    let tx = Transaction::from(tx);
+
+    assert!(mempool.send_transaction(tx.clone()).is_ok(), "First submission should be admitted");
+    assert_eq!(mempool.send_transaction(tx.clone()), Err("Transaction already in mempool"), "Exact resubmission should be rejected");
+    assert_eq!(mempool.pool.len(), 1, "Duplicate shouldn't take a second slot");
+    assert!(mempool.contains(&tx));
+
+    let mut other = TransferTransaction::new(account2.public_key, account1.public_key, 100, 1);
+    other.sign(&account1);
+    assert_ne!(Transaction::from(other).hash(), tx.hash(), "Distinct transactions should hash differently");
+
+    mempool.remove_included(std::slice::from_ref(&tx));
+    assert!(!mempool.contains(&tx) && mempool.pool.is_empty(), "Included transaction should leave every index");
+    assert!(mempool.send_transaction(tx).is_ok(), "Hash index should forget removed transactions");
+}
+
+#[test]
+fn test_mempool_preflight() {
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+    let mempool = Arc::new(RwLock::new(Mempool::new().with_preflight(Arc::clone(&db))));
+    let transfer = |amt: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    assert!(mempool.read().unwrap().send_transaction(transfer(60, 0)).is_ok());
+    let overdraft = transfer(1000, 1);
+    assert_eq!(mempool.read().unwrap().send_transaction(overdraft.clone()), Err("Transaction fails preflight against current state"));
+    assert!(mempool.read().unwrap().send_raw_transaction(overdraft).is_ok(), "Raw submissions only need valid signatures");
+    assert_eq!(mempool.read().unwrap().pool.len(), 2);
+
+    let builder = BlockBuilder::new(Arc::clone(&mempool), db, Arc::new(RwLock::new(Blockchain::new())));
+    let rpc = RpcServer::new(builder);
+    let overdraft = hex::encode(transfer(1000, 2).to_bytes());
+    let response = rpc.handle(rpc_request("sendTransaction", serde_json::json!([overdraft])));
+    assert_eq!(response.error.map(|e| e.code), Some(SERVER_ERROR));
+    let response = rpc.handle(rpc_request("sendTransaction", serde_json::json!([overdraft, { "skipPreflight": true }])));
+    assert!(response.error.is_none(), "skipPreflight should admit it anyway");
+    assert_eq!(mempool.read().unwrap().pool.len(), 3);
+}
+
+#[test]
+fn test_mempool_drain_for_block() {
+    let db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let mempool = Arc::new(Mempool::new());
+
+    let transfer = |nonce: u64, fee: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 1, nonce).with_fee(fee);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    let (low, high, mid) = (transfer(0, 1), transfer(1, 10), transfer(2, 5));
+    for tx in [low, high.clone(), mid.clone()] {
+        mempool.send_transaction(tx).unwrap();
+    }
+
+    assert_eq!(mempool.drain_for_block(2, usize::MAX), vec![high.clone(), mid.clone()], "Drain should take the highest-fee transactions");
+    assert_eq!(mempool.pool.len(), 1, "Drained transactions should leave the pool");
+    assert!(!mempool.contains(&high), "Drained transactions should leave the indexes");
+
+    mempool.requeue(vec![high.clone(), mid.clone()]);
+    assert_eq!(mempool.pool.len(), 3, "Requeued transactions should be pending again");
+
+    // The highest bid doesn't fit, so it's passed over for the smaller transactions behind it
+    let mut memo = MemoTransaction::new(account1.public_key, vec![0; MAX_MEMO_BYTES], 3).with_fee(100);
+    memo.sign(&account1);
+    let oversized = Transaction::from(memo);
+    mempool.send_transaction(oversized.clone()).unwrap();
+    let room = high.to_bytes().len() + mid.to_bytes().len();
+    assert!(oversized.to_bytes().len() > room);
+    assert_eq!(mempool.drain_for_block(3, room), vec![high, mid], "Transactions that fit should still be drained");
+    assert!(mempool.contains(&oversized), "The transaction that didn't fit should stay pending");
+
+    let capped = Mempool::new().with_max_block_bytes(Block::overhead() + room);
+    assert_eq!(capped.send_transaction(oversized), Err("Transaction is too large for a block"), "A transaction no block could hold shouldn't be admitted");
+
+    // Submissions racing a drain are either drained or left pending, never lost or duplicated
+    mempool.clear();
+    let transactions: Vec<Transaction> = (0..200).map(|nonce| transfer(nonce, nonce)).collect();
+    let submitter = {
+        let mempool = Arc::clone(&mempool);
+        let transactions = transactions.clone();
+        thread::spawn(move || {
+            for tx in transactions {
+                mempool.send_transaction(tx).unwrap();
+            }
+        })
+    };
+    let mut drained = vec![];
+    while !submitter.is_finished() {
+        drained.extend(mempool.drain_for_block(7, usize::MAX));
+    }
+    submitter.join().unwrap();
+    drained.extend(mempool.drain_for_block(usize::MAX, usize::MAX));
+
+    assert!(mempool.pool.is_empty(), "Everything should have been drained");
+    drained.sort_by_key(|tx| tx.nonce());
+    assert_eq!(drained, transactions, "Every submission should be drained exactly once");
+}
+
+#[test]
+fn test_configurable_block_capacity() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let builder = validator1.builder.clone().with_config(ChainConfig { max_transactions_per_block: 3, ..Default::default() });
+
+    let transactions: Vec<Transaction> = (0..4)
+        .map(|nonce| {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+            tx.sign(&account1);
+            Transaction::from(tx)
+        })
+        .collect();
+
+    mempool.read().unwrap().send_transaction(transactions[0].clone()).unwrap();
+    mempool.read().unwrap().send_transaction(transactions[1].clone()).unwrap();
+    assert!(builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().is_none(), "Builder should wait for a full block");
+
+    mempool.read().unwrap().send_transaction(transactions[2].clone()).unwrap();
+    mempool.read().unwrap().send_transaction(transactions[3].clone()).unwrap();
+    let block = builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().unwrap();
+    assert_eq!(block.transactions.len(), 3, "Block should be packed to the configured capacity");
+    assert_eq!(mempool.read().unwrap().pool.len(), 1, "Overflow should stay in the mempool");
+    assert!(builder.validate_block(&block).is_ok());
+
+    let oversized = Block::extending(&Block::create_genesis(), transactions.clone()).with_proposer(&validator1.wallet);
+    assert_eq!(builder.validate_block(&oversized), Err("Block has too many transactions"));
+
+    let config: NodeConfig = toml::from_str("[chain]\nmax_transactions_per_block = 64").unwrap();
+    assert_eq!(config.chain.max_transactions_per_block, 64, "Block capacity should be settable from the node config");
+}
+
+#[test]
+fn test_partial_block_timeout() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let config = ChainConfig { max_transactions_per_block: 4, partial_block_timeout_ms: Some(50), ..Default::default() };
+    let builder = validator1.builder.clone().with_config(config);
+
+    assert!(builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().is_none(), "An empty mempool has nothing to propose");
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    tx.sign(&account1);
+    mempool.read().unwrap().send_transaction(Transaction::from(tx)).unwrap();
+
+    assert!(builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().is_none(), "A partial block should wait out the timeout");
+    thread::sleep(Duration::from_millis(60));
+    let block = builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("A partial block should be built after the timeout");
+    assert_eq!(block.transactions, vec![Transaction::from(tx)]);
+    assert!(mempool.read().unwrap().pool.is_empty());
+
+    // The clock restarts for the next partial block
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, 1);
+    tx.sign(&account1);
+    mempool.read().unwrap().send_transaction(Transaction::from(tx)).unwrap();
+    assert!(builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().is_none(), "Timeout should restart after a block is built");
+}
+
+#[test]
+fn test_block_size_limit() {
+    let (validator1, validator2, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+
+    let transactions: Vec<Transaction> = (0..4)
+        .map(|nonce| {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+            tx.sign(&account1);
+            Transaction::from(tx)
+        })
+        .collect();
+    let tx_size = transactions[0].to_bytes().len();
+
+    // Room for exactly two transactions, though the count limit would allow four
+    let config = ChainConfig { max_transactions_per_block: 4, max_block_bytes: Block::overhead() + 2 * tx_size + 1, ..Default::default() };
+    let builder = validator1.builder.clone().with_config(config);
+    for tx in &transactions {
+        mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    }
+
+    let block = builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("Builder should pack what fits");
+    assert_eq!(block.transactions.len(), 2, "Builder should stop before exceeding the byte limit");
+    assert_eq!(block.size as usize, block.to_bytes().len(), "Block should record its serialized size");
+    assert!(block.size as usize <= config.max_block_bytes);
+    assert!(builder.validate_block(&block).is_ok());
+    assert_eq!(mempool.read().unwrap().pool.len(), 2, "Transactions that don't fit should stay pending");
+
+    let oversized = Block::extending(&Block::create_genesis(), transactions.clone()).with_proposer(&validator1.wallet);
+    assert_eq!(builder.validate_block(&oversized), Err("Block exceeds the maximum size"));
+
+    let mut lying = block.clone();
+    lying.size -= 1;
+    assert_eq!(builder.validate_block(&lying), Err("Block size does not match its encoding"));
+    assert!(Block::from_bytes(&lying.to_bytes()).is_err(), "A misreported size should fail to decode");
+
+    // The parent's certificate rides in the header, so it comes out of the same budget
+    let genesis = Block::create_genesis();
+    let votes = vec![Vote::new(genesis.hash, 0, &validator1.wallet), Vote::new(genesis.hash, 0, &validator2.wallet)];
+    let certificate = Quorum::aggregate(genesis.hash, 0, votes, &db.read().unwrap()).unwrap().certificate(&db.read().unwrap()).unwrap();
+    let header = Block::extending(&genesis, vec![]).with_certificate(Some(certificate.clone())).encoded_len();
+    validator1.builder.chain.write().unwrap().add_certificate(certificate);
+    mempool.read().unwrap().clear();
+    for tx in &transactions {
+        mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    }
+    let builder = validator1.builder.clone().with_config(ChainConfig { max_block_bytes: header + 2 * tx_size + 1, ..config });
+    let certified = builder.build(genesis.hash, &validator1.wallet).unwrap().expect("Builder should pack what fits");
+    assert!(certified.header.certificate.is_some());
+    assert_eq!(certified.transactions.len(), 2, "The certificate should leave room for just two transactions");
+    assert!(builder.validate_block(&certified).is_ok(), "A full block should pass our own size check");
+}
+
+#[test]
+fn test_compute_budget() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+
+    let transactions: Vec<Transaction> = (0..4)
+        .map(|nonce| {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+            tx.sign(&account1);
+            Transaction::from(tx)
+        })
+        .collect();
+    let units = transactions[0].compute_units();
+    assert_eq!(units, SIGNATURE_UNITS + 2 * ACCOUNT_UNITS + transactions[0].to_bytes().len() as u64 * BYTE_UNITS);
+
+    // Room for two transactions, though the count & byte limits would allow four
+    let config = ChainConfig { max_transactions_per_block: 4, max_block_compute_units: 2 * units + 1, ..Default::default() };
+    let builder = validator1.builder.clone().with_config(config);
+    for tx in &transactions {
+        mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    }
+
+    let block = builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("Builder should pack what fits");
+    assert_eq!(block.transactions.len(), 2, "Builder should stop before exceeding the compute budget");
+    assert!(builder.validate_block(&block).is_ok());
+    assert_eq!(mempool.read().unwrap().pool.len(), 2, "Transactions over budget should stay pending");
+
+    let over_budget = Block::extending(&Block::create_genesis(), transactions).with_proposer(&validator1.wallet);
+    assert_eq!(builder.validate_block(&over_budget), Err("Block exceeds the compute budget"));
+    let mut db_lock = db.write().unwrap();
+    db_lock.max_block_compute_units = config.max_block_compute_units;
+    assert_eq!(db_lock.finalize_block(&over_budget), Err("Block exceeds the compute budget"));
+
+    let receipts = db_lock.finalize_block(&block).unwrap();
+    assert!(receipts.iter().all(|receipt| receipt.compute_units == units), "Receipts should report the units consumed");
+
+    let mut greedy = InvokeTransaction::new(account1.public_key, account2.public_key, vec![], vec![], MAX_TRANSACTION_UNITS, 0);
+    greedy.sign(&account1);
+    assert!(Transaction::from(greedy).compute_units() > MAX_TRANSACTION_UNITS);
+}
+
+#[test]
+fn test_base_fee() {
+    assert_eq!(next_base_fee(0, 100, 100), 1, "A full block should lift even a zero base fee");
+    assert_eq!(next_base_fee(80, 100, 100), 90);
+    assert_eq!(next_base_fee(80, 50, 100), 80);
+    assert_eq!(next_base_fee(80, 0, 100), 70);
+    assert_eq!(next_base_fee(5, 40, 100), 4, "A small fee should still fall");
+    assert_eq!(next_base_fee(1, 0, 100), 0, "The fee should get back down to zero");
+    assert_eq!(next_base_fee(0, 0, 100), 0);
+
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let proposer = Wallet::generate();
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.mint(100);
+    db.base_fee = 2;
+
+    let transfer = |fee: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce).with_fee(fee);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    assert!(!transfer(1, 0).validate(&db), "Transactions must bid at least the base fee");
+    db.faucet = Some(account1.public_key);
+    let mut airdrop = AirdropTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    airdrop.sign(&account1);
+    assert!(Transaction::from(airdrop).validate_state(&db), "Airdrops should be exempt from the base fee");
+    db.faucet = None;
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![transfer(5, 0)]).with_proposer(&proposer);
+    db.max_block_compute_units = block1.compute_units();
+    let (undo, _) = db.finalize_block_with_undo(&block1).expect("Block should execute");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 85);
+    assert_eq!(db.get_account(&proposer.public_key).unwrap().balance, 3, "The tip should go to the proposer");
+    assert_eq!(db.total_supply, 98, "The base fee should be burned");
+    assert_eq!(db.base_fee, 3, "A full block should raise the base fee");
+
+    db.revert_block(undo);
+    assert_eq!((db.base_fee, db.total_supply), (2, 100));
+    assert!(db.get_account(&proposer.public_key).is_none());
+
+    // A block naming a proposer who didn't sign it pays that proposer nothing
+    let mut forged = Block::extending(&Block::create_genesis(), vec![transfer(5, 0)]);
+    forged.header.proposer = proposer.public_key;
+    db.finalize_block(&forged).expect("Block should execute");
+    assert!(db.get_account(&proposer.public_key).is_none(), "Tips shouldn't go to a proposer who didn't sign");
+    assert_eq!(db.total_supply, 95, "Unclaimed tips should be burned with the base fee");
+}
+
+#[test]
+fn test_priority_tips() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let senders: Vec<Wallet> = (0..3).map(|_| Wallet::generate()).collect();
+    let recipient = Wallet::generate();
+    {
+        let mut db_lock = db.write().unwrap();
+        db_lock.add_account(recipient.public_key, recipient.account());
+        for sender in &senders {
+            db_lock.add_account(sender.public_key, sender.account());
+            let _ = db_lock.increase_account_balance(&sender.public_key, 100);
+        }
+        db_lock.mint(300);
+    }
+
+    // Same fee from everyone, so only the tips tell them apart
+    let tipped = |sender: &Wallet, tip: u64| {
+        let transfer = TransferTransaction::new(recipient.public_key, sender.public_key, 10, 0).with_fee(1);
+        let mut tx = Transaction::tipped(TransactionBody::Transfer(transfer), tip);
+        tx.sign(sender);
+        tx
+    };
+    let transactions = vec![tipped(&senders[0], 0), tipped(&senders[1], 5), tipped(&senders[2], 3)];
+    assert_eq!(transactions[1].to_bytes()[0], TRANSACTION_V3);
+    assert_eq!(Transaction::from_bytes(&transactions[1].to_bytes()), Ok(transactions[1].clone()));
+    assert_eq!(transactions[1].bid(), 6);
+    assert!(!tipped(&senders[0], 95).validate(&db.read().unwrap()), "The tip has to be paid on top of the transfer & fee");
+    let mut retipped = transactions[1].clone();
+    if let Transaction::V3(inner) = &mut retipped {
+        inner.tip = 1;
+    }
+    assert!(!retipped.validate(&db.read().unwrap()), "The tip is part of the signed payload");
+
+    for tx in &transactions {
+        mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    }
+    let block = validator1.builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("Builder should fill a block");
+    assert_eq!(block.transactions, vec![transactions[1].clone(), transactions[2].clone()], "Higher tips should land first");
+    assert_eq!(mempool.read().unwrap().pool.len(), 1, "The lowest tip should wait for the next block");
+
+    let mut db_lock = db.write().unwrap();
+    db_lock.finalize_block(&block).expect("Tipped transactions should execute");
+    assert_eq!(db_lock.get_account(&senders[1].public_key).unwrap().balance, 84);
+    assert_eq!(db_lock.get_account(&senders[2].public_key).unwrap().balance, 86);
+    // Fees & tips alike go to the proposer while there's no base fee
+    assert_eq!(db_lock.get_account(&validator1.wallet.public_key).unwrap().balance, 10);
+    assert_eq!(db_lock.total_supply, 300);
+}
+
+#[test]
+fn test_parallel_transaction_validation() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let transfer = |amt: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    let mut forged = transfer(10, 1);
+    if let Transaction::Legacy(TransactionBody::Transfer(tx)) = &mut forged {
+        tx.amt = 20;
+    }
+    let mut overspend = TransferTransaction::new(account1.public_key, account2.public_key, 1000, 0);
+    overspend.sign(&account2);
+    // Valid, forged signature, valid, more than the balance
+    let transactions = vec![transfer(10, 0), forged, transfer(10, 1), Transaction::from(overspend)];
+
+    let errors = BlockBuilder::validate_transactions(&transactions, &db.read().unwrap());
+    assert_eq!(errors, vec![(1, "Invalid transaction signature"), (3, "Invalid transaction state")], "Every failure should be reported");
+
+    let config = ChainConfig { max_transactions_per_block: 4, partial_block_timeout_ms: Some(0), ..Default::default() };
+    let builder = validator1.builder.clone().with_config(config);
+    assert_eq!(builder.validate_block(&Block::extending(&Block::create_genesis(), transactions.clone()).with_proposer(&validator1.wallet)), Err("Invalid transaction signature"));
+
+    // The mempool only admits validly signed transactions, so the forgery never reaches the builder
+    let mempool_lock = mempool.read().unwrap();
+    for tx in [transactions[0].clone(), transactions[2].clone(), transactions[3].clone()] {
+        mempool_lock.send_transaction(tx).unwrap();
+    }
+    drop(mempool_lock);
+
+    let block = builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("Valid transactions should still be built");
+    assert_eq!(block.transactions.len(), 2, "The overspend should be dropped");
+    assert!(!block.transactions.contains(&transactions[3]));
+    assert!(mempool.read().unwrap().pool.is_empty(), "Dropped transactions shouldn't be requeued");
+}
+
+#[test]
+fn test_parallel_execution() {
+    let db = AccountsDB::new();
+    let wallets: Vec<Wallet> = (0..4).map(|_| Wallet::generate()).collect();
+    for wallet in &wallets {
+        db.add_account(wallet.public_key, wallet.account());
+        db.increase_account_balance(&wallet.public_key, 1000).unwrap();
+    }
+
+    let transfer = |from: usize, to: usize, amt: u64, fee: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(wallets[to].public_key, wallets[from].public_key, amt, nonce).with_fee(fee);
+        tx.sign(&wallets[from]);
+        Transaction::from(tx)
+    };
+    let transactions = vec![
+        transfer(0, 1, 100, 1, 0),
+        transfer(2, 3, 100, 2, 0),
+        // Spends what the first transfer credited, so it has to run after it
+        transfer(1, 2, 1050, 3, 0),
+        transfer(3, 0, 10, 4, 0),
+    ];
+
+    assert_eq!(schedule(&transactions), vec![vec![0, 1], vec![2, 3]], "Transactions sharing an account should be in later batches");
+
+    let mut sequential = db.clone();
+    let mut parallel = db.clone();
+    execute_sequential(&transactions, &mut sequential).unwrap();
+    execute_parallel(&transactions, &mut parallel).unwrap();
+
+    for wallet in &wallets {
+        assert_eq!(
+            sequential.get_account(&wallet.public_key).unwrap().balance,
+            parallel.get_account(&wallet.public_key).unwrap().balance,
+            "Parallel execution should match sequential execution"
+        );
+    }
+    assert_eq!(parallel.get_account(&wallets[1].public_key).unwrap().balance, 47);
+    assert_eq!(sequential.total_supply, parallel.total_supply);
+}
+
+#[test]
+fn test_builder_prevents_same_block_overspend() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let transfer = |amt: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    // Each fits the balance on its own, both together don't
+    let (first, second) = (transfer(70, 0), transfer(60, 1));
+
+    let overspend = Block::extending(&Block::create_genesis(), vec![first.clone(), second.clone()]).with_proposer(&validator1.wallet);
+    assert_eq!(validator1.builder.validate_block(&overspend), Err("Block overspends an account"));
+
+    mempool.read().unwrap().send_transaction(first.clone()).unwrap();
+    // Its nonce is only reached once the first has run, so preflight would refuse it
+    mempool.read().unwrap().send_raw_transaction(second.clone()).unwrap();
+
+    let block = validator1.builder.build(Block::create_genesis().hash, &validator1.wallet).unwrap().expect("The first transfer should still be built");
+    assert_eq!(block.transactions, vec![first], "Only a consistent set of transactions should be packed");
+    assert!(validator1.builder.validate_block(&block).is_ok());
+    assert!(mempool.read().unwrap().contains(&second), "The transaction that didn't fit should be put back");
+
+    // Spending the whole balance is consistent too
+    let exact = Block::extending(&Block::create_genesis(), vec![transfer(100, 0)]).with_state_root(db.read().unwrap().state_root()).with_proposer(&validator1.wallet);
+    assert!(validator1.builder.validate_block(&exact).is_ok());
+    db.write().unwrap().finalize_block(&exact).unwrap();
+    assert_eq!(db.read().unwrap().get_account(&account1.public_key).unwrap().balance, 0);
+}
+
+#[test]
+fn test_transaction_merkle_proofs() {
+    let (account1, account2) = (Wallet::generate(), Wallet::generate());
+    // An odd count exercises the unpaired node at the end of each level
+    let transactions: Vec<Transaction> = (0..5u64)
+        .map(|nonce| {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+            tx.sign(&account1);
+            Transaction::from(tx)
+        })
+        .collect();
+    let block = Block::extending(&Block::create_genesis(), transactions.clone());
+    assert!(block.is_consistent());
+    assert_eq!(block.hash, block.header.hash(), "The block hash should be the header hash");
+
+    // A light client holding only the header can check any transaction's inclusion
+    let header = block.header.clone();
+    for (index, tx) in transactions.iter().enumerate() {
+        let proof = block.prove_transaction(index).expect("Every transaction should have a proof");
+        assert!(header.verify_transaction(tx, &proof), "Proof {} should verify", index);
+        assert!(!header.verify_transaction(&transactions[(index + 1) % 5], &proof), "A proof shouldn't verify another transaction");
+    }
+    assert!(block.prove_transaction(5).is_none());
+
+    let mut wrong_index = block.prove_transaction(1).unwrap();
+    wrong_index.index = 0;
+    assert!(!header.verify_transaction(&transactions[1], &wrong_index), "A proof should be bound to its position");
+
+    assert_eq!(merkle_root(&[]), [0; 32]);
+    let single = MerkleProof::generate(&[transactions[0].leaf()], 0).unwrap();
+    assert!(single.verify(&transactions[0].leaf(), &merkle_root(&[transactions[0].leaf()])));
+
+    // Swapping the body out breaks the header's commitment
+    let mut swapped = block.clone();
+    swapped.transactions.pop();
+    assert!(!swapped.is_consistent(), "A body that doesn't match the transaction root should be rejected");
+}
+
+#[test]
+fn test_block_account_bloom() {
+    let (alice, bob) = (Wallet::generate(), Wallet::generate());
+    let mut tx = TransferTransaction::new(bob.public_key, alice.public_key, 10, 0);
+    tx.sign(&alice);
+    let block = Block::extending(&Block::create_genesis(), vec![Transaction::from(tx)]);
+    assert!(block.header.may_touch(&alice.public_key) && block.header.may_touch(&bob.public_key));
+    assert!(!Block::create_genesis().header.may_touch(&alice.public_key), "An empty block touches nothing");
+
+    // Keys the block doesn't touch should almost all be ruled out
+    let strangers: Vec<Pubkey> = (0..1000).map(|_| Wallet::generate().public_key).collect();
+    let false_positives = strangers.iter().filter(|pubkey| block.header.may_touch(pubkey)).count();
+    assert!(false_positives < 10, "{} false positives out of 1000", false_positives);
+    let mut crowded = AccountBloom::default();
+    for pubkey in &strangers[..200] {
+        crowded.insert(pubkey);
+    }
+    assert!(strangers[..200].iter().all(|pubkey| crowded.contains(pubkey)), "A bloom filter has no false negatives");
+
+    // The header commits to the filter, so it can't be doctored to hide an account
+    let mut doctored = block.clone();
+    doctored.header.account_bloom = AccountBloom::default();
+    assert!(!doctored.is_consistent());
+}
+
+#[test]
+fn test_proposer_signature() {
+    let (validator1, validator2, db, _) = setup_validators();
+    let (state_root, epoch_seed) = (db.read().unwrap().state_root(), db.read().unwrap().epoch_seed);
+    let unsigned = Block::extending(&Block::create_genesis(), vec![]).with_state_root(state_root);
+
+    let leader = validator1.builder.get_leader(1).unwrap();
+    let block = unsigned.clone().with_leader_proof(&leader, &epoch_seed).with_proposer(&leader);
+    assert_eq!(block.header.proposer, leader.public_key);
+    assert!(block.verify_proposer(), "The proposer's signature should verify");
+    assert!(validator1.builder.validate_block(&block).is_ok());
+    assert!(validator2.prevote(&block).is_some(), "Validators should vote for a block signed by a validator");
+
+    assert_eq!(validator1.builder.validate_block(&unsigned), Err("Invalid proposer signature"), "Anonymous blocks should be rejected");
+
+    // Claiming someone else proposed it changes the hash, which the original signature doesn't cover
+    let mut impersonated = block.clone();
+    impersonated.header.proposer = [validator1.wallet.public_key, validator2.wallet.public_key].into_iter().find(|key| *key != leader.public_key).unwrap();
+    impersonated.hash = impersonated.header.hash();
+    assert!(!impersonated.verify_proposer());
+    assert_eq!(validator1.builder.validate_block(&impersonated), Err("Invalid proposer signature"));
+
+    // A correctly signed block from outside the validator set doesn't get votes
+    let outsider = unsigned.with_proposer(&Wallet::generate());
+    assert!(validator1.builder.validate_block(&outsider).is_ok());
+    assert!(validator2.prevote(&outsider).is_none(), "Only validators may propose");
+}
+
+#[test]
+fn test_block_height_and_slot() {
+    let (validator1, _v, db, _) = setup_validators();
+    let builder = &validator1.builder;
+    let state_root = db.read().unwrap().state_root();
+    let genesis = builder.chain.read().unwrap().tip().clone();
+    let propose = |block: Block| block.with_state_root(state_root).with_proposer(&validator1.wallet);
+
+    let block = propose(Block::extending(&genesis, vec![]));
+    assert_eq!((block.height(), block.slot()), (1, 1), "Height & slot should increment from the parent");
+    assert!(builder.validate_block(&block).is_ok());
+
+    // Skipped slots are fine, going back in time or repeating one isn't
+    assert!(builder.validate_block(&propose(Block::extending(&genesis, vec![]).with_slot(5))).is_ok());
+    assert_eq!(builder.validate_block(&propose(Block::extending(&genesis, vec![]).with_slot(0))), Err("Block slot does not follow its parent"));
+    assert_eq!(builder.validate_block(&propose(Block::extending(&genesis, vec![]).with_height(2))), Err("Block height does not follow its parent"));
+
+    builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+    assert_eq!(builder.chain.read().unwrap().height(), 1);
+
+    let stale = propose(Block::extending(&genesis, vec![]));
+    assert_eq!(builder.validate_block(&stale), Err("Block does not extend the tip"));
+    let next = propose(Block::extending(&block, vec![]));
+    assert_eq!((next.height(), next.slot()), (2, 2));
+    assert!(builder.validate_block(&next).is_ok());
+
+    let mut chain = builder.chain.write().unwrap();
+    assert_eq!(chain.insert(Block::extending(&block, vec![]).with_slot(1)), Err("Block slot does not follow its parent"));
+}
+
+#[test]
+fn test_deterministic_block_hash() {
+    let (validator1, _v, db, _) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    tx.sign(&account1);
+
+    // Two validators building the same block at different moments agree on it byte for byte
+    let genesis = Block::create_genesis();
+    let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let build = || Block::extending(&genesis, vec![Transaction::from(tx)]).with_timestamp(timestamp).with_proposer(&validator1.wallet);
+    let first = build();
+    thread::sleep(Duration::from_millis(5));
+    let second = build();
+    assert_eq!(first.hash, second.hash, "Hashing shouldn't depend on when the block was built");
+    assert_eq!(first.to_bytes(), second.to_bytes());
+
+    // The proposer's timestamp & the slot are both committed to
+    assert_ne!(build().with_timestamp(timestamp + Duration::from_millis(1)).hash, first.hash);
+    assert_ne!(build().with_slot(2).hash, first.hash);
+    assert_eq!(Block::from_bytes(&first.to_bytes()).unwrap().header.timestamp, timestamp);
+
+    // Each moment has the one encoding, with under a second of nanoseconds
+    let overflowing = [&u64::MAX.to_le_bytes()[..], &1_000_000_000u32.to_le_bytes()].concat();
+    assert_eq!(crate::wire::deserialize_timestamp(&mut &overflowing[..]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    let carried = [&1_699_999_999u64.to_le_bytes()[..], &1_000_000_000u32.to_le_bytes()].concat();
+    assert!(crate::wire::deserialize_timestamp(&mut &carried[..]).is_err(), "Nanoseconds shouldn't carry into the seconds");
+
+    // A block can't claim to be older than its parent
+    let state_root = db.read().unwrap().state_root();
+    let parent = Block::extending(&genesis, vec![]).with_timestamp(timestamp).with_proposer(&validator1.wallet);
+    validator1.builder.chain.write().unwrap().apply(parent.clone(), &mut db.write().unwrap()).unwrap();
+    let propose = |timestamp| Block::extending(&parent, vec![]).with_timestamp(timestamp).with_state_root(state_root).with_proposer(&validator1.wallet);
+    assert_eq!(validator1.builder.validate_block(&propose(timestamp - Duration::from_secs(1))), Err("Block timestamp is before its parent"));
+    assert!(validator1.builder.validate_block(&propose(timestamp)).is_ok());
+}
+
+#[test]
+fn test_chain_verification() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let genesis_state = db.read().unwrap().clone();
+    let builder = &validator1.builder;
+
+    for nonce in [0, 2] {
+        for nonce in [nonce, nonce + 1] {
+            let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 100, nonce);
+            tx.sign(&account1);
+            mempool.read().unwrap().send_transaction(Transaction::from(tx)).unwrap();
+        }
+        let tip = builder.chain.read().unwrap().tip().hash;
+        let block = builder.build(tip, &validator1.wallet).unwrap().unwrap();
+        builder.chain.write().unwrap().apply(block, &mut db.write().unwrap()).unwrap();
+    }
+
+    let chain = builder.chain.read().unwrap().clone();
+    assert_eq!(chain.height(), 2);
+    assert_eq!(chain.verify(&genesis_state), Ok(()), "A chain built by the builder should verify");
+    assert_eq!(
+        chain.verify(&AccountsDB::new()),
+        Err((1, "Block state root does not match replayed state")),
+        "Replaying from the wrong genesis state should be caught at the first block"
+    );
+
+    // Chain storage doesn't check proposers, verification does
+    let unsigned = Block::extending(chain.tip(), vec![])
+        .with_timestamp(chain.tip().header.timestamp)
+        .with_state_root(db.read().unwrap().state_root());
+    let mut chain = chain;
+    chain.apply(unsigned, &mut db.write().unwrap()).unwrap();
+    assert_eq!(chain.verify(&genesis_state), Err((3, "Invalid proposer signature")));
+}
+
+#[test]
+fn test_genesis_config() {
+    let (alice, bob, validator) = (Wallet::generate(), Wallet::generate(), Wallet::generate());
+    let config: GenesisConfig = toml::from_str(&format!(r#"
+        timestamp = 1700000000
+
+        [[accounts]]
+        address = "{}"
+        balance = 1000
+
+        [[accounts]]
+        address = "{}"
+        balance = 500
+
+        [[validators]]
+        address = "{}"
+        stake = 250
+
+        [chain]
+        max_transactions_per_block = 8
+    "#, alice.address, bob.address, validator.address)).expect("Genesis config should parse");
+
+    let (chain, db) = Blockchain::from_genesis(&config).expect("Genesis state should build");
+    assert_eq!(db.get_account(&alice.public_key).unwrap().balance, 1000);
+    assert_eq!(db.get_account(&bob.public_key).unwrap().balance, 500);
+    assert_eq!(db.get_validator(&validator.public_key).unwrap().stake, 250);
+    assert_eq!(db.total_supply, 1750, "Genesis balances & stakes should make up the initial supply");
+    assert_eq!(config.chain.max_transactions_per_block, 8);
+
+    // The genesis block follows from the config alone, & commits to the state it describes
+    let genesis = chain.tip();
+    assert_eq!(genesis.height(), 0);
+    assert_eq!(genesis.header.state_root, db.state_root());
+    assert_eq!(genesis.hash, Blockchain::from_genesis(&config).unwrap().0.tip().hash, "Genesis hash should be deterministic");
+    assert_ne!(genesis.hash, Blockchain::new().tip().hash, "Different configs should give different genesis blocks");
+    assert!(Block::from_bytes(&genesis.to_bytes()).is_ok(), "Genesis should decode like any other block");
+
+    let mut richer = config.clone();
+    richer.accounts[0].balance += 1;
+    assert_ne!(Blockchain::from_genesis(&richer).unwrap().0.tip().hash, genesis.hash, "Genesis hash should cover balances");
+
+    let mut duplicate = config.clone();
+    duplicate.accounts.push(duplicate.accounts[0].clone());
+    assert_eq!(AccountsDB::from_genesis(&duplicate).err(), Some("Duplicate genesis account"));
+}
+
+#[test]
+fn test_dev_airdrop() {
+    let (faucet, alice) = (Wallet::generate(), Wallet::generate());
+    let config = GenesisConfig { faucet: Some(faucet.address.clone()), ..GenesisConfig::default() };
+    let mut db = AccountsDB::from_genesis(&config).expect("Genesis state should build");
+
+    // Funds an account that doesn't exist yet, through the same execution path as any transaction
+    let mut airdrop = Transaction::from(AirdropTransaction::new(alice.public_key, faucet.public_key, 1000, 0));
+    airdrop.sign(&faucet);
+    assert!(airdrop.validate(&db), "Faucet-signed airdrop should be valid on a dev chain");
+    execute_parallel(std::slice::from_ref(&airdrop), &mut db).expect("Airdrop should execute");
+    assert_eq!(db.get_account(&alice.public_key).unwrap().balance, 1000);
+    assert_eq!(db.total_supply, 1000, "Airdrops should be counted in the supply");
+    assert!(!airdrop.validate(&db), "An airdrop shouldn't be replayable");
+    assert_eq!(db.nonce(&faucet.public_key), 1);
+
+    let mut forged = Transaction::from(AirdropTransaction::new(alice.public_key, alice.public_key, 1000, 0));
+    forged.sign(&alice);
+    assert!(!forged.validate(&db), "Only the faucet may sign airdrops");
+
+    let no_faucet = AccountsDB::from_genesis(&GenesisConfig::default()).unwrap();
+    assert!(!airdrop.validate(&no_faucet), "Airdrops should be invalid on a chain without a faucet");
+
+    // Over RPC the node signs with its faucet key & submits to the mempool
+    let (validator1, _v, _db, mempool) = setup_validators();
+    let request = rpc_request("requestAirdrop", serde_json::json!([alice.address, 500]));
+    let response = RpcServer::new(validator1.builder.clone()).handle(request.clone());
+    assert_eq!(response.error.map(|e| e.code), Some(SERVER_ERROR), "Airdrops should need a faucet");
+
+    let rpc = RpcServer::new(validator1.builder.clone()).with_faucet(faucet.clone());
+    assert!(rpc.handle(request.clone()).error.is_none(), "requestAirdrop should succeed");
+    assert!(rpc.handle(request).error.is_none(), "Repeated airdrops should get fresh nonces");
+    assert_eq!(mempool.read().unwrap().pool.len(), 2, "Airdrops should land in the mempool");
+}
+
+#[test]
+fn test_memo_transaction() {
+    let db = AccountsDB::new();
+    let (account1, _) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 10);
+
+    let memo = |data: &[u8], nonce| {
+        let mut tx = MemoTransaction::new(account1.public_key, data.to_vec(), nonce).with_fee(3);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    // The memo is part of the signed payload & the canonical encoding
+    let tx = memo(b"hello litechain", 0);
+    assert!(tx.validate(&db));
+    assert_eq!(Transaction::from_bytes(&tx.to_bytes()), Ok(tx.clone()), "Memo should round-trip through the encoding");
+    assert_ne!(memo(b"hello litechain!", 0).hash(), tx.hash(), "Different memos should hash differently");
+
+    let block = Block::extending(&Block::create_genesis(), vec![tx.clone()]);
+    let tampered = Block::extending(&Block::create_genesis(), vec![memo(b"jello litechain", 0)]);
+    assert_ne!(block.hash, tampered.hash, "Block hash should commit to the memo data");
+
+    let Transaction::Legacy(TransactionBody::Memo(mut forged)) = tx.clone() else { unreachable!() };
+    forged.memo[0] = b'j';
+    assert!(!Transaction::from(forged).validate(&db), "Altering the memo should invalidate the signature");
+
+    assert!(memo(&[0; MAX_MEMO_BYTES], 0).validate(&db));
+    assert!(!memo(&[0; MAX_MEMO_BYTES + 1], 0).validate(&db), "Oversized memos should be rejected");
+
+    // Only the fee moves
+    let mut db = db;
+    tx.execute(&mut db).unwrap();
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 7);
+}
+
+#[test]
+fn test_batch_transfer() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let account3 = Wallet::generate();
+    db.add_account(account3.public_key, account3.account());
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+
+    let batch = |transfers: Vec<(Pubkey, u64)>, nonce| {
+        let mut tx = BatchTransferTransaction::new(account1.public_key, transfers, nonce).with_fee(5);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    let payout = batch(vec![(account2.public_key, 30), (account3.public_key, 40)], 0);
+    assert!(payout.validate(&db));
+    execute_parallel(std::slice::from_ref(&payout), &mut db).expect("Payout should execute");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 25);
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 30);
+    assert_eq!(db.get_account(&account3.public_key).unwrap().balance, 40);
+
+    // All or nothing: a batch that can't pay everyone, or names a missing recipient, pays no one
+    let overspend = batch(vec![(account2.public_key, 10), (account3.public_key, 20)], 1);
+    assert!(overspend.execute(&mut db).is_err(), "Batch over the balance should fail");
+    let missing = batch(vec![(account2.public_key, 10), (Wallet::generate().public_key, 1)], 2);
+    assert!(missing.execute(&mut db).is_err(), "Batch to an unknown account should fail");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 25, "Failed batches shouldn't debit the sender");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 30, "Failed batches shouldn't credit anyone");
+
+    // Recipients are part of the schedule, so a batch conflicts with transfers to any of them
+    let mut transfer = TransferTransaction::new(account1.public_key, account3.public_key, 10, 0);
+    transfer.sign(&account3);
+    assert_eq!(schedule(&[payout, Transaction::from(transfer)]), vec![vec![0], vec![1]]);
+}
+
+#[test]
+fn test_multisig_transfer() {
+    let mut db = AccountsDB::new();
+    let (_, recipient) = setup_accounts(&db);
+    let (alice, bob, carol) = (Wallet::generate(), Wallet::generate(), Wallet::generate());
+
+    let multisig = MultisigAccount::new(2, vec![alice.public_key, bob.public_key, carol.public_key]);
+    assert!(multisig.is_valid());
+    assert!(!MultisigAccount::new(0, vec![alice.public_key]).is_valid(), "Threshold should be at least one");
+    assert!(!MultisigAccount::new(2, vec![alice.public_key, alice.public_key]).is_valid(), "Signers should be distinct");
+
+    let address = multisig.address();
+    db.add_account(address, UserAccount::from_public_key(address));
+    let _ = db.increase_account_balance(&address, 100);
+
+    let mut tx = MultisigTransferTransaction::new(multisig, recipient.public_key, 60, 0).with_fee(1);
+    tx.sign(&alice);
+    let partial = Transaction::from(tx.clone());
+    assert!(!partial.validate(&db), "One of two signatures shouldn't be enough");
+    assert!(Mempool::new().send_transaction(partial).is_err(), "Mempool should reject under-signed multisig transfers");
+
+    // Signing twice with the same key, or with a key outside the account, doesn't count
+    tx.sign(&alice);
+    tx.sign(&Wallet::generate());
+    assert!(!Transaction::from(tx.clone()).validate(&db));
+
+    tx.sign(&carol);
+    let signed = Transaction::from(tx);
+    assert!(signed.validate(&db), "Two of three signatures should authorize the transfer");
+    assert_eq!(signed.get_signer(), address);
+    assert!(Transaction::verify_batch(std::slice::from_ref(&signed)), "Batch verification should cover every signature");
+    assert_eq!(Transaction::from_bytes(&signed.to_bytes()), Ok(signed.clone()), "Signatures should round-trip through the encoding");
+
+    execute_parallel(std::slice::from_ref(&signed), &mut db).expect("Multisig transfer should execute");
+    assert_eq!(db.get_account(&address).unwrap().balance, 39);
+    assert_eq!(db.get_account(&recipient.public_key).unwrap().balance, 60);
+}
+
+#[test]
+fn test_versioned_transactions() {
+    let db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+
+    // Legacy transactions keep the exact bytes they had before versioning
+    let mut transfer = TransferTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    transfer.sign(&account1);
+    let legacy = Transaction::from(transfer);
+    assert_eq!(legacy.to_bytes(), borsh::to_vec(&TransactionBody::Transfer(transfer)).unwrap());
+    assert_eq!(Transaction::from_bytes(&legacy.to_bytes()), Ok(legacy.clone()));
+    assert!(legacy.validate(&db));
+
+    let mut v1 = Transaction::new(TransactionBody::Transfer(TransferTransaction::new(account2.public_key, account1.public_key, 10, 0)));
+    v1.sign(&account1);
+    assert_eq!(v1.to_bytes()[0], TRANSACTION_V1, "V1 encodings should lead with their version byte");
+    assert_eq!(Transaction::from_bytes(&v1.to_bytes()), Ok(v1.clone()));
+    assert!(v1.validate(&db));
+    assert_ne!(v1.hash(), legacy.hash());
+
+    // The version is signed, so a signature can't be carried across encodings
+    let relabeled = Transaction::V1(legacy.body().clone());
+    assert!(!relabeled.validate(&db), "Legacy signature shouldn't verify as V1");
+    assert!(!Transaction::verify_batch(&[v1.clone(), relabeled]));
+
+    let mut unknown = v1.to_bytes();
+    unknown[0] = 0x80 | 4;
+    assert!(Transaction::from_bytes(&unknown).is_err(), "Unknown versions should be rejected");
+}
+
+#[test]
+fn test_sponsored_fees() {
+    let mut db = AccountsDB::new();
+    let (user, recipient) = setup_accounts(&db);
+    let sponsor = Wallet::generate();
+    db.add_account(sponsor.public_key, sponsor.account());
+    let _ = db.increase_account_balance(&user.public_key, 10);
+    let _ = db.increase_account_balance(&sponsor.public_key, 100);
+    db.mint(110);
+
+    // The user can cover the amount but not the fee on top
+    let transfer = TransferTransaction::new(recipient.public_key, user.public_key, 10, 0).with_fee(5);
+    let mut unsponsored = Transaction::new(TransactionBody::Transfer(transfer));
+    unsponsored.sign(&user);
+    assert!(!unsponsored.validate(&db));
+
+    let mut sponsored = Transaction::sponsored(TransactionBody::Transfer(transfer), sponsor.public_key);
+    sponsored.sign(&user);
+    assert!(!sponsored.validate(&db), "The fee payer has to sign too");
+    sponsored.sign(&sponsor);
+    assert!(sponsored.validate(&db));
+    assert_eq!(sponsored.to_bytes()[0], TRANSACTION_V2);
+    assert_eq!(Transaction::from_bytes(&sponsored.to_bytes()), Ok(sponsored.clone()));
+    assert_eq!(sponsored.fee_payer(), sponsor.public_key);
+
+    let mut other_payer = sponsored.clone();
+    if let Transaction::V2(inner) = &mut other_payer {
+        inner.fee_payer = recipient.public_key;
+    }
+    assert!(!other_payer.validate(&db), "The payer is part of the signed payload");
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![sponsored]).with_proposer(&Wallet::generate());
+    db.finalize_block(&block1).expect("Sponsored transfer should execute");
+    assert_eq!(db.get_account(&user.public_key).unwrap().balance, 0);
+    assert_eq!(db.get_account(&recipient.public_key).unwrap().balance, 10);
+    assert_eq!(db.get_account(&sponsor.public_key).unwrap().balance, 95, "The sponsor should pay the fee");
+    // With no base fee the whole fee is a tip, which stays in the supply
+    assert_eq!(db.total_supply, 110);
+}
+
+#[test]
+fn test_on_chain_votes() {
+    let (validator1, validator2, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let chain = Arc::clone(&validator1.builder.chain);
+    // Without BLS keys their votes can't be aggregated, so they're recorded one by one
+    for mut validator in db.read().unwrap().validators.iter_mut() {
+        validator.bls_key = None;
+    }
+
+    let transfer = |nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    let vote = Vote::new(Block::create_genesis().hash, 0, &validator1.wallet);
+    assert!(mempool.read().unwrap().send_transaction(Transaction::from(vote)).is_err(), "Votes shouldn't enter the pool directly");
+
+    mempool.read().unwrap().send_transaction(transfer(0)).unwrap();
+    mempool.read().unwrap().send_transaction(transfer(1)).unwrap();
+    let handles = [validator1.start(Duration::from_millis(10)), validator2.start(Duration::from_millis(10))];
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 1), "First block should finalize");
+    let first = chain.read().unwrap().tip().hash;
+
+    // The votes that finalized it wait for the next block, rather than causing one
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(chain.read().unwrap().height(), 1, "Pending votes alone shouldn't produce a block");
+    assert!(!chain.read().unwrap().is_confirmed(&first, &db.read().unwrap()));
+
+    mempool.read().unwrap().send_transaction(transfer(2)).unwrap();
+    mempool.read().unwrap().send_transaction(transfer(3)).unwrap();
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 2), "Second block should finalize");
+    for handle in handles {
+        assert!(handle.join().is_ok());
+    }
+
+    let chain_lock = chain.read().unwrap();
+    let recorded = chain_lock.recorded_votes(&first);
+    assert_eq!(chain_lock.tip().transactions.len(), 4, "Votes should ride along without taking transaction slots");
+    assert_eq!(recorded.len(), 2, "Both validators' votes should be on chain");
+    assert!(recorded.iter().any(|vote| vote.validator == validator2.wallet.public_key));
+    assert!(chain_lock.is_confirmed(&first, &db.read().unwrap()), "Finality should be readable from the chain");
+}
+
+#[test]
+fn test_quorum_certificates() {
+    let (validator1, validator2, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let chain = Arc::clone(&validator1.builder.chain);
+
+    // Votes fold into one signature, which only checks out against all of its signers' keys
+    let db_lock = db.read().unwrap();
+    let hash = chain.read().unwrap().tip().hash;
+    let votes = vec![Vote::new(hash, 0, &validator1.wallet), Vote::new(hash, 0, &validator2.wallet)];
+    let certificate = Quorum::aggregate(hash, 0, votes, &db_lock).unwrap().certificate(&db_lock).unwrap();
+    assert_eq!(certificate.verify(&db_lock), Ok(2));
+    let mut dropped = certificate.clone();
+    dropped.signers.retain(|signer| *signer != validator2.wallet.public_key);
+    assert_eq!(dropped.verify(&db_lock), Err("Invalid aggregate signature"), "Every signer's key should be needed");
+    let mut moved = certificate.clone();
+    moved.slot = 1;
+    assert_eq!(moved.verify(&db_lock), Err("Invalid aggregate signature"), "A certificate shouldn't carry over to another slot");
+    let lone = Quorum { block_hash: hash, slot: 0, phase: VotePhase::Precommit, votes: vec![Vote::new(hash, 0, &validator1.wallet)], weight: 1 };
+    assert_eq!(lone.certificate(&db_lock), Err("Not enough votes for quorum"));
+    drop(db_lock);
+
+    // Finalized blocks' votes go into the next header instead of its transactions
+    let transfer = |nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    mempool.read().unwrap().send_transaction(transfer(0)).unwrap();
+    mempool.read().unwrap().send_transaction(transfer(1)).unwrap();
+    let handles = [validator1.start(Duration::from_millis(10)), validator2.start(Duration::from_millis(10))];
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 1), "First block should finalize");
+    let first = chain.read().unwrap().tip().hash;
+    mempool.read().unwrap().send_transaction(transfer(2)).unwrap();
+    mempool.read().unwrap().send_transaction(transfer(3)).unwrap();
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 2), "Second block should finalize");
+    for handle in handles {
+        assert!(handle.join().is_ok());
+    }
+
+    let chain_lock = chain.read().unwrap();
+    assert_eq!(chain_lock.tip().transactions.len(), 2, "Aggregated votes shouldn't ride along as transactions");
+    assert!(chain_lock.recorded_votes(&first).is_empty());
+    assert_eq!(chain_lock.recorded_certificate(&first).map(|certificate| certificate.signers.len()), Some(2));
+    assert!(chain_lock.is_confirmed(&first, &db.read().unwrap()), "Finality should be readable from the header");
+    drop(chain_lock);
+
+    // A BLS key is only registered with proof of holding it
+    let db = AccountsDB::new();
+    let (account, other) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account.public_key, 500);
+    let register = |prover: &Wallet| {
+        let mut tx = RegisterValidatorTransaction::new(account.public_key, 200, 0).with_bls_key(&account);
+        tx.bls_proof = Some(prover.bls_proof_of_possession());
+        tx.sign(&account);
+        Transaction::from(tx)
+    };
+    assert!(!register(&other).validate(&db), "Another key's proof of possession should be refused");
+    register(&account).apply(&db).expect("Registration with a proven key should execute");
+    assert_eq!(db.get_validator(&account.public_key).unwrap().bls_key, Some(account.bls_public_key()));
+}
+
+#[test]
+fn test_register_validator() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 500);
+    let _ = db.increase_account_balance(&account2.public_key, 50);
+    let supply = db.total_supply;
+
+    let register = |wallet: &Wallet, stake: u64| {
+        let mut tx = RegisterValidatorTransaction::new(wallet.public_key, stake, 0).with_fee(1);
+        tx.sign(wallet);
+        Transaction::from(tx)
+    };
+
+    assert!(!register(&account1, db.min_validator_stake - 1).validate(&db), "Self-bond below the minimum should be rejected");
+    assert!(!register(&account2, db.min_validator_stake).validate(&db), "Bond has to be covered by the balance");
+
+    let registration = register(&account1, 200);
+    let block = Block::extending(&Block::create_genesis(), vec![registration.clone()]);
+    let (undo, _) = db.finalize_block_with_undo(&block).expect("Registration should execute");
+    assert_eq!(db.get_validator(&account1.public_key).map(|validator| validator.stake), Some(200));
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 299, "Bond & fee should leave the balance");
+    assert!(!registration.validate(&db), "An account can only register once");
+
+    db.revert_block(undo);
+    assert!(!db.is_validator(&account1.public_key), "Reverting should unregister the validator");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 500);
+    assert_eq!(db.total_supply, supply);
+}
+
+#[test]
+fn test_unbonding_withdrawn_stake() {
+    let mut db = AccountsDB::new();
+    let (account1, _) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 500);
+    db.unbonding_blocks = 2;
+
+    let mut registration = RegisterValidatorTransaction::new(account1.public_key, 200, 0);
+    registration.sign(&account1);
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(registration)]);
+    db.finalize_block(&block1).expect("Registration should execute");
+
+    let unstake = |amt: u64| {
+        let mut tx = UnstakeTransaction::new(account1.public_key, amt, 1);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    assert!(!unstake(250).validate(&db), "Can't withdraw more than the stake");
+    assert!(!unstake(150).validate(&db), "What's left has to be a full bond or nothing");
+
+    let block2 = Block::extending(&block1, vec![unstake(200)]);
+    db.finalize_block(&block2).expect("Unstake should execute");
+    assert_eq!(db.get_validator(&account1.public_key).map(|validator| validator.stake), Some(0));
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 300, "Withdrawn stake shouldn't be paid out yet");
+    assert_eq!(db.pending_withdrawals(&account1.public_key), vec![Unbonding { amount: 200, release_height: 4 }]);
+
+    let block3 = Block::extending(&block2, vec![]);
+    db.finalize_block(&block3).unwrap();
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 300);
+
+    let block4 = Block::extending(&block3, vec![]);
+    let (undo, _) = db.finalize_block_with_undo(&block4).unwrap();
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 500, "Stake should be paid out once unbonded");
+    assert!(db.pending_withdrawals(&account1.public_key).is_empty());
+
+    db.revert_block(undo);
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 300, "Reverting should put the withdrawal back in unbonding");
+    assert_eq!(db.pending_withdrawals(&account1.public_key).len(), 1);
+}
+
+#[test]
+fn test_burn_transaction() {
+    let mut db = AccountsDB::new();
+    let (account1, _) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.mint(100);
+
+    let burn = |amt: u64, nonce| {
+        let mut tx = BurnTransaction::new(account1.public_key, amt, nonce).with_fee(2);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    assert!(!burn(0, 0).validate(&db), "Burning nothing should be rejected");
+    assert!(!burn(99, 0).validate(&db), "The burn & fee have to be covered by the balance");
+
+    let txs = vec![burn(40, 0), burn(8, 1)];
+    let mut sequential = db.overlay(&txs);
+    execute_sequential(&txs, &mut sequential).unwrap();
+    execute_parallel(&txs, &mut db).unwrap();
+
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 48);
+    assert_eq!(db.total_supply, 48, "Burned tokens & fees should leave the supply");
+    assert_eq!(sequential.total_supply, db.total_supply);
+}
+
+#[test]
+fn test_token_mints() {
+    let mut db = AccountsDB::new();
+    let (alice, bob) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&alice.public_key, 10);
+    let _ = db.increase_account_balance(&bob.public_key, 10);
+
+    let mut create = CreateMintTransaction::new(alice.public_key, 6, 0).with_fee(1);
+    create.sign(&alice);
+    let id = create.mint_id();
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(create)]);
+    db.finalize_block(&block1).expect("Creating the mint should execute");
+    assert_eq!(db.get_mint(&id), Some(Mint { authority: alice.public_key, decimals: 6, supply: 0 }));
+    assert!(!Transaction::from(create).validate(&db), "A mint id can only be created once");
+
+    let mut forged = MintToTransaction::new(id, bob.public_key, bob.public_key, 100, 0);
+    forged.sign(&bob);
+    assert!(!Transaction::from(forged).validate(&db), "Only the mint authority can issue tokens");
+
+    let mut issue = MintToTransaction::new(id, alice.public_key, alice.public_key, 100, 1);
+    issue.sign(&alice);
+    let mut transfer = TokenTransferTransaction::new(id, alice.public_key, bob.public_key, 30, 2);
+    transfer.sign(&alice);
+    let block2 = Block::extending(&block1, vec![Transaction::from(issue), Transaction::from(transfer)]);
+    let (undo, _) = db.finalize_block_with_undo(&block2).expect("Issuing & transferring tokens should execute");
+
+    assert_eq!(db.get_mint(&id).unwrap().supply, 100);
+    assert_eq!(db.token_balance(&id, &alice.public_key), 70);
+    assert_eq!(db.token_balance(&id, &bob.public_key), 30);
+    assert_eq!(db.get_account(&bob.public_key).unwrap().balance, 10, "Token transfers leave native balances alone");
+
+    let mut overdraw = TokenTransferTransaction::new(id, bob.public_key, alice.public_key, 31, 0);
+    overdraw.sign(&bob);
+    assert!(!Transaction::from(overdraw).validate(&db), "Can't send more tokens than held");
+
+    db.revert_block(undo);
+    assert_eq!(db.get_mint(&id).unwrap().supply, 0, "Reverting should undo the issuance");
+    assert_eq!(db.token_balance(&id, &alice.public_key), 0);
+    assert_eq!(db.token_balance(&id, &bob.public_key), 0);
+}
+
+#[test]
+fn test_time_locked_transfer() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+
+    let mut tx = TimeLockedTransferTransaction::new(account1.public_key, account2.public_key, 60, 3, 0).with_fee(1);
+    tx.sign(&account1);
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(tx)]);
+    db.finalize_block(&block1).expect("Time-locked transfer should execute");
+
+    let recipient = db.get_account(&account2.public_key).unwrap();
+    assert_eq!((recipient.balance, recipient.locked_balance()), (0, 60), "Funds should arrive locked");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 39);
+
+    let mut spend = TransferTransaction::new(account1.public_key, account2.public_key, 10, 0);
+    spend.sign(&account2);
+    assert!(!Transaction::from(spend).validate(&db), "Locked funds shouldn't be spendable");
+
+    let block2 = Block::extending(&block1, vec![]);
+    db.finalize_block(&block2).unwrap();
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 0);
+
+    let block3 = Block::extending(&block2, vec![]);
+    let (undo, _) = db.finalize_block_with_undo(&block3).unwrap();
+    let recipient = db.get_account(&account2.public_key).unwrap();
+    assert_eq!((recipient.balance, recipient.locked_balance()), (60, 0), "Funds should unlock at the unlock height");
+    assert!(Transaction::from(spend).validate(&db));
+
+    db.revert_block(undo);
+    assert_eq!(db.get_account(&account2.public_key).unwrap().locked_balance(), 60, "Reverting should lock the funds again");
+}
+
+#[test]
+fn test_delegated_transfer() {
+    let mut db = AccountsDB::new();
+    let (owner, delegate) = setup_accounts(&db);
+    let recipient = Wallet::generate();
+    db.add_account(recipient.public_key, recipient.account());
+    let _ = db.increase_account_balance(&owner.public_key, 100);
+    let _ = db.increase_account_balance(&delegate.public_key, 5);
+
+    let delegated = |amt: u64, nonce| {
+        let mut tx = DelegatedTransferTransaction::new(owner.public_key, delegate.public_key, recipient.public_key, amt, nonce).with_fee(1);
+        tx.sign(&delegate);
+        Transaction::from(tx)
+    };
+    assert!(!delegated(10, 0).validate(&db), "Nothing can be moved without an allowance");
+
+    let mut approve = ApproveTransaction::new(owner.public_key, delegate.public_key, 50, 0);
+    approve.sign(&owner);
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(approve)]);
+    db.finalize_block(&block1).expect("Approval should execute");
+    assert_eq!(db.allowance(&owner.public_key, &delegate.public_key), 50);
+
+    let block2 = Block::extending(&block1, vec![delegated(30, 0)]);
+    let (undo, _) = db.finalize_block_with_undo(&block2).expect("Delegated transfer should execute");
+    assert_eq!(db.get_account(&owner.public_key).unwrap().balance, 70);
+    assert_eq!(db.get_account(&recipient.public_key).unwrap().balance, 30);
+    assert_eq!(db.get_account(&delegate.public_key).unwrap().balance, 4, "The delegate pays the fee");
+    assert_eq!(db.allowance(&owner.public_key, &delegate.public_key), 20);
+    assert!(!delegated(21, 1).validate(&db), "Can't move more than what's left of the allowance");
+
+    db.revert_block(undo);
+    assert_eq!(db.allowance(&owner.public_key, &delegate.public_key), 50, "Reverting should restore the allowance");
+}
+
+#[test]
+fn test_transaction_receipts() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    let mut chain = Blockchain::new();
+
+    let transfer = |amt: u64, nonce| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce).with_fee(2);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    let block = Block::extending(chain.tip(), vec![transfer(10, 0), transfer(20, 1)]);
+    chain.apply(block.clone(), &mut db).expect("Block should apply");
+
+    let receipts = chain.receipts(&block.hash).expect("Canonical blocks should have receipts");
+    assert_eq!(receipts.len(), 2);
+    for (index, (receipt, tx)) in receipts.iter().zip(&block.transactions).enumerate() {
+        assert_eq!((receipt.tx_hash, receipt.block_hash, receipt.index, receipt.fee), (tx.hash(), block.hash, index as u64, 2));
+    }
+
+    // A failing transaction reports why & isn't charged, the rest still run
+    let supply = db.total_supply;
+    let mut overspend = TransferTransaction::new(account1.public_key, account2.public_key, 1000, 0).with_fee(2);
+    overspend.sign(&account2);
+    let overspend = Transaction::from(overspend);
+    let results = execute_parallel_results(&[overspend.clone(), transfer(5, 2)], &mut db);
+    assert_eq!(results, vec![Err("Invalid transaction in execute"), Ok(())]);
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 100 - 34 - 7);
+    assert_eq!(db.total_supply, supply.saturating_sub(2));
+
+    // In a block, though, it fails the lot, so no receipt ever records a failure
+    let block = Block::extending(chain.tip(), vec![transfer(5, 3), overspend]);
+    assert!(chain.apply(block.clone(), &mut db).is_err());
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 100 - 34 - 7, "A failed block should change nothing");
+    assert_eq!(chain.receipts(&block.hash), None);
+}
+
+#[test]
+fn test_transaction_status() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 1000);
+    let builder = &validator1.builder;
+
+    let mut transfer = TransferTransaction::new(account2.public_key, account1.public_key, 500, 0);
+    transfer.sign(&account1);
+    let tx = Transaction::from(transfer);
+    let by_hash = TransactionId::Hash(tx.hash());
+    let by_signature = TransactionId::Signature(tx.signatures()[0].1.to_bytes());
+    assert_eq!(builder.get_transaction_status(&by_hash), TransactionStatus::Unknown);
+
+    mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    assert_eq!(builder.get_transaction_status(&by_hash), TransactionStatus::Pending);
+    assert_eq!(builder.get_transaction_status(&by_signature), TransactionStatus::Pending);
+
+    let mut db_lock = db.write().unwrap();
+    let mut chain_lock = builder.chain.write().unwrap();
+    let block = Block::extending(chain_lock.tip(), vec![tx.clone()]);
+    chain_lock.apply(block.clone(), &mut db_lock).unwrap();
+    drop(chain_lock);
+    drop(db_lock);
+    mempool.read().unwrap().remove_included(std::slice::from_ref(&tx));
+
+    let included = TransactionStatus::Included { block_hash: block.hash, height: 1 };
+    assert_eq!(builder.get_transaction_status(&by_hash), included);
+    assert_eq!(builder.get_transaction_status(&by_signature), included, "Lookups by signature should find the same transaction");
+
+    let rpc = RpcServer::new(builder.clone());
+    let response = rpc.handle(rpc_request("getTransactionStatus", serde_json::json!([hex::encode(tx.hash())])));
+    assert_eq!(response.result, Some(serde_json::json!(included)));
+    let response = rpc.handle(rpc_request("getTransactionStatus", serde_json::json!(["abcd"])));
+    assert_eq!(response.error.map(|e| e.code), Some(INVALID_PARAMS), "Ids must be a hash or a signature");
+}
+
+#[test]
+fn test_account_history() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let account3 = Wallet::generate();
+    db.add_account(account3.public_key, account3.account());
+    let _ = db.increase_account_balance(&account1.public_key, 1000);
+    let mut chain = Blockchain::new();
+
+    let transfer = |to: &Wallet, nonce| {
+        let mut tx = TransferTransaction::new(to.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+
+    let block1 = Block::extending(chain.tip(), vec![transfer(&account2, 0), transfer(&account3, 1)]);
+    chain.apply(block1.clone(), &mut db).unwrap();
+    let block2 = Block::extending(&block1, vec![transfer(&account2, 2)]);
+    chain.apply(block2.clone(), &mut db).unwrap();
+
+    assert_eq!(chain.account_history(&account1.public_key, 10, None), vec![(2, 0), (1, 1), (1, 0)], "Newest first");
+    assert_eq!(chain.account_history(&account2.public_key, 10, None), vec![(2, 0), (1, 0)]);
+    assert_eq!(chain.account_history(&account3.public_key, 10, None), vec![(1, 1)]);
+
+    // Paging with the last entry of the previous page as the cursor
+    let page = chain.account_history(&account1.public_key, 2, None);
+    assert_eq!(page, vec![(2, 0), (1, 1)]);
+    assert_eq!(chain.account_history(&account1.public_key, 2, page.last().copied()), vec![(1, 0)]);
+
+    // A competing block that wins fork choice replaces block 2's entries
+    let competing = Block::extending(&block1, vec![transfer(&account3, 2)]);
+    chain.add_votes(&competing.hash, 10);
+    chain.apply(competing, &mut db).unwrap();
+    assert_eq!(chain.account_history(&account2.public_key, 10, None), vec![(1, 0)], "Rolled back blocks should leave the history");
+    assert_eq!(chain.account_history(&account3.public_key, 10, None), vec![(2, 0), (1, 1)]);
+}
+
+#[test]
+fn test_block_queries() {
+    let (validator1, _v, db, _) = setup_validators();
+    let builder = &validator1.builder;
+
+    let mut blocks = vec![builder.build_genesis()];
+    for _ in 0..5 {
+        let block = Block::extending(blocks.last().unwrap(), vec![]);
+        builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+        blocks.push(block);
+    }
+
+    let rpc = RpcServer::new(builder.clone()).with_max_block_page(2);
+    let response = rpc.handle(rpc_request("getBlockByHash", serde_json::json!([hex::encode(blocks[3].hash)])));
+    assert_eq!(response.result, Some(serde_json::json!(blocks[3])));
+
+    let response = rpc.handle(rpc_request("getBlocks", serde_json::json!([1, 5])));
+    assert_eq!(response.result, Some(serde_json::json!(blocks[1..3])), "Ranges should be cut to the page size");
+
+    let response = rpc.handle(rpc_request("getBlockHeaders", serde_json::json!([4, 10])));
+    let expected: Vec<_> = blocks[4..].iter().map(|block| serde_json::json!({ "hash": hex::encode(block.hash), "header": block.header })).collect();
+    assert_eq!(response.result, Some(serde_json::json!(expected)), "Ranges should stop at the tip");
+
+    let response = rpc.handle(rpc_request("getBlockByHash", serde_json::json!(["abcd"])));
+    assert_eq!(response.error.map(|e| e.code), Some(INVALID_PARAMS));
+}
+
+#[test]
+fn test_get_multiple_accounts() {
+    let db = AccountsDB::new();
+    let wallets: Vec<Wallet> = (0..50).map(|_| Wallet::generate()).collect();
+    for (i, wallet) in wallets.iter().enumerate() {
+        if i % 3 != 0 {
+            db.add_account(wallet.public_key, wallet.account());
+            let _ = db.increase_account_balance(&wallet.public_key, i as u64);
+        }
+    }
+
+    let pubkeys: Vec<Pubkey> = wallets.iter().map(|wallet| wallet.public_key).collect();
+    let accounts = db.get_multiple_accounts(&pubkeys);
+    let expected: Vec<_> = pubkeys.iter().map(|pubkey| db.get_account(pubkey)).collect();
+    assert_eq!(accounts, expected, "Batch reads should match single reads, in order");
+    assert!(accounts[0].is_none() && accounts[1].as_ref().is_some_and(|account| account.balance == 1));
+
+    let (validator1, _v, shared, _) = setup_validators();
+    shared.read().unwrap().add_account(wallets[1].public_key, wallets[1].account());
+    let rpc = RpcServer::new(validator1.builder.clone());
+    let response = rpc.handle(rpc_request("getMultipleAccounts", serde_json::json!([[wallets[0].address, wallets[1].address]])));
+    assert_eq!(response.result, Some(serde_json::json!([null, wallets[1].account()])));
+}
+
+#[test]
+fn test_atomic_transfer() {
+    let db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 1000);
+    let _ = db.increase_account_balance(&account2.public_key, 1000);
+    let stranger = Wallet::generate();
+
+    assert_eq!(db.transfer(&account1.public_key, &stranger.public_key, 1), Err(TransferError::AccountNotFound));
+    assert_eq!(db.transfer(&account1.public_key, &account2.public_key, 1001), Err(TransferError::InsufficientBalance));
+    let _ = db.increase_account_balance(&account2.public_key, u64::MAX);
+    assert_eq!(db.transfer(&account1.public_key, &account2.public_key, 1), Err(TransferError::BalanceOverflow));
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 1000, "Failed transfers shouldn't move anything");
+    let _ = db.decrease_account_balance(&account2.public_key, u64::MAX - 1000);
+
+    // Opposing transfers racing on the same pair neither deadlock nor lose funds
+    let db = Arc::new(db);
+    let handles: Vec<_> = [(account1.public_key, account2.public_key), (account2.public_key, account1.public_key)]
+        .into_iter()
+        .map(|(from, to)| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || for _ in 0..1000 { let _ = db.transfer(&from, &to, 3); })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let total = db.get_account(&account1.public_key).unwrap().balance + db.get_account(&account2.public_key).unwrap().balance;
+    assert_eq!(total, 2000, "Transfers should conserve the total balance");
+}
+
+#[test]
+fn test_account_pagination() {
+    let db = AccountsDB::new();
+    let wallets: Vec<Wallet> = (0..25).map(|_| Wallet::generate()).collect();
+    for wallet in &wallets {
+        db.add_account(wallet.public_key, wallet.account());
+    }
+    assert_eq!(db.account_count(), 25);
+
+    let mut pages = vec![];
+    for offset in (0..25).step_by(10) {
+        pages.extend(db.iter_accounts(offset, 10));
+    }
+    let mut expected: Vec<Pubkey> = wallets.iter().map(|wallet| wallet.public_key).collect();
+    expected.sort();
+    assert_eq!(pages.iter().map(|account| account.public_key).collect::<Vec<_>>(), expected, "Pages should cover every account once, in key order");
+    assert!(db.iter_accounts(25, 10).is_empty());
+
+    let (validator1, _v, shared, _) = setup_validators();
+    assert_eq!(shared.read().unwrap().validator_count(), 2);
+    let rpc = RpcServer::new(validator1.builder.clone());
+    let response = rpc.handle(rpc_request("getValidators", serde_json::json!([1, 10])));
+    assert_eq!(response.result.unwrap().as_array().map(Vec::len), Some(1), "Offsets should skip validators");
+}
+
+#[test]
+fn test_account_data() {
+    let db = AccountsDB::new();
+    let (account1, _) = setup_accounts(&db);
+    assert_eq!(db.get_account_data(&account1.public_key), Some(vec![]));
+
+    let root = db.state_root();
+    db.set_account_data(&account1.public_key, b"program state".to_vec()).unwrap();
+    assert_eq!(db.get_account_data(&account1.public_key), Some(b"program state".to_vec()));
+    assert_ne!(db.state_root(), root, "Account data should be part of the state root");
+
+    assert!(db.set_account_data(&account1.public_key, vec![0; MAX_ACCOUNT_DATA_BYTES + 1]).is_err(), "Oversized data should be rejected");
+    assert!(db.set_account_data(&Wallet::generate().public_key, vec![1]).is_err(), "Data needs an account to live in");
+    db.set_account_data(&account1.public_key, vec![]).unwrap();
+    assert_eq!(db.state_root(), root);
+}
+
+#[test]
+fn test_account_owner() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let program = Wallet::generate().public_key;
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.mint(100);
+    assert_eq!(db.get_account(&account1.public_key).unwrap().owner, SYSTEM_OWNER);
+
+    let mut first = TransferTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    first.sign(&account1);
+    assert!(Transaction::from(first).validate(&db), "The key holder controls a system account");
+
+    let mut forged = AssignTransaction::new(account1.public_key, program, 0);
+    forged.sign(&account2);
+    assert!(!Transaction::from(forged).validate(&db), "Only the account's key can assign it");
+
+    let mut assign = AssignTransaction::new(account1.public_key, program, 0).with_fee(1);
+    assign.sign(&account1);
+    let root = db.state_root();
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(assign)]);
+    let (undo, _) = db.finalize_block_with_undo(&block1).expect("Assigning should execute");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().owner, program);
+    assert_ne!(db.state_root(), root, "The owner should be part of the state root");
+
+    // Both at the account's next nonce, so only the owner stops them
+    let mut transfer = TransferTransaction::new(account2.public_key, account1.public_key, 10, 1);
+    transfer.sign(&account1);
+    assert!(!Transaction::from(transfer).validate(&db), "The key holder can't debit a program owned account");
+    assert!(Transaction::from(transfer).execute(&mut db).is_err());
+    let mut reassign = AssignTransaction::new(account1.public_key, program, 1).with_fee(1);
+    reassign.sign(&account1);
+    assert!(!Transaction::from(reassign).validate(&db), "Only system accounts can be assigned");
+
+    db.revert_block(undo);
+    assert_eq!(db.get_account(&account1.public_key).unwrap().owner, SYSTEM_OWNER);
+    assert_eq!(db.state_root(), root);
+}
+
+#[test]
+fn test_epoch_boundaries() {
+    let mut db = AccountsDB::new();
+    let (_, poor) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&poor.public_key, 5);
+    (db.epoch_slots, db.rent_exempt_minimum, db.rent_per_epoch) = (4, 10, 1);
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![]).with_slot(3);
+    assert!(!db.ends_epoch(&block1));
+    db.finalize_block(&block1).unwrap();
+    assert_eq!(db.epoch_info(), EpochInfo { epoch: 0, slot_index: 3, slots_in_epoch: 4, absolute_slot: 3, block_height: 1 });
+
+    // Slot 4 went unfilled, so the block in slot 6 is the one to close out epoch 0
+    let seed = db.epoch_seed;
+    let block2 = Block::extending(&block1, vec![]).with_slot(6);
+    assert!(db.ends_epoch(&block2));
+    let (undo, _) = db.finalize_block_with_undo(&block2).unwrap();
+    assert_eq!(db.epoch_info().epoch, 1);
+    assert_eq!(db.epoch_info().slot_index, 2);
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 4, "Rent should be charged at the boundary");
+    assert_ne!(db.epoch_seed, seed, "The leader seed should roll over at the boundary");
+
+    db.revert_block(undo);
+    assert_eq!((db.epoch_info().epoch, db.epoch_seed), (0, seed));
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 5);
+
+    let block2 = Block::extending(&block1, vec![]);
+    db.finalize_block(&block2).unwrap();
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 4, "Slot 4 starts epoch 1");
+    db.finalize_block(&Block::extending(&block2, vec![]).with_slot(7)).unwrap();
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 4, "Only the first block of an epoch ends the one before");
+
+    let (validator, _v, _, _) = setup_validators();
+    let response = RpcServer::new(validator.builder.clone()).handle(rpc_request("getEpochInfo", serde_json::Value::Null));
+    assert_eq!(response.result, Some(serde_json::json!(EpochInfo { epoch: 0, slot_index: 0, slots_in_epoch: 100, absolute_slot: 0, block_height: 0 })));
+}
+
+#[test]
+fn test_state_rent() {
+    let mut db = AccountsDB::new();
+    let (rich, poor) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&rich.public_key, 50);
+    let _ = db.increase_account_balance(&poor.public_key, 5);
+    db.mint(55);
+    db.epoch_slots = 2;
+    db.rent_exempt_minimum = 10;
+    db.rent_per_epoch = 3;
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![]);
+    db.finalize_block(&block1).unwrap();
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 5, "Rent is only collected at epoch boundaries");
+
+    let block2 = Block::extending(&block1, vec![]);
+    db.finalize_block(&block2).unwrap();
+    assert_eq!(db.get_account(&rich.public_key).unwrap().balance, 50, "Exempt accounts don't pay rent");
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 2);
+    assert_eq!(db.total_supply, 52, "Rent should leave the supply");
+
+    let block3 = Block::extending(&block2, vec![]);
+    db.finalize_block(&block3).unwrap();
+    let block4 = Block::extending(&block3, vec![]);
+    let root = db.state_root();
+    let (undo, _) = db.finalize_block_with_undo(&block4).unwrap();
+    assert!(db.get_account(&poor.public_key).is_none(), "Accounts that run out should be reaped");
+    assert_eq!(db.total_supply, 50);
+
+    db.revert_block(undo);
+    assert_eq!(db.get_account(&poor.public_key).unwrap().balance, 2, "Reverting should bring a reaped account back");
+    assert_eq!(db.state_root(), root);
+}
+
+#[test]
+fn test_freeze_and_thaw() {
+    let mut db = AccountsDB::new();
+    let (authority, account1) = setup_accounts(&db);
+    let account2 = Wallet::generate();
+    db.add_account(account2.public_key, account2.account());
+    let _ = db.increase_account_balance(&authority.public_key, 10);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.freeze_authority = Some(authority.public_key);
+
+    let mut forged = FreezeTransaction::new(account1.public_key, account1.public_key, 0);
+    forged.sign(&account1);
+    assert!(!Transaction::from(forged).validate(&db), "Only the freeze authority can freeze accounts");
+
+    let mut freeze = FreezeTransaction::new(authority.public_key, account1.public_key, 0).with_fee(1);
+    freeze.sign(&authority);
+    let root = db.state_root();
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(freeze)]);
+    db.finalize_block(&block1).expect("Freezing should execute");
+    assert!(db.get_account(&account1.public_key).unwrap().frozen);
+    assert_ne!(db.state_root(), root, "Freezes should be part of the state root");
+    let mut refreeze = FreezeTransaction::new(authority.public_key, account1.public_key, 1).with_fee(1);
+    refreeze.sign(&authority);
+    assert!(!Transaction::from(refreeze).validate(&db), "An account can't be frozen twice");
+
+    let mut transfer = TransferTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    transfer.sign(&account1);
+    assert!(!Transaction::from(transfer).validate(&db), "A frozen account can't be debited");
+    assert_eq!(TransactionBody::Transfer(transfer).apply_state(&db), Err("Account is frozen"));
+
+    let mut thaw = ThawTransaction::new(authority.public_key, account1.public_key, 1).with_fee(1);
+    thaw.sign(&authority);
+    let block2 = Block::extending(&block1, vec![Transaction::from(thaw)]);
+    db.finalize_block(&block2).expect("Thawing should execute");
+    assert!(!db.get_account(&account1.public_key).unwrap().frozen);
+    assert!(Transaction::from(transfer).validate(&db), "A thawed account can be debited again");
+    let mut rethaw = ThawTransaction::new(authority.public_key, account1.public_key, 2).with_fee(1);
+    rethaw.sign(&authority);
+    assert!(!Transaction::from(rethaw).validate(&db), "Only frozen accounts can be thawed");
+}
+
+// Writes its input to account 2, which the program has to own, & moves 10 into it from the caller.
+// Spins forever without input.
+const VAULT_CONTRACT: &str = r#"
+(module
+  (import "env" "input_len" (func $input_len (result i32)))
+  (import "env" "read_input" (func $read_input (param i32)))
+  (import "env" "write_account_data" (func $write_account_data (param i32 i32 i32) (result i32)))
+  (import "env" "transfer" (func $transfer (param i32 i32 i64) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "entrypoint") (result i32)
+    (if (i32.eqz (call $input_len)) (then (loop $spin (br $spin))))
+    (call $read_input (i32.const 0))
+    (if (call $write_account_data (i32.const 2) (i32.const 0) (call $input_len)) (then (return (i32.const 1))))
+    (call $transfer (i32.const 0) (i32.const 2) (i64.const 10))))
+"#;
+
+#[test]
+fn test_wasm_contracts() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.mint(100);
+    let code = wat::parse_str(VAULT_CONTRACT).unwrap();
+
+    let mut invalid = DeployTransaction::new(account1.public_key, b"not wasm".to_vec(), 0);
+    invalid.sign(&account1);
+    assert!(!Transaction::from(invalid).validate(&db), "Only valid programs can be deployed");
+
+    let mut deploy = DeployTransaction::new(account1.public_key, code.clone(), 0).with_fee(1);
+    deploy.sign(&account1);
+    let program = deploy.address();
+    let mut assign = AssignTransaction::new(account2.public_key, program, 0);
+    assign.sign(&account2);
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(deploy.clone()), Transaction::from(assign)]);
+    db.finalize_block(&block1).expect("Deploying should execute");
+    let deployed = db.get_account(&program).unwrap();
+    assert_eq!((deployed.data, deployed.owner), (code.clone(), WASM_LOADER));
+    assert!(!Transaction::from(deploy).validate(&db), "A deploy can't be replayed over its program");
+
+    let mut invoke = InvokeTransaction::new(account1.public_key, program, vec![account2.public_key], b"hello".to_vec(), 100_000, 1).with_fee(1);
+    invoke.sign(&account1);
+    let block2 = Block::extending(&block1, vec![Transaction::from(invoke)]);
+    db.finalize_block(&block2).expect("Invoking should execute");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().data, b"hello");
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 10);
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 88);
+
+    let root = db.state_root();
+    let mut unowned = InvokeTransaction::new(account1.public_key, program, vec![account1.public_key], b"hello".to_vec(), 100_000, 2);
+    unowned.sign(&account1);
+    assert_eq!(TransactionBody::Invoke(unowned).apply_state(&db), Err("Program returned an error"), "Programs only write accounts they own");
+
+    let mut spin = InvokeTransaction::new(account1.public_key, program, vec![account2.public_key], vec![], 10_000, 2).with_fee(1);
+    spin.sign(&account1);
+    assert!(Transaction::from(spin.clone()).validate(&db));
+    assert_eq!(Transaction::from(spin).execute(&mut db), Err("Out of gas"));
+    assert_eq!(db.state_root(), root, "A failed invocation shouldn't change anything");
+
+    let mut greedy = InvokeTransaction::new(account1.public_key, program, vec![account2.public_key], b"hi".to_vec(), MAX_GAS + 1, 2);
+    greedy.sign(&account1);
+    assert!(!Transaction::from(greedy).validate(&db), "Gas limits are capped");
+
+    // Programs outlive epoch boundaries with rent on, though they hold nothing
+    (db.epoch_slots, db.rent_exempt_minimum, db.rent_per_epoch) = (4, 10, 1);
+    let block3 = Block::extending(&block2, vec![]).with_slot(4);
+    assert!(db.ends_epoch(&block3));
+    db.finalize_block(&block3).unwrap();
+    assert_eq!(db.get_account(&program).map(|program| program.data), Some(code), "Rent shouldn't reap a program");
+}
+
+// Sets the data of the account that signed to the instruction's data
+struct NoteProgram;
+
+impl Program for NoteProgram {
+    fn id(&self) -> ProgramId {
+        *b"litechain_test_note_program_____"
+    }
+
+    fn process(&self, accounts: &[AccountMeta], data: &[u8], db: &AccountsDB) -> Result<(), &'static str> {
+        let [account] = accounts else { return Err("Expected one account") };
+        if !account.signer {
+            return Err("Missing signature")
+        }
+        db.set_account_data(&account.pubkey, data.to_vec())
+    }
+}
+
+#[test]
+fn test_native_programs() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.mint(100);
+
+    let mut transfer = ProgramTransaction::new(account1.public_key, TRANSFER_PROGRAM, vec![account1.public_key, account2.public_key], 30u64.to_le_bytes().to_vec(), 0).with_fee(1);
+    transfer.sign(&account1);
+    let mut unsigned = ProgramTransaction::new(account1.public_key, TRANSFER_PROGRAM, vec![account2.public_key, account1.public_key], 30u64.to_le_bytes().to_vec(), 1);
+    unsigned.sign(&account1);
+    assert!(!Transaction::from(unsigned).validate(&db), "Built-in programs only debit accounts that signed");
+
+    let note = NoteProgram;
+    let mut write = ProgramTransaction::new(account2.public_key, note.id(), vec![account2.public_key], b"hello".to_vec(), 0);
+    write.sign(&account2);
+    assert!(!Transaction::from(write.clone()).validate(&db), "Programs have to be registered before they can be called");
+    db.register_program(Arc::new(NoteProgram)).unwrap();
+    assert!(db.register_program(Arc::new(NoteProgram)).is_err(), "Program ids are unique");
+    assert!(db.register_program(Arc::new(TransferProgram)).is_err(), "Built-in programs can't be replaced");
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(transfer), Transaction::from(write)]).with_proposer(&Wallet::generate());
+    db.finalize_block(&block1).expect("Program transactions should execute");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 69);
+    assert_eq!(db.get_account(&account2.public_key).unwrap().balance, 30);
+    assert_eq!(db.get_account(&account2.public_key).unwrap().data, b"hello");
+    assert_eq!(db.total_supply, 100);
+}
+
+#[test]
+fn test_message_transactions() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let (fresh, validator) = (Wallet::generate(), Wallet::generate());
+    db.add_validator(validator.public_key, ValidatorAccount::new(validator.public_key));
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    db.mint(100);
+
+    let instructions = vec![
+        Instruction::create_account(account1.public_key, fresh.public_key, 50),
+        Instruction::stake(fresh.public_key, validator.public_key, 20),
+    ];
+    let mut message = MessageTransaction::new(vec![account1.public_key, fresh.public_key], instructions, 0).with_fee(1);
+    message.sign(&account1);
+    assert!(!Transaction::from(message.clone()).validate(&db), "Every signer has to sign");
+    message.sign(&fresh);
+    assert!(Transaction::from(message.clone()).validate(&db), "Instructions should see the ones before them");
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![Transaction::from(message)]).with_proposer(&validator);
+    db.finalize_block(&block1).expect("The message should execute");
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 49);
+    assert_eq!(db.get_account(&fresh.public_key).unwrap().balance, 30);
+    assert_eq!(db.get_validator(&validator.public_key).unwrap().stake, 20);
+    assert_eq!(db.total_supply, 100);
+
+    let root = db.state_root();
+    let instructions = vec![
+        Instruction::transfer(account1.public_key, account2.public_key, 10),
+        Instruction::stake(account1.public_key, account2.public_key, 5),
+    ];
+    let mut partial = MessageTransaction::new(vec![account1.public_key], instructions, 1);
+    partial.sign(&account1);
+    assert!(!Transaction::from(partial.clone()).validate(&db));
+    assert!(TransactionBody::Message(partial).apply_state(&db).is_err());
+    assert_eq!(db.state_root(), root, "A failing instruction should undo the ones before it");
+}
+
+#[test]
+fn test_rollback_to_height() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    assert_eq!(db.rollback_to(0), Ok(()), "Nothing to roll back at genesis");
+
+    let transfer = |amt: u64, nonce| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    let genesis_root = db.state_root();
+    let block1 = Block::extending(&Block::create_genesis(), vec![transfer(10, 0)]);
+    db.apply_block(&block1).unwrap();
+    let root1 = db.state_root();
+    let block2 = Block::extending(&block1, vec![transfer(20, 1)]);
+    db.apply_block(&block2).unwrap();
+    let block3 = Block::extending(&block2, vec![transfer(30, 2)]);
+    db.apply_block(&block3).unwrap();
+    assert_eq!(db.version(), Some(3));
+
+    let failing = Block::extending(&block3, vec![transfer(10, 3), transfer(1000, 4)]);
+    assert!(db.apply_block(&failing).is_err());
+    assert_eq!(db.get_account(&account1.public_key).unwrap().balance, 40, "A failed block shouldn't leave anything half applied");
+    assert_eq!(db.version(), Some(3));
+
+    db.rollback_to(1).unwrap();
+    assert_eq!(db.latest_height, 1);
+    assert_eq!(db.latest_blockhash, block1.hash);
+    assert_eq!(db.state_root(), root1);
+    db.rollback_to(0).unwrap();
+    assert_eq!(db.state_root(), genesis_root);
+
+    let block1 = Block::extending(&Block::create_genesis(), vec![transfer(10, 0)]);
+    db.finalize_block(&block1).unwrap();
+    assert!(db.rollback_to(0).is_err(), "Blocks finalized without a record can't be rolled back");
+    assert_eq!(db.state_root(), root1);
+}
+
+#[test]
+fn test_state_diffs() {
+    let (validator1, _v, db, _) = setup_validators();
+    let builder = &validator1.builder;
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 30, 0);
+    tx.sign(&account1);
+    let genesis = builder.build_genesis();
+    let block = Block::extending(&genesis, vec![Transaction::from(tx)]);
+    builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+
+    let mut balances = vec![
+        ValueChange { pubkey: account1.public_key, old: Some(100), new: Some(70) },
+        ValueChange { pubkey: account2.public_key, old: Some(0), new: Some(30) },
+    ];
+    balances.sort_by_key(|change| change.pubkey);
+    let expected = StateDiff { balances, stakes: vec![] };
+    assert_eq!(builder.chain.read().unwrap().state_diff(&block.hash), Some(&expected));
+
+    let rewarded = Wallet::generate().public_key;
+    builder.chain.write().unwrap().credit_rewards(&block.hash, vec![(rewarded, 5)], &mut db.write().unwrap()).unwrap();
+    let diff = builder.chain.read().unwrap().state_diff(&block.hash).cloned().unwrap();
+    assert!(diff.balances.contains(&ValueChange { pubkey: rewarded, old: None, new: Some(5) }), "Rewards should show up in the diff");
+
+    let rpc = RpcServer::new(builder.clone());
+    let response = rpc.handle(rpc_request("getStateDiff", serde_json::json!([hex::encode(block.hash)])));
+    assert_eq!(response.result, Some(serde_json::json!(diff)));
+    let response = rpc.handle(rpc_request("getStateDiff", serde_json::json!([hex::encode(genesis.hash)])));
+    assert_eq!(response.result, Some(serde_json::Value::Null), "Genesis isn't executed, so it has no diff");
+}
+
+#[test]
+fn test_write_ahead_log() {
+    let mut db = AccountsDB::new();
+    let (account1, account2) = setup_accounts(&db);
+    let _ = db.increase_account_balance(&account1.public_key, 100);
+    let genesis_state = db.clone();
+
+    let path = std::env::temp_dir().join(format!("litechain-wal-{}.log", account1.address));
+    let mut chain = Blockchain::new().with_wal(WriteAheadLog::create(&path).unwrap());
+    let recovered = || {
+        let mut recovered = genesis_state.clone();
+        WriteAheadLog::recover(&path, &mut recovered).unwrap();
+        recovered
+    };
+    let balance = |db: &AccountsDB, pubkey: &Pubkey| db.get_account(pubkey).map(|account| account.balance);
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 40, 0);
+    tx.sign(&account1);
+    let block = Block::extending(chain.tip(), vec![Transaction::from(tx)]);
+    chain.apply(block.clone(), &mut db).unwrap();
+    let proposer = Wallet::generate().public_key;
+    chain.credit_rewards(&block.hash, vec![(proposer, 7)], &mut db).unwrap();
+
+    let replayed = recovered();
+    for pubkey in [account1.public_key, account2.public_key, proposer] {
+        assert_eq!(balance(&replayed, &pubkey), balance(&db, &pubkey), "Replaying the log should rebuild balances");
+    }
+    assert_eq!((replayed.latest_height, replayed.latest_blockhash, replayed.total_supply), (1, block.hash, db.total_supply));
+
+    // A record torn by a crash was never acknowledged, so it's ignored
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    std::io::Write::write_all(&mut file, &[200, 0, 0, 0, 1, 2, 3]).unwrap();
+    assert_eq!(WriteAheadLog::read(&path).unwrap().len(), 2);
+
+    let competing = Block::extending(chain.block_at(0).unwrap(), vec![]);
+    chain.add_votes(&competing.hash, 1_000);
+    let mut chain = chain.with_wal(WriteAheadLog::open(&path).unwrap());
+    chain.apply(competing.clone(), &mut db).unwrap();
+    assert_eq!(chain.tip().hash, competing.hash);
+
+    let replayed = recovered();
+    assert_eq!(balance(&replayed, &account1.public_key), Some(100), "Rollbacks should be logged too");
+    assert_eq!(balance(&replayed, &proposer), None);
+    assert_eq!((replayed.latest_height, replayed.latest_blockhash, replayed.total_supply), (1, competing.hash, db.total_supply));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_archive_queries() {
+    let (validator1, _v, db, _) = setup_validators();
+    let builder = &validator1.builder;
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let mut blocks = vec![builder.build_genesis()];
+    for (nonce, amt) in [(0, 10), (1, 20), (2, 30)] {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        let block = Block::extending(blocks.last().unwrap(), vec![Transaction::from(tx)]);
+        builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+        blocks.push(block);
+    }
+    let empty = Block::extending(blocks.last().unwrap(), vec![]);
+    builder.chain.write().unwrap().apply(empty, &mut db.write().unwrap()).unwrap();
+
+    assert!(db.read().unwrap().get_balance_at(&account1.public_key, 1).is_err(), "Only archive nodes serve historical state");
+    db.write().unwrap().archive = true;
+
+    let db_lock = db.read().unwrap();
+    let history: Vec<u64> = (0..=4).map(|height| db_lock.get_balance_at(&account1.public_key, height).unwrap()).collect();
+    assert_eq!(history, vec![100, 90, 70, 40, 40]);
+    assert_eq!(db_lock.get_balance_at(&account2.public_key, 2), Ok(30));
+    assert_eq!(db_lock.get_account_at(&Wallet::generate().public_key, 2), Ok(None));
+    assert!(db_lock.get_balance_at(&account1.public_key, 5).is_err(), "Can't query past the tip");
+    drop(db_lock);
+
+    let rpc = RpcServer::new(builder.clone());
+    let response = rpc.handle(rpc_request("getBalanceAt", serde_json::json!([account1.address, 2])));
+    assert_eq!(response.result, Some(serde_json::json!(70)));
+    let response = rpc.handle(rpc_request("getBalanceAt", serde_json::json!([account1.address, 9])));
+    assert_eq!(response.error.map(|e| e.code), Some(SERVER_ERROR));
+}
+
+#[test]
+fn test_pruning() {
+    let (validator1, _v, db, _) = setup_validators();
+    let builder = &validator1.builder;
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+    db.write().unwrap().archive = true;
+
+    let mut blocks = vec![builder.build_genesis()];
+    for nonce in 0..6 {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        let block = Block::extending(blocks.last().unwrap(), vec![Transaction::from(tx)]);
+        builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+        blocks.push(block);
+    }
+
+    let pruning = PruningConfig { keep_blocks: Some(2), snapshot_interval: Some(2), ..PruningConfig::default() };
+    pruning.prune(builder);
+    assert_eq!(builder.chain.read().unwrap().horizon(), 5);
+
+    let db_lock = db.read().unwrap();
+    assert_eq!(db_lock.get_balance_at(&account1.public_key, 4), Ok(60), "State within the kept window is still queryable");
+    assert_eq!(db_lock.get_balance_at(&account1.public_key, 2), Ok(80), "Snapshots should outlive pruning");
+    assert_eq!(db_lock.get_balance_at(&account1.public_key, 0), Ok(100));
+    assert_eq!(db_lock.get_balance_at(&account1.public_key, 3), Err(HistoryError::Pruned { horizon: 4 }));
+    drop(db_lock);
+
+    let rpc = RpcServer::new(builder.clone());
+    let response = rpc.handle(rpc_request("getBlock", serde_json::json!([3])));
+    assert_eq!(response.error.map(|e| e.code), Some(PRUNED));
+    let response = rpc.handle(rpc_request("getBlock", serde_json::json!([5])));
+    assert_eq!(response.result, Some(serde_json::json!(blocks[5])));
+    let response = rpc.handle(rpc_request("getBlockHeaders", serde_json::json!([1, 2])));
+    assert!(response.result.is_some(), "Headers are kept for pruned blocks");
+
+    let competing = Block::extending(&blocks[2], vec![]);
+    builder.chain.write().unwrap().add_votes(&competing.hash, 1_000);
+    assert!(builder.chain.write().unwrap().apply(competing, &mut db.write().unwrap()).is_err(), "Can't reorg below the horizon");
+    assert_eq!(builder.chain.read().unwrap().tip().hash, blocks[6].hash);
+    assert_eq!(builder.chain.read().unwrap().best_tip(), blocks[6].hash, "The fork shouldn't keep winning fork choice");
+}
+
+#[test]
+fn test_snapshot_fast_sync() {
+    let (validator1, validator2, db, _) = setup_validators();
+    let builder = &validator1.builder;
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let mut tip = builder.build_genesis();
+    for nonce in 0..3 {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        let block = Block::extending(&tip, vec![Transaction::from(tx)])
+            .with_state_root(db.read().unwrap().state_root())
+            .with_proposer(&validator1.wallet);
+        builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+        tip = block;
+    }
+
+    let snapshot = Snapshot::capture(&builder.chain.read().unwrap(), &db.read().unwrap());
+    assert_eq!(snapshot.height(), 3);
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, 3);
+    tx.sign(&account1);
+    let child = Block::extending(&tip, vec![Transaction::from(tx)])
+        .with_state_root(db.read().unwrap().state_root())
+        .with_proposer(&validator1.wallet);
+    builder.chain.write().unwrap().apply(child.clone(), &mut db.write().unwrap()).unwrap();
+    let certify = |block: &Block, db: &AccountsDB| {
+        let votes = [&validator1, &validator2].iter().map(|validator| Vote::new(block.hash, block.slot(), &validator.wallet)).collect();
+        Quorum::aggregate(block.hash, block.slot(), votes, db).unwrap().certificate(db).unwrap()
+    };
+    let certificate = certify(&child, &db.read().unwrap());
+    // What a fresh node trusts: its genesis validators
+    let trusted = AccountsDB::new();
+    for validator in [&validator1, &validator2] {
+        trusted.add_validator(validator.wallet.public_key, validator.account());
+    }
+
+    // The snapshot survives the trip over the wire
+    let Ok(Message::Snapshot(received)) = Message::from_bytes(&Message::Snapshot(Box::new(snapshot)).to_bytes()) else {
+        panic!("Snapshot message should decode")
+    };
+
+    let mut tampered = received.clone();
+    tampered.accounts.iter_mut().find(|(pubkey, _)| *pubkey == account2.public_key).unwrap().1.balance += 1;
+    let state = tampered.restore(&AccountsDB::new());
+    assert_eq!(tampered.verify(&state, &child, &certificate, &trusted), Err("Snapshot state does not match the block's state root"));
+    let mut tampered = received.clone();
+    tampered.base_fee += 1;
+    let state = tampered.restore(&AccountsDB::new());
+    assert!(tampered.verify(&state, &child, &certificate, &trusted).is_err(), "A snapshot's base fee should be checked too");
+    let mut tampered = received.clone();
+    tampered.epoch_seed = [7; 32];
+    let state = tampered.restore(&AccountsDB::new());
+    assert!(tampered.verify(&state, &child, &certificate, &trusted).is_err(), "A snapshot's epoch seed should be checked too");
+    let unsigned = Block::extending(&tip, vec![]).with_state_root(child.header.state_root);
+    let state = received.restore(&AccountsDB::new());
+    assert!(received.verify(&state, &unsigned, &certificate, &trusted).is_err(), "Only the certified block vouches for a snapshot");
+
+    // A peer can't vouch for its own forged snapshot by naming itself the only validator in it
+    let forger = Validator::new(Wallet::generate(), builder.clone());
+    let mut forged = received.clone();
+    forged.validators = vec![(forger.wallet.public_key, forger.account())];
+    forged.active_set = vec![(forger.wallet.public_key, forger.account().stake)];
+    let forged_state = forged.restore(&AccountsDB::new());
+    let forged_child = Block::extending(&tip, vec![]).with_state_root(forged_state.state_root()).with_proposer(&forger.wallet);
+    let votes = vec![Vote::new(forged_child.hash, forged_child.slot(), &forger.wallet)];
+    let forged_certificate = Quorum::aggregate(forged_child.hash, forged_child.slot(), votes, &forged_state).unwrap().certificate(&forged_state).unwrap();
+    assert!(forged.verify(&forged_state, &forged_child, &forged_certificate, &forged_state).is_ok(), "The forgery checks out against itself");
+    assert!(forged.verify(&forged_state, &forged_child, &forged_certificate, &trusted).is_err(), "But not against validators we trust");
+    assert_eq!(received.verify(&received.restore(&AccountsDB::new()), &child, &certificate, &AccountsDB::new()).err(), Some("Certificate signer has no BLS key"));
+
+    let fresh_chain = Arc::new(RwLock::new(Blockchain::new()));
+    let fresh_db = Arc::new(RwLock::new(trusted));
+    load_snapshot(&fresh_chain, &fresh_db, &received, &child, &certificate).unwrap();
+    assert_eq!(fresh_chain.read().unwrap().height(), 3);
+    assert_eq!(fresh_db.read().unwrap().get_account(&account2.public_key).unwrap().balance, 30);
+
+    // Only blocks after the snapshot get applied
+    fresh_chain.write().unwrap().apply(child.clone(), &mut fresh_db.write().unwrap()).unwrap();
+    assert_eq!(fresh_chain.read().unwrap().tip().hash, child.hash);
+    assert_eq!(fresh_chain.read().unwrap().block_at(4).map(|block| block.hash), Some(child.hash));
+    assert!(fresh_chain.read().unwrap().block_at(2).is_none(), "Nothing is kept below the snapshot");
+    assert_eq!(fresh_db.read().unwrap().state_root(), db.read().unwrap().state_root());
+}
+
+#[test]
+fn test_compressed_storage() {
+    let (validator1, _v, db, _) = setup_validators();
+    let builder = &validator1.builder;
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let mut blocks = vec![builder.build_genesis()];
+    for nonce in 0..4 {
+        let mut tx = MemoTransaction::new(account1.public_key, vec![7; 512], nonce);
+        tx.sign(&account1);
+        let block = Block::extending(blocks.last().unwrap(), vec![Transaction::from(tx)]);
+        builder.chain.write().unwrap().apply(block.clone(), &mut db.write().unwrap()).unwrap();
+        blocks.push(block);
+    }
+
+    let payload = borsh::to_vec(&blocks[1]).unwrap();
+    for codec in [Codec::None, Codec::Lz4, Codec::Zstd] {
+        let compressed = compress(codec, &payload).unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), payload);
+        if codec != Codec::None {
+            assert!(compressed.len() < payload.len(), "{:?} should shrink repetitive data", codec);
+        }
+    }
+    let mut future = compress(Codec::Zstd, &payload).unwrap();
+    future[4] += 1;
+    assert!(decompress(&future).is_err(), "Unknown format versions are rejected");
+    assert!(decompress(&payload).is_err(), "Data without the header is rejected");
+
+    let path = std::env::temp_dir().join(format!("litechain-snapshot-{}.bin", account2.address));
+    let snapshot = Snapshot::capture(&builder.chain.read().unwrap(), &db.read().unwrap());
+    snapshot.save(&path, Codec::Lz4).unwrap();
+    let loaded = Snapshot::load(&path).unwrap();
+    assert_eq!(loaded.block.hash, snapshot.block.hash);
+    assert_eq!(loaded.restore(&AccountsDB::new()).state_root(), db.read().unwrap().state_root());
+    std::fs::remove_file(&path).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("litechain-archive-{}", account2.address));
+    let pruning = PruningConfig { keep_blocks: Some(2), archive_dir: Some(dir.clone()), ..PruningConfig::default() };
+    pruning.prune(builder);
+    assert!(builder.chain.read().unwrap().block_at(2).unwrap().transactions.is_empty());
+
+    let archive = BlockArchive::open(&dir, Codec::Zstd).unwrap();
+    let archived = archive.load(2).unwrap().expect("Pruned blocks are archived");
+    assert_eq!(archived.hash, blocks[2].hash);
+    assert_eq!(archived.transactions.len(), 1, "Archived blocks keep their transactions");
+    assert!(archive.load(4).unwrap().is_none(), "Blocks within the window aren't archived");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_chain_export_import() {
+    let (alice, bob) = (Wallet::generate(), Wallet::generate());
+    let config = GenesisConfig {
+        accounts: vec![
+            GenesisAccount { address: alice.address.clone(), balance: 1000 },
+            GenesisAccount { address: bob.address.clone(), balance: 0 },
+        ],
+        ..GenesisConfig::default()
+    };
+    let (mut chain, mut db) = Blockchain::from_genesis(&config).unwrap();
+    for nonce in 0..3 {
+        let mut tx = TransferTransaction::new(bob.public_key, alice.public_key, 100, nonce);
+        tx.sign(&alice);
+        let block = Block::extending(chain.tip(), vec![Transaction::from(tx)]);
+        chain.apply(block.clone(), &mut db).unwrap();
+        chain.credit_rewards(&block.hash, vec![(bob.public_key, 5)], &mut db).unwrap();
+    }
+
+    let path = std::env::temp_dir().join(format!("litechain-export-{}.json", alice.address));
+    chain.export(&path).unwrap();
+    let (imported, imported_db) = Blockchain::import(&path).unwrap();
+    assert_eq!(imported.tip().hash, chain.tip().hash);
+    assert_eq!(imported.genesis_config(), &config);
+    assert_eq!(imported_db.state_root(), db.state_root(), "Rewards are replayed along with the blocks");
+    assert_eq!(imported_db.get_account(&bob.public_key).unwrap().balance, 315);
+    assert_eq!(imported_db.total_supply, db.total_supply);
+
+    std::fs::write(&path, serde_json::json!({ "format": "something-else", "version": 1 }).to_string()).unwrap();
+    assert_eq!(Blockchain::import(&path).err().map(|e| e.kind()), Some(std::io::ErrorKind::InvalidData));
+    std::fs::remove_file(&path).unwrap();
+
+    chain.prune(2);
+    assert!(chain.export(&path).is_err(), "A pruned chain can't be exported in full");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_metrics() {
+    let metrics = Arc::new(Metrics::new());
+    let mempool = Arc::new(RwLock::new(Mempool::new().with_metrics(Arc::clone(&metrics))));
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let builder = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new())))
+        .with_metrics(Arc::clone(&metrics));
+    let validator = Validator::new(Wallet::generate(), builder.clone());
+    db.read().unwrap().add_validator(validator.wallet.public_key, validator.account());
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    for nonce in 0..2 {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        mempool.read().unwrap().send_transaction(Transaction::from(tx)).unwrap();
+    }
+    assert_eq!(metrics.mempool_size.get(), 2);
+    assert_eq!(metrics.transactions_received.get(), 2);
+
+    let handle = validator.start(Duration::from_millis(10));
+    let chain = Arc::clone(&builder.chain);
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 1), "Block should finalize");
+    assert!(handle.join().is_ok());
+
+    assert_eq!(metrics.mempool_size.get(), 0, "Draining the pool should show in its size");
+    assert_eq!(metrics.blocks_built.get(), 1);
+    assert_eq!(metrics.blocks_finalized.get(), 1);
+    assert_eq!(metrics.transactions_finalized.get(), 2);
+    assert_eq!(metrics.votes.get(), 1);
+    assert_eq!(metrics.block_build_time.count(), 1);
+    assert_eq!(metrics.finalization_latency.count(), 1);
+
+    // Scraped over the RPC server's HTTP listener
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let server = tokio::spawn(RpcServer::new(builder).serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+
+    let response = tokio::task::spawn_blocking(move || {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        std::io::Write::write_all(&mut stream, b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+        response
+    }).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("text/plain; version=0.0.4"));
+    assert!(response.contains("\nlitechain_transactions_finalized_total 2\n"));
+    assert!(response.contains("\nlitechain_block_build_seconds_count 1\n"));
+    assert!(response.contains("litechain_finalization_seconds_bucket{le=\"+Inf\"} 1"));
+
+    shutdown_tx.send(true).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_client() {
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    // Single transactions go out without waiting for a full block
+    let config = ChainConfig { partial_block_timeout_ms: Some(0), ..Default::default() };
+    let builder = BlockBuilder::new(Arc::default(), Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new()))).with_config(config);
+    let validator = Validator::new(Wallet::generate(), builder);
+    db.read().unwrap().add_validator(validator.wallet.public_key, validator.account());
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let server = tokio::spawn(RpcServer::new(validator.builder.clone()).serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+
+    tokio::task::spawn_blocking(move || {
+        let client = LitechainClient::new(addr.to_string());
+        assert_eq!(client.get_balance(&account1.public_key).unwrap(), 100);
+        assert_eq!(client.get_account(&account2.public_key).unwrap(), Some(account2.account()));
+        assert_eq!(client.get_account(&Wallet::generate().public_key).unwrap(), None);
+        assert_eq!(client.get_block_height().unwrap(), 0);
+        assert!(client.get_validator(&validator.wallet.public_key).unwrap().is_some());
+
+        let hash = client.transfer(&account1, &account2.public_key, 40, 0).unwrap();
+        assert_eq!(client.get_transaction_status(&hash).unwrap(), TransactionStatus::Pending);
+        // Signing is deterministic, so this is the same transaction again
+        let mut resent = TransferTransaction::new(account2.public_key, account1.public_key, 40, 0);
+        resent.sign(&account1);
+        let error = client.send_transaction(&Transaction::from(resent)).unwrap_err();
+        assert!(matches!(error, ClientError::Rpc(RpcError { code: SERVER_ERROR, .. })), "A transaction already pending should be refused");
+
+        let handle = validator.start(Duration::from_millis(10));
+        let TransactionStatus::Included { block_hash, height } = client.poll_confirmation(&hash, Duration::from_secs(10)).unwrap() else {
+            panic!("Transfer should land in a block");
+        };
+        assert!(handle.join().is_ok());
+        assert_eq!(height, 1);
+        assert_eq!(client.get_latest_blockhash().unwrap(), Some(block_hash));
+        let block = client.get_block(1).unwrap().expect("Block should be served");
+        assert_eq!(block.hash, block_hash);
+        assert_eq!(block.transactions[0].hash(), hash);
+        assert_eq!(client.get_block_by_hash(&block_hash).unwrap().map(|block| block.hash), Some(block.hash));
+        assert_eq!(client.get_balance(&account2.public_key).unwrap(), 40);
+        assert_eq!(client.get_balance(&account1.public_key).unwrap(), 60);
+
+        let error = client.request::<serde_json::Value>("getNothing", vec![]).unwrap_err();
+        assert!(matches!(error, ClientError::Rpc(RpcError { code: METHOD_NOT_FOUND, .. })));
+    }).await.unwrap();
+
+    shutdown_tx.send(true).unwrap();
+    server.await.unwrap().unwrap();
+
+    let error = LitechainClient::new(addr.to_string()).with_timeout(Duration::from_secs(1)).get_block_height().unwrap_err();
+    assert!(matches!(error, ClientError::Io(_)), "Nothing should be listening once the server is down");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_grpc() {
+    let events = EventBus::default();
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let mempool = Arc::new(RwLock::new(Mempool::new().with_events(events.clone())));
+    let config = ChainConfig { partial_block_timeout_ms: Some(0), ..Default::default() };
+    let builder = BlockBuilder::new(mempool, Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new()))).with_config(config).with_events(events);
+    let validator = Validator::new(Wallet::generate(), builder.clone());
+    db.read().unwrap().add_validator(validator.wallet.public_key, validator.account());
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let server = tokio::spawn(GrpcServer::new(RpcServer::new(builder)).serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+    let mut client = NodeClient::connect(format!("http://{}", addr)).await.unwrap();
+
+    let account_request = |pubkey: &Pubkey| proto::AccountRequest { pubkey: pubkey.to_vec(), commitment: proto::Commitment::Processed.into() };
+    assert_eq!(client.get_balance(account_request(&account1.public_key)).await.unwrap().into_inner().balance, 100);
+    let account = client.get_account(account_request(&account2.public_key)).await.unwrap().into_inner().account;
+    assert_eq!(account.map(|account| account.public_key), Some(account2.public_key.to_vec()));
+    assert_eq!(client.get_account(account_request(&Wallet::generate().public_key)).await.unwrap().into_inner().account, None);
+    let error = client.get_balance(proto::AccountRequest { pubkey: vec![1; 3], commitment: 0 }).await.unwrap_err();
+    assert_eq!(error.code(), tonic::Code::InvalidArgument, "RPC errors should carry over as statuses");
+
+    // Streams opened before the transaction goes in see it admitted, finalized & paid out
+    let mut blocks = client.subscribe_blocks(proto::SubscribeBlocksRequest {}).await.unwrap().into_inner();
+    let mut admitted = client.subscribe_transactions(proto::SubscribeTransactionsRequest {}).await.unwrap().into_inner();
+    let accounts = proto::SubscribeAccountsRequest { pubkeys: vec![account2.public_key.to_vec()] };
+    let mut updates = client.subscribe_accounts(accounts).await.unwrap().into_inner();
+
+    let transfer = |amt: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, amt, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    };
+    let tx = transfer(40, 0);
+    let sent = client.send_transaction(proto::SendTransactionRequest { transaction: tx.to_bytes(), skip_preflight: false }).await.unwrap().into_inner();
+    assert_eq!(sent.hash, tx.hash().to_vec());
+    let next = admitted.message().await.unwrap().expect("Admitted transaction should be streamed");
+    assert_eq!((next.id, next.hash), (sent.id, sent.hash.clone()));
+    let status_request = proto::TransactionStatusRequest { id: sent.hash.clone(), commitment: proto::Commitment::Processed.into() };
+    let status = client.get_transaction_status(status_request.clone()).await.unwrap().into_inner();
+    assert!(matches!(status.status, Some(proto::transaction_status::Status::Pending(_))));
+
+    // Submissions over a stream are each answered, & the accepted ones followed until they land
+    let streamed = transfer(10, 1);
+    let submissions = vec![
+        proto::SendTransactionRequest { transaction: vec![1, 2, 3], skip_preflight: false },
+        proto::SendTransactionRequest { transaction: streamed.to_bytes(), skip_preflight: false },
+    ];
+    let mut submitted = client.stream_transactions(tokio_stream::iter(submissions)).await.unwrap().into_inner();
+    let rejected = submitted.message().await.unwrap().unwrap();
+    assert!(rejected.hash.is_empty() && matches!(rejected.update, Some(proto::submission_update::Update::Rejected(_))));
+    let accepted = submitted.message().await.unwrap().unwrap();
+    let Some(proto::submission_update::Update::Accepted(accepted_as)) = accepted.update else { panic!("Valid transaction should be accepted") };
+    assert_eq!((accepted.hash, accepted_as.hash), (streamed.hash().to_vec(), streamed.hash().to_vec()));
+    assert_eq!(admitted.message().await.unwrap().unwrap().hash, streamed.hash().to_vec());
+
+    let handle = validator.start(Duration::from_millis(10));
+    let block = tokio::time::timeout(Duration::from_secs(10), blocks.message()).await.unwrap().unwrap().expect("Finalized block should be streamed");
+    assert!(handle.join().is_ok());
+    assert_eq!(block.height, 1);
+    assert_eq!(block.transactions, vec![tx.to_bytes(), streamed.to_bytes()]);
+    assert_eq!(Block::from_bytes(&block.encoded).unwrap().hash.to_vec(), block.hash);
+    let update = updates.message().await.unwrap().expect("Balance change should be streamed");
+    assert_eq!((update.pubkey, update.height, update.balance), (account2.public_key.to_vec(), 1, Some(50)));
+    let landed = tokio::time::timeout(Duration::from_secs(10), submitted.message()).await.unwrap().unwrap().unwrap();
+    let Some(proto::submission_update::Update::Status(proto::TransactionStatus { status: Some(proto::transaction_status::Status::Included(included)) })) = landed.update else {
+        panic!("Streamed transaction should be followed into its block")
+    };
+    assert_eq!((landed.hash, included.block_hash, included.height), (streamed.hash().to_vec(), block.hash.clone(), 1));
+    assert!(matches!(submitted.message().await, Ok(None)), "Submission stream should end once nothing is pending");
+
+    let status = client.get_transaction_status(status_request).await.unwrap().into_inner();
+    let Some(proto::transaction_status::Status::Included(included)) = status.status else { panic!("Transfer should be in a block") };
+    assert_eq!((included.block_hash, included.height), (block.hash.clone(), 1));
+    let served = client.get_block(proto::BlockRequest { height: 1, commitment: 0 }).await.unwrap().into_inner().block;
+    assert_eq!(served.as_ref(), Some(&block));
+    assert_eq!(client.get_balance(account_request(&account2.public_key)).await.unwrap().into_inner().balance, 50);
+
+    // The rest of the queries answer as their JSON-RPC methods do
+    let found = client.get_transaction(proto::TransactionStatusRequest { id: streamed.hash().to_vec(), commitment: 0 }).await.unwrap().into_inner();
+    let found = found.transaction.expect("Included transaction should be found");
+    assert_eq!((found.transaction, found.block_hash, found.height, found.index), (streamed.to_bytes(), block.hash.clone(), 1, 1));
+    let headers = client.get_block_headers(proto::RangeRequest { start: 0, end: 1, commitment: 0 }).await.unwrap().into_inner().headers;
+    let header = headers.last().expect("Headers should be served");
+    assert_eq!((&header.hash, header.height), (&block.hash, 1));
+    assert_eq!(Block::from_bytes(&block.encoded).unwrap().header.hash().to_vec(), header.hash);
+    let proven = client.get_account_with_proof(account_request(&account2.public_key)).await.unwrap().into_inner();
+    assert_eq!((proven.account.map(|account| account.balance), proven.state_root.len()), (Some(50), 32));
+    assert!(proven.proof.is_some());
+    let validator_info = client.get_validator(account_request(&validator.wallet.public_key)).await.unwrap().into_inner().validator;
+    assert_eq!(validator_info.map(|validator| validator.public_key), Some(validator.wallet.public_key.to_vec()));
+    assert_eq!(client.get_epoch_info(proto::StateRequest::default()).await.unwrap().into_inner().block_height, 1);
+    let page = proto::PageRequest { offset: 0, limit: 10, commitment: 0 };
+    assert_eq!(client.get_accounts(page).await.unwrap().into_inner().accounts.len(), db.read().unwrap().account_count());
+    let diff = client.get_state_diff(proto::BlockByHashRequest { hash: block.hash.clone(), commitment: 0 }).await.unwrap().into_inner().diff;
+    assert!(diff.expect("Executed block should have a diff").balances.iter().any(|change| change.pubkey == account2.public_key.to_vec() && change.new == Some(50)));
+    let simulated = client.simulate_transaction(proto::SimulateTransactionRequest { transaction: transfer(5, 2).to_bytes(), commitment: 0 }).await.unwrap().into_inner();
+    assert_eq!(simulated.error, None);
+    assert!(simulated.changes.is_some_and(|changes| !changes.balances.is_empty()));
+
+    // Streams still open end with the server rather than holding up its shutdown
+    shutdown_tx.send(true).unwrap();
+    assert!(matches!(admitted.message().await, Ok(None)), "Streams should end when the server stops");
+    drop(client);
+    server.await.unwrap().unwrap();
+
+    // Calls are charged to the same quotas as JSON-RPC
+    let unlimited = Quota { requests_per_second: 0, request_burst: 0, bytes_per_second: 0, byte_burst: 0 };
+    let config = RateLimitConfig {
+        per_ip: Quota { requests_per_second: 1, request_burst: 2, ..unlimited },
+        api_keys: BTreeMap::from([("partner".to_string(), unlimited)]),
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let rpc = RpcServer::new(validator.builder.clone()).with_rate_limit(config);
+    let server = tokio::spawn(GrpcServer::new(rpc).serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+    let mut client = NodeClient::connect(format!("http://{}", addr)).await.unwrap();
+    for _ in 0..2 {
+        assert!(client.get_block_height(proto::StateRequest::default()).await.is_ok(), "Calls within the burst should go through");
+    }
+    let error = client.get_block_height(proto::StateRequest::default()).await.unwrap_err();
+    assert_eq!(error.code(), tonic::Code::ResourceExhausted);
+    let retry_after_ms: u64 = error.metadata().get("retry-after-ms").and_then(|wait| wait.to_str().ok()?.parse().ok()).expect("Throttled calls say when to retry");
+    assert!(retry_after_ms > 0 && retry_after_ms <= 1000);
+    assert!(client.subscribe_transactions(proto::SubscribeTransactionsRequest {}).await.is_err(), "Opening a stream should be charged too");
+    for _ in 0..5 {
+        let mut request = tonic::Request::new(proto::StateRequest::default());
+        request.metadata_mut().insert("x-api-key", "partner".parse().unwrap());
+        assert!(client.get_block_height(request).await.is_ok(), "Known API keys should be charged to themselves");
+    }
+    shutdown_tx.send(true).unwrap();
+    drop(client);
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send_and_confirm() {
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let mempool = Arc::new(RwLock::new(Mempool::new()));
+    let config = ChainConfig { partial_block_timeout_ms: Some(0), ..Default::default() };
+    let builder = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new()))).with_config(config);
+    let validator = Validator::new(Wallet::generate(), builder);
+    db.read().unwrap().add_validator(validator.wallet.public_key, validator.account());
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let server = tokio::spawn(RpcServer::new(validator.builder.clone()).serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+
+    tokio::task::spawn_blocking(move || {
+        let client = LitechainClient::new(addr.to_string())
+            .with_commitment(Commitment::Confirmed)
+            .with_rebroadcast_interval(Duration::from_millis(20));
+
+        let unsigned = Transaction::from(TransferTransaction::new(account2.public_key, account1.public_key, 40, 0));
+        let outcome = client.send_and_confirm(&unsigned, Duration::from_secs(1)).unwrap();
+        assert!(matches!(outcome, SendOutcome::Rejected(RpcError { code: SERVER_ERROR, .. })), "Unsigned transactions should be refused");
+
+        // Nobody is producing blocks yet
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 40, 0);
+        tx.sign(&account1);
+        let tx = Transaction::from(tx);
+        assert_eq!(client.send_and_confirm(&tx, Duration::from_millis(200)).unwrap(), SendOutcome::Expired);
+        assert_eq!(mempool.read().unwrap().pool.len(), 1, "An expired transaction is left pending");
+
+        // Lost by the node, so it has to be sent again
+        mempool.read().unwrap().drain_for_block(usize::MAX, usize::MAX);
+        let handle = validator.start(Duration::from_millis(10));
+        let outcome = client.send_and_confirm(&tx, Duration::from_secs(10)).unwrap();
+        assert!(handle.join().is_ok());
+        let SendOutcome::Confirmed { block_hash, height } = outcome else {
+            panic!("Transfer should be confirmed, got {:?}", outcome);
+        };
+        assert_eq!(height, 1);
+        assert_eq!(client.get_latest_blockhash().unwrap(), Some(block_hash));
+        assert_eq!(client.get_balance(&account2.public_key).unwrap(), 40);
+
+        // Already in a block, so it isn't sent again
+        assert_eq!(client.send_and_confirm(&tx, Duration::from_secs(1)).unwrap(), SendOutcome::Confirmed { block_hash, height });
+    }).await.unwrap();
+
+    shutdown_tx.send(true).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_quic_ingestion() {
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let mempool = Arc::new(RwLock::new(Mempool::new()));
+    let builder = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new())));
+    let validator = Wallet::generate();
+    db.read().unwrap().add_validator(validator.public_key, ValidatorAccount::new(validator.public_key));
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let config = QuicConfig { staked_connections: 1, unstaked_connections: 1, ..Default::default() };
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let server = tokio::spawn(QuicServer::new(builder).with_config(config).serve(socket, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+
+    let anonymous = QuicClient::connect(addr, None).await.unwrap();
+    let error = QuicClient::connect(addr, None).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused, "Unstaked connections should be capped");
+    let error = QuicClient::connect(addr, Some(&account2)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused, "A key without stake counts as unstaked");
+    // Validators have slots of their own
+    let staked = QuicClient::connect(addr, Some(&validator)).await.unwrap();
+    let error = QuicClient::connect(addr, Some(&validator)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused, "A validator is held to its share");
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, 0);
+    tx.sign(&account1);
+    let tx = Transaction::from(tx);
+    anonymous.send_transaction(&tx).await.unwrap();
+    let mut tx2 = TransferTransaction::new(account2.public_key, account1.public_key, 10, 1);
+    tx2.sign(&account1);
+    let tx2 = Transaction::from(tx2);
+    staked.send_transaction(&tx2).await.unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !(mempool.read().unwrap().contains(&tx) && mempool.read().unwrap().contains(&tx2)) {
+        assert!(Instant::now() < deadline, "Transactions sent over QUIC should reach the mempool");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // A closed connection gives its slot back
+    anonymous.close().await;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let reconnected = loop {
+        match QuicClient::connect(addr, None).await {
+            Ok(client) => break client,
+            Err(_) => assert!(Instant::now() < deadline, "Slot should free up once the connection closes"),
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    drop((reconnected, staked));
+    shutdown_tx.send(true).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rest_api() {
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let mempool = Arc::new(RwLock::new(Mempool::new()));
+    let config = ChainConfig { partial_block_timeout_ms: Some(0), ..Default::default() };
+    let builder = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new()))).with_config(config);
+    let validator = Validator::new(Wallet::generate(), builder);
+    db.read().unwrap().add_validator(validator.wallet.public_key, validator.account());
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 40, 0);
+    tx.sign(&account1);
+    let tx = Transaction::from(tx);
+    mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    let handle = validator.start(Duration::from_millis(10));
+    let chain = Arc::clone(&validator.builder.chain);
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 1), "Transfer should land in a block");
+    assert!(handle.join().is_ok());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let server = tokio::spawn(RpcServer::new(validator.builder.clone()).serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+
+    tokio::task::spawn_blocking(move || {
+        let get = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+            std::io::Write::write_all(&mut stream, request.as_bytes()).unwrap();
+            let mut response = String::new();
+            std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+            let status = response[9..12].parse::<u16>().unwrap();
+            let (_, body) = response.split_once("\r\n\r\n").unwrap();
+            (status, serde_json::from_str::<serde_json::Value>(body).unwrap())
+        };
+        let sender = hex::encode(account1.public_key);
+
+        let (status, body) = get(&format!("/accounts/{}", hex::encode(account2.public_key)));
+        assert_eq!(status, 200);
+        assert_eq!(body["balance"], 40);
+        assert_eq!(get(&format!("/accounts/{}", hex::encode(Wallet::generate().public_key))).0, 404);
+        assert_eq!(get("/accounts/not-hex").0, 400);
+
+        let (status, block) = get("/blocks/1");
+        assert_eq!(status, 200);
+        assert_eq!(block["header"]["height"], 1);
+        assert_eq!(get("/blocks/2").0, 404);
+        let (_, page) = get("/blocks?start=0&limit=1");
+        assert_eq!(page["items"].as_array().unwrap().len(), 1);
+        assert_eq!(page["next"], serde_json::json!({ "start": 1, "limit": 1 }));
+        let (_, page) = get("/blocks?start=1&limit=1");
+        assert_eq!(page["items"][0], block);
+        assert_eq!(page["next"], serde_json::Value::Null, "The tip is the last page");
+
+        let (status, found) = get(&format!("/transactions/{}", hex::encode(tx.hash())));
+        assert_eq!(status, 200);
+        assert_eq!(found["included"]["height"], 1);
+        assert_eq!(found["included"]["index"], 0);
+        assert_eq!(found["included"]["transaction"], serde_json::json!(tx));
+        assert_eq!(get(&format!("/transactions/{}", hex::encode([7u8; 32]))).0, 404);
+
+        let (_, history) = get(&format!("/accounts/{sender}/transactions?limit=1"));
+        assert_eq!(history["items"], serde_json::json!([{ "height": 1, "index": 0 }]));
+        let next = &history["next"];
+        let (_, rest) = get(&format!("/accounts/{sender}/transactions?limit=1&before_height={}&before_index={}", next["before_height"], next["before_index"]));
+        assert_eq!(rest["items"], serde_json::json!([]));
+        assert_eq!(rest["next"], serde_json::Value::Null);
+    }).await.unwrap();
+
+    shutdown_tx.send(true).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rpc_rate_limit() {
+    let (validator, _v, _db, _mempool) = setup_validators();
+    let unlimited = Quota { requests_per_second: 0, request_burst: 0, bytes_per_second: 0, byte_burst: 0 };
+    let config = RateLimitConfig {
+        per_ip: Quota { requests_per_second: 1, request_burst: 2, ..unlimited },
+        api_keys: BTreeMap::from([("partner".to_string(), unlimited)]),
+    };
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let rpc = RpcServer::new(validator.builder.clone()).with_rate_limit(config);
+    let server = tokio::spawn(rpc.serve(listener, async move { let _ = shutdown_rx.wait_for(|stop| *stop).await; }));
+
+    tokio::task::spawn_blocking(move || {
+        let client = LitechainClient::new(addr.to_string());
+        for _ in 0..2 {
+            assert!(client.get_block_height().is_ok(), "Requests within the burst should go through");
+        }
+        let Err(ClientError::Rpc(error)) = client.get_block_height() else { panic!("Third request should be throttled") };
+        assert_eq!(error.code, RATE_LIMITED);
+        let retry_after_ms = error.data.and_then(|data| data["retryAfterMs"].as_u64()).expect("Throttle errors say when to retry");
+        assert!(retry_after_ms > 0 && retry_after_ms <= 1000);
+
+        // Unknown keys are charged to the IP, known ones to themselves
+        assert!(client.clone().with_api_key("guess").get_block_height().is_err());
+        for _ in 0..5 {
+            assert!(client.clone().with_api_key("partner").get_block_height().is_ok());
+        }
+    }).await.unwrap();
+
+    shutdown_tx.send(true).unwrap();
+    server.await.unwrap().unwrap();
+
+    // Bandwidth: a request may overdraw the byte allowance, but the next waits for it to pay off
+    let limiter = RateLimiter::new(RateLimitConfig {
+        per_ip: Quota { requests_per_second: 0, request_burst: 0, bytes_per_second: 100, byte_burst: 100 },
+        api_keys: BTreeMap::new(),
     });
+    let key = limiter.key("127.0.0.1".parse().unwrap(), None);
+    assert_eq!(limiter.check(&key, 150), Ok(()));
+    let wait = limiter.check(&key, 10).unwrap_err();
+    assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
 
-    let validator2_handle = thread::spawn(move || {
-        let _ = validator2.start(Duration::from_millis(100));
+    // Past the cap, whoever was heard from least recently is forgotten rather than the limiter growing
+    let limiter = RateLimiter::new(RateLimitConfig {
+        per_ip: Quota { requests_per_second: 1, request_burst: 1, bytes_per_second: 0, byte_burst: 0 },
+        api_keys: BTreeMap::new(),
     });
+    let ip = |n: usize| limiter.key(std::net::IpAddr::from((n as u32).to_be_bytes()), None);
+    for n in 1..MAX_TRACKED_CLIENTS {
+        limiter.check(&ip(n), 0).unwrap();
+    }
+    let busy = ip(0);
+    assert!(limiter.check(&busy, 0).is_ok());
+    assert!(limiter.check(&busy, 0).is_err(), "The burst should be used up");
+    assert_eq!(limiter.tracked_clients(), MAX_TRACKED_CLIENTS);
+    limiter.check(&ip(MAX_TRACKED_CLIENTS), 0).unwrap();
+    assert_eq!(limiter.tracked_clients(), MAX_TRACKED_CLIENTS, "The cap should hold");
+    assert!(limiter.check(&busy, 0).is_err(), "A recent client should still be tracked");
+    assert!(limiter.check(&ip(1), 0).is_ok(), "The least recent client should have been forgotten");
+}
 
-    validator1_handle.join().unwrap();
-    validator2_handle.join().unwrap();
-    
-    let mempool_lock = mempool.read().unwrap();
+// Collects formatted log output so tests can look at it
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
 
-    assert_eq!(mempool_lock.pool.len(), 0, "Leftover transactions in mempool");
-}
\ No newline at end of file
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_structured_logging() {
+    let (validator1, _v, db, mempool) = setup_validators();
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    // A full block's worth, so the builder doesn't wait for more
+    let overdrafts: Vec<Transaction> = (0..2).map(|nonce| {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 1000, nonce);
+        tx.sign(&account1);
+        Transaction::from(tx)
+    }).collect();
+    for tx in &overdrafts {
+        mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+    }
+
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        let built = validator1.builder.build(Block::create_genesis().hash, &validator1.wallet);
+        assert!(matches!(built, Ok(None)), "Every transaction is invalid");
+    });
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let line = logs.lines().find(|line| line.contains("Dropping invalid transaction")).expect("The dropped transaction should be logged");
+    assert!(line.contains("WARN"));
+    assert!(line.contains(&format!("build{{parent={}}}", hex::encode(Block::create_genesis().hash))), "Events should carry the build span");
+    assert!(overdrafts.iter().any(|tx| line.contains(&format!("tx={}", hex::encode(tx.hash())))));
+}
+
+#[test]
+fn test_event_bus() {
+    let events = EventBus::default();
+    let mempool = Arc::new(RwLock::new(Mempool::new().with_events(events.clone())));
+    let db = Arc::new(RwLock::new(AccountsDB::new()));
+    let builder = BlockBuilder::new(Arc::clone(&mempool), Arc::clone(&db), Arc::new(RwLock::new(Blockchain::new())))
+        .with_events(events.clone());
+    let validator = Validator::new(Wallet::generate(), builder.clone());
+    db.read().unwrap().add_validator(validator.wallet.public_key, validator.account());
+    let (account1, account2) = setup_accounts(&db.read().unwrap());
+    let _ = db.read().unwrap().increase_account_balance(&account1.public_key, 100);
+
+    let mut subscriber = events.subscribe();
+    let mut transactions = vec![];
+    for nonce in 0..2 {
+        let mut tx = TransferTransaction::new(account2.public_key, account1.public_key, 10, nonce);
+        tx.sign(&account1);
+        let tx = Transaction::from(tx);
+        let id = mempool.read().unwrap().send_transaction(tx.clone()).unwrap();
+        transactions.push((tx.hash(), id));
+    }
+
+    let handle = validator.start(Duration::from_millis(10));
+    let chain = Arc::clone(&builder.chain);
+    assert!(wait_until(Duration::from_secs(10), || chain.read().unwrap().height() == 1), "Block should finalize");
+    assert!(handle.join().is_ok());
+    let block = chain.read().unwrap().tip().clone();
+
+    let mut received = vec![];
+    while let Ok(event) = subscriber.try_recv() {
+        received.push(event);
+    }
+    let expected = [
+        Event::TransactionAdmitted { hash: transactions[0].0, id: transactions[0].1 },
+        Event::TransactionAdmitted { hash: transactions[1].0, id: transactions[1].1 },
+        Event::BlockProposed { hash: block.hash, height: 1, slot: block.slot(), transactions: 2 },
+        Event::BlockFinalized { hash: block.hash, height: 1 },
+    ];
+    assert_eq!(received[..4], expected, "Lifecycle events should arrive in order");
+    assert!(received.contains(&Event::AccountUpdated { pubkey: account1.public_key, height: 1, balance: Some(80) }));
+    assert!(received.contains(&Event::AccountUpdated { pubkey: account2.public_key, height: 1, balance: Some(20) }));
+
+    // Emitting with nobody subscribed is fine
+    drop(subscriber);
+    events.emit(Event::BlockFinalized { hash: block.hash, height: 1 });
+}
+
+// Mirrors balances & the canonical chain the way an external indexer would
+#[derive(Default)]
+struct MirrorPlugin {
+    balances: std::sync::Mutex<std::collections::HashMap<Pubkey, u64>>,
+    blocks: std::sync::Mutex<Vec<(u64, crate::structures::Blockhash)>>,
+}
+
+impl StatePlugin for MirrorPlugin {
+    fn name(&self) -> &str {
+        "mirror"
+    }
+
+    fn account_updated(&self, pubkey: &Pubkey, account: Option<&crate::structures::UserAccount>, _height: u64) {
+        let mut balances = self.balances.lock().unwrap();
+        match account {
+            Some(account) => { balances.insert(*pubkey, account.balance); }
+            None => { balances.remove(pubkey); }
+        }
+    }
+
+    fn block_finalized(&self, block: &Block, height: u64) {
+        self.blocks.lock().unwrap().push((height, block.hash));
+    }
+
+    fn block_reverted(&self, block: &Block, height: u64) {
+        assert_eq!(self.blocks.lock().unwrap().pop(), Some((height, block.hash)), "Only the tip can be reverted");
+    }
+}
+
+#[test]
+fn test_state_plugins() {
+    let (alice, bob, carol) = (Wallet::generate(), Wallet::generate(), Wallet::generate());
+    let config = GenesisConfig {
+        accounts: vec![
+            GenesisAccount { address: alice.address.clone(), balance: 1000 },
+            GenesisAccount { address: bob.address.clone(), balance: 0 },
+        ],
+        ..GenesisConfig::default()
+    };
+    let (mut chain, mut db) = Blockchain::from_genesis(&config).unwrap();
+    let plugin = Arc::new(MirrorPlugin::default());
+    chain.add_plugin(plugin.clone());
+    assert!(format!("{:?}", chain).contains("plugins: [\"mirror\"]"));
+
+    let transfer = |amt: u64, nonce: u64| {
+        let mut tx = TransferTransaction::new(bob.public_key, alice.public_key, amt, nonce);
+        tx.sign(&alice);
+        Transaction::from(tx)
+    };
+    let block = Block::extending(chain.tip(), vec![transfer(100, 0)]);
+    chain.apply(block.clone(), &mut db).unwrap();
+    chain.credit_rewards(&block.hash, vec![(carol.public_key, 7)], &mut db).unwrap();
+    let next = Block::extending(&block, vec![transfer(50, 1)]);
+    chain.apply(next.clone(), &mut db).unwrap();
+
+    assert_eq!(*plugin.blocks.lock().unwrap(), vec![(1, block.hash), (2, next.hash)]);
+    let mirrored = |pubkey: &Pubkey| plugin.balances.lock().unwrap().get(pubkey).copied();
+    assert_eq!(mirrored(&alice.public_key), Some(850));
+    assert_eq!(mirrored(&bob.public_key), Some(150));
+    assert_eq!(mirrored(&carol.public_key), Some(7), "Reward recipients are reported too");
+
+    // A heavier fork off genesis rolls both blocks back, & the mirror follows
+    let competing = Block::extending(&chain.block_at(0).unwrap().clone(), vec![transfer(10, 0)]);
+    chain.add_votes(&competing.hash, 1_000);
+    chain.apply(competing.clone(), &mut db).unwrap();
+    assert_eq!(*plugin.blocks.lock().unwrap(), vec![(1, competing.hash)]);
+    assert_eq!(mirrored(&alice.public_key), Some(990));
+    assert_eq!(mirrored(&bob.public_key), Some(10));
+    assert_eq!(mirrored(&carol.public_key), None, "Accounts that no longer exist are reported as gone");
+}
@@ -0,0 +1,82 @@
+use crate::structures::Blockhash;
+
+// Most votes a tower holds. Past that the oldest becomes the root, which the validator never votes
+// against again.
+pub const MAX_LOCKOUT_HISTORY: usize = 31;
+
+// A precommit & how long it binds the validator to the fork it's on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lockout {
+    pub slot: u64,
+    pub block_hash: Blockhash,
+    // Votes stacked on top of this one, plus one
+    pub confirmations: u32,
+}
+
+impl Lockout {
+    // Slots the vote binds for past its own, doubling with each confirmation
+    pub fn lockout(&self) -> u64 {
+        1u64.checked_shl(self.confirmations).unwrap_or(u64::MAX)
+    }
+
+    // Last slot the vote still binds
+    pub fn expiration(&self) -> u64 {
+        self.slot.saturating_add(self.lockout())
+    }
+
+    pub fn is_locked_out_at(&self, slot: u64) -> bool {
+        self.expiration() >= slot
+    }
+}
+
+// A validator's recent precommits, after Solana's Tower BFT. Each vote stacked on top doubles the
+// lockouts of those under it, so the longer a validator sticks with a fork the longer it would have to
+// sit out before it could vote on another one. Votes whose lockout has run out fall off the top.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tower {
+    votes: Vec<Lockout>,
+    root: Option<Lockout>,
+}
+
+impl Tower {
+    pub fn votes(&self) -> &[Lockout] {
+        &self.votes
+    }
+
+    pub fn root(&self) -> Option<&Lockout> {
+        self.root.as_ref()
+    }
+
+    pub fn last_voted_slot(&self) -> Option<u64> {
+        self.votes.last().map(|vote| vote.slot)
+    }
+
+    // Whether a vote at `slot` keeps to every lockout still binding then. `is_ancestor` says whether
+    // a voted block is one the new vote's block builds on.
+    pub fn can_vote(&self, slot: u64, is_ancestor: impl Fn(&Blockhash) -> bool) -> bool {
+        if self.last_voted_slot().is_some_and(|last| slot <= last) {
+            return false
+        }
+        self.root.iter()
+            .chain(self.votes.iter().filter(|vote| vote.is_locked_out_at(slot)))
+            .all(|vote| is_ancestor(&vote.block_hash))
+    }
+
+    // Push a vote, first popping the ones that have expired by its slot
+    pub fn record(&mut self, slot: u64, block_hash: Blockhash) {
+        while self.votes.last().is_some_and(|vote| !vote.is_locked_out_at(slot)) {
+            self.votes.pop();
+        }
+        self.votes.push(Lockout { slot, block_hash, confirmations: 1 });
+
+        let depth = self.votes.len();
+        for (index, vote) in self.votes.iter_mut().enumerate() {
+            if depth > index + vote.confirmations as usize {
+                vote.confirmations += 1;
+            }
+        }
+        if self.votes.len() > MAX_LOCKOUT_HISTORY {
+            self.root = Some(self.votes.remove(0));
+        }
+    }
+}
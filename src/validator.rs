@@ -0,0 +1,232 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use tracing::{error, info, info_span, warn};
+
+use crate::{
+    builder::BlockBuilder,
+    events::Event,
+    structures::{Block, ValidatorAccount},
+    vote::{Quorum, Vote},
+    wallet::Wallet,
+};
+
+// A validator running in this process: its signing wallet plus handles to the local node state.
+// Its on-chain stake & bookkeeping live in the `ValidatorAccount` stored in `AccountsDB`.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    pub wallet: Wallet,
+    pub builder: BlockBuilder,
+}
+
+impl Validator {
+    // Registers the wallet with the builder so this validator votes on blocks proposed locally
+    pub fn new(wallet: Wallet, builder: BlockBuilder) -> Self {
+        builder.signers.insert(wallet.public_key, wallet.clone());
+        Self { wallet, builder }
+    }
+
+    // Fresh on-chain state for this validator, to be added to the db
+    pub fn account(&self) -> ValidatorAccount {
+        ValidatorAccount::new(self.wallet.public_key).with_bls_key(self.wallet.bls_public_key())
+    }
+
+    // Run the validator loop on its own thread until the returned handle is stopped
+    pub fn start(&self, interval: Duration) -> ValidatorHandle {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let validator = self.clone();
+        let flag = Arc::clone(&running);
+        let thread = thread::spawn(move || validator.run(interval, &flag));
+
+        ValidatorHandle { running, thread: Some(thread) }
+    }
+
+    fn run(&self, interval: Duration, running: &AtomicBool) -> Result<(), &'static str> {
+        while running.load(Ordering::SeqCst) {
+            thread::sleep(interval);
+            self.tick()?;
+        }
+
+        info!(validator = %self.wallet.address, "Validator shut down");
+        Ok(())
+    }
+
+    // Async variant of the validator loop, ticking on a tokio interval until `shutdown` resolves.
+    // Each slot's work takes the shared std locks, so it runs on the blocking pool rather than
+    // stalling the runtime's worker threads that networking & RPC share.
+    pub async fn run_async(&self, interval: Duration, shutdown: impl Future<Output = ()>) -> Result<(), &'static str> {
+        let mut ticker = tokio::time::interval(interval);
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = ticker.tick() => {}
+            }
+
+            let validator = self.clone();
+            tokio::task::spawn_blocking(move || validator.tick())
+                .await
+                .map_err(|_| "Validator task panicked")??;
+        }
+
+        info!(validator = %self.wallet.address, "Validator shut down");
+        Ok(())
+    }
+
+    // One slot of work: if we're the leader, build a block, collect a quorum of votes & finalize it. If
+    // the slot's leader doesn't get a block finalized in time, the slot moves on & another one leads.
+    fn tick(&self) -> Result<(), &'static str> {
+        if !self.builder.mempool.read().unwrap().pool.is_empty() {
+            self.builder.start_slot_clock();
+        }
+        let (prev_hash, tip_slot) = {
+            let chain_lock = self.builder.chain.read().unwrap();
+            (chain_lock.tip().hash, chain_lock.tip().slot())
+        };
+        let slot = self.builder.current_slot();
+        if self.builder.get_leader(slot).is_none_or(|leader| leader.public_key != self.wallet.public_key) {
+            return Ok(())
+        }
+
+        let proposed_block = match self.builder.build_for_slot(prev_hash, slot, &self.wallet) {
+            Ok(Some(block)) => block,
+            // Nothing to propose yet
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                error!(parent = %hex::encode(prev_hash), error = e, "Failed to build block");
+                return Ok(())
+            }
+        };
+
+        let proposed_at = Instant::now();
+        let _span = info_span!("slot", slot, height = proposed_block.height(), block = %hex::encode(proposed_block.hash)).entered();
+        // Prevote, then precommit once a quorum has prevoted. Only a quorum of precommits finalizes.
+        let prevotes = self.collect_votes(&proposed_block, |signer| self.builder.prevote(&proposed_block, signer));
+        let quorum = prevotes.and_then(|prevotes| {
+            self.collect_votes(&proposed_block, |signer| self.builder.precommit(&proposed_block, &prevotes, signer))
+        });
+        let certificate = quorum.as_ref().ok().and_then(|quorum| quorum.certificate(&self.builder.db.read().unwrap()).ok());
+
+        // The block's transactions were drained from the mempool when it was built, so if it doesn't
+        // finalize they need to go back
+        let quorum = match quorum {
+            Ok(quorum) => quorum,
+            Err(e) => {
+                warn!(error = e, "Block did not reach quorum");
+                self.builder.mempool.read().unwrap().requeue(proposed_block.transactions);
+                return Ok(())
+            }
+        };
+
+        let mut db_lock = self.builder.db.write().unwrap();
+        let mut chain_lock = self.builder.chain.write().unwrap();
+        // Split against pre-block stake, the same weights the quorum was counted with
+        let rewards = self.builder.rewards.split(slot, &self.wallet.public_key, &quorum, &db_lock);
+
+        chain_lock.add_votes(&proposed_block.hash, quorum.weight);
+        if let Err(e) = chain_lock.apply(proposed_block.clone(), &mut db_lock) {
+            error!(error = e, "Failed to apply finalized block");
+            drop(chain_lock);
+            self.builder.mempool.read().unwrap().requeue(proposed_block.transactions);
+            return Err(e)
+        }
+        chain_lock.credit_rewards(&proposed_block.hash, rewards, &mut db_lock)?;
+        if let Some(certificate) = certificate.clone() {
+            chain_lock.add_certificate(certificate);
+        }
+        let diff = chain_lock.state_diff(&proposed_block.hash).cloned().unwrap_or_default();
+        drop(chain_lock);
+
+        info!(transactions = proposed_block.transactions.len(), votes = quorum.votes.len(), stake = quorum.weight, "Block finalized");
+        let metrics = &self.builder.metrics;
+        metrics.blocks_finalized.inc();
+        metrics.transactions_finalized.add(proposed_block.transactions.iter().filter(|tx| tx.vote().is_none()).count() as u64);
+        metrics.votes.add(quorum.votes.len() as u64);
+        metrics.slots_skipped.add(slot - tip_slot - 1);
+        metrics.finalization_latency.observe(proposed_at.elapsed());
+
+        let height = proposed_block.height();
+        let events = &self.builder.events;
+        events.emit(Event::BlockFinalized { hash: proposed_block.hash, height });
+        for change in diff.balances {
+            events.emit(Event::AccountUpdated { pubkey: change.pubkey, height, balance: change.new });
+        }
+
+        if let Some(network) = &self.builder.network {
+            network.broadcast_block(&proposed_block);
+        }
+
+        for mut entry in db_lock.validators.iter_mut() {
+            let validator = entry.value_mut();
+            validator.update_last_finalized_hash(proposed_block.hash);
+        }
+
+        // Record the votes that finalized this block in the next one, aggregated in its header if they
+        // could be, else one by one
+        if certificate.is_none() {
+            self.builder.mempool.read().unwrap().add_votes(quorum.votes);
+        }
+
+        Ok(())
+    }
+
+    // One round of votes from the signers running here, counted against pre-block state
+    fn collect_votes(&self, block: &Block, vote: impl Fn(&Wallet) -> Option<Vote>) -> Result<Quorum, &'static str> {
+        let votes: Vec<Vote> = self.builder.signers.iter().filter_map(|signer| vote(signer.value())).collect();
+        let db_lock = self.builder.db.read().unwrap();
+        let votes = votes.into_iter().filter(|vote| db_lock.is_validator(&vote.validator)).collect();
+        Quorum::aggregate(block.hash, block.slot(), votes, &db_lock)
+    }
+
+    // Prevote for the block, see `BlockBuilder::prevote`
+    pub fn prevote(&self, block: &Block) -> Option<Vote> {
+        self.builder.prevote(block, &self.wallet)
+    }
+
+    // Precommit to the block once a quorum has prevoted it, see `BlockBuilder::precommit`
+    pub fn precommit(&self, block: &Block, prevotes: &Quorum) -> Option<Vote> {
+        self.builder.precommit(block, prevotes, &self.wallet)
+    }
+}
+
+// Control over a running validator loop. Dropping the handle stops the loop without waiting for it.
+#[derive(Debug)]
+pub struct ValidatorHandle {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), &'static str>>>,
+}
+
+impl ValidatorHandle {
+    // Ask the loop to exit after its current iteration
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst) && self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+
+    // Stop the loop & wait for it to exit, returning the error that ended it, if any
+    pub fn join(mut self) -> Result<(), &'static str> {
+        self.stop();
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| "Validator thread panicked")?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ValidatorHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,289 @@
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+use crate::{
+    bls::{self, BlsPubkey, BlsSignature},
+    db::AccountsDB,
+    structures::{Blockhash, Pubkey, TransactionSign},
+    wallet::Wallet,
+    wire,
+};
+
+// Blocks are voted on in two rounds, Tendermint style. A prevote says the block is valid; once a
+// validator sees prevotes from a quorum it precommits, & locks on the block. A quorum of precommits
+// finalizes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub enum VotePhase {
+    Prevote,
+    Precommit,
+}
+
+// A validator's signed attestation that a block at a given slot is valid. It's signed twice over: with
+// the validator's ed25519 key, & with its BLS key so it can be folded into a `QuorumCertificate`.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Vote {
+    pub block_hash: Blockhash,
+    pub slot: u64,
+    pub phase: VotePhase,
+    pub validator: Pubkey,
+    #[borsh(serialize_with = "wire::serialize_signature", deserialize_with = "wire::deserialize_signature")]
+    signature: Signature,
+    #[serde(with = "wire::byte_array")]
+    pub bls_signature: BlsSignature,
+}
+
+impl Vote {
+    // A precommit, the vote that finalizes a block
+    pub fn new(block_hash: Blockhash, slot: u64, wallet: &Wallet) -> Self {
+        Self::signed(block_hash, slot, VotePhase::Precommit, wallet)
+    }
+
+    pub fn prevote(block_hash: Blockhash, slot: u64, wallet: &Wallet) -> Self {
+        Self::signed(block_hash, slot, VotePhase::Prevote, wallet)
+    }
+
+    fn signed(block_hash: Blockhash, slot: u64, phase: VotePhase, wallet: &Wallet) -> Self {
+        let message = Self::message(&block_hash, slot, phase);
+        Vote {
+            block_hash,
+            slot,
+            phase,
+            validator: wallet.public_key,
+            signature: wallet.sign(&message),
+            bls_signature: wallet.bls_sign(&message),
+        }
+    }
+
+    // The phase is signed, so a prevote can't be passed off as a precommit
+    fn message(block_hash: &Blockhash, slot: u64, phase: VotePhase) -> Vec<u8> {
+        let mut data = vec![];
+
+        data.extend(block_hash);
+        data.extend(&slot.to_le_bytes());
+        data.push(phase as u8);
+
+        data
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    pub fn verify(&self) -> bool {
+        match PublicKey::from_bytes(&self.validator) {
+            Ok(public_key) => public_key.verify(&Self::message(&self.block_hash, self.slot, self.phase), &self.signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+// Recorded on chain as a transaction. Its payload is the vote message, so the signature that counted
+// towards a quorum is the one that authorizes the transaction.
+impl TransactionSign for Vote {
+    fn signatures(&self) -> Vec<(Pubkey, Signature)> {
+        vec![(self.validator, self.signature)]
+    }
+
+    fn sign_message(&mut self, wallet: &Wallet, message: &[u8]) {
+        self.signature = wallet.sign(message);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        Self::message(&self.block_hash, self.slot, self.phase)
+    }
+
+    fn validate(&self, db: &AccountsDB) -> bool {
+        self.validate_state(db) && self.verify_signatures()
+    }
+
+    // Only precommits are recorded, since they're what finalized the block
+    fn validate_state(&self, db: &AccountsDB) -> bool {
+        self.phase == VotePhase::Precommit && db.is_validator(&self.validator)
+    }
+
+    fn apply(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate(db) {
+            return Err("Invalid transaction in Vote execute")
+        }
+        self.apply_state(db)
+    }
+
+    // Votes move no funds, they're only recorded
+    fn apply_state(&self, db: &AccountsDB) -> Result<(), &'static str> {
+        if !self.validate_state(db) {
+            return Err("Invalid transaction in Vote execute")
+        }
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut AccountsDB) -> Result<(), &'static str> {
+        self.apply(db)
+    }
+}
+
+// How much a validator's vote counts for. Weighted by the stake it holds in the epoch's active set, so
+// validators outside it don't count at all, except while none of them has any stake (e.g. right after
+// genesis) when every one counts equally.
+pub fn voting_weight(db: &AccountsDB, validator: &Pubkey) -> u64 {
+    let candidates = db.candidates();
+    match candidates.iter().find(|(candidate, _)| candidate == validator) {
+        Some(_) if total_stake(&candidates) == 0 => 1,
+        Some((_, stake)) => *stake,
+        None => 0,
+    }
+}
+
+pub fn total_voting_weight(db: &AccountsDB) -> u64 {
+    let candidates = db.candidates();
+    match total_stake(&candidates) {
+        0 => candidates.len() as u64,
+        total_stake => total_stake,
+    }
+}
+
+fn total_stake(candidates: &[(Pubkey, u64)]) -> u64 {
+    candidates.iter().map(|(_, stake)| *stake).fold(0u64, u64::saturating_add)
+}
+
+// The block a validator precommitted at a height. Until a quorum prevotes another block at that height
+// in a later slot, it won't prevote or precommit anything else there, so a block that might have been
+// finalized can't be outvoted by validators changing their minds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoteLock {
+    pub height: u64,
+    pub slot: u64,
+    pub block_hash: Blockhash,
+}
+
+impl VoteLock {
+    // Whether this lock rules out voting for `block_hash` at `height`
+    pub fn forbids(&self, height: u64, block_hash: &Blockhash) -> bool {
+        self.height == height && self.block_hash != *block_hash
+    }
+}
+
+// Whether `weight` is more than two thirds of `db`'s voting weight. Then any two quorums share more
+// than a third of it, so they conflict only if over a third of the stake has voted twice.
+pub fn is_quorum(weight: u64, db: &AccountsDB) -> bool {
+    exceeds_two_thirds(weight, total_voting_weight(db))
+}
+
+fn exceeds_two_thirds(weight: u64, total_weight: u64) -> bool {
+    weight as u128 * 3 > total_weight as u128 * 2
+}
+
+// A verified set of votes from one phase for one block, holding more than two thirds of the total
+// voting weight
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct Quorum {
+    pub block_hash: Blockhash,
+    pub slot: u64,
+    pub phase: VotePhase,
+    pub votes: Vec<Vote>,
+    pub weight: u64,
+}
+
+impl Quorum {
+    pub fn aggregate(block_hash: Blockhash, slot: u64, votes: Vec<Vote>, db: &AccountsDB) -> Result<Self, &'static str> {
+        let phase = votes.first().map_or(VotePhase::Precommit, |vote| vote.phase);
+        let mut counted: Vec<Vote> = vec![];
+        let mut weight = 0u64;
+
+        for vote in votes {
+            if vote.block_hash != block_hash || vote.slot != slot {
+                return Err("Vote is for a different block")
+            }
+            if vote.phase != phase {
+                return Err("Votes are from different phases")
+            }
+            if !db.is_validator(&vote.validator) {
+                return Err("Vote from a non-validator")
+            }
+            if counted.iter().any(|existing| existing.validator == vote.validator) {
+                return Err("Duplicate vote")
+            }
+            if !vote.verify() {
+                return Err("Invalid vote signature")
+            }
+
+            weight = weight.saturating_add(voting_weight(db, &vote.validator));
+            counted.push(vote);
+        }
+
+        if !is_quorum(weight, db) {
+            return Err("Not enough votes for quorum")
+        }
+
+        Ok(Quorum {
+            block_hash,
+            slot,
+            phase,
+            votes: counted,
+            weight,
+        })
+    }
+
+    // Fold the votes of validators with BLS keys into one certificate, if they hold a quorum of `db`'s
+    // voting weight between them
+    pub fn certificate(&self, db: &AccountsDB) -> Result<QuorumCertificate, &'static str> {
+        if self.phase != VotePhase::Precommit {
+            return Err("Only precommits finalize a block")
+        }
+        let mut votes: Vec<&Vote> = self.votes
+            .iter()
+            .filter(|vote| db.get_validator(&vote.validator).is_some_and(|validator| validator.bls_key.is_some()))
+            .collect();
+        votes.sort_by_key(|vote| vote.validator);
+        let signatures: Vec<BlsSignature> = votes.iter().map(|vote| vote.bls_signature).collect();
+        let certificate = QuorumCertificate {
+            block_hash: self.block_hash,
+            slot: self.slot,
+            signers: votes.iter().map(|vote| vote.validator).collect(),
+            signature: bls::aggregate(&signatures).ok_or("No BLS votes to aggregate")?,
+        };
+        certificate.verify(db)?;
+        Ok(certificate)
+    }
+}
+
+// A quorum's precommits as one BLS signature: small enough for the next block's header to carry as proof the
+// block was finalized, & checkable by anyone who knows the signers' keys
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct QuorumCertificate {
+    pub block_hash: Blockhash,
+    pub slot: u64,
+    // Validators whose votes are folded in, in key order
+    pub signers: Vec<Pubkey>,
+    #[serde(with = "wire::byte_array")]
+    pub signature: BlsSignature,
+}
+
+impl QuorumCertificate {
+    // Check the signature against the signers' registered BLS keys & that they hold more than two thirds
+    // of `db`'s voting weight. Returns the weight.
+    pub fn verify(&self, db: &AccountsDB) -> Result<u64, &'static str> {
+        let validator = |signer: &Pubkey| Some((db.get_validator(signer)?.bls_key?, voting_weight(db, signer)));
+        self.verify_with(validator, total_voting_weight(db))
+    }
+
+    // `verify` against a validator set held some other way, e.g. by a light client. `validator` gives a
+    // signer's BLS key & voting weight.
+    pub fn verify_with(&self, validator: impl Fn(&Pubkey) -> Option<(BlsPubkey, u64)>, total_weight: u64) -> Result<u64, &'static str> {
+        if !self.signers.is_sorted_by(|a, b| a < b) {
+            return Err("Certificate signers are out of order")
+        }
+        let mut keys = vec![];
+        let mut weight = 0u64;
+        for signer in &self.signers {
+            let (key, signer_weight) = validator(signer).ok_or("Certificate signer has no BLS key")?;
+            keys.push(key);
+            weight = weight.saturating_add(signer_weight);
+        }
+        if !bls::verify_aggregate(&keys, &Vote::message(&self.block_hash, self.slot, VotePhase::Precommit), &self.signature) {
+            return Err("Invalid aggregate signature")
+        }
+        if !exceeds_two_thirds(weight, total_weight) {
+            return Err("Not enough votes for quorum")
+        }
+        Ok(weight)
+    }
+}
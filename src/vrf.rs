@@ -0,0 +1,101 @@
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_POINT,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::IsIdentity,
+};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{merkle::Hash, structures::Pubkey, wallet::Wallet};
+
+// Verifiable random function over validators' ed25519 keys, after ECVRF-EDWARDS25519-SHA512 (RFC 9381)
+// with its own domain separation. Only the key's holder can evaluate it, the output is fixed by the key
+// & input alone, & anyone holding the proof can check it.
+const HASH_TO_CURVE_DOMAIN: &[u8] = b"litechain_vrf_hash_to_curve";
+const CHALLENGE_DOMAIN: &[u8] = b"litechain_vrf_challenge";
+const OUTPUT_DOMAIN: &[u8] = b"litechain_vrf_output";
+
+// That `gamma` is the signer's secret scalar times the input's curve point: a Chaum-Pedersen proof it
+// shares its discrete log with the signer's public key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct VrfProof {
+    pub gamma: [u8; 32],
+    pub challenge: [u8; 32],
+    pub response: [u8; 32],
+}
+
+// What leaders are drawn from: the epoch's seed & the slot being led
+pub fn leader_input(epoch_seed: &Hash, slot: u64) -> Vec<u8> {
+    [&epoch_seed[..], &slot.to_le_bytes()].concat()
+}
+
+// Evaluate the VRF on `input` with `wallet`'s key, returning the output & the proof of it
+pub fn prove(wallet: &Wallet, input: &[u8]) -> (Hash, VrfProof) {
+    // The same secret scalar & nonce key ed25519 signing expands the secret key into
+    let expanded = Sha512::digest(wallet.secret_key());
+    let mut key: [u8; 32] = expanded[..32].try_into().expect("Half a sha512 digest is 32 bytes");
+    key[0] &= 248;
+    key[31] &= 127;
+    key[31] |= 64;
+    let secret = Scalar::from_bits(key);
+
+    let point = hash_to_curve(&wallet.public_key, input);
+    let gamma = secret * point;
+    let nonce = wide_scalar(Sha512::new().chain_update(&expanded[32..]).chain_update(point.compress().as_bytes()));
+    let challenge = challenge(&point, &gamma, &(nonce * ED25519_BASEPOINT_POINT), &(nonce * point));
+    let proof = VrfProof {
+        gamma: gamma.compress().to_bytes(),
+        challenge: challenge.to_bytes(),
+        response: (nonce + challenge * secret).to_bytes(),
+    };
+    (output(&gamma), proof)
+}
+
+// `signer`'s output on `input`, if `proof` shows it's theirs
+pub fn verify(signer: &Pubkey, input: &[u8], proof: &VrfProof) -> Option<Hash> {
+    let public_key = CompressedEdwardsY(*signer).decompress().filter(|point| !point.is_small_order())?;
+    let gamma = CompressedEdwardsY(proof.gamma).decompress()?;
+    let challenge = Scalar::from_canonical_bytes(proof.challenge)?;
+    let response = Scalar::from_canonical_bytes(proof.response)?;
+
+    let point = hash_to_curve(signer, input);
+    let u = EdwardsPoint::vartime_double_scalar_mul_basepoint(&-challenge, &public_key, &response);
+    let v = response * point - challenge * gamma;
+    (self::challenge(&point, &gamma, &u, &v) == challenge).then(|| output(&gamma))
+}
+
+// A leader's output scaled down by its stake. The lowest score in a slot wins it, so the more stake a
+// validator has the more slots it leads, though not in exact proportion.
+pub fn leader_score(output: &Hash, stake: u64) -> u128 {
+    let draw = u64::from_be_bytes(output[..8].try_into().expect("Hashes are longer than 8 bytes"));
+    ((draw as u128) << 64) / stake.max(1) as u128
+}
+
+// Try-and-increment: the first digest that decodes to a point, cleared of its small-order part. Half of
+// all digests decode, so this is all but certain to finish within a couple of tries.
+fn hash_to_curve(signer: &Pubkey, input: &[u8]) -> EdwardsPoint {
+    (0..=u8::MAX)
+        .find_map(|counter| {
+            let digest = Sha512::new().chain_update(HASH_TO_CURVE_DOMAIN).chain_update(signer).chain_update(input).chain_update([counter]).finalize();
+            let point = CompressedEdwardsY(digest[..32].try_into().ok()?).decompress()?.mul_by_cofactor();
+            (!point.is_identity()).then_some(point)
+        })
+        .expect("Some digest decodes to a point")
+}
+
+fn challenge(point: &EdwardsPoint, gamma: &EdwardsPoint, u: &EdwardsPoint, v: &EdwardsPoint) -> Scalar {
+    let mut hasher = Sha512::new().chain_update(CHALLENGE_DOMAIN);
+    for point in [point, gamma, u, v] {
+        hasher.update(point.compress().as_bytes());
+    }
+    wide_scalar(hasher)
+}
+
+fn wide_scalar(hasher: Sha512) -> Scalar {
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+}
+
+// Multiplying by the cofactor first means a prover can't vary the output by adding small-order points
+fn output(gamma: &EdwardsPoint) -> Hash {
+    Sha256::new().chain_update(OUTPUT_DOMAIN).chain_update(gamma.mul_by_cofactor().compress().as_bytes()).finalize().into()
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db::AccountsDB,
+    structures::{Blockhash, Pubkey, StateDiff, UserAccount, ValidatorAccount},
+};
+
+const CHECKSUM_LENGTH: usize = 32;
+
+// Balances & stakes as they stand after a block is applied, its rewards are credited or it's rolled
+// back, along with the block the state is now at. Values are absolute, so replaying records in order
+// lands on the state after the last one.
+#[derive(Debug, Clone, PartialEq, Eq, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct WalRecord {
+    pub height: u64,
+    pub block_hash: Blockhash,
+    pub total_supply: u64,
+    // `None` means the account or validator no longer exists
+    pub balances: Vec<(Pubkey, Option<u64>)>,
+    pub stakes: Vec<(Pubkey, Option<u64>)>,
+}
+
+impl WalRecord {
+    // State after `diff`'s block was applied
+    pub fn applied(db: &AccountsDB, diff: &StateDiff) -> Self {
+        Self {
+            height: db.latest_height,
+            block_hash: db.latest_blockhash,
+            total_supply: db.total_supply,
+            balances: diff.balances.iter().map(|change| (change.pubkey, change.new)).collect(),
+            stakes: diff.stakes.iter().map(|change| (change.pubkey, change.new)).collect(),
+        }
+    }
+
+    // State after `diff`'s block was rolled back
+    pub fn reverted(db: &AccountsDB, diff: &StateDiff) -> Self {
+        Self {
+            height: db.latest_height,
+            block_hash: db.latest_blockhash,
+            total_supply: db.total_supply,
+            balances: diff.balances.iter().map(|change| (change.pubkey, change.old)).collect(),
+            stakes: diff.stakes.iter().map(|change| (change.pubkey, change.old)).collect(),
+        }
+    }
+
+    pub fn replay(&self, db: &mut AccountsDB) {
+        for (pubkey, balance) in &self.balances {
+            match balance {
+                Some(balance) => db.accounts.entry(*pubkey).or_insert_with(|| UserAccount::from_public_key(*pubkey)).balance = *balance,
+                None => { db.accounts.remove(pubkey); }
+            }
+        }
+        for (pubkey, stake) in &self.stakes {
+            match stake {
+                Some(stake) => db.validators.entry(*pubkey).or_insert_with(|| ValidatorAccount::new(*pubkey)).stake = *stake,
+                None => { db.validators.remove(pubkey); }
+            }
+        }
+        db.latest_height = self.height;
+        db.latest_blockhash = self.block_hash;
+        db.total_supply = self.total_supply;
+    }
+}
+
+// Append-only log of `WalRecord`s. Each record is written as a little-endian u32 length, the borsh
+// encoded record & a sha256 of it, and is fsynced before `append` returns.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    file: File,
+}
+
+impl WriteAheadLog {
+    // Open the log at `path` for appending, creating it if needed. A torn record left at the end by a
+    // crash is cut off first, so new records don't land behind it.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(path)?;
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        let (_, valid) = parse(&contents);
+        file.set_len(valid as u64)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self { file })
+    }
+
+    // Start an empty log at `path`, discarding whatever was there
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub fn append(&mut self, record: &WalRecord) -> io::Result<()> {
+        let payload = borsh::to_vec(record)?;
+        let length = u32::try_from(payload.len()).map_err(|_| Error::new(ErrorKind::InvalidInput, "Record too large"))?;
+
+        let mut entry = length.to_le_bytes().to_vec();
+        entry.extend(&payload);
+        entry.extend(Sha256::digest(&payload));
+        self.file.write_all(&entry)?;
+        self.file.sync_data()
+    }
+
+    // Every complete record in the log at `path`, oldest first. A record cut short or corrupted by a
+    // crash mid-write ends the log, since it was never acknowledged.
+    pub fn read(path: &Path) -> io::Result<Vec<WalRecord>> {
+        let mut contents = vec![];
+        File::open(path)?.read_to_end(&mut contents)?;
+        Ok(parse(&contents).0)
+    }
+
+    // Rebuild logged state on top of the state the log started from, e.g. the chain's genesis state
+    pub fn recover(path: &Path, db: &mut AccountsDB) -> io::Result<()> {
+        for record in Self::read(path)? {
+            record.replay(db);
+        }
+        Ok(())
+    }
+}
+
+// The complete records at the start of `contents`, & how many bytes they take up
+fn parse(contents: &[u8]) -> (Vec<WalRecord>, usize) {
+    let mut records = vec![];
+    let mut rest = contents;
+    while let Some((length, tail)) = rest.split_first_chunk::<4>() {
+        let length = u32::from_le_bytes(*length) as usize;
+        if tail.len() < length.saturating_add(CHECKSUM_LENGTH) {
+            break
+        }
+        let (payload, tail) = tail.split_at(length);
+        let (checksum, tail) = tail.split_at(CHECKSUM_LENGTH);
+        if Sha256::digest(payload).as_slice() != checksum {
+            break
+        }
+        let Ok(record) = borsh::from_slice(payload) else { break };
+        records.push(record);
+        rest = tail;
+    }
+    (records, contents.len() - rest.len())
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer as DalekSigner, SECRET_KEY_LENGTH};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    bls::{self, BlsPubkey, BlsSignature},
+    mnemonic,
+    structures::{Address, Pubkey, Seckey, UserAccount},
+};
+
+// Signing material for one identity. This is the only place a secret key lives: on-chain
+// `UserAccount` / `ValidatorAccount` state only ever holds the public half.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Wallet {
+    pub address: Address,
+    pub public_key: Pubkey,
+    secret_key: Seckey,
+}
+
+impl Wallet {
+    pub fn generate() -> Self {
+        let mut secret_key = [0u8; SECRET_KEY_LENGTH];
+        OsRng.fill_bytes(&mut secret_key);
+        Self::from_secret_key(secret_key).expect("Any 32 bytes are a valid ed25519 secret key")
+    }
+
+    pub fn from_secret_key(secret_key: Seckey) -> Result<Self, &'static str> {
+        let secret = SecretKey::from_bytes(&secret_key).map_err(|_| "Invalid secret key")?;
+        let public_key = PublicKey::from(&secret).to_bytes();
+
+        Ok(Wallet {
+            address: hex::encode(public_key),
+            public_key,
+            secret_key,
+        })
+    }
+
+    // Account number `account` of an HD wallet seeded by `phrase`
+    pub fn from_mnemonic(phrase: &str, passphrase: &str, account: u32) -> Result<Self, &'static str> {
+        Self::from_secret_key(mnemonic::derive_account_key(phrase, passphrase, account)?)
+    }
+
+    pub fn secret_key(&self) -> &Seckey {
+        &self.secret_key
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        let keypair = Keypair {
+            public: PublicKey::from_bytes(&self.public_key).expect("Invalid public key"),
+            secret: SecretKey::from_bytes(&self.secret_key).expect("Invalid secret key"),
+        };
+
+        keypair.sign(message)
+    }
+
+    // Public half of the BLS key this wallet votes with, derived from its secret key
+    pub fn bls_public_key(&self) -> BlsPubkey {
+        bls::public_key(&bls::secret_key(&self.secret_key))
+    }
+
+    pub fn bls_sign(&self, message: &[u8]) -> BlsSignature {
+        bls::sign(&bls::secret_key(&self.secret_key), message)
+    }
+
+    // What registering the BLS key as a validator takes, see `bls`
+    pub fn bls_proof_of_possession(&self) -> BlsSignature {
+        bls::prove_possession(&bls::secret_key(&self.secret_key))
+    }
+
+    // A fresh, empty on-chain account owned by this wallet
+    pub fn account(&self) -> UserAccount {
+        UserAccount::from_public_key(self.public_key)
+    }
+}
+
+// Never print the secret key
+impl fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wallet").field("address", &self.address).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::{Error, ErrorKind, Read, Result, Write},
+    time::{Duration, SystemTime},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use ed25519_dalek::Signature;
+
+// Borsh adapters for foreign types that show up in our canonical wire format.
+// Used through `#[borsh(serialize_with = ..., deserialize_with = ...)]` on the fields that need them.
+
+pub(crate) fn serialize_signature<W: Write>(signature: &Signature, writer: &mut W) -> Result<()> {
+    writer.write_all(&signature.to_bytes())
+}
+
+pub(crate) fn deserialize_signature<R: Read>(reader: &mut R) -> Result<Signature> {
+    let bytes = <[u8; Signature::BYTE_SIZE]>::deserialize_reader(reader)?;
+    Signature::from_bytes(&bytes).map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid signature bytes"))
+}
+
+// A multisig's signatures, each tagged with its signer's index: a u32 count, then (index, signature) pairs
+pub(crate) fn serialize_signature_set<W: Write>(signatures: &[(u8, Signature)], writer: &mut W) -> Result<()> {
+    (signatures.len() as u32).serialize(writer)?;
+    for (index, signature) in signatures {
+        index.serialize(writer)?;
+        serialize_signature(signature, writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn deserialize_signature_set<R: Read>(reader: &mut R) -> Result<Vec<(u8, Signature)>> {
+    let count = u32::deserialize_reader(reader)?;
+    (0..count)
+        .map(|_| Ok((u8::deserialize_reader(reader)?, deserialize_signature(reader)?)))
+        .collect()
+}
+
+// Timestamps are encoded as (seconds, nanoseconds) since the unix epoch
+pub(crate) fn serialize_timestamp<W: Write>(timestamp: &SystemTime, writer: &mut W) -> Result<()> {
+    let duration = timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Timestamp before unix epoch"))?;
+
+    duration.as_secs().serialize(writer)?;
+    duration.subsec_nanos().serialize(writer)
+}
+
+pub(crate) fn deserialize_timestamp<R: Read>(reader: &mut R) -> Result<SystemTime> {
+    let secs = u64::deserialize_reader(reader)?;
+    let nanos = u32::deserialize_reader(reader)?;
+    // Anything over a second would carry into the seconds, giving the same time a second encoding, &
+    // overflow them if they're already at the maximum
+    if nanos >= 1_000_000_000 {
+        return Err(Error::new(ErrorKind::InvalidData, "Timestamp nanoseconds out of range"))
+    }
+
+    SystemTime::UNIX_EPOCH
+        .checked_add(Duration::new(secs, nanos))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Timestamp out of range"))
+}
+
+// Serde adapters for byte arrays longer than the 32 elements serde derives handle, like BLS keys &
+// signatures. Used through `#[serde(with = ...)]`.
+pub(crate) mod byte_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+        Vec::<u8>::deserialize(deserializer)?.try_into().map_err(|_| D::Error::custom("Byte array has the wrong length"))
+    }
+}
+
+pub(crate) mod optional_byte_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer, const N: usize>(bytes: &Option<[u8; N]>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&bytes[..]),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<Option<[u8; N]>, D::Error> {
+        Option::<Vec<u8>>::deserialize(deserializer)?
+            .map(|bytes| bytes.try_into().map_err(|_| D::Error::custom("Byte array has the wrong length")))
+            .transpose()
+    }
+}